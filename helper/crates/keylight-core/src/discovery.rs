@@ -0,0 +1,135 @@
+//! mDNS browsing for lights, without saving anything. keylightd's `discover` and the
+//! discovery it keeps running while it serves are built on this and store what it
+//! finds; tools that just want to know what's on the network can call `browse`.
+
+use flume::{Receiver, RecvTimeoutError, TryRecvError};
+use mdns_sd::{ResolvedService, ServiceDaemon, ServiceEvent};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+/// Service type Elgato lights announce.
+pub const ELGATO_SERVICE: &str = "_elg._tcp.local.";
+/// How often each browse is checked while waiting on several.
+const POLL: Duration = Duration::from_millis(50);
+
+/// A light that answered the browse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Found {
+    /// mDNS instance name, the id keylightd stores the light under.
+    pub id: String,
+    pub hostname: String,
+    pub port: u16,
+    /// Sorted, so the same announcement compares equal however it arrived.
+    pub addresses: Vec<String>,
+}
+
+impl Found {
+    /// Address to reach the light at: IPv4 when it announced one.
+    pub fn address(&self) -> Option<String> {
+        crate::store::select_address(&self.addresses)
+    }
+}
+
+impl From<&ResolvedService> for Found {
+    fn from(info: &ResolvedService) -> Self {
+        let mut addresses: Vec<String> = info
+            .get_addresses()
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect();
+        addresses.sort();
+        Self {
+            id: info.get_fullname().to_string(),
+            hostname: info.get_hostname().to_string(),
+            port: info.get_port(),
+            addresses,
+        }
+    }
+}
+
+/// Browses for several service types at once.
+pub struct Browse {
+    services: Vec<String>,
+    receivers: Vec<Receiver<ServiceEvent>>,
+}
+
+impl Browse {
+    pub fn start(daemon: &ServiceDaemon, services: &[String]) -> Result<Self, mdns_sd::Error> {
+        let receivers = services
+            .iter()
+            .map(|service| daemon.browse(service))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            services: services.to_vec(),
+            receivers,
+        })
+    }
+
+    /// The next event from any of the browses, waiting at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ServiceEvent, RecvTimeoutError> {
+        if let [receiver] = &self.receivers[..] {
+            return receiver.recv_timeout(timeout);
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            for receiver in &self.receivers {
+                match receiver.try_recv() {
+                    Ok(event) => return Ok(event),
+                    Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                    Err(TryRecvError::Empty) => {}
+                }
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            thread::sleep(remaining.min(POLL));
+        }
+    }
+
+    pub fn stop(&self, daemon: &ServiceDaemon) -> Result<(), mdns_sd::Error> {
+        for service in &self.services {
+            daemon.stop_browse(service)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lights announcing any of `services` (usually just `ELGATO_SERVICE`) within
+/// `timeout`. `each` sees every announcement as it arrives, repeats included; the
+/// result has each light once, as it last announced itself.
+pub fn browse(
+    services: &[String],
+    timeout: Duration,
+    mut each: impl FnMut(&Found),
+) -> Result<Vec<Found>, Error> {
+    let daemon = ServiceDaemon::new()?;
+    let browser = Browse::start(&daemon, services)?;
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<Found> = Vec::new();
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match browser.recv_timeout(remaining) {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                let light = Found::from(&*info);
+                each(&light);
+                found.retain(|known| known.id != light.id);
+                found.push(light);
+            }
+            Ok(ServiceEvent::SearchStopped(_)) => break,
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                daemon.shutdown().ok();
+                return Err("mDNS daemon went away".into());
+            }
+        }
+    }
+    browser.stop(&daemon).ok();
+    daemon.shutdown().ok();
+    Ok(found)
+}
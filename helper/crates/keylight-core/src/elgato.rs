@@ -0,0 +1,76 @@
+//! Requests to an Elgato light (or a clone speaking its API) on port 9123.
+//!
+//! Addresses are those `Store::targets` returns: an IP, or `ip#zone` for one zone of
+//! a multi-zone light. Timeouts are the `Client`'s; nothing here retries.
+
+use crate::protocol::{split_zone, LightState, LightUpdate, LightsPayload};
+use crate::Error;
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+/// Port every Elgato light serves its API on.
+pub const PORT: u16 = 9123;
+
+fn url(ip: &str, path: &str) -> String {
+    format!("http://{}:{PORT}/elgato/{path}", split_zone(ip).0)
+}
+
+fn get(client: &Client, ip: &str, path: &str) -> Result<Value, Error> {
+    Ok(client
+        .get(url(ip, path))
+        .send()?
+        .error_for_status()?
+        .json()?)
+}
+
+/// Product name, firmware, serial number and display name.
+pub fn accessory_info(client: &Client, ip: &str) -> Result<Value, Error> {
+    get(client, ip, "accessory-info")
+}
+
+/// Power-on behavior and, on some clones, the supported temperature range.
+pub fn settings(client: &Client, ip: &str) -> Result<Value, Error> {
+    get(client, ip, "lights/settings")
+}
+
+/// Current state of the light, or of the zone `ip` names.
+pub fn state(client: &Client, ip: &str) -> Result<LightState, Error> {
+    let payload: LightsPayload<LightState> = serde_json::from_value(get(client, ip, "lights")?)?;
+    let zone = split_zone(ip).1.map_or(0, usize::from);
+    payload
+        .lights
+        .into_iter()
+        .nth(zone)
+        .ok_or_else(|| format!("{ip} reported no zone {zone}").into())
+}
+
+/// Zones the light has; more than one on some Ring Light firmware.
+pub fn zone_count(client: &Client, ip: &str) -> Result<u8, Error> {
+    let payload: LightsPayload<Value> = serde_json::from_value(get(client, ip, "lights")?)?;
+    Ok(payload.number_of_lights)
+}
+
+/// Apply `update` and return the state the light reports back.
+///
+/// A zone address updates only that zone; the others are sent empty, which leaves
+/// them as they are.
+pub fn set_state(client: &Client, ip: &str, update: &LightUpdate) -> Result<LightState, Error> {
+    let zone = usize::from(split_zone(ip).1.unwrap_or(0));
+    let mut lights = vec![LightUpdate::default(); zone];
+    lights.push(update.clone());
+    let payload = LightsPayload {
+        number_of_lights: lights.len() as u8,
+        lights,
+    };
+    let response: LightsPayload<LightState> = client
+        .put(url(ip, "lights"))
+        .json(&payload)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    response
+        .lights
+        .into_iter()
+        .nth(zone)
+        .ok_or_else(|| format!("{ip} reported no zone {zone}").into())
+}
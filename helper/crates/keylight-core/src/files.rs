@@ -0,0 +1,85 @@
+//! Writing config files the way keylightd does: private to the current user, and
+//! replaced in one rename so a reader never sees one half written.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Create `dir` (and any missing parents) accessible only by the current user.
+pub fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+    }
+    #[cfg(not(unix))]
+    {
+        fs::create_dir_all(dir)
+    }
+}
+
+/// Write `bytes` to `path`, creating it as 0600 so future secrets (API tokens, MQTT
+/// credentials) aren't readable by other users. Existing files keep their mode.
+///
+/// The bytes go to a file beside `path` that is then renamed over it, so a request
+/// reading the config on another worker never sees it half written.
+pub fn write_private_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write as _;
+
+    static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+    // Replace what a symlinked config points at, not the link.
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut temp = path.clone().into_os_string();
+    temp.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp = PathBuf::from(temp);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(&temp).and_then(|mut file| {
+        file.write_all(bytes)?;
+        if let Ok(existing) = fs::metadata(&path) {
+            file.set_permissions(existing.permissions())?;
+        }
+        fs::rename(&temp, &path)
+    });
+    if written.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_replace_the_file_and_keep_it_private() {
+        let dir = std::env::temp_dir().join(format!("keylight-core-files-{}", std::process::id()));
+        create_private_dir(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        write_private_file(&path, b"first").unwrap();
+        write_private_file(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,44 @@
+//! Light control without keylightd in between: the Elgato HTTP protocol, the lights
+//! and groups keylightd has saved, mDNS discovery and private config file writes,
+//! for Rust tools that would rather not shell out to `keylightd` or go through its
+//! REST API.
+//!
+//! ```no_run
+//! use keylight_core::protocol::{KelvinRange, LightUpdate};
+//! use keylight_core::store::{Store, Target};
+//! use keylight_core::elgato;
+//! use std::time::Duration;
+//!
+//! # fn main() -> Result<(), keylight_core::Error> {
+//! let client = keylight_core::Client::builder()
+//!     .timeout(Duration::from_secs(3))
+//!     .build()?;
+//! let store = Store::load()?;
+//! let update = LightUpdate {
+//!     on: Some(1),
+//!     brightness: Some(40),
+//!     temperature: Some(KelvinRange::DEFAULT.kelvin_to_mired(4500)),
+//!     ..LightUpdate::default()
+//! };
+//! for ip in store.targets(&Target::Group("desk".into()))? {
+//!     elgato::set_state(&client, &ip, &update)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only Elgato lights (and clones of their API) are driven from here; WiZ, LIFX and
+//! custom lights, calibration, brightness limits, schedules and the event log stay
+//! with keylightd.
+
+pub mod discovery;
+pub mod elgato;
+pub mod files;
+pub mod protocol;
+pub mod store;
+
+/// The HTTP client `elgato` takes; its timeouts apply to every request.
+pub use reqwest::blocking::Client;
+
+/// Errors from this crate: I/O, HTTP, parse errors and messages about targets.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -0,0 +1,209 @@
+//! The JSON Elgato lights speak on port 9123, and the Kelvin/mired conversions
+//! around it. The API itself takes color temperature in mired; these types keep
+//! that, and `KelvinRange` converts for people.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Warmest temperature of an Elgato Key Light, in Kelvin.
+pub const KELVIN_MIN: u16 = 2900;
+/// Coolest temperature of an Elgato Key Light, in Kelvin.
+pub const KELVIN_MAX: u16 = 7000;
+
+/// Body of `GET`/`PUT /elgato/lights`: one entry per zone, usually just one.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LightsPayload<T> {
+    pub number_of_lights: u8,
+    pub lights: Vec<T>,
+}
+
+/// What a light reports about itself.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LightState {
+    /// 1 when on, 0 when off.
+    pub on: u8,
+    pub brightness: u8,
+    /// Mired. Missing while a Light Strip is in color mode.
+    #[serde(default)]
+    pub temperature: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hue: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+}
+
+/// A change to send to a light; fields left `None` stay as they are.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LightUpdate {
+    /// 1 to switch on, 0 to switch off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    /// Mired; see `KelvinRange::kelvin_to_mired`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<u16>,
+    /// Light Strip color, 0-360 degrees.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hue: Option<f64>,
+    /// Light Strip color saturation, 0-100.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+}
+
+impl LightUpdate {
+    /// Fold a later update into this one. Later fields win, and a color replaces a
+    /// temperature (or the other way round) rather than both being sent.
+    pub fn merge(&mut self, later: &LightUpdate) {
+        if later.temperature.is_some() {
+            self.hue = None;
+            self.saturation = None;
+        }
+        if later.hue.is_some() || later.saturation.is_some() {
+            self.temperature = None;
+        }
+        self.on = later.on.or(self.on);
+        self.brightness = later.brightness.or(self.brightness);
+        self.temperature = later.temperature.or(self.temperature);
+        self.hue = later.hue.or(self.hue);
+        self.saturation = later.saturation.or(self.saturation);
+    }
+}
+
+/// Color temperature range a light supports, in Kelvin.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KelvinRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl KelvinRange {
+    /// Elgato Key Light range; used for devices that don't report their own.
+    pub const DEFAULT: KelvinRange = KelvinRange {
+        min: KELVIN_MIN,
+        max: KELVIN_MAX,
+    };
+
+    pub fn clamp_kelvin(self, kelvin: u16) -> u16 {
+        kelvin.clamp(self.min, self.max)
+    }
+
+    pub fn clamp_mired(self, mired: u16) -> u16 {
+        let lowest = (1_000_000u32 / self.max as u32) as u16;
+        let highest = (1_000_000u32 / self.min as u32) as u16;
+        mired.clamp(lowest, highest)
+    }
+
+    pub fn kelvin_to_mired(self, kelvin: u16) -> u16 {
+        let clamped = self.clamp_kelvin(kelvin) as u32;
+        let mired = ((1_000_000u32 + clamped / 2) / clamped) as u16;
+        self.clamp_mired(mired)
+    }
+
+    pub fn mired_to_kelvin(self, mired: u16) -> u16 {
+        let clamped = self.clamp_mired(mired) as u32;
+        ((1_000_000u32 + clamped / 2) / clamped) as u16
+    }
+}
+
+/// `mired` in Kelvin, within the Key Light range.
+pub fn mired_to_kelvin(mired: u16) -> u16 {
+    KelvinRange::DEFAULT.mired_to_kelvin(mired)
+}
+
+/// Range listed in a light's `/elgato/lights/settings` or accessory-info, if any.
+/// Elgato's own lights don't report one; some third-party `_elg._tcp` devices do.
+pub fn kelvin_range_from_value(value: &Value) -> Option<KelvinRange> {
+    const KEYS: [(&str, &str); 2] = [
+        ("temperatureMin", "temperatureMax"),
+        ("minTemperature", "maxTemperature"),
+    ];
+    KEYS.iter().find_map(|(min_key, max_key)| {
+        let a = value.get(min_key)?.as_u64()?;
+        let b = value.get(max_key)?.as_u64()?;
+        // Elgato's API speaks mired, where the larger number is the warmer end.
+        let (min, max) = if a < 1000 && b < 1000 {
+            (1_000_000 / b.max(1), 1_000_000 / a.max(1))
+        } else {
+            (a, b)
+        };
+        let plausible = 1000..=20_000;
+        (plausible.contains(&min) && plausible.contains(&max) && min < max).then_some(KelvinRange {
+            min: min as u16,
+            max: max as u16,
+        })
+    })
+}
+
+/// Light Strips take hue/saturation; Key Lights only do white.
+pub fn supports_color(accessory_info: Option<&Value>) -> bool {
+    accessory_info
+        .and_then(|info| info.get("productName"))
+        .and_then(Value::as_str)
+        .is_some_and(|name| name.contains("Light Strip"))
+}
+
+/// The device address and zone of a target address. One zone of a multi-zone light
+/// (a Ring Light's inner and outer ring) is addressed as `ip#zone`.
+pub fn split_zone(address: &str) -> (&str, Option<u8>) {
+    match address.rsplit_once('#') {
+        Some((ip, zone)) => match zone.parse() {
+            Ok(zone) => (ip, Some(zone)),
+            Err(_) => (address, None),
+        },
+        None => (address, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kelvin_to_mired_clamps_and_rounds() {
+        let range = KelvinRange::DEFAULT;
+        assert_eq!(range.kelvin_to_mired(7000), 143);
+        assert_eq!(range.kelvin_to_mired(10000), 143);
+        assert_eq!(range.kelvin_to_mired(2900), 344);
+        assert_eq!(range.kelvin_to_mired(1000), 344);
+    }
+
+    #[test]
+    fn mired_to_kelvin_clamps_and_rounds() {
+        assert_eq!(mired_to_kelvin(143), 6993);
+        assert_eq!(mired_to_kelvin(344), 2907);
+        assert_eq!(mired_to_kelvin(999), 2907);
+    }
+
+    #[test]
+    fn kelvin_range_is_read_from_mired_or_kelvin() {
+        let mired = serde_json::json!({"temperatureMin": 143, "temperatureMax": 370});
+        assert_eq!(
+            kelvin_range_from_value(&mired),
+            Some(KelvinRange {
+                min: 2702,
+                max: 6993
+            })
+        );
+        let kelvin = serde_json::json!({"minTemperature": 2700, "maxTemperature": 6500});
+        assert_eq!(
+            kelvin_range_from_value(&kelvin),
+            Some(KelvinRange {
+                min: 2700,
+                max: 6500
+            })
+        );
+        let inverted = serde_json::json!({"minTemperature": 6500, "maxTemperature": 2700});
+        assert_eq!(kelvin_range_from_value(&inverted), None);
+    }
+
+    #[test]
+    fn zones_are_split_from_addresses() {
+        assert_eq!(split_zone("192.168.1.30#1"), ("192.168.1.30", Some(1)));
+        assert_eq!(split_zone("192.168.1.30"), ("192.168.1.30", None));
+        assert_eq!(split_zone("fe80::1#x"), ("fe80::1#x", None));
+    }
+}
@@ -0,0 +1,426 @@
+//! The lights and groups keylightd has saved, read from its files: groups from
+//! `config.toml` in the config dir, lights from `state.json` in the state dir.
+//!
+//! Reading only. keylightd migrates these files between versions and rewrites
+//! `config.toml` keeping its comments, so changes should go through it (its CLI or
+//! REST API) rather than be written here.
+
+use crate::protocol::KelvinRange;
+use crate::Error;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory name used under each XDG base directory.
+pub const APP_DIR: &str = "limelight-keylight";
+
+/// Resolve an XDG base directory, falling back to `$HOME/<home_default>` when the
+/// variable is unset, empty, or relative (the spec says to ignore those).
+pub fn xdg_base_dir(var: &str, home_default: &str) -> Result<PathBuf, Error> {
+    if let Some(dir) = std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+    {
+        return Ok(dir);
+    }
+    match std::env::var_os("HOME") {
+        Some(home) if !home.is_empty() => Ok(PathBuf::from(home).join(home_default)),
+        _ => Err(format!("Unable to determine {var} directory").into()),
+    }
+}
+
+/// `config.toml`: groups, schedules and the other hand-editable settings.
+pub fn config_path() -> Result<PathBuf, Error> {
+    Ok(xdg_base_dir("XDG_CONFIG_HOME", ".config")?
+        .join(APP_DIR)
+        .join("config.toml"))
+}
+
+/// `state.json`: the lights discovery found.
+pub fn state_path() -> Result<PathBuf, Error> {
+    Ok(xdg_base_dir("XDG_STATE_HOME", ".local/state")?
+        .join(APP_DIR)
+        .join("state.json"))
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A saved light. Fields keylightd keeps only for itself are left out.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Light {
+    /// mDNS instance name, or `backend:serial` for lights found another way. One
+    /// zone of a multi-zone light has `#zone` appended.
+    pub id: String,
+    pub alias: Option<String>,
+    pub name: String,
+    pub hostname: String,
+    pub port: u16,
+    pub addresses: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// `elgato` unless the light was found as a WiZ, LIFX or custom light. Only
+    /// Elgato lights can be driven with `crate::elgato`.
+    #[serde(default = "Light::elgato")]
+    pub backend: String,
+    #[serde(default)]
+    pub kelvin_range: Option<KelvinRange>,
+    #[serde(default, alias = "location")]
+    pub room: Option<String>,
+    #[serde(default)]
+    pub zone: Option<u8>,
+}
+
+impl Light {
+    fn elgato() -> String {
+        "elgato".into()
+    }
+
+    /// Temperature range of the light; the Key Light's unless it reported another.
+    pub fn supported_kelvin(&self) -> KelvinRange {
+        self.kelvin_range.unwrap_or(KelvinRange::DEFAULT)
+    }
+}
+
+/// What resolving targets needs to know about a saved light. keylightd implements
+/// it for its own records, which keep much more, so both resolve names the same way.
+pub trait SavedLight {
+    fn id(&self) -> &str;
+    fn name(&self) -> &str;
+    fn alias(&self) -> Option<&str>;
+    fn enabled(&self) -> bool;
+    fn addresses(&self) -> &[String];
+    fn zone(&self) -> Option<u8>;
+
+    /// Whether this id, name or alias names the light.
+    fn is_named(&self, ident: &str) -> bool {
+        self.id() == ident || self.name() == ident || self.alias() == Some(ident)
+    }
+
+    /// Address to reach the light at; `ip#zone` for one zone of a multi-zone light.
+    fn address(&self) -> Option<String> {
+        let ip = select_address(self.addresses())?;
+        Some(match self.zone() {
+            Some(zone) => format!("{ip}#{zone}"),
+            None => ip,
+        })
+    }
+}
+
+impl SavedLight for Light {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn addresses(&self) -> &[String] {
+        &self.addresses
+    }
+
+    fn zone(&self) -> Option<u8> {
+        self.zone
+    }
+}
+
+/// The address to use out of those a light announced: IPv4 when there is one.
+pub fn select_address(addresses: &[String]) -> Option<String> {
+    addresses
+        .iter()
+        .find(|addr| addr.contains('.'))
+        .cloned()
+        .or_else(|| addresses.first().cloned())
+}
+
+/// A saved group. Members are light ids, names or aliases.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub stagger_ms: Option<u64>,
+}
+
+/// A saved group as target resolution sees it; see `SavedLight`.
+pub trait SavedGroup {
+    fn name(&self) -> &str;
+    fn members(&self) -> &[String];
+}
+
+impl SavedGroup for Group {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn members(&self) -> &[String] {
+        &self.members
+    }
+}
+
+/// Lights to act on, as the CLI's `--id`, `--group` and `--all` name them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// A light by id, name or alias.
+    Light(String),
+    Group(String),
+    /// Every enabled light.
+    All,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Store {
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+}
+
+impl Store {
+    /// Read keylightd's files from their usual places. Missing files read as empty,
+    /// so a fresh machine has no lights rather than an error.
+    pub fn load() -> Result<Self, Error> {
+        Self::load_from(&config_path()?, &state_path()?)
+    }
+
+    pub fn load_from(config: &Path, state: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_value(read_merged(config, state)?)?)
+    }
+
+    /// The light with this id, name or alias.
+    pub fn light(&self, ident: &str) -> Option<&Light> {
+        find(&self.lights, ident)
+    }
+
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// Addresses an update to `target` goes to; see `resolve`.
+    pub fn targets(&self, target: &Target) -> Result<Vec<String>, TargetError> {
+        resolve(&self.lights, &self.groups, target)
+    }
+}
+
+/// Why a target leaves nothing to send to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetError {
+    LightNotFound(String),
+    LightDisabled(String),
+    NoAddress(String),
+    GroupNotFound(String),
+    NoEnabledMembers(String),
+    NoLights,
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LightNotFound(ident) => write!(f, "No persisted light found with id '{ident}'"),
+            Self::LightDisabled(ident) => write!(f, "Light '{ident}' is disabled"),
+            Self::NoAddress(ident) => write!(f, "Light '{ident}' has no known address"),
+            Self::GroupNotFound(name) => write!(f, "No group named '{name}'"),
+            Self::NoEnabledMembers(name) => write!(f, "Group '{name}' has no enabled members"),
+            Self::NoLights => f.write_str("No persisted lights found. Run `discover` first."),
+        }
+    }
+}
+
+impl std::error::Error for TargetError {}
+
+/// The light among `lights` with this id, name or alias.
+pub fn find<'a, L: SavedLight>(lights: &'a [L], ident: &str) -> Option<&'a L> {
+    lights.iter().find(|light| light.is_named(ident))
+}
+
+/// The light a group member names and the address an update would go to, or why
+/// the member would be skipped.
+pub fn resolve_member<'a, L: SavedLight>(
+    lights: &'a [L],
+    ident: &str,
+) -> Result<(&'a L, String), &'static str> {
+    let light = find(lights, ident).ok_or("no persisted light has this id, name or alias")?;
+    if !light.enabled() {
+        return Err("light is disabled");
+    }
+    let ip = light.address().ok_or("light has no known address")?;
+    Ok((light, ip))
+}
+
+/// Addresses an update to `target` goes to, deduplicated. Disabled lights and group
+/// members that name no saved light are left out; a target that leaves nothing is
+/// an error, as it is for the CLI.
+pub fn resolve<L: SavedLight, G: SavedGroup>(
+    lights: &[L],
+    groups: &[G],
+    target: &Target,
+) -> Result<Vec<String>, TargetError> {
+    let mut ips: Vec<String> = match target {
+        Target::Light(ident) => {
+            let light =
+                find(lights, ident).ok_or_else(|| TargetError::LightNotFound(ident.clone()))?;
+            if !light.enabled() {
+                return Err(TargetError::LightDisabled(ident.clone()));
+            }
+            light.address().into_iter().collect()
+        }
+        Target::Group(name) => groups
+            .iter()
+            .find(|group| group.name() == name)
+            .ok_or_else(|| TargetError::GroupNotFound(name.clone()))?
+            .members()
+            .iter()
+            .filter_map(|member| resolve_member(lights, member).ok())
+            .map(|(_, ip)| ip)
+            .collect(),
+        Target::All => lights
+            .iter()
+            .filter(|light| light.enabled())
+            .filter_map(|light| light.address())
+            .collect(),
+    };
+    ips.sort();
+    ips.dedup();
+    if ips.is_empty() {
+        return Err(match target {
+            Target::Light(ident) => TargetError::NoAddress(ident.clone()),
+            Target::Group(name) => TargetError::NoEnabledMembers(name.clone()),
+            Target::All => TargetError::NoLights,
+        });
+    }
+    Ok(ips)
+}
+
+/// A file of keylightd's that couldn't be read or parsed.
+#[derive(Debug)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.reason)
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl FileError {
+    fn new(path: &Path, reason: impl ToString) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// `config.toml` as one JSON object with the `lights` of `state.json` added, which
+/// is how keylightd reads its two files back into a single config. A missing file
+/// reads as empty; `version` is left as `config.toml` has it, absent if it's missing.
+pub fn read_merged(config: &Path, state: &Path) -> Result<Value, FileError> {
+    let mut value = match read_optional(config)? {
+        Some(bytes) => toml_edit::de::from_str(&String::from_utf8_lossy(&bytes))
+            .map_err(|err| FileError::new(config, err))?,
+        None => Value::Object(Default::default()),
+    };
+    let lights = match read_optional(state)? {
+        Some(bytes) => serde_json::from_slice::<Value>(&bytes)
+            .map_err(|err| FileError::new(state, err))?
+            .get("lights")
+            .cloned()
+            .unwrap_or_else(|| Value::Array(Vec::new())),
+        None => Value::Array(Vec::new()),
+    };
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("lights".into(), lights);
+    }
+    Ok(value)
+}
+
+/// The file's contents, or `None` when there's no such file.
+pub fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, FileError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(FileError::new(path, err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn store() -> Store {
+        let lights = json!([
+            {
+                "id": "Left._elg._tcp.local.", "alias": "left", "name": "Key Light Left",
+                "hostname": "left.local.", "port": 9123,
+                "addresses": ["fe80::1", "192.168.1.20"], "last_seen_unix": 0,
+                "soft_start": true
+            },
+            {
+                "id": "Ring._elg._tcp.local.#1", "alias": null, "name": "Ring Light",
+                "hostname": "ring.local.", "port": 9123, "addresses": ["192.168.1.30"],
+                "zone": 1, "kelvin_range": {"min": 2700, "max": 6500}
+            },
+            {
+                "id": "Old._elg._tcp.local.", "alias": null, "name": "Old",
+                "hostname": "old.local.", "port": 9123, "addresses": ["192.168.1.40"],
+                "enabled": false
+            }
+        ]);
+        let groups = json!([
+            {"name": "desk", "members": ["left", "Ring Light", "Old", "gone"]},
+            {"name": "retired", "members": ["Old"]}
+        ]);
+        serde_json::from_value(json!({ "lights": lights, "groups": groups })).unwrap()
+    }
+
+    #[test]
+    fn lights_are_read_with_keylightd_defaults() {
+        let store = store();
+        let left = store.light("left").unwrap();
+        assert_eq!((left.enabled, left.backend.as_str()), (true, "elgato"));
+        assert_eq!(left.supported_kelvin(), KelvinRange::DEFAULT);
+        assert_eq!(left.address().as_deref(), Some("192.168.1.20"));
+        let ring = store.light("Ring Light").unwrap();
+        assert_eq!(ring.address().as_deref(), Some("192.168.1.30#1"));
+        assert_eq!(ring.supported_kelvin().min, 2700);
+    }
+
+    #[test]
+    fn targets_skip_disabled_and_unknown_lights() {
+        let store = store();
+        let desk = vec!["192.168.1.20".to_string(), "192.168.1.30#1".to_string()];
+        assert_eq!(store.targets(&Target::Group("desk".into())).unwrap(), desk);
+        assert_eq!(store.targets(&Target::All).unwrap(), desk);
+        assert!(store.targets(&Target::Group("retired".into())).is_err());
+        assert!(store.targets(&Target::Group("nope".into())).is_err());
+        assert!(store.targets(&Target::Light("Old".into())).is_err());
+        assert_eq!(
+            store.targets(&Target::Light("left".into())).unwrap(),
+            ["192.168.1.20"]
+        );
+    }
+
+    #[test]
+    fn missing_files_are_an_empty_store() {
+        let dir = std::env::temp_dir().join("keylight-core-missing");
+        let store = Store::load_from(&dir.join("config.toml"), &dir.join("state.json")).unwrap();
+        assert_eq!(store, Store::default());
+    }
+}
@@ -0,0 +1,179 @@
+//! Ambient mode: sample the screen every few seconds and tint a color-capable light
+//! (Light Strip) to its average color.
+//!
+//! Frames come from the Screenshot portal rather than a ScreenCast stream; reading a
+//! PipeWire stream would pull libpipewire into the build. Each screenshot is a full
+//! resolution PNG encoded and written to disk by the desktop (some also flash or
+//! play a shutter sound), so samples are at least 5 seconds apart; a strip that
+//! follows the screen that slowly still catches a change of wallpaper or window.
+//! The portal's file is deleted after sampling.
+
+use reqwest::blocking::Client;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const MIN_INTERVAL_SECS: u64 = 5;
+pub const MAX_INTERVAL_SECS: u64 = 30;
+pub const DEFAULT_INTERVAL_SECS: u64 = 5;
+
+/// Longest side the screenshot is scaled to before averaging.
+const SAMPLE_SIZE: u32 = 64;
+/// Changes smaller than this (degrees / percent) aren't worth a request.
+const HUE_STEP: f64 = 4.0;
+const SATURATION_STEP: f64 = 4.0;
+
+/// A running ambient worker; dropping it stops the worker.
+pub struct AmbientMode {
+    stop: Arc<AtomicBool>,
+    status: Arc<Mutex<Option<String>>>,
+}
+
+impl AmbientMode {
+    pub fn start(client: Arc<Client>, api_url: &str, light_id: String, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(None));
+        let url = format!("{}/v1/lights/{}", api_url, urlencoding::encode(&light_id));
+        let worker = Worker {
+            client,
+            url,
+            interval: interval.max(Duration::from_secs(MIN_INTERVAL_SECS)),
+            stop: Arc::clone(&stop),
+            status: Arc::clone(&status),
+        };
+        thread::spawn(move || worker.run());
+        Self { stop, status }
+    }
+
+    /// Last error from the worker, if the most recent frame failed.
+    pub fn status(&self) -> Option<String> {
+        self.status.lock().ok().and_then(|s| s.clone())
+    }
+}
+
+impl Drop for AmbientMode {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Worker {
+    client: Arc<Client>,
+    url: String,
+    interval: Duration,
+    stop: Arc<AtomicBool>,
+    status: Arc<Mutex<Option<String>>>,
+}
+
+impl Worker {
+    fn run(self) {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(rt) => rt,
+            Err(e) => {
+                self.set_status(Some(format!("failed to start runtime: {e}")));
+                return;
+            }
+        };
+        let mut last: Option<(f64, f64)> = None;
+        while !self.stop.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            match runtime.block_on(capture_color()) {
+                Ok((hue, saturation)) => {
+                    if last.is_none_or(|prev| changed(prev, (hue, saturation))) {
+                        match self.send(hue, saturation) {
+                            Ok(()) => {
+                                last = Some((hue, saturation));
+                                self.set_status(None);
+                            }
+                            Err(e) => self.set_status(Some(e)),
+                        }
+                    }
+                }
+                Err(e) => self.set_status(Some(e)),
+            }
+            // Sleep in short steps so turning the mode off takes effect promptly.
+            while started.elapsed() < self.interval && !self.stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+
+    fn send(&self, hue: f64, saturation: f64) -> Result<(), String> {
+        self.client
+            .put(&self.url)
+            .json(&serde_json::json!({"hue": hue, "saturation": saturation}))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn set_status(&self, status: Option<String>) {
+        if let Ok(mut slot) = self.status.lock() {
+            *slot = status;
+        }
+    }
+}
+
+fn changed(prev: (f64, f64), next: (f64, f64)) -> bool {
+    let hue_delta = (prev.0 - next.0).abs();
+    hue_delta.min(360.0 - hue_delta) >= HUE_STEP || (prev.1 - next.1).abs() >= SATURATION_STEP
+}
+
+async fn capture_color() -> Result<(f64, f64), String> {
+    let rgb = capture_screen(SAMPLE_SIZE).await?;
+    let mut sum = [0u64; 3];
+    for pixel in rgb.pixels() {
+        for (total, value) in sum.iter_mut().zip(pixel.0) {
+            *total += value as u64;
+        }
+    }
+    let count = (rgb.width() * rgb.height()).max(1) as f64;
+    let [r, g, b] = sum.map(|total| total as f64 / count / 255.0);
+    Ok(rgb_to_hue_saturation(r, g, b))
+}
+
+/// Take a screenshot through the portal, scaled so its longest side is `size`.
+pub async fn capture_screen(size: u32) -> Result<image::RgbImage, String> {
+    use ashpd::desktop::screenshot::Screenshot;
+
+    let response = Screenshot::request()
+        .interactive(false)
+        .modal(false)
+        .send()
+        .await
+        .and_then(|request| request.response())
+        .map_err(|e| format!("screenshot portal: {e}"))?;
+    let path = response
+        .uri()
+        .to_file_path()
+        .map_err(|_| "screenshot is not a local file".to_string())?;
+    let image = image::open(&path).map_err(|e| format!("unable to read screenshot: {e}"));
+    let _ = std::fs::remove_file(&path);
+    Ok(image?.thumbnail(size, size).to_rgb8())
+}
+
+/// HSV hue (degrees) and saturation (percent); value is left to the brightness slider.
+fn rgb_to_hue_saturation(r: f64, g: f64, b: f64) -> (f64, f64) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    if delta <= f64::EPSILON {
+        return (0.0, 0.0);
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (
+        (hue * 10.0).round() / 10.0,
+        (delta / max * 1000.0).round() / 10.0,
+    )
+}
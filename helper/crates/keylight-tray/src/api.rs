@@ -0,0 +1,396 @@
+//! Daemon requests made on behalf of the window. They run one at a time on a
+//! background thread so a slow or unreachable daemon never holds up a frame; the
+//! window sends `Request`s and applies the `Reply`s it finds each frame.
+//!
+//! Requests are handled in the order sent, so a refresh queued right after a change
+//! sees that change.
+
+use eframe::egui;
+use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use super::{
+    GroupRecord, GroupRequest, GroupSuggestion, LightHealth, LightRecord, LightStateResponse,
+    PresetRecord, ProfileStatus, SceneRecord, ScheduleRecord, TimerRecord,
+};
+
+pub enum Request {
+    Lights,
+    States,
+    Health,
+    Groups,
+    GroupSuggestions,
+    Schedules,
+    Scenes,
+    Presets,
+    /// Ask the daemon to look for new lights.
+    Discover,
+    SaveGroup {
+        name: String,
+        members: Vec<String>,
+    },
+    DeleteGroup(String),
+    /// Move a group to `position` in the list; answered with every group.
+    SetGroupPosition {
+        name: String,
+        position: usize,
+    },
+    /// Save the lights' current state as a scene.
+    SaveScene(String),
+    ApplyScene(String),
+    RenameScene {
+        from: String,
+        to: String,
+    },
+    DeleteScene(String),
+    /// Every scene name, in the new order.
+    ReorderScenes(Vec<String>),
+    SetSceneFavorite {
+        name: String,
+        favorite: bool,
+    },
+    SetLight {
+        id: String,
+        setting: LightSetting,
+    },
+    SetIcon {
+        id: String,
+        icon: Option<String>,
+    },
+    /// Move a light to `position` in the list; answered with every light.
+    SetLightPosition {
+        id: String,
+        position: usize,
+    },
+    SetScheduleOverride {
+        id: String,
+        paused: Option<bool>,
+        skip_next: Option<bool>,
+    },
+    ResetLight(String),
+    EndDnd,
+    /// Put back the lights the last change touched.
+    Undo,
+    Timers,
+    CancelTimer(u64),
+    Profiles,
+    /// Activate a profile; `None` lets the daemon's rules pick again.
+    SetProfile(Option<String>),
+}
+
+/// A light setting changed in Manage Lights.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LightSetting {
+    Enabled(bool),
+    SoftStart(bool),
+    Alias(Option<String>),
+    Room(Option<String>),
+    Favorite(bool),
+}
+
+/// What came back for a request. Changes the daemon refused, and requests whose
+/// answer the window doesn't use, have no reply.
+pub enum Reply {
+    Lights(Vec<LightRecord>),
+    States(Vec<LightStateResponse>),
+    /// Light states couldn't be fetched; the daemon may be gone.
+    StatesFailed,
+    Health(Vec<LightHealth>),
+    Groups(Vec<GroupRecord>),
+    GroupSuggestions(Vec<GroupSuggestion>),
+    Schedules(Vec<ScheduleRecord>),
+    Scenes(Vec<SceneRecord>),
+    Presets(Vec<PresetRecord>),
+    /// The daemon's reason for refusing a scene change.
+    SceneFailed(String),
+    /// Whether the daemon saved a light setting.
+    LightSet {
+        id: String,
+        setting: LightSetting,
+        saved: bool,
+    },
+    IconSaved {
+        id: String,
+        icon: Option<String>,
+    },
+    ScheduleOverride(LightRecord),
+    LightReset(String),
+    DndEnded,
+    Undone,
+    Timers(Vec<TimerRecord>),
+    Profiles(ProfileStatus),
+}
+
+/// Background request thread; dropping it stops the thread once it's idle.
+pub struct ApiWorker {
+    requests: Sender<(String, Request)>,
+    replies: Receiver<(String, Reply)>,
+}
+
+impl ApiWorker {
+    pub fn start(ctx: egui::Context, client: Arc<Client>) -> Self {
+        let (requests, pending) = mpsc::channel::<(String, Request)>();
+        let (done, replies) = mpsc::channel();
+        thread::spawn(move || {
+            let mut cache = ListCache::new();
+            for (api_url, request) in pending {
+                let Some(reply) = perform(&client, &mut cache, &api_url, request) else {
+                    continue;
+                };
+                if done.send((api_url, reply)).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+        Self { requests, replies }
+    }
+
+    /// Queue a request to the daemon at `api_url`.
+    pub fn send(&self, api_url: &str, request: Request) {
+        let _ = self.requests.send((api_url.to_string(), request));
+    }
+
+    /// The next reply from the daemon at `api_url`. Replies from a host the window
+    /// has since switched away from are dropped.
+    pub fn try_recv(&self, api_url: &str) -> Option<Reply> {
+        loop {
+            let (url, reply) = self.replies.try_recv().ok()?;
+            if url == api_url {
+                return Some(reply);
+            }
+        }
+    }
+}
+
+fn light_url(api_url: &str, id: &str, action: &str) -> String {
+    format!("{api_url}/v1/lights/{}/{action}", urlencoding::encode(id))
+}
+
+/// Last body of each list the daemon tagged with an `ETag`, by URL, so a poll
+/// answered `304 Not Modified` is read from here instead of sent again.
+type ListCache = HashMap<String, (String, Vec<u8>)>;
+
+fn get<T: DeserializeOwned>(client: &Client, cache: &mut ListCache, url: &str) -> Option<T> {
+    let mut request = client.get(url);
+    if let Some((etag, _)) = cache.get(url) {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag.as_str());
+    }
+    let response = request.send().and_then(|r| r.error_for_status()).ok()?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        let (_, body) = cache.get(url)?;
+        return serde_json::from_slice(body).ok();
+    }
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response.bytes().ok()?;
+    let value = serde_json::from_slice(&body).ok();
+    match etag {
+        Some(etag) => {
+            cache.insert(url.to_string(), (etag, body.to_vec()));
+        }
+        None => {
+            cache.remove(url);
+        }
+    }
+    value
+}
+
+fn put_position<T: DeserializeOwned>(client: &Client, url: &str, position: usize) -> Option<T> {
+    client
+        .put(url)
+        .json(&json!({ "position": position }))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .ok()
+}
+
+fn scene_url(api_url: &str, name: &str) -> String {
+    format!("{api_url}/v1/scenes/{}", urlencoding::encode(name))
+}
+
+/// `None` when the daemon took a scene change, else why it didn't.
+fn scene_failure(response: reqwest::Result<reqwest::blocking::Response>) -> Option<Reply> {
+    let message = match response {
+        Ok(response) if response.status().is_success() => return None,
+        Ok(response) => response
+            .json::<serde_json::Value>()
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| "The daemon refused the change".into()),
+        Err(err) => err.to_string(),
+    };
+    Some(Reply::SceneFailed(message))
+}
+
+fn perform(
+    client: &Client,
+    cache: &mut ListCache,
+    api_url: &str,
+    request: Request,
+) -> Option<Reply> {
+    match request {
+        Request::Lights => get(client, cache, &format!("{api_url}/v1/lights")).map(Reply::Lights),
+        Request::States => Some(
+            get(client, cache, &format!("{api_url}/v1/lights/states"))
+                .map_or(Reply::StatesFailed, Reply::States),
+        ),
+        Request::Health => {
+            get(client, cache, &format!("{api_url}/v1/lights/health")).map(Reply::Health)
+        }
+        Request::Groups => get(client, cache, &format!("{api_url}/v1/groups")).map(Reply::Groups),
+        Request::GroupSuggestions => {
+            get(client, cache, &format!("{api_url}/v1/groups/suggestions"))
+                .map(Reply::GroupSuggestions)
+        }
+        Request::Schedules => {
+            get(client, cache, &format!("{api_url}/v1/schedules")).map(Reply::Schedules)
+        }
+        Request::Scenes => get(client, cache, &format!("{api_url}/v1/scenes")).map(Reply::Scenes),
+        Request::Presets => {
+            get(client, cache, &format!("{api_url}/v1/presets")).map(Reply::Presets)
+        }
+        Request::Timers => get(client, cache, &format!("{api_url}/v1/timers")).map(Reply::Timers),
+        Request::Profiles => {
+            get(client, cache, &format!("{api_url}/v1/profiles")).map(Reply::Profiles)
+        }
+        Request::Discover => {
+            let _ = client
+                .post(format!("{api_url}/v1/lights/refresh"))
+                .json(&json!({"timeout": 3}))
+                .send();
+            None
+        }
+        Request::SaveGroup { name, members } => {
+            let _ = client
+                .post(format!("{api_url}/v1/groups"))
+                .json(&GroupRequest { name, members })
+                .send();
+            None
+        }
+        Request::DeleteGroup(name) => {
+            let url = format!("{api_url}/v1/groups/{}", urlencoding::encode(&name));
+            let _ = client.delete(url).send();
+            None
+        }
+        Request::SetGroupPosition { name, position } => {
+            let url = format!(
+                "{api_url}/v1/groups/{}/position",
+                urlencoding::encode(&name)
+            );
+            put_position(client, &url, position).map(Reply::Groups)
+        }
+        Request::SaveScene(name) => scene_failure(
+            client
+                .post(format!("{api_url}/v1/scenes"))
+                .json(&json!({ "name": name }))
+                .send(),
+        ),
+        Request::ApplyScene(name) => scene_failure(
+            client
+                .post(format!("{}/apply", scene_url(api_url, &name)))
+                .send(),
+        ),
+        Request::RenameScene { from, to } => scene_failure(
+            client
+                .put(scene_url(api_url, &from))
+                .json(&json!({ "name": to }))
+                .send(),
+        ),
+        Request::DeleteScene(name) => {
+            scene_failure(client.delete(scene_url(api_url, &name)).send())
+        }
+        Request::ReorderScenes(names) => scene_failure(
+            client
+                .put(format!("{api_url}/v1/scenes/order"))
+                .json(&json!({ "names": names }))
+                .send(),
+        ),
+        Request::SetSceneFavorite { name, favorite } => scene_failure(
+            client
+                .put(format!("{}/favorite", scene_url(api_url, &name)))
+                .json(&json!({ "favorite": favorite }))
+                .send(),
+        ),
+        Request::SetLight { id, setting } => {
+            let (action, body) = match &setting {
+                LightSetting::Enabled(enabled) => ("enabled", json!({ "enabled": enabled })),
+                LightSetting::SoftStart(enabled) => ("soft_start", json!({ "enabled": enabled })),
+                LightSetting::Alias(alias) => ("alias", json!({ "alias": alias })),
+                // The daemon clears the room on an empty string and keeps it when absent.
+                LightSetting::Favorite(favorite) => ("favorite", json!({ "favorite": favorite })),
+                LightSetting::Room(room) => {
+                    ("meta", json!({ "room": room.as_deref().unwrap_or("") }))
+                }
+            };
+            let saved = client
+                .put(light_url(api_url, &id, action))
+                .json(&body)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .is_ok();
+            Some(Reply::LightSet { id, setting, saved })
+        }
+        Request::SetIcon { id, icon } => client
+            .put(light_url(api_url, &id, "icon"))
+            .json(&json!({ "icon": icon }))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .ok()
+            .map(|_| Reply::IconSaved { id, icon }),
+        Request::SetLightPosition { id, position } => {
+            put_position(client, &light_url(api_url, &id, "position"), position).map(Reply::Lights)
+        }
+        Request::SetScheduleOverride {
+            id,
+            paused,
+            skip_next,
+        } => client
+            .put(light_url(api_url, &id, "schedule"))
+            .json(&json!({ "paused": paused, "skip_next": skip_next }))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .ok()
+            .map(Reply::ScheduleOverride),
+        Request::ResetLight(id) => client
+            .post(light_url(api_url, &id, "reset"))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .ok()
+            .map(|_| Reply::LightReset(id)),
+        Request::EndDnd => client
+            .delete(format!("{api_url}/v1/dnd"))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .ok()
+            .map(|_| Reply::DndEnded),
+        Request::Undo => client
+            .post(format!("{api_url}/v1/undo"))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .ok()
+            .map(|_| Reply::Undone),
+        Request::SetProfile(name) => client
+            .post(format!("{api_url}/v1/profiles/active"))
+            .json(&json!({ "name": name }))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .ok()
+            .map(Reply::Profiles),
+        Request::CancelTimer(id) => {
+            let _ = client.delete(format!("{api_url}/v1/timers/{id}")).send();
+            None
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Connection to keylightd: polls `GET /v1/status` in the background so the header
+//! can show whether the daemon is there, and so data is refreshed once it's back
+//! instead of the window quietly showing what it last saw.
+//!
+//! When the tray started the daemon itself it also supervises it: a daemon that
+//! exited is started again, backing off if it keeps dying.
+
+use eframe::egui;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// First retry after a failed poll; doubled on each further failure.
+const MIN_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(30);
+/// First wait before restarting a daemon that exited; doubled while it keeps exiting.
+const MIN_RESTART: Duration = Duration::from_secs(2);
+const MAX_RESTART: Duration = Duration::from_secs(60);
+
+/// The daemon process the tray started, if it started one.
+pub type DaemonProcess = Arc<Mutex<Option<Child>>>;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DaemonStatus {
+    pub version: String,
+    pub uptime_secs: u64,
+    pub lights: usize,
+    pub automations: Vec<String>,
+    pub dnd: DndStatus,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct DndStatus {
+    pub active: bool,
+    pub remaining_secs: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Connection {
+    /// `None` until the first poll finished.
+    pub connected: Option<bool>,
+    pub status: Option<DaemonStatus>,
+    /// When the next attempt is due while disconnected.
+    pub retry_at: Option<Instant>,
+    /// Times the supervised daemon was started again since it last answered.
+    pub restarts: u32,
+}
+
+#[derive(Default)]
+struct Shared {
+    connection: Connection,
+    /// A status the UI hasn't taken yet, with when it arrived.
+    fresh: Option<(DaemonStatus, Instant)>,
+    /// The daemon answered again after being unreachable.
+    reconnected: bool,
+}
+
+/// Background status poller; dropping it stops the poller.
+pub struct ConnectionMonitor {
+    shared: Arc<Mutex<Shared>>,
+    stop: Arc<AtomicBool>,
+    poke: Arc<AtomicBool>,
+    /// Seconds between polls while the daemon answers.
+    poll_secs: Arc<AtomicU64>,
+}
+
+impl ConnectionMonitor {
+    /// Start polling every `poll` while the daemon answers; `daemon` is supervised
+    /// when given.
+    pub fn start(
+        ctx: egui::Context,
+        client: Arc<Client>,
+        api_url: &str,
+        poll: Duration,
+        daemon: Option<DaemonProcess>,
+    ) -> Self {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let poke = Arc::new(AtomicBool::new(false));
+        let poll_secs = Arc::new(AtomicU64::new(poll.as_secs().max(1)));
+        let poller = Poller {
+            ctx,
+            client,
+            url: format!("{api_url}/v1/status"),
+            shared: Arc::clone(&shared),
+            stop: Arc::clone(&stop),
+            poke: Arc::clone(&poke),
+            poll_secs: Arc::clone(&poll_secs),
+            daemon,
+        };
+        thread::spawn(move || poller.run());
+        Self {
+            shared,
+            stop,
+            poke,
+            poll_secs,
+        }
+    }
+
+    /// Used from the next poll on.
+    pub fn set_poll_interval(&self, poll: Duration) {
+        self.poll_secs
+            .store(poll.as_secs().max(1), Ordering::Relaxed);
+    }
+
+    /// Check right away, e.g. because a request just failed.
+    pub fn poke(&self) {
+        self.poke.store(true, Ordering::Relaxed);
+    }
+
+    pub fn connection(&self) -> Connection {
+        self.shared
+            .lock()
+            .map(|shared| shared.connection.clone())
+            .unwrap_or_default()
+    }
+
+    /// The latest status and when it arrived, once per poll.
+    pub fn take_status(&self) -> Option<(DaemonStatus, Instant)> {
+        self.shared
+            .lock()
+            .ok()
+            .and_then(|mut shared| shared.fresh.take())
+    }
+
+    /// Whether the daemon came back since the last call; the UI should reload then.
+    pub fn take_reconnected(&self) -> bool {
+        self.shared
+            .lock()
+            .map(|mut shared| std::mem::take(&mut shared.reconnected))
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for ConnectionMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Poller {
+    ctx: egui::Context,
+    client: Arc<Client>,
+    url: String,
+    shared: Arc<Mutex<Shared>>,
+    stop: Arc<AtomicBool>,
+    poke: Arc<AtomicBool>,
+    poll_secs: Arc<AtomicU64>,
+    daemon: Option<DaemonProcess>,
+}
+
+impl Poller {
+    fn run(self) {
+        let mut retry = MIN_RETRY;
+        let mut restart = MIN_RESTART;
+        let mut restart_at: Option<Instant> = None;
+        while !self.stop.load(Ordering::Relaxed) {
+            let status = self
+                .client
+                .get(&self.url)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.json::<DaemonStatus>());
+            let wait = if status.is_ok() {
+                Duration::from_secs(self.poll_secs.load(Ordering::Relaxed))
+            } else {
+                retry
+            };
+            retry = if status.is_ok() {
+                MIN_RETRY
+            } else {
+                (retry * 2).min(MAX_RETRY)
+            };
+            if status.is_ok() {
+                restart = MIN_RESTART;
+                restart_at = None;
+            } else if self.daemon_exited() {
+                let due = *restart_at.get_or_insert_with(|| Instant::now() + restart);
+                if Instant::now() >= due {
+                    self.restart_daemon();
+                    restart = (restart * 2).min(MAX_RESTART);
+                    restart_at = None;
+                }
+            }
+            self.publish(status.ok(), wait);
+            // Sleep in short steps so closing the window isn't held up.
+            let started = Instant::now();
+            while started.elapsed() < wait
+                && !self.stop.load(Ordering::Relaxed)
+                && !self.poke.swap(false, Ordering::Relaxed)
+            {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+
+    /// Whether the daemon we started is no longer running.
+    fn daemon_exited(&self) -> bool {
+        let Some(daemon) = &self.daemon else {
+            return false;
+        };
+        let Ok(mut child) = daemon.lock() else {
+            return false;
+        };
+        match child.as_mut().map(Child::try_wait) {
+            Some(Ok(None)) => false,
+            Some(Ok(Some(_)) | Err(_)) | None => true,
+        }
+    }
+
+    fn restart_daemon(&self) {
+        let Some(daemon) = &self.daemon else {
+            return;
+        };
+        eprintln!("keylightd exited; starting it again");
+        if let Ok(mut child) = daemon.lock() {
+            *child = crate::spawn_daemon();
+        }
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.connection.restarts += 1;
+        }
+    }
+
+    /// Store a poll's result, and repaint only if it changes what the window shows
+    /// outside a tooltip: polls of an idle daemon shouldn't wake the UI.
+    fn publish(&self, status: Option<DaemonStatus>, wait: Duration) {
+        let Ok(mut shared) = self.shared.lock() else {
+            return;
+        };
+        let connected = status.is_some();
+        let was = shared.connection.connected;
+        let dnd = |status: Option<&DaemonStatus>| status.map(|s| s.dnd.clone());
+        // While offline the banner repaints itself to count down to the next retry.
+        let changed = was != Some(connected)
+            || dnd(shared.connection.status.as_ref()) != dnd(status.as_ref());
+        shared.reconnected |= connected && was == Some(false);
+        shared.connection.connected = Some(connected);
+        if connected {
+            shared.connection.restarts = 0;
+        }
+        shared.connection.retry_at = (!connected).then(|| Instant::now() + wait);
+        if let Some(status) = status {
+            shared.fresh = Some((status.clone(), Instant::now()));
+            shared.connection.status = Some(status);
+        }
+        drop(shared);
+        if changed {
+            self.ctx.request_repaint();
+        }
+    }
+}
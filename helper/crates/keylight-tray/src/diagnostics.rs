@@ -0,0 +1,161 @@
+//! "Export diagnostics": bundle what a bug report needs into one zip, saved through
+//! the FileChooser portal. The daemon parts are fetched from its API; if the daemon
+//! doesn't answer, the error goes in the bundle instead, which is a finding too.
+
+use eframe::egui;
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use super::settings;
+
+/// Daemon endpoints included, with the file each one is stored as.
+const DAEMON_ENDPOINTS: [(&str, &str); 4] = [
+    ("/v1/health", "daemon/health.json"),
+    ("/v1/version", "daemon/version.json"),
+    ("/v1/lights", "daemon/lights.json"),
+    ("/v1/logs", "daemon/logs.json"),
+];
+/// Setting names whose values never leave the machine.
+const SECRET_KEYS: [&str; 3] = ["password", "secret", "token"];
+
+/// Ask where to save and write the bundle on a background thread. `Ok(None)` means
+/// the dialog was cancelled.
+pub fn spawn(
+    ctx: egui::Context,
+    client: Arc<Client>,
+    api_url: String,
+) -> mpsc::Receiver<Result<Option<PathBuf>, String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = pick_destination().and_then(|path| match path {
+            Some(path) => {
+                let entries = collect(&client, &api_url);
+                write_zip(&path, &entries).map(|()| Some(path))
+            }
+            None => Ok(None),
+        });
+        let _ = tx.send(result);
+        ctx.request_repaint();
+    });
+    rx
+}
+
+fn pick_destination() -> Result<Option<PathBuf>, String> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+    runtime.block_on(async {
+        use ashpd::desktop::file_chooser::{FileFilter, SelectedFiles};
+
+        let request = SelectedFiles::save_file()
+            .title("Export diagnostics")
+            .accept_label("Export")
+            .modal(true)
+            .current_name("limelight-diagnostics.zip")
+            .filter(FileFilter::new("Zip archives").mimetype("application/zip"))
+            .send()
+            .await
+            .map_err(|e| format!("file chooser unavailable: {e}"))?;
+        match request.response() {
+            Ok(files) => Ok(files.uris().first().and_then(|uri| uri.to_file_path().ok())),
+            Err(ashpd::Error::Response(ashpd::desktop::ResponseError::Cancelled)) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    })
+}
+
+/// Files of the bundle, by name inside the zip.
+fn collect(client: &Client, api_url: &str) -> Vec<(String, Vec<u8>)> {
+    let mut entries = vec![("system.txt".to_string(), system_info().into_bytes())];
+
+    if let Some(dir) = settings::dir() {
+        let contents = std::fs::read_to_string(dir.join(settings::FILE_NAME))
+            .map_err(|e| e.to_string())
+            .and_then(|text| toml_edit::de::from_str::<Value>(&text).map_err(|e| e.to_string()));
+        entries.push(match contents {
+            Ok(mut value) => {
+                redact(&mut value);
+                ("tray/settings.json".to_string(), pretty(&value))
+            }
+            Err(e) => ("tray/settings.error.txt".to_string(), e.into_bytes()),
+        });
+    }
+
+    for (endpoint, name) in DAEMON_ENDPOINTS {
+        let response = client
+            .get(format!("{api_url}{endpoint}"))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json::<Value>());
+        entries.push(match response {
+            Ok(mut value) => {
+                redact(&mut value);
+                (name.to_string(), pretty(&value))
+            }
+            Err(e) => (format!("{name}.error.txt"), e.to_string().into_bytes()),
+        });
+    }
+    entries
+}
+
+fn system_info() -> String {
+    let env = |name| std::env::var(name).unwrap_or_else(|_| "unset".into());
+    format!(
+        "tray: {}\nos: {} {}\nXDG_CURRENT_DESKTOP: {}\nXDG_SESSION_TYPE: {}\nflatpak: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        env("XDG_CURRENT_DESKTOP"),
+        env("XDG_SESSION_TYPE"),
+        Path::new("/.flatpak-info").exists(),
+    )
+}
+
+fn pretty(value: &Value) -> Vec<u8> {
+    serde_json::to_vec_pretty(value).unwrap_or_default()
+}
+
+/// Blank out secrets and replace the home directory in paths with `~`, so the
+/// bundle can be attached to a public issue.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+                    *value = Value::String("[redacted]".into());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        Value::String(text) => {
+            if let Some(home) = dirs::home_dir().and_then(|home| home.to_str().map(str::to_owned)) {
+                if !home.is_empty() && home != "/" && text.contains(&home) {
+                    *text = text.replace(&home, "~");
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn write_zip(path: &Path, entries: &[(String, Vec<u8>)]) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| format!("unable to create file: {e}"))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (name, bytes) in entries {
+        zip.start_file(name.as_str(), options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(bytes).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
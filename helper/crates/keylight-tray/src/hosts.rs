@@ -0,0 +1,75 @@
+//! keylightd instances the tray can control. The first is always the daemon on this
+//! computer (`KEYLIGHT_API_URL`, else the default port); others are added in Settings
+//! and saved to `sublime.toml` with the one last switched to.
+
+use serde::{Deserialize, Serialize};
+
+use super::{settings, DEFAULT_API_URL};
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Host {
+    pub name: String,
+    /// API base URL without a trailing slash, e.g. `http://studio.lan:9124`.
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HostSettings {
+    pub remotes: Vec<Host>,
+    /// URL of the host in use; `None` is this computer.
+    pub active: Option<String>,
+}
+
+impl HostSettings {
+    /// This computer followed by the remotes.
+    pub fn hosts(&self) -> Vec<Host> {
+        let mut hosts = vec![local()];
+        hosts.extend(self.remotes.iter().cloned());
+        hosts
+    }
+
+    /// Index into `hosts()` of the host in use; a removed one falls back to this computer.
+    pub fn active_index(&self) -> usize {
+        self.active
+            .as_ref()
+            .and_then(|url| self.remotes.iter().position(|host| &host.url == url))
+            .map_or(0, |index| index + 1)
+    }
+}
+
+pub fn local() -> Host {
+    Host {
+        name: "This computer".into(),
+        url: std::env::var("KEYLIGHT_API_URL")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| DEFAULT_API_URL.into()),
+    }
+}
+
+pub fn load() -> HostSettings {
+    settings::load().hosts
+}
+
+pub fn save(hosts: &HostSettings) -> Result<(), std::io::Error> {
+    settings::update(|settings| settings.hosts = hosts.clone())
+}
+
+/// Turn what was typed into an API base URL: `studio.lan:9124` becomes
+/// `http://studio.lan:9124`, and a trailing slash is dropped.
+pub fn normalize_url(input: &str) -> Result<String, String> {
+    let input = input.trim().trim_end_matches('/');
+    if input.is_empty() {
+        return Err("Enter the daemon's address".into());
+    }
+    let url = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("http://{input}")
+    };
+    match reqwest::Url::parse(&url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => Ok(url),
+        Ok(_) => Err("Only http:// and https:// addresses are supported".into()),
+        Err(e) => Err(format!("Invalid address: {e}")),
+    }
+}
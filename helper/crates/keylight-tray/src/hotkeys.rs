@@ -0,0 +1,381 @@
+//! Global shortcuts: the XDG GlobalShortcuts portal on Wayland, X11 key grabs otherwise.
+
+use eframe::egui;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc::{error::TryRecvError, UnboundedReceiver, UnboundedSender};
+
+use super::settings;
+use super::tray::TraySignals;
+use super::{GroupRecord, LightRecord, LightStateResponse, PendingUpdates, UpdateRequest};
+
+const BRIGHTNESS_STEP: u8 = 10;
+
+/// Saved as `action = ...` and `group = ...`; `group` also carries the scene name
+/// for `apply_scene`, since the key predates scene shortcuts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "action", content = "group", rename_all = "snake_case")]
+pub enum HotkeyAction {
+    ToggleAll,
+    BrightnessUp,
+    BrightnessDown,
+    ToggleGroup(String),
+    ApplyScene(String),
+}
+
+impl HotkeyAction {
+    pub fn label(&self) -> String {
+        match self {
+            HotkeyAction::ToggleAll => "Toggle all lights".into(),
+            HotkeyAction::BrightnessUp => "Brightness up".into(),
+            HotkeyAction::BrightnessDown => "Brightness down".into(),
+            HotkeyAction::ToggleGroup(name) => format!("Toggle group {name}"),
+            HotkeyAction::ApplyScene(name) => format!("Apply scene {name}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HotkeyBinding {
+    #[serde(flatten)]
+    pub action: HotkeyAction,
+    /// Accelerator such as `Ctrl+Alt+L`; empty while unassigned.
+    pub keys: String,
+}
+
+pub fn default_bindings() -> Vec<HotkeyBinding> {
+    vec![
+        HotkeyBinding {
+            action: HotkeyAction::ToggleAll,
+            keys: "Ctrl+Alt+L".into(),
+        },
+        HotkeyBinding {
+            action: HotkeyAction::BrightnessUp,
+            keys: "Ctrl+Alt+ArrowUp".into(),
+        },
+        HotkeyBinding {
+            action: HotkeyAction::BrightnessDown,
+            keys: "Ctrl+Alt+ArrowDown".into(),
+        },
+    ]
+}
+
+pub fn load_bindings() -> Vec<HotkeyBinding> {
+    settings::load().hotkeys
+}
+
+pub fn save_bindings(bindings: &[HotkeyBinding]) -> Result<(), std::io::Error> {
+    settings::update(|settings| settings.hotkeys = bindings.to_vec())
+}
+
+/// Accelerator text for a key press recorded in the settings tab.
+pub fn accelerator(modifiers: egui::Modifiers, key: egui::Key) -> String {
+    let mut parts = Vec::new();
+    if modifiers.ctrl {
+        parts.push("Ctrl");
+    }
+    if modifiers.alt {
+        parts.push("Alt");
+    }
+    if modifiers.shift {
+        parts.push("Shift");
+    }
+    parts.push(key.name());
+    parts.join("+")
+}
+
+/// Convert `Ctrl+Alt+ArrowUp` to the portal's shortcut syntax, `CTRL+ALT+Up`.
+fn portal_trigger(keys: &str) -> String {
+    keys.split('+')
+        .map(|part| match part {
+            "Ctrl" => "CTRL".to_string(),
+            "Alt" => "ALT".to_string(),
+            "Shift" => "SHIFT".to_string(),
+            "Super" => "LOGO".to_string(),
+            "Space" => "space".to_string(),
+            key if key.len() == 1 => key.to_ascii_lowercase(),
+            key => key.strip_prefix("Arrow").unwrap_or(key).to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Handle to the shortcut listener thread.
+pub struct HotkeyService {
+    rebind: UnboundedSender<Vec<HotkeyBinding>>,
+    /// Daemon the shortcuts act on; follows the host picked in the window.
+    api_url: Arc<Mutex<String>>,
+}
+
+impl HotkeyService {
+    pub fn spawn(
+        bindings: Vec<HotkeyBinding>,
+        ctx: egui::Context,
+        pending: PendingUpdates,
+        signals: Arc<TraySignals>,
+        api_url: String,
+    ) -> Self {
+        let (rebind, rebind_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (actions, actions_rx) = mpsc::channel();
+        let api_url = Arc::new(Mutex::new(api_url));
+
+        let shared_url = Arc::clone(&api_url);
+        thread::spawn(move || {
+            let dispatcher = Dispatcher {
+                client: Client::builder()
+                    .timeout(Duration::from_secs(2))
+                    .build()
+                    .unwrap(),
+                api_url: shared_url,
+                pending,
+                signals,
+                ctx,
+            };
+            for action in actions_rx {
+                dispatcher.dispatch(&action);
+            }
+        });
+        thread::spawn(move || listen(bindings, rebind_rx, actions));
+
+        Self { rebind, api_url }
+    }
+
+    pub fn rebind(&self, bindings: Vec<HotkeyBinding>) {
+        let _ = self.rebind.send(bindings);
+    }
+
+    pub fn set_api_url(&self, url: &str) {
+        if let Ok(mut api_url) = self.api_url.lock() {
+            *api_url = url.to_string();
+        }
+    }
+}
+
+fn listen(
+    bindings: Vec<HotkeyBinding>,
+    mut rebind: UnboundedReceiver<Vec<HotkeyBinding>>,
+    actions: mpsc::Sender<HotkeyAction>,
+) {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build();
+        match runtime {
+            Ok(rt) => match rt.block_on(listen_portal(bindings.clone(), &mut rebind, &actions)) {
+                Ok(()) => return,
+                Err(e) => eprintln!("shortcuts portal unavailable, falling back to X11: {e}"),
+            },
+            Err(e) => eprintln!("failed to start shortcut runtime: {e}"),
+        }
+    }
+    if let Err(e) = listen_x11(bindings, &mut rebind, &actions) {
+        eprintln!("global shortcuts unavailable: {e}");
+    }
+}
+
+async fn listen_portal(
+    mut bindings: Vec<HotkeyBinding>,
+    rebind: &mut UnboundedReceiver<Vec<HotkeyBinding>>,
+    actions: &mpsc::Sender<HotkeyAction>,
+) -> ashpd::Result<()> {
+    use ashpd::desktop::global_shortcuts::{GlobalShortcuts, NewShortcut};
+    use futures_util::StreamExt;
+
+    let proxy = GlobalShortcuts::new().await?;
+    loop {
+        // The portal binds once per session, so a rebind starts a fresh one.
+        let session = proxy.create_session().await?;
+        let shortcuts: Vec<NewShortcut> = bindings
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| !b.keys.is_empty())
+            .map(|(i, b)| {
+                NewShortcut::new(i.to_string(), b.action.label())
+                    .preferred_trigger(Some(portal_trigger(&b.keys).as_str()))
+            })
+            .collect();
+        proxy
+            .bind_shortcuts(&session, &shortcuts, None)
+            .await?
+            .response()?;
+
+        let mut activated = proxy.receive_activated().await?;
+        loop {
+            tokio::select! {
+                Some(event) = activated.next() => {
+                    let binding = event
+                        .shortcut_id()
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|i| bindings.get(i));
+                    if let Some(binding) = binding {
+                        let _ = actions.send(binding.action.clone());
+                    }
+                }
+                new = rebind.recv() => match new {
+                    Some(new) => {
+                        bindings = new;
+                        break;
+                    }
+                    None => return Ok(()),
+                },
+            }
+        }
+        let _ = session.close().await;
+    }
+}
+
+fn listen_x11(
+    mut bindings: Vec<HotkeyBinding>,
+    rebind: &mut UnboundedReceiver<Vec<HotkeyBinding>>,
+    actions: &mpsc::Sender<HotkeyAction>,
+) -> Result<(), global_hotkey::Error> {
+    use global_hotkey::hotkey::HotKey;
+    use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+    let manager = GlobalHotKeyManager::new()?;
+    let events = GlobalHotKeyEvent::receiver();
+    loop {
+        let mut registered: HashMap<u32, HotkeyAction> = HashMap::new();
+        let mut hotkeys = Vec::new();
+        for binding in bindings.iter().filter(|b| !b.keys.is_empty()) {
+            match binding.keys.parse::<HotKey>() {
+                Ok(hotkey) => match manager.register(hotkey) {
+                    Ok(()) => {
+                        registered.insert(hotkey.id(), binding.action.clone());
+                        hotkeys.push(hotkey);
+                    }
+                    Err(e) => eprintln!("failed to grab {}: {e}", binding.keys),
+                },
+                Err(e) => eprintln!("invalid shortcut {}: {e}", binding.keys),
+            }
+        }
+
+        loop {
+            match rebind.try_recv() {
+                Ok(new) => {
+                    bindings = new;
+                    break;
+                }
+                Err(TryRecvError::Disconnected) => return Ok(()),
+                Err(TryRecvError::Empty) => {}
+            }
+            if let Ok(event) = events.recv_timeout(Duration::from_millis(200)) {
+                if event.state == HotKeyState::Pressed {
+                    if let Some(action) = registered.get(&event.id) {
+                        let _ = actions.send(action.clone());
+                    }
+                }
+            }
+        }
+        manager.unregister_all(&hotkeys)?;
+    }
+}
+
+/// Turns shortcut actions into queued updates, reading current state from the daemon
+/// since the window may be hidden and its copy stale.
+struct Dispatcher {
+    client: Client,
+    api_url: Arc<Mutex<String>>,
+    pending: PendingUpdates,
+    signals: Arc<TraySignals>,
+    ctx: egui::Context,
+}
+
+impl Dispatcher {
+    fn api_url(&self) -> String {
+        self.api_url
+            .lock()
+            .map(|url| url.clone())
+            .unwrap_or_default()
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Option<T> {
+        self.client
+            .get(format!("{}{}", self.api_url(), path))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .ok()?
+            .json()
+            .ok()
+    }
+
+    fn dispatch(&self, action: &HotkeyAction) {
+        if let HotkeyAction::ApplyScene(name) = action {
+            // Applied by the window, like the tray menu's scene items, so the cards
+            // read the lights back afterwards.
+            *self
+                .signals
+                .apply_scene
+                .lock()
+                .unwrap_or_else(|p| p.into_inner()) = Some(name.clone());
+            self.ctx.request_repaint();
+            return;
+        }
+        let Some(states) = self.get::<Vec<LightStateResponse>>("/v1/lights/states") else {
+            return;
+        };
+        let update = |on: Option<u8>, brightness: Option<u8>| UpdateRequest {
+            on,
+            brightness,
+            kelvin: None,
+            mired: None,
+        };
+        let api_url = self.api_url();
+        let url_all = format!("{}/v1/all", api_url);
+
+        match action {
+            HotkeyAction::ToggleAll => {
+                let on = !states.iter().any(|s| s.on);
+                self.pending
+                    .queue("all_power".into(), url_all, update(Some(on as u8), None));
+            }
+            HotkeyAction::BrightnessUp | HotkeyAction::BrightnessDown => {
+                if states.is_empty() {
+                    return;
+                }
+                let avg = (states.iter().map(|s| s.brightness as u32).sum::<u32>()
+                    / states.len() as u32) as u8;
+                let brightness = if *action == HotkeyAction::BrightnessUp {
+                    avg.saturating_add(BRIGHTNESS_STEP).min(100)
+                } else {
+                    avg.saturating_sub(BRIGHTNESS_STEP)
+                };
+                self.pending
+                    .queue("all_b".into(), url_all, update(None, Some(brightness)));
+            }
+            HotkeyAction::ToggleGroup(name) => {
+                let (Some(groups), Some(lights)) = (
+                    self.get::<Vec<GroupRecord>>("/v1/groups"),
+                    self.get::<Vec<LightRecord>>("/v1/lights"),
+                ) else {
+                    return;
+                };
+                let Some(group) = groups.iter().find(|g| &g.name == name) else {
+                    return;
+                };
+                let member_on = |state: &LightStateResponse| {
+                    lights.iter().any(|l| {
+                        l.id == state.id
+                            && group.members.iter().any(|m| {
+                                l.id == *m || l.name == *m || l.alias.as_deref() == Some(m)
+                            })
+                    }) && state.on
+                };
+                let on = !states.iter().any(member_on);
+                let url = format!("{}/v1/groups/{}", api_url, urlencoding::encode(name));
+                self.pending
+                    .queue(format!("gp_{}", name), url, update(Some(on as u8), None));
+            }
+            HotkeyAction::ApplyScene(_) => return,
+        }
+        self.pending.flush();
+        self.signals.refresh.store(true, Ordering::Relaxed);
+        self.ctx.request_repaint();
+    }
+}
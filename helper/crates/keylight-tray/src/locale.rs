@@ -0,0 +1,69 @@
+//! Locale-aware number formatting for slider values and status text.
+
+use std::sync::OnceLock;
+
+/// How the user's locale groups digits, picked from `LC_ALL`, `LC_NUMERIC` or `LANG`
+/// in the order the C library checks them. Nothing shown to people has a fractional
+/// part yet, so the decimal separator isn't tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Thousands separator; `None` in the C locale.
+    grouping: Option<char>,
+}
+
+impl NumberFormat {
+    const PLAIN: Self = Self { grouping: None };
+
+    /// Format for a POSIX locale name such as `de_DE.UTF-8`. Languages not listed
+    /// group with commas.
+    pub fn for_locale(locale: &str) -> Self {
+        let name = locale.split(['.', '@']).next().unwrap_or_default();
+        let (language, territory) = name.split_once('_').unwrap_or((name, ""));
+        let grouping = match (language, territory) {
+            ("" | "C" | "POSIX", _) => return Self::PLAIN,
+            ("de" | "fr" | "it", "CH") => '\'',
+            ("de" | "nl" | "it" | "es" | "pt" | "da" | "el" | "id" | "tr", _) => '.',
+            (
+                "fr" | "ru" | "uk" | "pl" | "cs" | "sk" | "fi" | "sv" | "nb" | "nn" | "no" | "hu"
+                | "bg" | "et" | "lv" | "lt",
+                _,
+            ) => '\u{a0}',
+            _ => ',',
+        };
+        Self {
+            grouping: Some(grouping),
+        }
+    }
+
+    /// `value` with thousands separators.
+    pub fn integer(&self, value: impl Into<i64>) -> String {
+        let value = value.into();
+        let digits = value.unsigned_abs().to_string();
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+        if value < 0 {
+            out.push('-');
+        }
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                if let Some(separator) = self.grouping {
+                    out.push(separator);
+                }
+            }
+            out.push(digit);
+        }
+        out
+    }
+}
+
+/// Format for the environment this process was started in.
+pub fn current() -> NumberFormat {
+    static FORMAT: OnceLock<NumberFormat> = OnceLock::new();
+    *FORMAT.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|key| std::env::var(key).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        NumberFormat::for_locale(&locale)
+    })
+}
@@ -2,33 +2,49 @@ use eframe::egui;
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
+use theme::{Theme, ThemePreference};
 
 #[cfg(target_os = "linux")]
 extern "C" {
     fn malloc_trim(__pad: usize) -> std::ffi::c_int;
 }
 
+mod ambient;
+mod api;
+mod connection;
+mod diagnostics;
+mod hosts;
+mod hotkeys;
+mod locale;
+mod nearby;
+mod notifier;
+mod photo_match;
+mod preferences;
+mod screen_match;
+mod settings;
+mod state_cache;
+mod theme;
+mod tray;
+
 const DEFAULT_API_URL: &str = "http://127.0.0.1:9124";
 
-mod colors {
-    use eframe::egui::Color32;
-    pub const BG_LIGHT: Color32 = Color32::from_rgb(245, 250, 255);
-    pub const BG_CARD: Color32 = Color32::from_rgb(255, 255, 255);
-    pub const ACCENT: Color32 = Color32::from_rgb(70, 150, 220);
-    pub const ACCENT_LIGHT: Color32 = Color32::from_rgb(100, 175, 235);
-    pub const TEXT_PRIMARY: Color32 = Color32::from_rgb(30, 50, 80);
-    pub const TEXT_SECONDARY: Color32 = Color32::from_rgb(120, 140, 160);
-    pub const BORDER: Color32 = Color32::from_rgb(200, 220, 240);
-    pub const POWER_ON: Color32 = Color32::from_rgb(80, 190, 110);
-    pub const POWER_OFF: Color32 = Color32::from_rgb(160, 170, 180);
-    pub const WARM: Color32 = Color32::from_rgb(255, 170, 70);
-    pub const COOL: Color32 = Color32::from_rgb(140, 195, 255);
-    pub const BRIGHT_HIGH: Color32 = Color32::from_rgb(255, 252, 240);
-    pub const BRIGHT_LOW: Color32 = Color32::from_rgb(50, 55, 65);
-}
+/// How long the worker lets a slider drag settle before sending.
+const DRAG_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Temperature range of Elgato Key Lights; used for lights that don't report one and
+/// for the All Lights and group sliders.
+const DEFAULT_KELVIN_RANGE: (u16, u16) = (2900, 7000);
+
+/// Brightness range of lights without limits, and of the All Lights and group sliders.
+const FULL_BRIGHTNESS: (u8, u8) = (0, 100);
+
+/// How long after a timer was due to read the lights again; the daemon checks its
+/// timers every second.
+const TIMER_GRACE: Duration = Duration::from_secs(2);
 
 #[derive(Clone, Debug, Deserialize)]
 struct LightRecord {
@@ -36,6 +52,60 @@ struct LightRecord {
     alias: Option<String>,
     name: String,
     enabled: bool,
+    #[serde(default)]
+    schedule_paused: bool,
+    #[serde(default)]
+    schedule_skip_next: bool,
+    #[serde(default)]
+    capabilities: Capabilities,
+    #[serde(default)]
+    kelvin_range: Option<KelvinRange>,
+    #[serde(default)]
+    soft_start: bool,
+    #[serde(default)]
+    icon: Option<String>,
+    #[serde(default)]
+    room: Option<String>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    favorite: bool,
+    #[serde(default)]
+    brightness_limits: Option<BrightnessLimits>,
+    /// Set on each zone of a multi-zone light, which gets a card per zone.
+    #[serde(default)]
+    zone: Option<u8>,
+}
+
+/// Brightness range the daemon clamps a light's updates to.
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct BrightnessLimits {
+    min: u8,
+    max: u8,
+}
+
+/// Controls the daemon says a light supports; missing means an Elgato Key Light.
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct Capabilities {
+    brightness: bool,
+    temperature: bool,
+    color: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            brightness: true,
+            temperature: true,
+            color: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct KelvinRange {
+    min: u16,
+    max: u16,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -44,7 +114,53 @@ struct GroupRecord {
     members: Vec<String>,
 }
 
-#[derive(Serialize, Clone)]
+/// A saved scene, as `GET /v1/scenes` lists it.
+#[derive(Clone, Debug, Deserialize)]
+struct SceneRecord {
+    name: String,
+    lights: usize,
+    #[serde(default)]
+    favorite: bool,
+}
+
+/// A brightness/temperature preset from the daemon's config, as `GET /v1/presets`
+/// lists it.
+#[derive(Clone, Debug, Deserialize)]
+struct PresetRecord {
+    name: String,
+    brightness: Option<u8>,
+    kelvin: Option<u16>,
+}
+
+impl PresetRecord {
+    fn summary(&self) -> String {
+        match (self.brightness, self.kelvin) {
+            (Some(b), Some(k)) => format!("{b}% · {k}K"),
+            (Some(b), None) => format!("{b}%"),
+            (None, Some(k)) => format!("{k}K"),
+            (None, None) => String::new(),
+        }
+    }
+
+    fn update(&self) -> UpdateRequest {
+        UpdateRequest {
+            on: Some(1),
+            brightness: self.brightness,
+            kelvin: self.kelvin,
+            mired: None,
+        }
+    }
+}
+
+/// A group the daemon suggests from light aliases.
+#[derive(Clone, Debug, Deserialize)]
+struct GroupSuggestion {
+    name: String,
+    members: Vec<String>,
+    aliases: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 struct UpdateRequest {
     on: Option<u8>,
     brightness: Option<u8>,
@@ -52,6 +168,43 @@ struct UpdateRequest {
     mired: Option<u16>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ScheduleTarget {
+    All,
+    Light(String),
+    Group(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ScheduleRecord {
+    target: ScheduleTarget,
+    enabled: bool,
+}
+
+/// A pending one-shot timer, as `GET /v1/timers` lists it.
+#[derive(Clone, Debug, Deserialize)]
+struct TimerRecord {
+    id: u64,
+    target: ScheduleTarget,
+    update: UpdateRequest,
+    remaining_secs: u64,
+}
+
+/// The daemon's profiles and which is active, as `GET /v1/profiles` reports them.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct ProfileStatus {
+    active: Option<String>,
+    /// Picked by hand rather than by the profile rules.
+    manual: bool,
+    profiles: Vec<ProfileRecord>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ProfileRecord {
+    name: String,
+}
+
 #[derive(Serialize)]
 struct GroupRequest {
     name: String,
@@ -66,14 +219,139 @@ struct LightStateResponse {
     kelvin: u16,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+struct LightHealth {
+    id: String,
+    restart_required: bool,
+}
+
 #[derive(Clone)]
 struct LightControl {
     id: String,
+    /// Daemon-side name, kept to match schedule/group members by name.
+    name: String,
+    alias: Option<String>,
     label: String,
     enabled: bool,
     on: bool,
     brightness: u8,
     kelvin: u16,
+    has_schedule: bool,
+    schedule_paused: bool,
+    schedule_skip_next: bool,
+    /// Supported temperature range, `(min, max)` Kelvin.
+    kelvin_range: (u16, u16),
+    /// Which sliders to show; `color` lights can be used for ambient mode.
+    capabilities: Capabilities,
+    /// Daemon ramps brightness when this light is switched on or off.
+    soft_start: bool,
+    /// Preset icon name or emoji shown before the label.
+    icon: Option<String>,
+    /// Shown under the label; the Lights tab can be narrowed to one room.
+    room: Option<String>,
+    /// Shown when hovering the room.
+    notes: Option<String>,
+    /// Starred; shown in the favorites strip and at the top of the tray menu.
+    favorite: bool,
+    /// Brightness the daemon lets this light reach, `(min, max)` percent.
+    brightness_limits: (u8, u8),
+    /// Daemon saw repeated failures; the light probably needs a restart.
+    restart_required: bool,
+}
+
+impl LightControl {
+    fn matches(&self, ident: &str) -> bool {
+        self.id == ident || self.name == ident || self.alias.as_deref() == Some(ident)
+    }
+
+    /// Whether the filter box's `query` finds this light by label, name, alias or room.
+    fn matches_filter(&self, query: &str) -> bool {
+        [
+            Some(self.label.as_str()),
+            Some(self.name.as_str()),
+            self.alias.as_deref(),
+            self.room.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|text| contains_ignoring_case(text, query))
+    }
+
+    /// Label with the light's icon in front, for cards and the tray menu.
+    fn icon_label(&self) -> String {
+        match &self.icon {
+            Some(icon) => format!("{} {}", icon_glyph(icon), self.label),
+            None => self.label.clone(),
+        }
+    }
+}
+
+fn contains_ignoring_case(text: &str, query: &str) -> bool {
+    query.is_empty() || text.to_lowercase().contains(&query.to_lowercase())
+}
+
+/// `text` with the first match of the filter `query` highlighted.
+fn highlighted(
+    text: &str,
+    query: &str,
+    size: f32,
+    color: egui::Color32,
+    theme: &Theme,
+) -> egui::text::LayoutJob {
+    let format = |background| egui::TextFormat {
+        font_id: egui::FontId::proportional(size),
+        color,
+        background,
+        ..Default::default()
+    };
+    let mut job = egui::text::LayoutJob::default();
+    let lower = text.to_lowercase();
+    // Lowercasing can change lengths; only highlight when byte offsets still line up.
+    let found = (!query.is_empty() && lower.len() == text.len())
+        .then(|| lower.find(&query.to_lowercase()))
+        .flatten()
+        .filter(|start| text.is_char_boundary(start + query.len()));
+    match found {
+        Some(start) => {
+            let end = start + query.len();
+            job.append(&text[..start], 0.0, format(egui::Color32::TRANSPARENT));
+            job.append(
+                &text[start..end],
+                0.0,
+                format(theme.accent.gamma_multiply(0.35)),
+            );
+            job.append(&text[end..], 0.0, format(egui::Color32::TRANSPARENT));
+        }
+        None => job.append(text, 0.0, format(egui::Color32::TRANSPARENT)),
+    }
+    job
+}
+
+/// Icon presets the daemon accepts, with the glyph each is drawn as.
+const LIGHT_ICONS: [(&str, &str); 4] = [
+    ("panel", "🔲"),
+    ("ring", "⭕"),
+    ("strip", "📏"),
+    ("bulb", "💡"),
+];
+
+/// Glyph for a light's icon: presets map to an emoji, anything else already is one.
+fn icon_glyph(icon: &str) -> &str {
+    LIGHT_ICONS
+        .iter()
+        .find(|(name, _)| *name == icon)
+        .map_or(icon, |(_, glyph)| glyph)
+}
+
+/// A light's current value for the same kind of setting as `like`.
+fn light_setting(light: &LightControl, like: &api::LightSetting) -> api::LightSetting {
+    match like {
+        api::LightSetting::Enabled(_) => api::LightSetting::Enabled(light.enabled),
+        api::LightSetting::SoftStart(_) => api::LightSetting::SoftStart(light.soft_start),
+        api::LightSetting::Alias(_) => api::LightSetting::Alias(light.alias.clone()),
+        api::LightSetting::Room(_) => api::LightSetting::Room(light.room.clone()),
+        api::LightSetting::Favorite(_) => api::LightSetting::Favorite(light.favorite),
+    }
 }
 
 struct GroupControl {
@@ -86,24 +364,172 @@ struct GroupControl {
 enum Tab {
     Lights,
     Groups,
+    Scenes,
     Settings,
 }
 
+/// What a dragged card carries: its index in `lights`, `groups` or `scenes`.
+struct DraggedLight(usize);
+struct DraggedGroup(usize);
+struct DraggedScene(usize);
+
 #[derive(PartialEq, Clone, Copy)]
 enum ModalState {
     None,
     Discover,
     CreateGroup,
+    SaveScene,
+}
+
+#[derive(Default)]
+struct PendingState {
+    /// Pending update per key: (url, request)
+    updates: HashMap<String, (String, UpdateRequest)>,
+    /// Send what's queued now instead of waiting out the drag batch interval.
+    flush_now: bool,
+    /// The worker has drained updates and is still sending them.
+    sending: bool,
+}
+
+#[derive(Default)]
+struct PendingQueue {
+    state: Mutex<PendingState>,
+    wake: Condvar,
+}
+
+impl PendingQueue {
+    /// Queue an update - overwrites any pending update for the same key
+    /// so only the latest value is sent once the worker wakes up
+    fn queue(&self, key: String, url: String, update: UpdateRequest) {
+        let mut state = self.state.lock().unwrap();
+        state.updates.insert(key, (url, update));
+        self.wake.notify_one();
+    }
+
+    /// Ask the worker to send whatever is queued without waiting.
+    fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.updates.is_empty() {
+            state.flush_now = true;
+            self.wake.notify_one();
+        }
+    }
+}
+
+type PendingUpdates = Arc<PendingQueue>;
+
+/// A light setting the daemon hasn't confirmed yet, keyed by light id and kind of
+/// setting.
+struct UnsavedSetting {
+    /// Changes sent and not answered yet.
+    in_flight: usize,
+    /// The value the daemon last accepted, to go back to if the latest change fails.
+    confirmed: api::LightSetting,
 }
 
-/// Pending update: (url, request)
-type PendingUpdates = Arc<Mutex<HashMap<String, (String, UpdateRequest)>>>;
+type SettingKey = (String, std::mem::Discriminant<api::LightSetting>);
+
+/// Sends queued updates. Sleeps while nothing is pending, batches for
+/// `DRAG_BATCH_INTERVAL` while a drag is in progress, and sends at once on flush.
+fn run_update_worker(client: Arc<Client>, pending: PendingUpdates) {
+    loop {
+        let updates: Vec<(String, UpdateRequest)> = {
+            let mut state = pending.state.lock().unwrap();
+            while state.updates.is_empty() {
+                state = pending.wake.wait(state).unwrap();
+            }
+            if !state.flush_now {
+                state = pending
+                    .wake
+                    .wait_timeout_while(state, DRAG_BATCH_INTERVAL, |s| !s.flush_now)
+                    .unwrap()
+                    .0;
+            }
+            state.flush_now = false;
+            state.sending = true;
+            state.updates.drain().map(|(_, v)| v).collect()
+        };
+        for (url, req) in updates {
+            let _ = client.put(&url).json(&req).send();
+        }
+        pending.state.lock().unwrap().sending = false;
+        pending.wake.notify_all();
+    }
+}
+
+/// Unsent updates left over from the previous run.
+fn pending_updates_path() -> Option<std::path::PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::cache_dir)
+        .map(|p| p.join("limelight").join("pending-updates.json"))
+}
+
+/// Re-queue updates persisted by `flush_pending_on_exit` and remove the file.
+fn restore_pending_updates(pending: &PendingQueue) {
+    let Some(path) = pending_updates_path() else {
+        return;
+    };
+    let Ok(bytes) = std::fs::read(&path) else {
+        return;
+    };
+    let _ = std::fs::remove_file(&path);
+    if let Ok(saved) = serde_json::from_slice::<HashMap<String, (String, UpdateRequest)>>(&bytes) {
+        let mut state = pending.state.lock().unwrap();
+        for (key, value) in saved {
+            state.updates.entry(key).or_insert(value);
+        }
+        state.flush_now = !state.updates.is_empty();
+        pending.wake.notify_all();
+    }
+}
+
+/// Deliver whatever is still queued before the process exits, waiting for an
+/// in-flight batch first. Anything the daemon doesn't accept is saved for next launch.
+fn flush_pending_on_exit(client: &Client, pending: &PendingQueue) {
+    let updates: HashMap<String, (String, UpdateRequest)> = {
+        let state = pending.state.lock().unwrap();
+        let mut state = pending
+            .wake
+            .wait_timeout_while(state, Duration::from_secs(3), |s| s.sending)
+            .unwrap()
+            .0;
+        state.updates.drain().collect()
+    };
+    let unsent: HashMap<_, _> = updates
+        .into_iter()
+        .filter(|(_, (url, req))| {
+            client
+                .put(url)
+                .json(req)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .is_err()
+        })
+        .collect();
+    if unsent.is_empty() {
+        return;
+    }
+    let Some(path) = pending_updates_path() else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    match serde_json::to_vec(&unsent) {
+        Ok(bytes) => {
+            if let Err(e) = std::fs::write(&path, bytes) {
+                eprintln!("failed to save unsent updates: {e}");
+            }
+        }
+        Err(e) => eprintln!("failed to save unsent updates: {e}"),
+    }
+}
 
 const AUTOSTART_DESKTOP: &str = r#"[Desktop Entry]
 Type=Application
 Name=LimeLight
 Comment=Elgato Key Light Controller
-Exec=limelight
+Exec=limelight --start-minimized
 Icon=io.github.chimi6.limelight-linux-elgato-lights-controller
 Terminal=false
 Categories=Utility;
@@ -132,16 +558,79 @@ fn set_autostart(enabled: bool) -> Result<(), std::io::Error> {
     Ok(())
 }
 
+/// What quitting does to a keylightd the tray started, saved under `[daemon]` in
+/// `sublime.toml`. A daemon started some other way is never stopped.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct DaemonSettings {
+    /// Stop the daemon on quit; otherwise leave it running for scripts and plugins.
+    stop_on_quit: bool,
+}
+
+impl Default for DaemonSettings {
+    fn default() -> Self {
+        Self { stop_on_quit: true }
+    }
+}
+
+fn load_daemon_settings() -> DaemonSettings {
+    settings::load().daemon
+}
+
+fn save_daemon_settings(daemon: DaemonSettings) -> Result<(), std::io::Error> {
+    settings::update(|settings| settings.daemon = daemon)
+}
+
+/// Ask the daemon to exit through `POST /v1/shutdown` so it can finish writing its
+/// files, and kill it only if it's still there a few seconds later.
+fn stop_daemon(client: &Client, api_url: &str, mut child: std::process::Child) {
+    let asked = client
+        .post(format!("{api_url}/v1/shutdown"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .is_ok();
+    if asked {
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while Instant::now() < deadline {
+            if !matches!(child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
 struct KeylightApp {
     client: Arc<Client>,
+    /// Sends the window's daemon requests from a background thread.
+    api: api::ApiWorker,
     api_url: String,
     lights: Vec<LightControl>,
     groups: Vec<GroupRecord>,
+    schedules: Vec<ScheduleRecord>,
     group_controls: HashMap<String, GroupControl>,
+    /// Group cards opened to list their members.
+    expanded_groups: HashSet<String>,
     active_tab: Tab,
+    /// Card the keyboard shortcuts act on: an index into `lights` on the Lights tab
+    /// and into `groups` on the Groups tab.
+    focused_card: Option<usize>,
+    /// Scroll the focused card into view on the next frame.
+    scroll_to_focus: bool,
     modal_state: ModalState,
     new_group_name: String,
     new_group_members: HashSet<String>,
+    group_suggestions: Vec<GroupSuggestion>,
+    scenes: Vec<SceneRecord>,
+    /// Shown as chips on light and group cards.
+    presets: Vec<PresetRecord>,
+    new_scene_name: String,
+    /// Scene being renamed and the name typed so far.
+    renaming_scene: Option<(String, String)>,
+    /// Why the daemon refused the last scene change.
+    scene_error: Option<String>,
     pending_updates: PendingUpdates,
     logo: Option<egui::TextureHandle>,
     power_icon: Option<egui::TextureHandle>,
@@ -150,11 +639,67 @@ struct KeylightApp {
     all_brightness: u8,
     all_kelvin: u16,
     editing_aliases: HashMap<String, String>,
+    editing_rooms: HashMap<String, String>,
+    /// Room the Lights tab is narrowed to, if any.
+    room_filter: Option<String>,
+    /// Text typed in the filter box above the Lights and Groups tabs.
+    filter: String,
+    /// Give the filter box the keyboard on the next frame (`/`).
+    focus_filter: bool,
+    unsaved_settings: HashMap<SettingKey, UnsavedSetting>,
+    /// Emoji being typed in a light's icon picker.
+    custom_icon: String,
     autostart_enabled: bool,
+    daemon_settings: DaemonSettings,
+    preferences: preferences::Preferences,
     brightness_gradient: Option<egui::TextureHandle>,
     temperature_gradient: Option<egui::TextureHandle>,
     url_all: String,
     last_trim: Instant,
+    tray: Option<tray::TrayHandle>,
+    tray_signals: Arc<tray::TraySignals>,
+    hotkeys: hotkeys::HotkeyService,
+    notifier: notifier::Notifier,
+    hotkey_bindings: Vec<hotkeys::HotkeyBinding>,
+    /// Index of the binding waiting for a key press in the settings tab.
+    recording_hotkey: Option<usize>,
+    new_hotkey_action: hotkeys::HotkeyAction,
+    /// Result of a running "Match photo" pick.
+    photo_match: Option<std::sync::mpsc::Receiver<Result<Option<u16>, String>>>,
+    photo_match_status: Option<String>,
+    /// Running ambient worker; dropped to stop it.
+    ambient: Option<ambient::AmbientMode>,
+    /// Light picked for ambient mode in settings.
+    ambient_light: Option<String>,
+    ambient_interval_secs: u64,
+    /// Running screen match worker; `None` while no group follows the screen.
+    screen_match: Option<screen_match::ScreenMatch>,
+    screen_match_settings: screen_match::ScreenMatchSettings,
+    /// Result of a running "Export diagnostics".
+    diagnostics: Option<std::sync::mpsc::Receiver<Result<Option<std::path::PathBuf>, String>>>,
+    diagnostics_status: Option<String>,
+    /// End of the daemon's do-not-disturb window, as last reported.
+    dnd_until: Option<Instant>,
+    /// Pending timers and when each runs, as last reported.
+    timers: Vec<(TimerRecord, Instant)>,
+    profiles: ProfileStatus,
+    /// Light states seen last, shown until fresh ones arrive.
+    state_cache: state_cache::StateCache,
+    host_settings: hosts::HostSettings,
+    /// This computer, then the saved remotes; `hosts[active_host]` is the one shown.
+    hosts: Vec<hosts::Host>,
+    active_host: usize,
+    /// Status poller per host, in `hosts` order, for the connection dots.
+    connections: Vec<connection::ConnectionMonitor>,
+    new_host_name: String,
+    new_host_url: String,
+    host_error: Option<String>,
+    /// Daemons announcing themselves on the network, offered in Settings.
+    nearby: Option<nearby::NearbyDaemons>,
+    theme_preference: ThemePreference,
+    /// Desktop color scheme from the portal, updated by a watcher thread.
+    system_dark: Arc<std::sync::atomic::AtomicBool>,
+    theme: Theme,
 }
 
 fn configure_egui(ctx: &egui::Context) {
@@ -162,10 +707,6 @@ fn configure_egui(ctx: &egui::Context) {
     style.spacing.item_spacing = egui::vec2(4.0, 3.0);
     style.spacing.button_padding = egui::vec2(4.0, 2.0);
     ctx.set_style(style);
-
-    let mut visuals = egui::Visuals::light();
-    visuals.panel_fill = colors::BG_LIGHT;
-    ctx.set_visuals(visuals);
 }
 
 fn load_svg_texture(
@@ -245,104 +786,378 @@ fn create_gradient_texture(
     ctx.load_texture(name, image, egui::TextureOptions::LINEAR)
 }
 
+/// Width of the value shown to the right of each slider; fits a grouped `6,500K`.
+const VALUE_WIDTH: f32 = 46.0;
+
+/// Height of a slider track, and of one in compact mode.
+const SLIDER_HEIGHT: f32 = 18.0;
+const COMPACT_SLIDER_HEIGHT: f32 = 12.0;
+
+/// Window width from which light and group cards are laid out in two columns.
+const TWO_COLUMN_WIDTH: f32 = 720.0;
+/// Card width from which brightness and temperature sliders sit side by side.
+const SIDE_BY_SIDE_SLIDERS_WIDTH: f32 = 460.0;
+/// Width each extra column of cards costs: the frame's margin and stroke plus a gap.
+const COLUMN_GAP: f32 = 24.0;
+
+/// Number of card columns for a panel `width` wide, and the width of each card.
+fn card_columns(width: f32) -> (usize, f32) {
+    let columns = if width >= TWO_COLUMN_WIDTH { 2 } else { 1 };
+    let gaps = (columns - 1) as f32 * COLUMN_GAP;
+    (columns, (width - gaps) / columns as f32)
+}
+
+/// Lay out a card's brightness and temperature sliders, side by side on wide cards
+/// and stacked otherwise. Each closure gets its slider width; returns what changed.
+fn slider_pair(
+    ui: &mut egui::Ui,
+    card_width: f32,
+    brightness: impl FnOnce(&mut egui::Ui, f32) -> bool,
+    temperature: impl FnOnce(&mut egui::Ui, f32) -> bool,
+) -> (bool, bool) {
+    let full = card_width - 16.0;
+    if card_width < SIDE_BY_SIDE_SLIDERS_WIDTH {
+        let brightness_changed = brightness(ui, full);
+        ui.add_space(1.0);
+        return (brightness_changed, temperature(ui, full));
+    }
+    ui.horizontal(|ui| {
+        let gap = 8.0;
+        let half = (full - gap - 2.0 * ui.spacing().item_spacing.x) / 2.0;
+        let brightness_changed = brightness(ui, half);
+        ui.add_space(gap);
+        (brightness_changed, temperature(ui, half))
+    })
+    .inner
+}
+
+/// Parse a typed slider value, accepting an optional unit suffix (`4000K`, `35%`) and
+/// thousands separators in any locale's style (`4,000K`, `4.000K`).
+fn parse_entry(text: &str, range: (u16, u16)) -> Option<u16> {
+    let digits: String = text
+        .trim()
+        .trim_end_matches(['k', 'K', '%'])
+        .chars()
+        .filter(|c| !matches!(c, ',' | '.' | '\'' | ' ' | '\u{a0}'))
+        .collect();
+    digits
+        .parse::<u16>()
+        .ok()
+        .filter(|v| (range.0..=range.1).contains(v))
+}
+
+/// Current slider value; click it to type an exact one. Enter commits, Esc cancels,
+/// and out-of-range input is shown in red and not applied.
+fn value_entry(
+    ui: &mut egui::Ui,
+    slider_id: egui::Id,
+    value: &mut u16,
+    range: (u16, u16),
+    suffix: &str,
+    theme: &Theme,
+) -> bool {
+    let edit_id = slider_id.with("entry");
+    let Some(mut text) = ui.data(|d| d.get_temp::<String>(edit_id)) else {
+        let format = locale::current();
+        let response = ui
+            .add_sized(
+                [VALUE_WIDTH, 18.0],
+                egui::Label::new(
+                    egui::RichText::new(format!("{}{suffix}", format.integer(*value)))
+                        .size(10.0)
+                        .color(theme.text_secondary),
+                )
+                .sense(egui::Sense::click()),
+            )
+            .on_hover_text(format!(
+                "Click to enter {}–{}{suffix}",
+                format.integer(range.0),
+                format.integer(range.1)
+            ));
+        if response.clicked() {
+            ui.data_mut(|d| d.insert_temp(edit_id, value.to_string()));
+        }
+        return false;
+    };
+
+    let parsed = parse_entry(&text, range);
+    let invalid = parsed.is_none() && !text.trim().is_empty();
+    let mut edit = egui::TextEdit::singleline(&mut text)
+        .desired_width(VALUE_WIDTH)
+        .font(egui::TextStyle::Small);
+    if invalid {
+        edit = edit.text_color(ui.visuals().error_fg_color);
+    }
+    let response = ui.add(edit);
+    if !response.has_focus() && !response.lost_focus() {
+        response.request_focus();
+    }
+    let (enter, escape) = ui.input(|i| {
+        (
+            i.key_pressed(egui::Key::Enter),
+            i.key_pressed(egui::Key::Escape),
+        )
+    });
+    if escape || (response.lost_focus() && !enter) {
+        ui.data_mut(|d| d.remove::<String>(edit_id));
+        return false;
+    }
+    if response.lost_focus() {
+        if let Some(new_value) = parse_entry(&text, range) {
+            ui.data_mut(|d| d.remove::<String>(edit_id));
+            let changed = new_value != *value;
+            *value = new_value;
+            return changed;
+        }
+        // Keep the field open so the value can be corrected.
+        response.request_focus();
+    }
+    ui.data_mut(|d| d.insert_temp(edit_id, text));
+    false
+}
+
+/// Keyboard and wheel control of a slider track. While it has focus, arrows move by
+/// `step`, Page Up/Down by `page` and Home/End to the ends; over it, each wheel
+/// event is one step and doesn't scroll the page. Returns the new value, if any.
+fn slider_input(
+    ui: &egui::Ui,
+    response: &egui::Response,
+    value: u16,
+    range: (u16, u16),
+    step: u16,
+    page: u16,
+) -> Option<u16> {
+    let (min, max) = (i32::from(range.0), i32::from(range.1));
+    let (step, page) = (i32::from(step), i32::from(page));
+    let mut target = i32::from(value);
+    if response.has_focus() {
+        // Keep egui from moving focus to the next widget on arrow keys.
+        ui.memory_mut(|m| {
+            m.set_focus_lock_filter(
+                response.id,
+                egui::EventFilter {
+                    horizontal_arrows: true,
+                    vertical_arrows: true,
+                    ..Default::default()
+                },
+            )
+        });
+        ui.input(|i| {
+            let pressed = |key| i.num_presses(key) as i32;
+            target += (pressed(egui::Key::ArrowRight) + pressed(egui::Key::ArrowUp)) * step;
+            target -= (pressed(egui::Key::ArrowLeft) + pressed(egui::Key::ArrowDown)) * step;
+            target += pressed(egui::Key::PageUp) * page;
+            target -= pressed(egui::Key::PageDown) * page;
+            if i.key_pressed(egui::Key::Home) {
+                target = min;
+            }
+            if i.key_pressed(egui::Key::End) {
+                target = max;
+            }
+        });
+    }
+    if response.hovered() {
+        let scroll = ui.input(|i| i.raw_scroll_delta);
+        let delta = if scroll.y != 0.0 { scroll.y } else { scroll.x };
+        if delta != 0.0 {
+            target += step * delta.signum() as i32;
+            ui.ctx()
+                .input_mut(|i| i.smooth_scroll_delta = egui::Vec2::ZERO);
+        }
+    }
+    if target == i32::from(value) {
+        return None;
+    }
+    let target = target.clamp(min, max) as u16;
+    (target != value).then_some(target)
+}
+
+/// Focus ring around a slider track reached with Tab.
+fn paint_focus(ui: &egui::Ui, response: &egui::Response, rect: egui::Rect) {
+    if response.has_focus() {
+        let stroke = ui.visuals().selection.stroke;
+        ui.painter().rect_stroke(rect.expand(2.0), 4.0, stroke);
+    }
+}
+
+/// Cover the part of a slider track between `from` and `to` (fractions of its width)
+/// with hatching, for values a light won't take.
+fn hatch_track(ui: &egui::Ui, track: egui::Rect, from: f32, to: f32, theme: &Theme) {
+    if to <= from {
+        return;
+    }
+    let area = egui::Rect::from_x_y_ranges(
+        track.left() + from * track.width()..=track.left() + to * track.width(),
+        track.y_range(),
+    );
+    let painter = ui.painter().with_clip_rect(area);
+    painter.rect_filled(area, 0.0, theme.card.gamma_multiply(0.75));
+    let stroke = egui::Stroke::new(1.0, theme.text_secondary.gamma_multiply(0.6));
+    let mut x = area.left() - area.height();
+    while x < area.right() {
+        painter.line_segment(
+            [
+                egui::pos2(x, area.bottom()),
+                egui::pos2(x + area.height(), area.top()),
+            ],
+            stroke,
+        );
+        x += 5.0;
+    }
+}
+
 /// Returns true if the value changed (queue updates on every change, deduplication happens in pending map)
+/// Values outside `limits` are hatched and can't be picked.
 fn brightness_slider(
     ui: &mut egui::Ui,
     value: &mut u8,
+    limits: (u8, u8),
     width: f32,
+    height: f32,
     gradient: Option<&egui::TextureHandle>,
+    theme: &Theme,
 ) -> bool {
-    let height = 18.0;
-    let (rect, response) = ui.allocate_exact_size(
-        egui::Vec2::new(width, height),
-        egui::Sense::click_and_drag(),
-    );
+    ui.horizontal(|ui| {
+        let track_width = width - VALUE_WIDTH - ui.spacing().item_spacing.x;
+        let (rect, response) = ui.allocate_exact_size(
+            egui::Vec2::new(track_width, height),
+            egui::Sense::click_and_drag(),
+        );
 
-    let mut changed = false;
-    if response.dragged() || response.clicked() {
-        if let Some(pos) = ui.ctx().pointer_latest_pos() {
-            let t = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
-            let new_val = (t * 100.0) as u8;
-            if new_val != *value {
-                *value = new_val;
-                changed = true;
+        let mut changed = false;
+        if response.dragged() || response.clicked() {
+            if let Some(pos) = ui.ctx().pointer_latest_pos() {
+                let t = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+                let new_val = ((t * 100.0) as u8).clamp(limits.0, limits.1);
+                if new_val != *value {
+                    *value = new_val;
+                    changed = true;
+                }
             }
         }
-    }
+        let range = (limits.0 as u16, limits.1 as u16);
+        if let Some(new_val) = slider_input(ui, &response, *value as u16, range, 1, 10) {
+            *value = new_val as u8;
+            changed = true;
+        }
+        let current = *value;
+        response.widget_info(|| egui::WidgetInfo::slider(true, current as f64, "Brightness"));
 
-    if let Some(tex) = gradient {
-        ui.painter().image(
-            tex.id(),
-            rect,
-            egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
+        if let Some(tex) = gradient {
+            ui.painter().image(
+                tex.id(),
+                rect,
+                egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
+                egui::Color32::WHITE,
+            );
+        }
+        let (min, max) = limits;
+        hatch_track(ui, rect, 0.0, min as f32 / 100.0, theme);
+        hatch_track(ui, rect, max as f32 / 100.0, 1.0, theme);
+        let response = if limits == FULL_BRIGHTNESS {
+            response
+        } else {
+            response.on_hover_text(format!("This light is limited to {min}–{max}%"))
+        };
+
+        paint_focus(ui, &response, rect);
+
+        let thumb = height / 2.0 - 1.0;
+        let thumb_x = (rect.left() + (*value as f32 / 100.0) * rect.width())
+            .clamp(rect.left() + thumb, rect.right() - thumb);
+        ui.painter().circle_filled(
+            egui::Pos2::new(thumb_x, rect.center().y),
+            thumb,
             egui::Color32::WHITE,
         );
-    }
+        ui.painter().circle_stroke(
+            egui::Pos2::new(thumb_x, rect.center().y),
+            thumb,
+            egui::Stroke::new(1.5, theme.accent),
+        );
 
-    let thumb_x = (rect.left() + (*value as f32 / 100.0) * rect.width())
-        .clamp(rect.left() + 8.0, rect.right() - 8.0);
-    ui.painter().circle_filled(
-        egui::Pos2::new(thumb_x, rect.center().y),
-        8.0,
-        egui::Color32::WHITE,
-    );
-    ui.painter().circle_stroke(
-        egui::Pos2::new(thumb_x, rect.center().y),
-        8.0,
-        egui::Stroke::new(1.5, colors::ACCENT),
-    );
+        let mut entry = *value as u16;
+        if value_entry(ui, response.id, &mut entry, range, "%", theme) {
+            *value = entry as u8;
+            changed = true;
+        }
 
-    changed
+        changed
+    })
+    .inner
 }
 
 /// Returns true if the value changed (queue updates on every change, deduplication happens in pending map)
 fn temperature_slider(
     ui: &mut egui::Ui,
     kelvin: &mut u16,
+    range: (u16, u16),
     width: f32,
+    height: f32,
     gradient: Option<&egui::TextureHandle>,
+    theme: &Theme,
 ) -> bool {
-    let height = 18.0;
-    let (rect, response) = ui.allocate_exact_size(
-        egui::Vec2::new(width, height),
-        egui::Sense::click_and_drag(),
-    );
+    ui.horizontal(|ui| {
+        let (min, max) = range;
+        let track_width = width - VALUE_WIDTH - ui.spacing().item_spacing.x;
+        let (rect, response) = ui.allocate_exact_size(
+            egui::Vec2::new(track_width, height),
+            egui::Sense::click_and_drag(),
+        );
 
-    let mut changed = false;
-    if response.dragged() || response.clicked() {
-        if let Some(pos) = ui.ctx().pointer_latest_pos() {
-            let t = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
-            let new_val = 2900 + (t * (7000.0 - 2900.0)) as u16;
-            if new_val != *kelvin {
-                *kelvin = new_val;
-                changed = true;
+        let mut changed = false;
+        if response.dragged() || response.clicked() {
+            if let Some(pos) = ui.ctx().pointer_latest_pos() {
+                let t = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+                let new_val = min + (t * (max - min) as f32) as u16;
+                if new_val != *kelvin {
+                    *kelvin = new_val;
+                    changed = true;
+                }
             }
         }
-    }
+        if let Some(new_val) = slider_input(ui, &response, *kelvin, range, 50, 500) {
+            *kelvin = new_val;
+            changed = true;
+        }
+        let current = *kelvin;
+        response.widget_info(|| {
+            egui::WidgetInfo::slider(true, current as f64, "Color temperature (kelvin)")
+        });
 
-    if let Some(tex) = gradient {
-        ui.painter().image(
-            tex.id(),
-            rect,
-            egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
+        if let Some(tex) = gradient {
+            ui.painter().image(
+                tex.id(),
+                rect,
+                egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1.0, 1.0)),
+                egui::Color32::WHITE,
+            );
+        }
+
+        paint_focus(ui, &response, rect);
+
+        let t = ((*kelvin).clamp(min, max) - min) as f32 / (max - min).max(1) as f32;
+        let thumb = height / 2.0 - 1.0;
+        let thumb_x =
+            (rect.left() + t * rect.width()).clamp(rect.left() + thumb, rect.right() - thumb);
+        ui.painter().circle_filled(
+            egui::Pos2::new(thumb_x, rect.center().y),
+            thumb,
             egui::Color32::WHITE,
         );
-    }
+        ui.painter().circle_stroke(
+            egui::Pos2::new(thumb_x, rect.center().y),
+            thumb,
+            egui::Stroke::new(1.5, theme.accent),
+        );
 
-    let t = (*kelvin as f32 - 2900.0) / (7000.0 - 2900.0);
-    let thumb_x = (rect.left() + t * rect.width()).clamp(rect.left() + 8.0, rect.right() - 8.0);
-    ui.painter().circle_filled(
-        egui::Pos2::new(thumb_x, rect.center().y),
-        8.0,
-        egui::Color32::WHITE,
-    );
-    ui.painter().circle_stroke(
-        egui::Pos2::new(thumb_x, rect.center().y),
-        8.0,
-        egui::Stroke::new(1.5, colors::ACCENT),
-    );
+        if value_entry(ui, response.id, kelvin, range, "K", theme) {
+            changed = true;
+        }
 
-    changed
+        changed
+    })
+    .inner
 }
 
 fn power_button(
@@ -350,14 +1165,11 @@ fn power_button(
     on: &mut bool,
     size: f32,
     icon: Option<&egui::TextureHandle>,
+    theme: &Theme,
 ) -> bool {
     let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(size), egui::Sense::click());
 
-    let bg = if *on {
-        colors::POWER_ON
-    } else {
-        colors::POWER_OFF
-    };
+    let bg = if *on { theme.power_on } else { theme.power_off };
     ui.painter()
         .circle_filled(rect.center(), size / 2.0 - 1.0, bg);
 
@@ -379,65 +1191,704 @@ fn power_button(
     false
 }
 
-impl KeylightApp {
-    fn new() -> Self {
-        let api_url = std::env::var("KEYLIGHT_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.into());
-        let client = Arc::new(
-            Client::builder()
-                .timeout(Duration::from_secs(2))
-                .build()
-                .unwrap(),
+/// Handle a card is dragged by to reorder it; `payload` says which card it is.
+fn drag_handle<P: std::any::Any + Send + Sync>(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    payload: P,
+    theme: &Theme,
+) {
+    ui.dnd_drag_source(id, payload, |ui| {
+        ui.label(
+            egui::RichText::new("☰")
+                .size(11.0)
+                .color(theme.text_secondary),
         );
-        let pending_updates: PendingUpdates = Arc::new(Mutex::new(HashMap::new()));
+    })
+    .response
+    .on_hover_text("Drag to reorder");
+}
 
-        // Spawn worker thread that sends pending updates every 50ms
-        {
-            let client = Arc::clone(&client);
-            let pending = Arc::clone(&pending_updates);
-            thread::spawn(move || {
-                loop {
-                    thread::sleep(Duration::from_millis(50));
-                    // Drain all pending updates and send them
-                    let updates: Vec<(String, UpdateRequest)> = {
-                        let mut map = pending.lock().unwrap();
-                        map.drain().map(|(_, v)| v).collect()
-                    };
-                    for (url, req) in updates {
-                        let _ = client.put(&url).json(&req).send();
-                    }
-                }
-            });
+/// Outline `card` while a `P` is dragged over it, and return one dropped on it.
+fn drop_target<P: std::any::Any + Send + Sync>(
+    ui: &egui::Ui,
+    card: &egui::Response,
+    theme: &Theme,
+) -> Option<Arc<P>> {
+    if card.dnd_hover_payload::<P>().is_some() {
+        ui.painter()
+            .rect_stroke(card.rect, 6.0, egui::Stroke::new(1.5, theme.accent));
+    }
+    card.dnd_release_payload::<P>()
+}
+
+/// Most preset chips shown on a card; the daemon's first ones win.
+const MAX_PRESET_CHIPS: usize = 4;
+
+/// A row of preset chips; returns the one clicked.
+fn preset_chips(
+    ui: &mut egui::Ui,
+    presets: &[PresetRecord],
+    theme: &Theme,
+) -> Option<PresetRecord> {
+    if presets.is_empty() {
+        return None;
+    }
+    ui.add_space(2.0);
+    let mut clicked = None;
+    ui.horizontal_wrapped(|ui| {
+        for preset in presets.iter().take(MAX_PRESET_CHIPS) {
+            let chip = egui::Button::new(
+                egui::RichText::new(&preset.name)
+                    .size(9.0)
+                    .color(theme.text_primary),
+            )
+            .fill(theme.bg)
+            .stroke(egui::Stroke::new(1.0, theme.border))
+            .rounding(8.0);
+            if ui.add(chip).on_hover_text(preset.summary()).clicked() {
+                clicked = Some(preset.clone());
+            }
         }
+    });
+    clicked
+}
 
-        let url_all = format!("{}/v1/all", api_url);
-        let mut app = Self {
-            client,
-            api_url,
-            lights: Vec::new(),
-            groups: Vec::new(),
-            group_controls: HashMap::new(),
-            active_tab: Tab::Lights,
-            modal_state: ModalState::None,
-            new_group_name: String::new(),
-            new_group_members: HashSet::new(),
-            pending_updates,
-            logo: None,
-            power_icon: None,
-            refresh_icon: None,
-            editing_aliases: HashMap::new(),
+/// Arrow that collapses a card to its header row or expands it again; true if clicked.
+fn collapse_toggle(ui: &mut egui::Ui, collapsed: bool, theme: &Theme) -> bool {
+    let arrow = if collapsed { "▸" } else { "▾" };
+    ui.add(
+        egui::Button::new(
+            egui::RichText::new(arrow)
+                .size(11.0)
+                .color(theme.text_secondary),
+        )
+        .frame(false),
+    )
+    .on_hover_text(if collapsed { "Expand" } else { "Collapse" })
+    .clicked()
+}
+
+/// Star marking a light or scene as a favorite. Returns true when clicked.
+fn star_button(ui: &mut egui::Ui, favorite: bool, theme: &Theme) -> bool {
+    let (star, color) = if favorite {
+        ("★", theme.accent)
+    } else {
+        ("☆", theme.text_secondary)
+    };
+    ui.add(egui::Button::new(egui::RichText::new(star).size(11.0).color(color)).frame(false))
+        .on_hover_text(if favorite {
+            "Remove from favorites"
+        } else {
+            "Add to favorites"
+        })
+        .clicked()
+}
+
+/// Warning for a light the daemon flagged as wedged. Returns true when Reset was clicked.
+fn restart_notice(ui: &mut egui::Ui) -> bool {
+    let color = ui.visuals().warn_fg_color;
+    ui.horizontal_wrapped(|ui| {
+        ui.label(
+            egui::RichText::new(
+                "Not responding. Try Reset; if that fails, unplug it for a few seconds.",
+            )
+            .size(9.0)
+            .color(color),
+        );
+        ui.small_button("Reset").clicked()
+    })
+    .inner
+}
+
+/// Right-aligned schedule badge with its pause/skip popup. Returns the override the
+/// user picked, as `(paused, skip_next)`.
+fn schedule_menu(
+    ui: &mut egui::Ui,
+    id: &str,
+    paused: bool,
+    skip_next: bool,
+    theme: &Theme,
+) -> Option<(Option<bool>, Option<bool>)> {
+    let mut request = None;
+    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+        let popup_id = ui.make_persistent_id(("schedule", id));
+        let badge = schedule_badge(ui, paused, skip_next, theme);
+        if badge.clicked() {
+            ui.memory_mut(|m| m.toggle_popup(popup_id));
+        }
+        egui::popup_below_widget(
+            ui,
+            popup_id,
+            &badge,
+            egui::PopupCloseBehavior::CloseOnClickOutside,
+            |ui| {
+                ui.set_min_width(150.0);
+                let mut p = paused;
+                if ui.checkbox(&mut p, "Pause schedules").changed() {
+                    request = Some((Some(p), None));
+                }
+                ui.add_enabled_ui(!paused, |ui| {
+                    let text = if skip_next {
+                        "Don't skip next run"
+                    } else {
+                        "Skip next run"
+                    };
+                    if ui.small_button(text).clicked() {
+                        request = Some((None, Some(!skip_next)));
+                    }
+                });
+            },
+        );
+    });
+    request
+}
+
+/// Icon picker for the Manage Lights list. Returns the icon to set (`None` clears it)
+/// once one is picked; `custom` holds the emoji being typed.
+fn icon_menu(
+    ui: &mut egui::Ui,
+    id: &str,
+    icon: Option<&str>,
+    custom: &mut String,
+) -> Option<Option<String>> {
+    let mut picked = None;
+    let popup_id = ui.make_persistent_id(("icon", id));
+    let button = ui
+        .small_button(icon.map_or("＋", icon_glyph))
+        .on_hover_text("Icon");
+    if button.clicked() {
+        custom.clear();
+        ui.memory_mut(|m| m.toggle_popup(popup_id));
+    }
+    egui::popup_below_widget(
+        ui,
+        popup_id,
+        &button,
+        egui::PopupCloseBehavior::CloseOnClickOutside,
+        |ui| {
+            ui.set_min_width(150.0);
+            ui.horizontal(|ui| {
+                for (name, glyph) in LIGHT_ICONS {
+                    if ui.small_button(glyph).on_hover_text(name).clicked() {
+                        picked = Some(Some(name.to_string()));
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                let field = ui.add(
+                    egui::TextEdit::singleline(custom)
+                        .hint_text("Emoji")
+                        .desired_width(60.0),
+                );
+                let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (ui.small_button("Set").clicked() || entered) && !custom.trim().is_empty() {
+                    picked = Some(Some(custom.trim().to_string()));
+                }
+            });
+            if icon.is_some() && ui.small_button("No icon").clicked() {
+                picked = Some(None);
+            }
+        },
+    );
+    if picked.is_some() {
+        ui.memory_mut(|m| m.close_popup());
+    }
+    picked
+}
+
+/// Small clock badge for cards with active schedules; muted and struck through while paused.
+fn schedule_badge(
+    ui: &mut egui::Ui,
+    paused: bool,
+    skip_next: bool,
+    theme: &Theme,
+) -> egui::Response {
+    let size = 16.0;
+    let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(size), egui::Sense::click());
+    let color = if paused {
+        theme.text_secondary
+    } else {
+        theme.accent
+    };
+    let stroke = egui::Stroke::new(1.5, color);
+    let c = rect.center();
+    let r = size / 2.0 - 1.5;
+    ui.painter().circle_stroke(c, r, stroke);
+    ui.painter()
+        .line_segment([c, egui::Pos2::new(c.x, c.y - r * 0.6)], stroke);
+    ui.painter()
+        .line_segment([c, egui::Pos2::new(c.x + r * 0.45, c.y)], stroke);
+    if paused {
+        ui.painter()
+            .line_segment([rect.left_bottom(), rect.right_top()], stroke);
+    }
+    let hint = if paused {
+        "Schedules paused for this light"
+    } else if skip_next {
+        "Next scheduled run will be skipped"
+    } else {
+        "Scheduled"
+    };
+    response.on_hover_text(hint)
+}
+
+/// Header dot: green while keylightd answers, red while it doesn't (with when it's
+/// tried next), gray until the first check is done.
+fn connection_dot(ui: &mut egui::Ui, connection: &connection::Connection, theme: &Theme) {
+    let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(10.0), egui::Sense::hover());
+    let color = match connection.connected {
+        Some(true) => egui::Color32::from_rgb(0x3c, 0xb3, 0x71),
+        Some(false) => egui::Color32::from_rgb(0xd9, 0x4a, 0x4a),
+        None => theme.text_secondary,
+    };
+    ui.painter().circle_filled(rect.center(), 4.0, color);
+    let hint = match (connection.connected, &connection.status) {
+        (Some(true), Some(status)) => {
+            let mut hint = format!(
+                "keylightd {} · up {} · {} light{}",
+                status.version,
+                format_uptime(status.uptime_secs),
+                status.lights,
+                if status.lights == 1 { "" } else { "s" }
+            );
+            if !status.automations.is_empty() {
+                hint.push_str(&format!(" · {}", status.automations.join(", ")));
+            }
+            hint
+        }
+        (Some(false), _) => {
+            let retry = connection
+                .retry_at
+                .map(|at| at.saturating_duration_since(Instant::now()).as_secs())
+                .unwrap_or(0);
+            format!(
+                "keylightd isn't responding; showing the last known state. Retrying in {retry}s"
+            )
+        }
+        _ => "Connecting to keylightd…".to_string(),
+    };
+    response.on_hover_text(hint);
+}
+
+/// Banner text while keylightd doesn't answer.
+fn offline_message(connection: &connection::Connection) -> String {
+    let retry = connection
+        .retry_at
+        .map(|at| at.saturating_duration_since(Instant::now()).as_secs())
+        .unwrap_or(0);
+    match connection.restarts {
+        0 => format!("Daemon offline · retrying in {retry}s"),
+        1 => format!("Daemon offline · restarted keylightd, retrying in {retry}s"),
+        n => format!("Daemon offline · restarted keylightd {n} times, retrying in {retry}s"),
+    }
+}
+
+/// Header menu for picking the active profile. `Some(None)` goes back to the
+/// daemon's rules.
+fn profile_picker(
+    ui: &mut egui::Ui,
+    status: &ProfileStatus,
+    theme: &Theme,
+) -> Option<Option<String>> {
+    let mut picked = None;
+    let active = status.active.as_deref().unwrap_or("No profile");
+    let title = egui::RichText::new(format!("{active} ▾"))
+        .size(10.0)
+        .color(theme.text_secondary);
+    ui.menu_button(title, |ui| {
+        let automatic = ui
+            .selectable_label(!status.manual, "Automatic")
+            .on_hover_text("Let the profile rules pick");
+        if automatic.clicked() {
+            picked = Some(None);
+            ui.close_menu();
+        }
+        ui.separator();
+        for profile in &status.profiles {
+            let current = status.active.as_deref() == Some(profile.name.as_str());
+            if ui.selectable_label(current, &profile.name).clicked() {
+                picked = Some(Some(profile.name.clone()));
+                ui.close_menu();
+            }
+        }
+    })
+    .response
+    .on_hover_text("Profile");
+    picked
+}
+
+/// Header menu for picking the daemon the window controls, with each one's status dot.
+fn host_switcher(
+    ui: &mut egui::Ui,
+    hosts: &[hosts::Host],
+    active: usize,
+    connections: &[connection::Connection],
+    theme: &Theme,
+) -> Option<usize> {
+    let mut picked = None;
+    let title = egui::RichText::new(format!("{} ▾", hosts[active].name))
+        .size(10.0)
+        .color(theme.text_secondary);
+    ui.menu_button(title, |ui| {
+        for (index, (host, connection)) in hosts.iter().zip(connections).enumerate() {
+            ui.horizontal(|ui| {
+                connection_dot(ui, connection, theme);
+                let label = ui
+                    .selectable_label(index == active, &host.name)
+                    .on_hover_text(&host.url);
+                if label.clicked() {
+                    picked = Some(index);
+                    ui.close_menu();
+                }
+            });
+        }
+    });
+    picked
+}
+
+fn format_uptime(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h {}m", secs / 3600, secs / 60 % 60),
+        _ => format!("{}d {}h", secs / 86400, secs / 3600 % 24),
+    }
+}
+
+/// Header button that puts back the lights the last change touched.
+fn undo_button(ui: &mut egui::Ui, theme: &Theme) -> egui::Response {
+    ui.add(
+        egui::Button::new(
+            egui::RichText::new("⟲")
+                .size(15.0)
+                .color(theme.text_primary),
+        )
+        .fill(egui::Color32::TRANSPARENT)
+        .min_size(egui::vec2(24.0, 24.0)),
+    )
+    .on_hover_text("Undo the last change")
+}
+
+/// `left` as `m:ss`, or `h:mm:ss` from an hour up, rounded up to the second.
+fn countdown(left: Duration) -> String {
+    let secs = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+fn countdown_chip(ui: &mut egui::Ui, text: String, theme: &Theme) -> egui::Response {
+    ui.add(
+        egui::Button::new(egui::RichText::new(text).size(10.0).color(theme.accent))
+            .stroke(egui::Stroke::new(1.0, theme.accent))
+            .fill(egui::Color32::TRANSPARENT)
+            .rounding(9.0)
+            .min_size(egui::vec2(0.0, 18.0)),
+    )
+}
+
+/// Header chip counting down a do-not-disturb window; clicking it ends the window.
+fn dnd_chip(ui: &mut egui::Ui, left: Duration, theme: &Theme) -> egui::Response {
+    countdown_chip(ui, format!("DND {}", countdown(left)), theme)
+        .on_hover_text("Automations are paused. Click to resume them now")
+}
+
+/// Card chip counting down a light's next timer; clicking it cancels the timer.
+fn timer_chip(
+    ui: &mut egui::Ui,
+    timer: &TimerRecord,
+    left: Duration,
+    theme: &Theme,
+) -> egui::Response {
+    let action = match timer.update.on {
+        Some(0) => "switches this light off",
+        Some(_) => "switches this light on",
+        None => "changes this light",
+    };
+    countdown_chip(ui, format!("⏲ {}", countdown(left)), theme)
+        .on_hover_text(format!("A timer {action}. Click to cancel it"))
+}
+
+/// Whether `target` (of a schedule or timer) covers `light`.
+fn target_includes(target: &ScheduleTarget, groups: &[GroupRecord], light: &LightControl) -> bool {
+    match target {
+        ScheduleTarget::All => true,
+        ScheduleTarget::Light(ident) => light.matches(ident),
+        ScheduleTarget::Group(name) => groups
+            .iter()
+            .find(|g| &g.name == name)
+            .is_some_and(|g| g.members.iter().any(|m| light.matches(m))),
+    }
+}
+
+impl KeylightApp {
+    fn new(
+        ctx: &egui::Context,
+        pending_updates: PendingUpdates,
+        start_minimized: bool,
+        daemon: Option<connection::DaemonProcess>,
+    ) -> Self {
+        let host_settings = hosts::load();
+        let hosts = host_settings.hosts();
+        let active_host = host_settings.active_index();
+        let api_url = hosts[active_host].url.clone();
+        let client = Arc::new(
+            Client::builder()
+                .timeout(Duration::from_secs(2))
+                .build()
+                .unwrap(),
+        );
+        restore_pending_updates(&pending_updates);
+
+        {
+            let client = Arc::clone(&client);
+            let pending = Arc::clone(&pending_updates);
+            thread::spawn(move || run_update_worker(client, pending));
+        }
+
+        let tray_signals = Arc::new(tray::TraySignals::default());
+        let tray = tray::TrayIcon::new(
+            ctx.clone(),
+            Arc::clone(&pending_updates),
+            Arc::clone(&tray_signals),
+            api_url.clone(),
+        )
+        .spawn();
+        // Without a tray host there'd be no way to bring the window back.
+        if start_minimized && tray.is_some() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+
+        let hotkey_bindings = hotkeys::load_bindings();
+        let hotkeys = hotkeys::HotkeyService::spawn(
+            hotkey_bindings.clone(),
+            ctx.clone(),
+            Arc::clone(&pending_updates),
+            Arc::clone(&tray_signals),
+            api_url.clone(),
+        );
+
+        let theme_preference = theme::load_preference();
+        let system_dark = theme::watch_system_scheme(ctx.clone());
+        let theme = theme_preference.resolve(system_dark.load(Ordering::Relaxed));
+        theme.apply(ctx);
+
+        let url_all = format!("{}/v1/all", api_url);
+        // Only the daemon on this computer can have been started by the tray.
+        let mut daemon = daemon;
+        let preferences = preferences::load();
+        let connections = hosts
+            .iter()
+            .map(|host| {
+                connection::ConnectionMonitor::start(
+                    ctx.clone(),
+                    Arc::clone(&client),
+                    &host.url,
+                    preferences.poll_interval(),
+                    daemon.take(),
+                )
+            })
+            .collect();
+        let api = api::ApiWorker::start(ctx.clone(), Arc::clone(&client));
+        let nearby = nearby::NearbyDaemons::start(ctx.clone())
+            .map_err(|e| eprintln!("failed to look for daemons on the network: {e}"))
+            .ok();
+        let notifier = notifier::Notifier::start(
+            Arc::clone(&client),
+            &api_url,
+            preferences.notify_automations,
+            preferences.notify_errors,
+        );
+        let mut app = Self {
+            client,
+            api,
+            api_url,
+            lights: Vec::new(),
+            groups: Vec::new(),
+            schedules: Vec::new(),
+            group_controls: HashMap::new(),
+            expanded_groups: HashSet::new(),
+            active_tab: Tab::Lights,
+            focused_card: None,
+            scroll_to_focus: false,
+            modal_state: ModalState::None,
+            new_group_name: String::new(),
+            new_group_members: HashSet::new(),
+            group_suggestions: Vec::new(),
+            scenes: Vec::new(),
+            presets: Vec::new(),
+            new_scene_name: String::new(),
+            renaming_scene: None,
+            scene_error: None,
+            pending_updates,
+            logo: None,
+            power_icon: None,
+            refresh_icon: None,
+            editing_aliases: HashMap::new(),
+            editing_rooms: HashMap::new(),
+            room_filter: None,
+            filter: String::new(),
+            focus_filter: false,
+            unsaved_settings: HashMap::new(),
+            custom_icon: String::new(),
             all_on: true,
             all_brightness: 50,
             all_kelvin: 4500,
             autostart_enabled: is_autostart_enabled(),
+            daemon_settings: load_daemon_settings(),
+            preferences,
             brightness_gradient: None,
             temperature_gradient: None,
             url_all,
             last_trim: Instant::now(),
+            tray,
+            tray_signals,
+            hotkeys,
+            notifier,
+            hotkey_bindings,
+            recording_hotkey: None,
+            new_hotkey_action: hotkeys::HotkeyAction::ToggleAll,
+            photo_match: None,
+            photo_match_status: None,
+            ambient: None,
+            ambient_light: None,
+            ambient_interval_secs: ambient::DEFAULT_INTERVAL_SECS,
+            screen_match: None,
+            screen_match_settings: screen_match::load_settings(),
+            diagnostics: None,
+            diagnostics_status: None,
+            dnd_until: None,
+            timers: Vec::new(),
+            profiles: ProfileStatus::default(),
+            state_cache: state_cache::load(),
+            host_settings,
+            hosts,
+            active_host,
+            connections,
+            new_host_name: String::new(),
+            new_host_url: String::new(),
+            host_error: None,
+            nearby,
+            theme_preference,
+            system_dark,
+            theme,
         };
         app.refresh_all();
         app
     }
 
+    fn connection(&self) -> &connection::ConnectionMonitor {
+        &self.connections[self.active_host]
+    }
+
+    /// Control another daemon. Everything shown, and the workers sending to the old
+    /// one, belong to the previous host, so they're dropped before reloading.
+    fn switch_host(&mut self, index: usize) {
+        if index == self.active_host || index >= self.hosts.len() {
+            return;
+        }
+        self.active_host = index;
+        self.api_url = self.hosts[index].url.clone();
+        self.url_all = format!("{}/v1/all", self.api_url);
+        self.lights.clear();
+        self.groups.clear();
+        self.schedules.clear();
+        self.group_controls.clear();
+        self.group_suggestions.clear();
+        self.scenes.clear();
+        self.renaming_scene = None;
+        self.scene_error = None;
+        self.editing_aliases.clear();
+        self.editing_rooms.clear();
+        self.room_filter = None;
+        self.unsaved_settings.clear();
+        self.new_group_members.clear();
+        self.dnd_until = None;
+        self.profiles = ProfileStatus::default();
+        self.ambient = None;
+        self.ambient_light = None;
+        if let Some(tray) = &self.tray {
+            let url = self.api_url.clone();
+            tray.update(|t| t.api_url = url);
+        }
+        self.hotkeys.set_api_url(&self.api_url);
+        self.notifier.set_api_url(&self.api_url);
+        self.host_settings.active = (index > 0).then(|| self.api_url.clone());
+        self.save_hosts();
+        self.refresh_all();
+        self.start_screen_match();
+    }
+
+    fn add_host(&mut self, ctx: &egui::Context) {
+        let url = match hosts::normalize_url(&self.new_host_url) {
+            Ok(url) => url,
+            Err(e) => {
+                self.host_error = Some(e);
+                return;
+            }
+        };
+        if self.hosts.iter().any(|host| host.url == url) {
+            self.host_error = Some("That daemon is already in the list".into());
+            return;
+        }
+        let name = match self.new_host_name.trim() {
+            "" => url.split("://").nth(1).unwrap_or(&url).to_string(),
+            name => name.to_string(),
+        };
+        self.connections.push(connection::ConnectionMonitor::start(
+            ctx.clone(),
+            Arc::clone(&self.client),
+            &url,
+            self.preferences.poll_interval(),
+            None,
+        ));
+        self.host_settings.remotes.push(hosts::Host { name, url });
+        self.hosts = self.host_settings.hosts();
+        self.new_host_name.clear();
+        self.new_host_url.clear();
+        self.host_error = None;
+        self.save_hosts();
+    }
+
+    /// Forget a remote host; this computer (index 0) stays.
+    fn remove_host(&mut self, index: usize) {
+        if index == 0 || index >= self.hosts.len() {
+            return;
+        }
+        if index == self.active_host {
+            self.switch_host(0);
+        } else if index < self.active_host {
+            self.active_host -= 1;
+        }
+        self.connections.remove(index);
+        self.host_settings.remotes.remove(index - 1);
+        self.hosts = self.host_settings.hosts();
+        self.save_hosts();
+    }
+
+    fn save_hosts(&self) {
+        if let Err(e) = hosts::save(&self.host_settings) {
+            eprintln!("failed to save hosts: {e}");
+        }
+    }
+
+    /// Switch themes when the preference or the desktop color scheme changed.
+    /// Gradients are baked from theme colors, so they are rebuilt too.
+    fn sync_theme(&mut self, ctx: &egui::Context) {
+        let theme = self
+            .theme_preference
+            .resolve(self.system_dark.load(Ordering::Relaxed));
+        if theme != self.theme {
+            self.theme = theme;
+            theme.apply(ctx);
+            self.brightness_gradient = None;
+            self.temperature_gradient = None;
+        }
+    }
+
     fn ensure_textures(&mut self, ctx: &egui::Context) {
         if self.logo.is_none() {
             let bytes = include_bytes!("../../../../public/Limecon.png");
@@ -462,16 +1913,16 @@ impl KeylightApp {
             self.brightness_gradient = Some(create_gradient_texture(
                 ctx,
                 "bright_grad",
-                colors::BRIGHT_LOW,
-                colors::BRIGHT_HIGH,
+                self.theme.bright_low,
+                self.theme.bright_high,
             ));
         }
         if self.temperature_gradient.is_none() {
             self.temperature_gradient = Some(create_gradient_texture(
                 ctx,
                 "temp_grad",
-                colors::WARM,
-                colors::COOL,
+                self.theme.warm,
+                self.theme.cool,
             ));
         }
     }
@@ -479,30 +1930,154 @@ impl KeylightApp {
     fn refresh_all(&mut self) {
         self.refresh_lights();
         self.refresh_groups();
+        self.refresh_scenes();
+        self.request(api::Request::Presets);
+        self.request(api::Request::Profiles);
+        self.refresh_schedules();
         self.refresh_light_states();
     }
 
-    fn refresh_light_states(&mut self) {
-        let url = format!("{}/v1/lights/states", self.api_url);
-        if let Ok(res) = self
-            .client
-            .get(&url)
-            .send()
-            .and_then(|r| r.error_for_status())
-        {
-            if let Ok(states) = res.json::<Vec<LightStateResponse>>() {
-                for state in states {
-                    if let Some(light) = self.lights.iter_mut().find(|l| l.id == state.id) {
-                        light.on = state.on;
-                        light.brightness = state.brightness;
-                        light.kelvin = state.kelvin;
+    fn request(&self, request: api::Request) {
+        self.api.send(&self.api_url, request);
+    }
+
+    /// Apply what the API worker got back since the last frame.
+    fn poll_api(&mut self) {
+        while let Some(reply) = self.api.try_recv(&self.api_url) {
+            match reply {
+                api::Reply::Lights(records) => self.set_lights(records),
+                api::Reply::States(states) => {
+                    let cached = self.state_cache.entry(self.api_url.clone()).or_default();
+                    for state in states {
+                        if let Some(light) = self.lights.iter_mut().find(|l| l.id == state.id) {
+                            light.on = state.on;
+                            light.brightness = state.brightness;
+                            light.kelvin = state.kelvin;
+                        }
+                        let saved = state_cache::CachedState {
+                            on: state.on,
+                            brightness: state.brightness,
+                            kelvin: state.kelvin,
+                        };
+                        cached.insert(state.id, saved);
+                    }
+                    if let Err(e) = state_cache::save(&self.state_cache) {
+                        eprintln!("failed to save light states: {e}");
+                    }
+                    self.sync_all_state();
+                }
+                // Don't wait for the next status poll to notice the daemon is gone.
+                api::Reply::StatesFailed => self.connection().poke(),
+                api::Reply::Health(health) => {
+                    for light in &mut self.lights {
+                        light.restart_required = health
+                            .iter()
+                            .any(|h| h.id == light.id && h.restart_required);
+                    }
+                }
+                api::Reply::Groups(groups) => self.set_groups(groups),
+                api::Reply::GroupSuggestions(suggestions) => self.group_suggestions = suggestions,
+                api::Reply::Scenes(scenes) => self.set_scenes(scenes),
+                api::Reply::Presets(presets) => self.presets = presets,
+                api::Reply::SceneFailed(error) => self.scene_error = Some(error),
+                api::Reply::Schedules(schedules) => {
+                    self.schedules = schedules;
+                    self.sync_schedule_badges();
+                }
+                api::Reply::LightSet { id, setting, saved } => {
+                    self.light_setting_answered(id, setting, saved)
+                }
+                api::Reply::IconSaved { id, icon } => {
+                    if let Some(l) = self.lights.iter_mut().find(|l| l.id == id) {
+                        l.icon = icon;
+                    }
+                    self.update_tray_lights();
+                }
+                api::Reply::ScheduleOverride(record) => {
+                    if let Some(l) = self.lights.iter_mut().find(|l| l.id == record.id) {
+                        l.schedule_paused = record.schedule_paused;
+                        l.schedule_skip_next = record.schedule_skip_next;
+                    }
+                }
+                api::Reply::LightReset(id) => {
+                    if let Some(light) = self.lights.iter_mut().find(|l| l.id == id) {
+                        light.restart_required = false;
                     }
                 }
-                self.sync_all_state();
+                api::Reply::DndEnded => self.dnd_until = None,
+                api::Reply::Undone => self.refresh_light_states(),
+                api::Reply::Profiles(profiles) => self.profiles = profiles,
+                api::Reply::Timers(timers) => {
+                    let now = Instant::now();
+                    self.timers = timers
+                        .into_iter()
+                        .map(|timer| {
+                            let due = now + Duration::from_secs(timer.remaining_secs);
+                            (timer, due)
+                        })
+                        .collect();
+                }
             }
         }
     }
 
+    fn refresh_light_states(&mut self) {
+        self.request(api::Request::States);
+        self.request(api::Request::Timers);
+        // Rules may have switched the profile meanwhile.
+        self.request(api::Request::Profiles);
+        self.refresh_light_health();
+    }
+
+    /// Let automations touch the lights again before the window runs out.
+    fn end_dnd(&mut self) {
+        self.request(api::Request::EndDnd);
+    }
+
+    fn undo(&mut self) {
+        self.request(api::Request::Undo);
+    }
+
+    /// Activate a profile, or let the daemon's rules pick one again for `None`.
+    fn set_profile(&mut self, name: Option<String>) {
+        self.request(api::Request::SetProfile(name));
+        // The profile's scene changes the lights.
+        self.refresh_light_states();
+    }
+
+    /// Forget timers that have run, and show what they did.
+    fn expire_timers(&mut self) {
+        let now = Instant::now();
+        if self.timers.iter().any(|(_, due)| *due + TIMER_GRACE <= now) {
+            self.timers.retain(|(_, due)| *due + TIMER_GRACE > now);
+            self.refresh_light_states();
+        }
+    }
+
+    fn cancel_timer(&mut self, id: u64) {
+        self.timers.retain(|(timer, _)| timer.id != id);
+        self.request(api::Request::CancelTimer(id));
+    }
+
+    /// The next timer that touches `light`, and the time left on it.
+    fn next_timer(&self, light: &LightControl) -> Option<(&TimerRecord, Duration)> {
+        self.timers
+            .iter()
+            .filter(|(timer, _)| target_includes(&timer.target, &self.groups, light))
+            .min_by_key(|(_, due)| *due)
+            .map(|(timer, due)| (timer, due.saturating_duration_since(Instant::now())))
+    }
+
+    fn refresh_light_health(&mut self) {
+        self.request(api::Request::Health);
+    }
+
+    /// Ask the daemon to soft-reset a wedged light. If it doesn't take, the notice
+    /// stays up and tells the user to power-cycle it.
+    fn reset_light(&mut self, id: &str) {
+        self.request(api::Request::ResetLight(id.to_string()));
+    }
+
     fn sync_all_state(&mut self) {
         let enabled: Vec<_> = self.lights.iter().filter(|l| l.enabled).collect();
         if !enabled.is_empty() {
@@ -514,154 +2089,1221 @@ impl KeylightApp {
         }
     }
 
-    fn refresh_lights(&mut self) {
-        let url = format!("{}/v1/lights", self.api_url);
-        if let Ok(res) = self
-            .client
-            .get(&url)
-            .send()
-            .and_then(|r| r.error_for_status())
-        {
-            if let Ok(records) = res.json::<Vec<LightRecord>>() {
-                let mut updated = Vec::new();
-                for record in records {
-                    let label = record.alias.clone().unwrap_or_else(|| {
-                        record
-                            .name
-                            .split('.')
-                            .next()
-                            .unwrap_or(&record.name)
-                            .to_string()
-                    });
-                    let prev = self.lights.iter().find(|l| l.id == record.id).cloned();
-                    updated.push(LightControl {
-                        id: record.id.clone(),
-                        label,
-                        enabled: record.enabled,
-                        on: prev.as_ref().map(|p| p.on).unwrap_or(true),
-                        brightness: prev.as_ref().map(|p| p.brightness).unwrap_or(50),
-                        kelvin: prev.as_ref().map(|p| p.kelvin).unwrap_or(4500),
-                    });
-                }
-                self.lights = updated;
-                for light in &self.lights {
-                    self.editing_aliases
-                        .entry(light.id.clone())
-                        .or_insert_with(|| light.label.clone());
-                }
-            }
-        }
+    /// Switch one light on or off, as its card's power button does.
+    fn set_light_on(&mut self, index: usize, on: bool) {
+        self.lights[index].on = on;
+        let id = &self.lights[index].id;
+        let url = format!("{}/v1/lights/{}", self.api_url, urlencoding::encode(id));
+        self.queue_update(
+            &format!("p_{}", id),
+            url,
+            UpdateRequest {
+                on: Some(if on { 1 } else { 0 }),
+                brightness: None,
+                kelvin: None,
+                mired: None,
+            },
+        );
+        self.sync_all_state();
     }
 
-    fn refresh_groups(&mut self) {
-        let url = format!("{}/v1/groups", self.api_url);
-        if let Ok(res) = self
-            .client
-            .get(&url)
-            .send()
-            .and_then(|r| r.error_for_status())
-        {
-            if let Ok(groups) = res.json::<Vec<GroupRecord>>() {
-                for g in &groups {
-                    self.group_controls
-                        .entry(g.name.clone())
-                        .or_insert(GroupControl {
-                            on: true,
-                            brightness: 50,
-                            kelvin: 4500,
-                        });
-                }
-                self.groups = groups;
-            }
+    /// Change a light's brightness by `step` percent, within its limits.
+    fn step_light_brightness(&mut self, index: usize, step: i16) {
+        let light = &mut self.lights[index];
+        let (min, max) = light.brightness_limits;
+        let b = (i16::from(light.brightness) + step).clamp(i16::from(min), i16::from(max)) as u8;
+        if b == light.brightness {
+            return;
         }
+        light.brightness = b;
+        let id = light.id.clone();
+        let url = format!("{}/v1/lights/{}", self.api_url, urlencoding::encode(&id));
+        self.queue_update(
+            &format!("b_{id}"),
+            url,
+            UpdateRequest {
+                on: None,
+                brightness: Some(b),
+                kelvin: None,
+                mired: None,
+            },
+        );
     }
 
-    fn save_group(&mut self, name: String, members: Vec<String>) {
-        let url = format!("{}/v1/groups", self.api_url);
-        let _ = self
-            .client
-            .post(&url)
-            .json(&GroupRequest { name, members })
-            .send();
-        self.refresh_groups();
+    fn set_group_on(&mut self, name: &str, on: bool) {
+        if let Some(c) = self.group_controls.get_mut(name) {
+            c.on = on;
+        }
+        let url = format!("{}/v1/groups/{}", self.api_url, urlencoding::encode(name));
+        self.queue_update(
+            &format!("gp_{}", name),
+            url,
+            UpdateRequest {
+                on: Some(if on { 1 } else { 0 }),
+                brightness: None,
+                kelvin: None,
+                mired: None,
+            },
+        );
     }
 
-    fn delete_group(&mut self, name: &str) {
+    fn step_group_brightness(&mut self, name: &str, step: i16) {
+        let Some(c) = self.group_controls.get_mut(name) else {
+            return;
+        };
+        let b = (i16::from(c.brightness) + step).clamp(0, 100) as u8;
+        if b == c.brightness {
+            return;
+        }
+        c.brightness = b;
         let url = format!("{}/v1/groups/{}", self.api_url, urlencoding::encode(name));
-        let _ = self.client.delete(&url).send();
-        self.group_controls.remove(name);
-        self.refresh_groups();
+        self.queue_update(
+            &format!("gb_{}", name),
+            url,
+            UpdateRequest {
+                on: None,
+                brightness: Some(b),
+                kelvin: None,
+                mired: None,
+            },
+        );
     }
 
-    /// Queue an update - overwrites any pending update for the same key
-    /// The worker thread sends these every 50ms, so only the latest value is sent
-    fn queue_update(&self, key: &str, url: String, update: UpdateRequest) {
-        let mut map = self.pending_updates.lock().unwrap();
-        map.insert(key.to_string(), (url, update));
+    /// Member rows of an expanded group card: each light's live state and a power
+    /// toggle, so the one left on is easy to spot.
+    fn group_members(
+        &mut self,
+        ui: &mut egui::Ui,
+        members: &[String],
+        power_tex: Option<&egui::TextureHandle>,
+        theme: &Theme,
+    ) {
+        for member in members {
+            ui.horizontal(|ui| {
+                let Some(index) = self.lights.iter().position(|l| l.matches(member)) else {
+                    ui.add_space(20.0);
+                    ui.label(
+                        egui::RichText::new(member)
+                            .size(10.0)
+                            .color(theme.text_primary),
+                    );
+                    ui.label(
+                        egui::RichText::new("not found")
+                            .size(9.0)
+                            .color(theme.text_secondary),
+                    );
+                    return;
+                };
+                let light = &self.lights[index];
+                let label = light.icon_label();
+                let state = if !light.enabled {
+                    "disabled".to_string()
+                } else if light.on {
+                    format!("{}% · {}K", light.brightness, light.kelvin)
+                } else {
+                    "off".to_string()
+                };
+                let enabled = light.enabled;
+                let mut on = light.on;
+                if enabled {
+                    if power_button(ui, &mut on, 16.0, power_tex, theme) {
+                        self.set_light_on(index, on);
+                    }
+                } else {
+                    ui.add_space(20.0);
+                }
+                ui.label(
+                    egui::RichText::new(label)
+                        .size(10.0)
+                        .color(theme.text_primary),
+                );
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(
+                        egui::RichText::new(state)
+                            .size(9.0)
+                            .color(theme.text_secondary),
+                    );
+                });
+            });
+        }
     }
 
-    fn refresh_discovery(&mut self) {
-        let url = format!("{}/v1/lights/refresh", self.api_url);
-        let _ = self
-            .client
-            .post(&url)
-            .json(&serde_json::json!({"timeout": 3}))
-            .send();
-        self.refresh_lights();
-        self.refresh_light_states();
+    fn refresh_lights(&mut self) {
+        self.request(api::Request::Lights);
     }
 
-    fn set_light_enabled(&mut self, id: &str, enabled: bool) {
-        let url = format!(
-            "{}/v1/lights/{}/enabled",
-            self.api_url,
-            urlencoding::encode(id)
-        );
-        let _ = self
-            .client
-            .put(&url)
-            .json(&serde_json::json!({ "enabled": enabled }))
-            .send();
-    }
-
-    fn set_light_alias(&mut self, id: &str, alias: &str) {
-        let url = format!(
-            "{}/v1/lights/{}/alias",
-            self.api_url,
-            urlencoding::encode(id)
-        );
-        let val: Option<&str> = if alias.trim().is_empty() {
-            None
-        } else {
-            Some(alias.trim())
-        };
-        let _ = self
-            .client
-            .put(&url)
-            .json(&serde_json::json!({ "alias": val }))
-            .send();
-        if let Some(l) = self.lights.iter_mut().find(|l| l.id == id) {
-            l.label = if alias.trim().is_empty() {
-                l.id.split('.').next().unwrap_or(&l.id).to_string()
-            } else {
-                alias.trim().to_string()
-            };
+    fn set_lights(&mut self, records: Vec<LightRecord>) {
+        let mut updated = Vec::new();
+        for record in records {
+            let label = record.alias.clone().unwrap_or_else(|| {
+                let name = record.name.split('.').next().unwrap_or(&record.name);
+                match record.zone {
+                    Some(zone) => format!("{name} #{zone}"),
+                    None => name.to_string(),
+                }
+            });
+            let prev = self.lights.iter().find(|l| l.id == record.id).cloned();
+            // Until the daemon reports a light's state, show the one it had last time.
+            let cached = self
+                .state_cache
+                .get(&self.api_url)
+                .and_then(|states| states.get(&record.id));
+            updated.push(LightControl {
+                id: record.id.clone(),
+                name: record.name.clone(),
+                alias: record.alias.clone(),
+                label,
+                enabled: record.enabled,
+                on: prev
+                    .as_ref()
+                    .map(|p| p.on)
+                    .or(cached.map(|c| c.on))
+                    .unwrap_or(true),
+                brightness: prev
+                    .as_ref()
+                    .map(|p| p.brightness)
+                    .or(cached.map(|c| c.brightness))
+                    .unwrap_or(50),
+                kelvin: prev
+                    .as_ref()
+                    .map(|p| p.kelvin)
+                    .or(cached.map(|c| c.kelvin))
+                    .unwrap_or(4500),
+                has_schedule: prev.as_ref().map(|p| p.has_schedule).unwrap_or(false),
+                schedule_paused: record.schedule_paused,
+                schedule_skip_next: record.schedule_skip_next,
+                kelvin_range: record
+                    .kelvin_range
+                    .map(|r| (r.min, r.max))
+                    .unwrap_or(DEFAULT_KELVIN_RANGE),
+                capabilities: record.capabilities,
+                soft_start: record.soft_start,
+                icon: record.icon.clone(),
+                room: record.room.clone(),
+                notes: record.notes.clone(),
+                favorite: record.favorite,
+                brightness_limits: record
+                    .brightness_limits
+                    .map_or(FULL_BRIGHTNESS, |l| (l.min, l.max)),
+                restart_required: prev.as_ref().is_some_and(|p| p.restart_required),
+            });
         }
+        // What's still being saved wins over what the daemon listed before it got it.
+        let unsaved: Vec<(String, api::LightSetting)> = self
+            .unsaved_settings
+            .iter()
+            .filter_map(|((id, _), unsaved)| {
+                let light = self.lights.iter().find(|l| &l.id == id)?;
+                Some((id.clone(), light_setting(light, &unsaved.confirmed)))
+            })
+            .collect();
+        self.lights = updated;
+        for (id, setting) in unsaved {
+            self.apply_light_setting(&id, &setting);
+        }
+        self.sync_all_state();
+        for light in &self.lights {
+            self.editing_aliases
+                .entry(light.id.clone())
+                .or_insert_with(|| light.label.clone());
+            self.editing_rooms
+                .entry(light.id.clone())
+                .or_insert_with(|| light.room.clone().unwrap_or_default());
+        }
+        if let Some(room) = &self.room_filter {
+            if !self.lights.iter().any(|l| l.room.as_ref() == Some(room)) {
+                self.room_filter = None;
+            }
+        }
+        self.update_tray_lights();
     }
-}
-
-impl eframe::App for KeylightApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.ensure_textures(ctx);
 
-        #[cfg(target_os = "linux")]
-        if self.last_trim.elapsed() >= Duration::from_secs(5) {
-            self.last_trim = Instant::now();
-            unsafe { malloc_trim(0) };
+    fn update_tray_lights(&self) {
+        if let Some(tray) = &self.tray {
+            let lights = self
+                .lights
+                .iter()
+                .filter(|light| light.enabled)
+                .map(|light| (light.id.clone(), light.icon_label()))
+                .collect::<Vec<_>>();
+            let favorites = self
+                .lights
+                .iter()
+                .filter(|light| light.enabled && light.favorite)
+                .map(|light| (light.id.clone(), light.icon_label()))
+                .collect();
+            tray.update(|t| {
+                t.lights = lights;
+                t.favorites = favorites;
+            });
         }
+    }
 
-        if ctx.input(|i| i.pointer.any_down()) {
-            ctx.request_repaint_after(Duration::from_millis(16));
+    fn set_scenes(&mut self, scenes: Vec<SceneRecord>) {
+        self.scenes = scenes;
+        if let Some(tray) = &self.tray {
+            let names = self
+                .scenes
+                .iter()
+                .filter(|s| s.favorite)
+                .map(|s| s.name.clone())
+                .collect();
+            tray.update(|t| t.scenes = names);
+        }
+    }
+
+    fn refresh_groups(&mut self) {
+        self.request(api::Request::Groups);
+        self.refresh_group_suggestions();
+    }
+
+    fn set_groups(&mut self, groups: Vec<GroupRecord>) {
+        for g in &groups {
+            self.group_controls
+                .entry(g.name.clone())
+                .or_insert(GroupControl {
+                    on: true,
+                    brightness: 50,
+                    kelvin: 4500,
+                });
+        }
+        self.expanded_groups
+            .retain(|name| groups.iter().any(|g| &g.name == name));
+        self.groups = groups;
+        if let Some(tray) = &self.tray {
+            let names = self.groups.iter().map(|g| g.name.clone()).collect();
+            tray.update(|t| t.groups = names);
+        }
+        // Screen match only follows groups that exist, so it waits for the first list.
+        if self.screen_match.is_none() {
+            self.start_screen_match();
+        }
+    }
+
+    fn refresh_group_suggestions(&mut self) {
+        self.request(api::Request::GroupSuggestions);
+    }
+
+    fn refresh_schedules(&mut self) {
+        self.request(api::Request::Schedules);
+    }
+
+    /// Mark lights that at least one enabled schedule would touch.
+    fn sync_schedule_badges(&mut self) {
+        for light in &mut self.lights {
+            light.has_schedule = self
+                .schedules
+                .iter()
+                .filter(|s| s.enabled)
+                .any(|s| target_includes(&s.target, &self.groups, light));
+        }
+    }
+
+    fn set_schedule_override(&mut self, id: &str, paused: Option<bool>, skip_next: Option<bool>) {
+        self.request(api::Request::SetScheduleOverride {
+            id: id.to_string(),
+            paused,
+            skip_next,
+        });
+    }
+
+    fn save_group(&mut self, name: String, members: Vec<String>) {
+        self.request(api::Request::SaveGroup { name, members });
+        self.refresh_groups();
+    }
+
+    fn delete_group(&mut self, name: &str) {
+        self.request(api::Request::DeleteGroup(name.to_string()));
+        self.group_controls.remove(name);
+        self.refresh_groups();
+    }
+
+    fn apply_light_preset(&mut self, index: usize, preset: &PresetRecord) {
+        let light = &mut self.lights[index];
+        light.on = true;
+        light.brightness = preset.brightness.unwrap_or(light.brightness);
+        light.kelvin = preset.kelvin.unwrap_or(light.kelvin);
+        let id = light.id.clone();
+        let url = format!("{}/v1/lights/{}", self.api_url, urlencoding::encode(&id));
+        self.queue_update(&format!("preset_{id}"), url, preset.update());
+        self.flush_updates();
+        self.sync_all_state();
+    }
+
+    fn apply_group_preset(&mut self, name: &str, preset: &PresetRecord) {
+        if let Some(c) = self.group_controls.get_mut(name) {
+            c.on = true;
+            c.brightness = preset.brightness.unwrap_or(c.brightness);
+            c.kelvin = preset.kelvin.unwrap_or(c.kelvin);
+        }
+        let url = format!("{}/v1/groups/{}", self.api_url, urlencoding::encode(name));
+        self.queue_update(&format!("gpreset_{}", name), url, preset.update());
+        self.flush_updates();
+    }
+
+    /// Collapse or expand a light's card, remembered across restarts.
+    fn toggle_collapsed(&mut self, id: &str) {
+        let collapsed = &mut self.preferences.collapsed_lights;
+        if !collapsed.remove(id) {
+            collapsed.insert(id.to_string());
+        }
+        if let Err(e) = preferences::save(&self.preferences) {
+            eprintln!("failed to save preferences: {e}");
+        }
+    }
+
+    /// Move the light at `from` to where the one at `to` is, here and on the daemon.
+    fn move_light(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.lights.len() || to >= self.lights.len() {
+            return;
+        }
+        let light = self.lights.remove(from);
+        let id = light.id.clone();
+        self.lights.insert(to, light);
+        self.focused_card = Some(to);
+        self.request(api::Request::SetLightPosition { id, position: to });
+    }
+
+    /// Move the group at `from` to where the one at `to` is, here and on the daemon.
+    fn move_group(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.groups.len() || to >= self.groups.len() {
+            return;
+        }
+        let group = self.groups.remove(from);
+        let name = group.name.clone();
+        self.groups.insert(to, group);
+        self.focused_card = Some(to);
+        self.request(api::Request::SetGroupPosition { name, position: to });
+    }
+
+    fn refresh_scenes(&mut self) {
+        self.request(api::Request::Scenes);
+    }
+
+    fn save_scene(&mut self, name: String) {
+        self.scene_error = None;
+        self.request(api::Request::SaveScene(name));
+        self.refresh_scenes();
+    }
+
+    /// Apply a scene, then read the lights back so the cards show it.
+    fn apply_scene(&mut self, name: &str) {
+        self.scene_error = None;
+        self.request(api::Request::ApplyScene(name.to_string()));
+        self.refresh_light_states();
+    }
+
+    fn rename_scene(&mut self, from: String, to: String) {
+        self.scene_error = None;
+        if let Some(scene) = self.scenes.iter_mut().find(|s| s.name == from) {
+            scene.name = to.clone();
+        }
+        self.request(api::Request::RenameScene { from, to });
+        self.refresh_scenes();
+    }
+
+    fn set_scene_favorite(&mut self, name: &str, favorite: bool) {
+        self.scene_error = None;
+        if let Some(scene) = self.scenes.iter_mut().find(|s| s.name == name) {
+            scene.favorite = favorite;
+        }
+        self.request(api::Request::SetSceneFavorite {
+            name: name.to_string(),
+            favorite,
+        });
+        self.refresh_scenes();
+    }
+
+    fn delete_scene(&mut self, name: &str) {
+        self.scene_error = None;
+        self.scenes.retain(|s| s.name != name);
+        self.request(api::Request::DeleteScene(name.to_string()));
+        self.refresh_scenes();
+    }
+
+    /// Move the scene at `from` to `to` and send the new order.
+    fn move_scene(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.scenes.len() || to >= self.scenes.len() {
+            return;
+        }
+        let scene = self.scenes.remove(from);
+        self.scenes.insert(to, scene);
+        let names = self.scenes.iter().map(|s| s.name.clone()).collect();
+        self.request(api::Request::ReorderScenes(names));
+        self.refresh_scenes();
+    }
+
+    /// The Scenes tab: the "save current" form when open, then a card per scene.
+    /// Cards are dragged by their handle to reorder them.
+    fn scenes_tab(&mut self, ui: &mut egui::Ui, w: f32, theme: &Theme) {
+        if self.modal_state == ModalState::SaveScene {
+            egui::Frame::none()
+                .fill(theme.card)
+                .stroke(egui::Stroke::new(1.0, theme.border))
+                .rounding(6.0)
+                .inner_margin(8.0)
+                .show(ui, |ui| {
+                    ui.set_width(w - 4.0);
+                    ui.horizontal(|ui| {
+                        ui.label(
+                            egui::RichText::new("Save Current as Scene")
+                                .size(12.0)
+                                .strong()
+                                .color(theme.text_primary),
+                        );
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.small_button("×").clicked() {
+                                self.modal_state = ModalState::None;
+                            }
+                        });
+                    });
+                    let field = ui.add(
+                        egui::TextEdit::singleline(&mut self.new_scene_name)
+                            .hint_text("Name")
+                            .desired_width(w - 16.0),
+                    );
+                    let entered =
+                        field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    let can = !self.new_scene_name.trim().is_empty();
+                    ui.add_enabled_ui(can, |ui| {
+                        if ui.small_button("Save").clicked() || (can && entered) {
+                            let name = self.new_scene_name.trim().to_string();
+                            self.save_scene(name);
+                            self.new_scene_name.clear();
+                            self.modal_state = ModalState::None;
+                        }
+                    });
+                });
+            ui.add_space(4.0);
+        }
+
+        if let Some(error) = &self.scene_error {
+            ui.label(
+                egui::RichText::new(error)
+                    .size(10.0)
+                    .color(egui::Color32::from_rgb(0xd9, 0x4a, 0x4a)),
+            );
+            ui.add_space(2.0);
+        }
+
+        let mut apply = None;
+        let mut delete = None;
+        let mut start_rename = None;
+        let mut rename = None;
+        let mut cancel_rename = false;
+        let mut moved = None;
+        let mut starred = None;
+        for (index, scene) in self.scenes.iter().enumerate() {
+            let card = egui::Frame::none()
+                .fill(theme.card)
+                .stroke(egui::Stroke::new(1.0, theme.border))
+                .rounding(6.0)
+                .inner_margin(8.0)
+                .show(ui, |ui| {
+                    ui.set_width(w - 4.0);
+                    ui.horizontal(|ui| {
+                        drag_handle(
+                            ui,
+                            egui::Id::new(("scene", index)),
+                            DraggedScene(index),
+                            theme,
+                        );
+                        match &mut self.renaming_scene {
+                            Some((from, to)) if *from == scene.name => {
+                                let edit =
+                                    ui.add(egui::TextEdit::singleline(to).desired_width(w - 120.0));
+                                let entered = edit.lost_focus()
+                                    && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                if ui.small_button("Save").clicked() || entered {
+                                    rename = Some((from.clone(), to.trim().to_string()));
+                                }
+                                if ui.small_button("Cancel").clicked() {
+                                    cancel_rename = true;
+                                }
+                            }
+                            _ => {
+                                ui.label(
+                                    egui::RichText::new(&scene.name)
+                                        .size(11.0)
+                                        .strong()
+                                        .color(theme.text_primary),
+                                );
+                                let lights = match scene.lights {
+                                    1 => "1 light".to_string(),
+                                    n => format!("{n} lights"),
+                                };
+                                ui.label(
+                                    egui::RichText::new(lights)
+                                        .size(9.0)
+                                        .color(theme.text_secondary),
+                                );
+                                ui.with_layout(
+                                    egui::Layout::right_to_left(egui::Align::Center),
+                                    |ui| {
+                                        if ui.small_button("×").clicked() {
+                                            delete = Some(scene.name.clone());
+                                        }
+                                        if star_button(ui, scene.favorite, theme) {
+                                            starred = Some((scene.name.clone(), !scene.favorite));
+                                        }
+                                        if ui.small_button("✏").on_hover_text("Rename").clicked()
+                                        {
+                                            start_rename = Some(scene.name.clone());
+                                        }
+                                        if ui.small_button("Apply").clicked() {
+                                            apply = Some(scene.name.clone());
+                                        }
+                                    },
+                                );
+                            }
+                        }
+                    });
+                });
+            if let Some(from) = drop_target::<DraggedScene>(ui, &card.response, theme) {
+                moved = Some((from.0, index));
+            }
+            ui.add_space(3.0);
+        }
+
+        if let Some(name) = apply {
+            self.apply_scene(&name);
+        }
+        if let Some(name) = delete {
+            self.delete_scene(&name);
+        }
+        if let Some((name, favorite)) = starred {
+            self.set_scene_favorite(&name, favorite);
+        }
+        if let Some(name) = start_rename {
+            self.renaming_scene = Some((name.clone(), name));
+        }
+        if cancel_rename {
+            self.renaming_scene = None;
+        }
+        if let Some((from, to)) = rename {
+            self.renaming_scene = None;
+            if !to.is_empty() && to != from {
+                self.rename_scene(from, to);
+            }
+        }
+        if let Some((from, to)) = moved {
+            self.move_scene(from, to);
+        }
+
+        if self.scenes.is_empty() && self.modal_state == ModalState::None {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    egui::RichText::new("No scenes. Click + to save the current lights.")
+                        .size(10.0)
+                        .color(theme.text_secondary),
+                );
+            });
+        }
+    }
+
+    fn queue_update(&self, key: &str, url: String, update: UpdateRequest) {
+        self.pending_updates.queue(key.to_string(), url, update);
+    }
+
+    /// Send whatever is queued without waiting (drag released, click).
+    fn flush_updates(&self) {
+        self.pending_updates.flush();
+    }
+
+    fn apply_hotkey_bindings(&mut self) {
+        if let Err(e) = hotkeys::save_bindings(&self.hotkey_bindings) {
+            eprintln!("failed to save shortcuts: {e}");
+        }
+        self.hotkeys.rebind(self.hotkey_bindings.clone());
+    }
+
+    /// (Re)start screen match for the opted-in groups that still exist.
+    fn start_screen_match(&mut self) {
+        self.screen_match = None;
+        let mut settings = self.screen_match_settings.clone();
+        settings
+            .groups
+            .retain(|name| self.groups.iter().any(|g| &g.name == name));
+        if !settings.groups.is_empty() {
+            self.screen_match = Some(screen_match::ScreenMatch::start(
+                Arc::clone(&self.client),
+                &self.api_url,
+                &settings,
+                DEFAULT_KELVIN_RANGE,
+            ));
+        }
+    }
+
+    /// While a binding is recording, take the next key press as its shortcut.
+    fn switch_tab(&mut self, tab: Tab) {
+        self.active_tab = tab;
+        self.modal_state = ModalState::None;
+        self.focused_card = None;
+    }
+
+    /// Shortcuts inside the window, while no text field or slider has the keyboard:
+    /// 1-4 switch tabs, R reloads, / filters, Up/Down move between cards, Space
+    /// switches the focused card's light or group and +/- change its brightness; Esc
+    /// unfocuses.
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        if self.recording_hotkey.is_some() || ctx.wants_keyboard_input() {
+            return;
+        }
+        let pressed = |key| ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key));
+        for (key, tab) in [
+            (egui::Key::Num1, Tab::Lights),
+            (egui::Key::Num2, Tab::Groups),
+            (egui::Key::Num3, Tab::Scenes),
+            (egui::Key::Num4, Tab::Settings),
+        ] {
+            if pressed(key) {
+                self.switch_tab(tab);
+            }
+        }
+        if pressed(egui::Key::R) {
+            self.refresh_all();
+        }
+        if pressed(egui::Key::Slash) && matches!(self.active_tab, Tab::Lights | Tab::Groups) {
+            self.focus_filter = true;
+        }
+        if pressed(egui::Key::Escape) {
+            self.focused_card = None;
+        }
+
+        let cards: Vec<usize> = match self.active_tab {
+            Tab::Lights => self.visible_lights(),
+            Tab::Groups => self.visible_groups(),
+            Tab::Scenes | Tab::Settings => return,
+        };
+        let Some(last) = cards.len().checked_sub(1) else {
+            return;
+        };
+        let at = self
+            .focused_card
+            .and_then(|card| cards.iter().position(|&c| c == card));
+        let moved_to = if pressed(egui::Key::ArrowDown) {
+            Some(at.map_or(0, |at| (at + 1).min(last)))
+        } else if pressed(egui::Key::ArrowUp) {
+            Some(at.map_or(0, |at| at.saturating_sub(1)))
+        } else {
+            at
+        };
+        let Some(index) = moved_to.map(|at| cards[at]) else {
+            return;
+        };
+        if moved_to != at {
+            self.focused_card = Some(index);
+            self.scroll_to_focus = true;
+        }
+
+        let step = if pressed(egui::Key::Plus) || pressed(egui::Key::Equals) {
+            10
+        } else if pressed(egui::Key::Minus) {
+            -10
+        } else {
+            0
+        };
+        let toggle = pressed(egui::Key::Space);
+        if self.active_tab == Tab::Lights {
+            if toggle {
+                self.set_light_on(index, !self.lights[index].on);
+            }
+            if step != 0 {
+                self.step_light_brightness(index, step);
+            }
+        } else {
+            let name = self.groups[index].name.clone();
+            if toggle {
+                let on = self.group_controls.get(&name).is_some_and(|c| c.on);
+                self.set_group_on(&name, !on);
+            }
+            if step != 0 {
+                self.step_group_brightness(&name, step);
+            }
+        }
+        self.flush_updates();
+    }
+
+    /// Outline the focused card and let a click on any part of a card focus it.
+    fn card_focus(&mut self, ui: &egui::Ui, card: &egui::Response, index: usize) {
+        if card.contains_pointer() && ui.input(|i| i.pointer.primary_pressed()) {
+            self.focused_card = Some(index);
+        }
+        if self.focused_card == Some(index) {
+            let stroke = ui.visuals().selection.stroke;
+            ui.painter().rect_stroke(card.rect.expand(1.0), 6.0, stroke);
+            if std::mem::take(&mut self.scroll_to_focus) {
+                card.scroll_to_me(None);
+            }
+        }
+    }
+
+    fn record_hotkey(&mut self, ctx: &egui::Context) {
+        let Some(index) = self.recording_hotkey else {
+            return;
+        };
+        let pressed = ctx.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => Some((*key, *modifiers)),
+                _ => None,
+            })
+        });
+        let Some((key, modifiers)) = pressed else {
+            return;
+        };
+        self.recording_hotkey = None;
+        if key == egui::Key::Escape {
+            return;
+        }
+        if let Some(binding) = self.hotkey_bindings.get_mut(index) {
+            binding.keys = if key == egui::Key::Backspace && !modifiers.any() {
+                String::new()
+            } else {
+                hotkeys::accelerator(modifiers, key)
+            };
+        }
+        self.apply_hotkey_bindings();
+    }
+
+    /// Apply a finished "Match photo" estimate to all enabled lights.
+    fn poll_photo_match(&mut self) {
+        let Some(rx) = &self.photo_match else {
+            return;
+        };
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => Err("photo match failed".into()),
+        };
+        self.photo_match = None;
+        match result {
+            Ok(Some(estimated)) => {
+                let kelvin = estimated.clamp(DEFAULT_KELVIN_RANGE.0, DEFAULT_KELVIN_RANGE.1);
+                self.all_kelvin = kelvin;
+                for l in &mut self.lights {
+                    if l.enabled {
+                        l.kelvin = kelvin;
+                    }
+                }
+                self.queue_update(
+                    "all_k",
+                    self.url_all.clone(),
+                    UpdateRequest {
+                        on: None,
+                        brightness: None,
+                        kelvin: Some(kelvin),
+                        mired: None,
+                    },
+                );
+                self.flush_updates();
+                let format = locale::current();
+                self.photo_match_status = Some(if kelvin == estimated {
+                    format!("Matched photo: {}K", format.integer(kelvin))
+                } else {
+                    format!(
+                        "Photo is {}K; closest supported is {}K",
+                        format.integer(estimated),
+                        format.integer(kelvin)
+                    )
+                });
+            }
+            Ok(None) => self.photo_match_status = None,
+            Err(e) => self.photo_match_status = Some(format!("Match photo: {e}")),
+        }
+    }
+
+    fn poll_diagnostics(&mut self) {
+        let Some(rx) = &self.diagnostics else {
+            return;
+        };
+        let result = match rx.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => Err("export failed".into()),
+        };
+        self.diagnostics = None;
+        self.diagnostics_status = match result {
+            Ok(Some(path)) => Some(format!("Saved to {}", path.display())),
+            Ok(None) => None,
+            Err(e) => Some(format!("Export diagnostics: {e}")),
+        };
+    }
+
+    fn refresh_discovery(&mut self) {
+        self.request(api::Request::Discover);
+        self.refresh_lights();
+        self.refresh_light_states();
+        self.refresh_group_suggestions();
+    }
+
+    /// Show a light setting right away and save it in the background. Changes are
+    /// sent in order; when the latest one fails, the setting goes back to the last
+    /// value the daemon accepted.
+    fn change_light_setting(&mut self, id: &str, setting: api::LightSetting) {
+        let Some(light) = self.lights.iter().find(|l| l.id == id) else {
+            return;
+        };
+        let current = light_setting(light, &setting);
+        self.unsaved_settings
+            .entry((id.to_string(), std::mem::discriminant(&setting)))
+            .or_insert(UnsavedSetting {
+                in_flight: 0,
+                confirmed: current,
+            })
+            .in_flight += 1;
+        self.apply_light_setting(id, &setting);
+        self.request(api::Request::SetLight {
+            id: id.to_string(),
+            setting,
+        });
+    }
+
+    fn light_setting_answered(&mut self, id: String, setting: api::LightSetting, saved: bool) {
+        let key = (id, std::mem::discriminant(&setting));
+        let Some(unsaved) = self.unsaved_settings.get_mut(&key) else {
+            return;
+        };
+        unsaved.in_flight = unsaved.in_flight.saturating_sub(1);
+        if saved {
+            unsaved.confirmed = setting;
+        }
+        if unsaved.in_flight > 0 {
+            return;
+        }
+        let Some(unsaved) = self.unsaved_settings.remove(&key) else {
+            return;
+        };
+        // After a success the light already shows the saved value.
+        if !saved {
+            self.apply_light_setting(&key.0, &unsaved.confirmed);
+        }
+    }
+
+    fn apply_light_setting(&mut self, id: &str, setting: &api::LightSetting) {
+        let Some(light) = self.lights.iter_mut().find(|l| l.id == id) else {
+            return;
+        };
+        match setting {
+            api::LightSetting::Enabled(enabled) => light.enabled = *enabled,
+            api::LightSetting::SoftStart(enabled) => light.soft_start = *enabled,
+            api::LightSetting::Alias(alias) => {
+                light.alias = alias.clone();
+                light.label = alias.clone().unwrap_or_else(|| {
+                    light
+                        .name
+                        .split('.')
+                        .next()
+                        .unwrap_or(&light.name)
+                        .to_string()
+                });
+                self.editing_aliases
+                    .insert(light.id.clone(), light.label.clone());
+            }
+            api::LightSetting::Favorite(favorite) => light.favorite = *favorite,
+            api::LightSetting::Room(room) => {
+                light.room = room.clone();
+                self.editing_rooms
+                    .insert(light.id.clone(), room.clone().unwrap_or_default());
+            }
+        }
+        self.update_tray_lights();
+    }
+
+    /// Enabled lights on the Lights tab, narrowed by the room chips and filter box.
+    fn visible_lights(&self) -> Vec<usize> {
+        (0..self.lights.len())
+            .filter(|&index| {
+                let light = &self.lights[index];
+                light.enabled
+                    && (self.room_filter.is_none() || light.room == self.room_filter)
+                    && light.matches_filter(&self.filter)
+            })
+            .collect()
+    }
+
+    /// Groups whose name, or the label of a member, matches the filter box.
+    fn visible_groups(&self) -> Vec<usize> {
+        (0..self.groups.len())
+            .filter(|&index| {
+                let group = &self.groups[index];
+                contains_ignoring_case(&group.name, &self.filter)
+                    || group.members.iter().any(|member| {
+                        self.lights
+                            .iter()
+                            .any(|l| l.matches(member) && l.matches_filter(&self.filter))
+                    })
+            })
+            .collect()
+    }
+
+    /// Text field narrowing the Lights and Groups tabs, Manage Lights and the new
+    /// group's member list as you type.
+    fn filter_box(&mut self, ui: &mut egui::Ui, w: f32, theme: &Theme) {
+        ui.horizontal(|ui| {
+            let field = ui.add(
+                egui::TextEdit::singleline(&mut self.filter)
+                    .hint_text("Filter by name or room  (/)")
+                    .desired_width(w - 34.0),
+            );
+            if self.focus_filter {
+                field.request_focus();
+                self.focus_filter = false;
+            }
+            if field.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.filter.clear();
+                field.surrender_focus();
+            }
+            if !self.filter.is_empty() && ui.small_button("×").on_hover_text("Clear").clicked() {
+                self.filter.clear();
+            }
+        });
+        if !self.filter.is_empty() {
+            let none = match self.active_tab {
+                Tab::Groups => self.visible_groups().is_empty(),
+                _ => self.visible_lights().is_empty(),
+            };
+            if none {
+                ui.label(
+                    egui::RichText::new(format!("Nothing matches \"{}\".", self.filter))
+                        .size(10.0)
+                        .color(theme.text_secondary),
+                );
+            }
+        }
+        ui.add_space(3.0);
+    }
+
+    /// Starred lights and scenes as chips above the All Lights card: a light's chip
+    /// switches it and shows whether it's on, a scene's applies it.
+    fn favorites_strip(&mut self, ui: &mut egui::Ui, theme: &Theme) {
+        let lights: Vec<usize> = (0..self.lights.len())
+            .filter(|&i| self.lights[i].enabled && self.lights[i].favorite)
+            .collect();
+        let scenes: Vec<String> = self
+            .scenes
+            .iter()
+            .filter(|s| s.favorite)
+            .map(|s| s.name.clone())
+            .collect();
+        if lights.is_empty() && scenes.is_empty() {
+            return;
+        }
+        ui.horizontal_wrapped(|ui| {
+            ui.label(egui::RichText::new("★").size(11.0).color(theme.accent))
+                .on_hover_text("Favorites");
+            for index in lights {
+                let light = &self.lights[index];
+                let label = egui::RichText::new(light.icon_label()).size(10.0);
+                let on = light.on;
+                let chip = ui.selectable_label(on, label).on_hover_text(if on {
+                    "Switch off"
+                } else {
+                    "Switch on"
+                });
+                if chip.clicked() {
+                    self.set_light_on(index, !on);
+                    self.flush_updates();
+                }
+            }
+            for name in scenes {
+                let label = egui::RichText::new(format!("▶ {name}")).size(10.0);
+                if ui.button(label).on_hover_text("Apply scene").clicked() {
+                    self.apply_scene(&name);
+                }
+            }
+        });
+        ui.add_space(3.0);
+    }
+
+    /// Chips narrowing the Lights tab to one room; only shown once a light has one.
+    fn room_picker(&mut self, ui: &mut egui::Ui, theme: &Theme) {
+        let mut rooms: Vec<String> = self
+            .lights
+            .iter()
+            .filter(|l| l.enabled)
+            .filter_map(|l| l.room.clone())
+            .collect();
+        rooms.sort_by_key(|room| room.to_lowercase());
+        rooms.dedup();
+        if rooms.is_empty() {
+            return;
+        }
+        ui.horizontal_wrapped(|ui| {
+            let chip = |ui: &mut egui::Ui, selected: bool, text: &str| {
+                ui.selectable_label(
+                    selected,
+                    egui::RichText::new(text)
+                        .size(10.0)
+                        .color(theme.text_secondary),
+                )
+                .clicked()
+            };
+            if chip(ui, self.room_filter.is_none(), "All rooms") {
+                self.room_filter = None;
+            }
+            for room in rooms {
+                let selected = self.room_filter.as_ref() == Some(&room);
+                if chip(ui, selected, &room) {
+                    self.room_filter = (!selected).then_some(room);
+                }
+            }
+        });
+        ui.add_space(3.0);
+    }
+
+    /// Card for the room the Lights tab is narrowed to, switching and dimming all its
+    /// lights at once; shows whether any is on and their average brightness.
+    fn room_header(&mut self, ui: &mut egui::Ui, room: &str, w: f32, theme: &Theme) {
+        let in_room: Vec<usize> = (0..self.lights.len())
+            .filter(|&i| self.lights[i].enabled && self.lights[i].room.as_deref() == Some(room))
+            .collect();
+        if in_room.is_empty() {
+            return;
+        }
+        let mut on = in_room.iter().any(|&i| self.lights[i].on);
+        let mut b = (in_room
+            .iter()
+            .map(|&i| u32::from(self.lights[i].brightness))
+            .sum::<u32>()
+            / in_room.len() as u32) as u8;
+        let slider_height = if self.preferences.compact {
+            COMPACT_SLIDER_HEIGHT
+        } else {
+            SLIDER_HEIGHT
+        };
+        let power_tex = self.power_icon.clone();
+        let gradient = self.brightness_gradient.clone();
+        let url = format!("{}/v1/rooms/{}", self.api_url, urlencoding::encode(room));
+        egui::Frame::none()
+            .fill(theme.card)
+            .stroke(egui::Stroke::new(1.0, theme.border))
+            .rounding(6.0)
+            .inner_margin(8.0)
+            .show(ui, |ui| {
+                ui.set_width(w - 4.0);
+                ui.horizontal(|ui| {
+                    if power_button(ui, &mut on, 26.0, power_tex.as_ref(), theme) {
+                        for &i in &in_room {
+                            self.lights[i].on = on;
+                        }
+                        self.queue_update(
+                            &format!("room_power_{room}"),
+                            url.clone(),
+                            UpdateRequest {
+                                on: Some(if on { 1 } else { 0 }),
+                                brightness: None,
+                                kelvin: None,
+                                mired: None,
+                            },
+                        );
+                        self.sync_all_state();
+                    }
+                    ui.add_space(4.0);
+                    ui.label(
+                        egui::RichText::new(room)
+                            .size(11.0)
+                            .strong()
+                            .color(theme.text_primary),
+                    );
+                });
+                ui.add_space(2.0);
+                if brightness_slider(
+                    ui,
+                    &mut b,
+                    FULL_BRIGHTNESS,
+                    w - 16.0,
+                    slider_height,
+                    gradient.as_ref(),
+                    theme,
+                ) {
+                    for &i in &in_room {
+                        self.lights[i].brightness = b;
+                    }
+                    self.queue_update(
+                        &format!("room_b_{room}"),
+                        url.clone(),
+                        UpdateRequest {
+                            on: None,
+                            brightness: Some(b),
+                            kelvin: None,
+                            mired: None,
+                        },
+                    );
+                    self.sync_all_state();
+                }
+            });
+        ui.add_space(3.0);
+    }
+
+    /// The icon is shown once the daemon accepted it.
+    fn set_light_icon(&mut self, id: &str, icon: Option<String>) {
+        self.request(api::Request::SetIcon {
+            id: id.to_string(),
+            icon,
+        });
+    }
+}
+
+impl eframe::App for KeylightApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.sync_theme(ctx);
+        self.ensure_textures(ctx);
+        let theme = self.theme;
+
+        // Close to tray; only Quit from the tray menu exits while the icon is up.
+        if ctx.input(|i| i.viewport().close_requested())
+            && self.preferences.close_to_tray
+            && self.tray.is_some()
+            && !self.tray_signals.quit.load(Ordering::Relaxed)
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+
+        if self.tray_signals.refresh.swap(false, Ordering::Relaxed) {
+            self.refresh_light_states();
+        }
+        let picked_scene = self
+            .tray_signals
+            .apply_scene
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .take();
+        if let Some(name) = picked_scene {
+            self.apply_scene(&name);
+        }
+        self.record_hotkey(ctx);
+        self.handle_shortcuts(ctx);
+        self.poll_api();
+        self.poll_photo_match();
+        self.poll_diagnostics();
+        self.expire_timers();
+        if self.connection().take_reconnected() {
+            // Whatever is on screen is from before the daemon went away.
+            self.refresh_all();
+        }
+        if let Some((status, received)) = self.connection().take_status() {
+            self.dnd_until = status
+                .dnd
+                .active
+                .then(|| received + Duration::from_secs(status.dnd.remaining_secs));
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.last_trim.elapsed() >= Duration::from_secs(5) {
+            self.last_trim = Instant::now();
+            unsafe { malloc_trim(0) };
+        }
+
+        // Keep frames coming while a slider is dragged, so queued updates are shown
+        // and sent; otherwise only input, workers and countdowns repaint.
+        if ctx.dragged_id().is_some() {
+            ctx.request_repaint_after(Duration::from_millis(16));
         }
 
         // Header
@@ -669,7 +3311,7 @@ impl eframe::App for KeylightApp {
             .exact_height(40.0)
             .frame(
                 egui::Frame::none()
-                    .fill(colors::BG_CARD)
+                    .fill(theme.card)
                     .inner_margin(egui::Margin::symmetric(8.0, 4.0)),
             )
             .show(ctx, |ui| {
@@ -682,15 +3324,30 @@ impl eframe::App for KeylightApp {
                         egui::RichText::new("LimeLight")
                             .size(14.0)
                             .strong()
-                            .color(colors::TEXT_PRIMARY),
+                            .color(theme.text_primary),
                     );
+                    connection_dot(ui, &self.connection().connection(), &theme);
+                    if self.hosts.len() > 1 {
+                        let connections: Vec<_> =
+                            self.connections.iter().map(|c| c.connection()).collect();
+                        let picked =
+                            host_switcher(ui, &self.hosts, self.active_host, &connections, &theme);
+                        if let Some(index) = picked {
+                            self.switch_host(index);
+                        }
+                    }
+                    if !self.profiles.profiles.is_empty() {
+                        if let Some(choice) = profile_picker(ui, &self.profiles, &theme) {
+                            self.set_profile(choice);
+                        }
+                    }
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         let (rect, response) =
                             ui.allocate_exact_size(egui::Vec2::splat(24.0), egui::Sense::click());
                         let bg = if response.hovered() {
-                            colors::ACCENT_LIGHT
+                            theme.accent_light
                         } else {
-                            colors::ACCENT
+                            theme.accent
                         };
                         ui.painter().rect_filled(rect, 4.0, bg);
                         if let Some(tex) = &self.refresh_icon {
@@ -711,34 +3368,68 @@ impl eframe::App for KeylightApp {
                         if response.clicked() {
                             self.refresh_all();
                         }
+                        ui.add_space(4.0);
+                        if undo_button(ui, &theme).clicked() {
+                            self.undo();
+                        }
+                        let remaining = self
+                            .dnd_until
+                            .map(|until| until.saturating_duration_since(Instant::now()))
+                            .filter(|left| !left.is_zero());
+                        if let Some(left) = remaining {
+                            ui.add_space(4.0);
+                            if dnd_chip(ui, left, &theme).clicked() {
+                                self.end_dnd();
+                            }
+                            ui.ctx().request_repaint_after(Duration::from_secs(1));
+                        }
                     });
                 });
             });
 
+        let connection = self.connection().connection();
+        if connection.connected == Some(false) {
+            egui::TopBottomPanel::top("offline")
+                .frame(
+                    egui::Frame::none()
+                        .fill(egui::Color32::from_rgb(0xd9, 0x4a, 0x4a))
+                        .inner_margin(egui::Margin::symmetric(8.0, 3.0)),
+                )
+                .show(ctx, |ui| {
+                    ui.label(
+                        egui::RichText::new(offline_message(&connection))
+                            .size(10.0)
+                            .color(egui::Color32::WHITE),
+                    );
+                });
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+
         // Tabs
         egui::TopBottomPanel::top("tabs")
             .exact_height(28.0)
             .frame(
                 egui::Frame::none()
-                    .fill(colors::BG_LIGHT)
+                    .fill(theme.bg)
                     .inner_margin(egui::Margin::symmetric(6.0, 2.0)),
             )
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
                     let lights_sel = self.active_tab == Tab::Lights;
                     let groups_sel = self.active_tab == Tab::Groups;
+                    let scenes_sel = self.active_tab == Tab::Scenes;
                     let settings_sel = self.active_tab == Tab::Settings;
                     if ui
                         .add(
                             egui::Button::new(egui::RichText::new("Lights").size(11.0).color(
                                 if lights_sel {
-                                    colors::ACCENT
+                                    theme.accent
                                 } else {
-                                    colors::TEXT_SECONDARY
+                                    theme.text_secondary
                                 },
                             ))
                             .fill(if lights_sel {
-                                colors::BG_CARD
+                                theme.card
                             } else {
                                 egui::Color32::TRANSPARENT
                             })
@@ -747,20 +3438,40 @@ impl eframe::App for KeylightApp {
                         )
                         .clicked()
                     {
-                        self.active_tab = Tab::Lights;
-                        self.modal_state = ModalState::None;
+                        self.switch_tab(Tab::Lights);
                     }
                     if ui
                         .add(
                             egui::Button::new(egui::RichText::new("Groups").size(11.0).color(
                                 if groups_sel {
-                                    colors::ACCENT
+                                    theme.accent
                                 } else {
-                                    colors::TEXT_SECONDARY
+                                    theme.text_secondary
                                 },
                             ))
                             .fill(if groups_sel {
-                                colors::BG_CARD
+                                theme.card
+                            } else {
+                                egui::Color32::TRANSPARENT
+                            })
+                            .rounding(3.0)
+                            .min_size(egui::vec2(50.0, 20.0)),
+                        )
+                        .clicked()
+                    {
+                        self.switch_tab(Tab::Groups);
+                    }
+                    if ui
+                        .add(
+                            egui::Button::new(egui::RichText::new("Scenes").size(11.0).color(
+                                if scenes_sel {
+                                    theme.accent
+                                } else {
+                                    theme.text_secondary
+                                },
+                            ))
+                            .fill(if scenes_sel {
+                                theme.card
                             } else {
                                 egui::Color32::TRANSPARENT
                             })
@@ -769,20 +3480,19 @@ impl eframe::App for KeylightApp {
                         )
                         .clicked()
                     {
-                        self.active_tab = Tab::Groups;
-                        self.modal_state = ModalState::None;
+                        self.switch_tab(Tab::Scenes);
                     }
                     if ui
                         .add(
                             egui::Button::new(egui::RichText::new("Settings").size(11.0).color(
                                 if settings_sel {
-                                    colors::ACCENT
+                                    theme.accent
                                 } else {
-                                    colors::TEXT_SECONDARY
+                                    theme.text_secondary
                                 },
                             ))
                             .fill(if settings_sel {
-                                colors::BG_CARD
+                                theme.card
                             } else {
                                 egui::Color32::TRANSPARENT
                             })
@@ -791,18 +3501,17 @@ impl eframe::App for KeylightApp {
                         )
                         .clicked()
                     {
-                        self.active_tab = Tab::Settings;
-                        self.modal_state = ModalState::None;
+                        self.switch_tab(Tab::Settings);
                     }
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        // Only show + button for Lights and Groups tabs
+                        // Only show + button for Lights, Groups and Scenes tabs
                         if self.active_tab != Tab::Settings {
                             let (rect, response) = ui
                                 .allocate_exact_size(egui::Vec2::splat(22.0), egui::Sense::click());
                             let bg = if response.hovered() {
-                                colors::ACCENT_LIGHT
+                                theme.accent_light
                             } else {
-                                colors::ACCENT
+                                theme.accent
                             };
                             ui.painter().rect_filled(rect, 4.0, bg);
                             let c = rect.center();
@@ -840,6 +3549,14 @@ impl eframe::App for KeylightApp {
                                             ModalState::CreateGroup
                                         }
                                     }
+                                    Tab::Scenes => {
+                                        if self.modal_state == ModalState::SaveScene {
+                                            ModalState::None
+                                        } else {
+                                            self.new_scene_name.clear();
+                                            ModalState::SaveScene
+                                        }
+                                    }
                                     Tab::Settings => ModalState::None,
                                 };
                             }
@@ -852,7 +3569,7 @@ impl eframe::App for KeylightApp {
         egui::CentralPanel::default()
             .frame(
                 egui::Frame::none()
-                    .fill(colors::BG_LIGHT)
+                    .fill(theme.bg)
                     .inner_margin(egui::Margin::same(6.0)),
             )
             .show(ctx, |ui| {
@@ -860,476 +3577,1452 @@ impl eframe::App for KeylightApp {
                 let power_tex = self.power_icon.clone();
                 let bright_grad = self.brightness_gradient.clone();
                 let temp_grad = self.temperature_gradient.clone();
+                let slider_height = if self.preferences.compact {
+                    COMPACT_SLIDER_HEIGHT
+                } else {
+                    SLIDER_HEIGHT
+                };
+
+                if matches!(self.active_tab, Tab::Lights | Tab::Groups) {
+                    self.filter_box(ui, w, &theme);
+                }
+
+                match self.active_tab {
+                    Tab::Lights => {
+                        if self.modal_state == ModalState::Discover {
+                            egui::Frame::none()
+                                .fill(theme.card)
+                                .stroke(egui::Stroke::new(1.0, theme.border))
+                                .rounding(6.0)
+                                .inner_margin(8.0)
+                                .show(ui, |ui| {
+                                    ui.set_width(w - 4.0);
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            egui::RichText::new("Manage Lights")
+                                                .size(12.0)
+                                                .strong()
+                                                .color(theme.text_primary),
+                                        );
+                                        ui.with_layout(
+                                            egui::Layout::right_to_left(egui::Align::Center),
+                                            |ui| {
+                                                if ui.small_button("Done").clicked() {
+                                                    self.modal_state = ModalState::None;
+                                                }
+                                            },
+                                        );
+                                    });
+                                    if ui.small_button("Scan").clicked() {
+                                        self.refresh_discovery();
+                                    }
+                                    let mut pending: Vec<(String, bool)> = Vec::new();
+                                    let mut pending_aliases: Vec<(String, String)> = Vec::new();
+                                    let mut pending_rooms: Vec<(String, String)> = Vec::new();
+                                    let mut pending_soft: Vec<(String, bool)> = Vec::new();
+                                    let mut pending_icons: Vec<(String, Option<String>)> =
+                                        Vec::new();
+                                    for idx in 0..self.lights.len() {
+                                        if !self.lights[idx].matches_filter(&self.filter) {
+                                            continue;
+                                        }
+                                        let id = self.lights[idx].id.clone();
+                                        let mut en = self.lights[idx].enabled;
+                                        let mut soft = self.lights[idx].soft_start;
+                                        let alias = self
+                                            .editing_aliases
+                                            .entry(id.clone())
+                                            .or_insert_with(|| self.lights[idx].label.clone());
+                                        let room =
+                                            self.editing_rooms.entry(id.clone()).or_default();
+                                        ui.horizontal(|ui| {
+                                            if ui.checkbox(&mut en, "").changed() {
+                                                pending.push((id.clone(), en));
+                                            }
+                                            if let Some(icon) = icon_menu(
+                                                ui,
+                                                &id,
+                                                self.lights[idx].icon.as_deref(),
+                                                &mut self.custom_icon,
+                                            ) {
+                                                pending_icons.push((id.clone(), icon));
+                                            }
+                                            let r = ui.add(
+                                                egui::TextEdit::singleline(alias)
+                                                    .desired_width(w - 204.0),
+                                            );
+                                            if r.lost_focus() {
+                                                pending_aliases.push((id.clone(), alias.clone()));
+                                            }
+                                            let r = ui.add(
+                                                egui::TextEdit::singleline(room)
+                                                    .hint_text("Room")
+                                                    .desired_width(72.0),
+                                            );
+                                            if r.lost_focus() {
+                                                pending_rooms.push((id.clone(), room.clone()));
+                                            }
+                                            if ui
+                                                .checkbox(&mut soft, "Soft")
+                                                .on_hover_text("Fade in and out when switched")
+                                                .changed()
+                                            {
+                                                pending_soft.push((id.clone(), soft));
+                                            }
+                                        });
+                                    }
+                                    for (id, en) in pending {
+                                        self.change_light_setting(
+                                            &id,
+                                            api::LightSetting::Enabled(en),
+                                        );
+                                    }
+                                    for (id, soft) in pending_soft {
+                                        self.change_light_setting(
+                                            &id,
+                                            api::LightSetting::SoftStart(soft),
+                                        );
+                                    }
+                                    for (id, icon) in pending_icons {
+                                        self.set_light_icon(&id, icon);
+                                    }
+                                    for (id, room) in pending_rooms {
+                                        let room = room.trim();
+                                        self.change_light_setting(
+                                            &id,
+                                            api::LightSetting::Room(
+                                                (!room.is_empty()).then(|| room.to_string()),
+                                            ),
+                                        );
+                                    }
+                                    for (id, al) in pending_aliases {
+                                        let al = al.trim();
+                                        self.change_light_setting(
+                                            &id,
+                                            api::LightSetting::Alias(
+                                                (!al.is_empty()).then(|| al.to_string()),
+                                            ),
+                                        );
+                                    }
+                                });
+                            ui.add_space(4.0);
+                        }
+
+                        self.favorites_strip(ui, &theme);
+
+                        // All lights
+                        egui::Frame::none()
+                            .fill(theme.card)
+                            .stroke(egui::Stroke::new(1.0, theme.border))
+                            .rounding(6.0)
+                            .inner_margin(8.0)
+                            .show(ui, |ui| {
+                                ui.set_width(w - 4.0);
+                                ui.horizontal(|ui| {
+                                    if power_button(
+                                        ui,
+                                        &mut self.all_on,
+                                        26.0,
+                                        power_tex.as_ref(),
+                                        &theme,
+                                    ) {
+                                        let state = self.all_on;
+                                        for l in &mut self.lights {
+                                            if l.enabled {
+                                                l.on = state;
+                                            }
+                                        }
+                                        self.queue_update(
+                                            "all_power",
+                                            self.url_all.clone(),
+                                            UpdateRequest {
+                                                on: Some(if state { 1 } else { 0 }),
+                                                brightness: None,
+                                                kelvin: None,
+                                                mired: None,
+                                            },
+                                        );
+                                    }
+                                    ui.add_space(4.0);
+                                    ui.label(
+                                        egui::RichText::new("All Lights")
+                                            .size(11.0)
+                                            .strong()
+                                            .color(theme.text_primary),
+                                    );
+                                    ui.with_layout(
+                                        egui::Layout::right_to_left(egui::Align::Center),
+                                        |ui| {
+                                            let busy = self.photo_match.is_some();
+                                            let button = ui
+                                                .add_enabled(
+                                                    !busy,
+                                                    egui::Button::new(
+                                                        egui::RichText::new("Match photo…")
+                                                            .size(10.0),
+                                                    )
+                                                    .small(),
+                                                )
+                                                .on_hover_text(
+                                                    "Set all lights to a reference photo's color temperature",
+                                                );
+                                            if button.clicked() {
+                                                self.photo_match_status = None;
+                                                self.photo_match =
+                                                    Some(photo_match::spawn(ui.ctx().clone()));
+                                            }
+                                        },
+                                    );
+                                });
+                                if let Some(status) = &self.photo_match_status {
+                                    ui.label(
+                                        egui::RichText::new(status)
+                                            .size(9.0)
+                                            .color(theme.text_secondary),
+                                    );
+                                }
+                                ui.add_space(2.0);
+                                let mut b = self.all_brightness;
+                                let mut k = self.all_kelvin;
+                                let (b_changed, k_changed) = slider_pair(
+                                    ui,
+                                    w,
+                                    |ui, sw| {
+                                        brightness_slider(
+                                            ui,
+                                            &mut b,
+                                            FULL_BRIGHTNESS,
+                                            sw,
+                                            slider_height,
+                                            bright_grad.as_ref(),
+                                            &theme,
+                                        )
+                                    },
+                                    |ui, sw| {
+                                        temperature_slider(
+                                            ui,
+                                            &mut k,
+                                            DEFAULT_KELVIN_RANGE,
+                                            sw,
+                                            slider_height,
+                                            temp_grad.as_ref(),
+                                            &theme,
+                                        )
+                                    },
+                                );
+                                if b_changed {
+                                    self.all_brightness = b;
+                                    for l in &mut self.lights {
+                                        if l.enabled {
+                                            l.brightness = b;
+                                        }
+                                    }
+                                    self.queue_update(
+                                        "all_b",
+                                        self.url_all.clone(),
+                                        UpdateRequest {
+                                            on: None,
+                                            brightness: Some(b),
+                                            kelvin: None,
+                                            mired: None,
+                                        },
+                                    );
+                                }
+                                if k_changed {
+                                    self.all_kelvin = k;
+                                    for l in &mut self.lights {
+                                        if l.enabled {
+                                            l.kelvin = k;
+                                        }
+                                    }
+                                    self.queue_update(
+                                        "all_k",
+                                        self.url_all.clone(),
+                                        UpdateRequest {
+                                            on: None,
+                                            brightness: None,
+                                            kelvin: Some(k),
+                                            mired: None,
+                                        },
+                                    );
+                                }
+                            });
+                        ui.add_space(3.0);
+
+                        self.room_picker(ui, &theme);
+                        if let Some(room) = self.room_filter.clone() {
+                            self.room_header(ui, &room, w, &theme);
+                        }
+
+                        // Individual lights, two columns when the window is wide enough
+                        let (columns, card_w) = card_columns(w);
+                        let visible = self.visible_lights();
+                        // Applied after the loop so indices stay valid while drawing.
+                        let mut moved_light = None;
+                        for row in visible.chunks(columns) {
+                            ui.horizontal_top(|ui| {
+                                for &index in row {
+                                    ui.vertical(|ui| {
+                                        let id = self.lights[index].id.clone();
+                                        let label = self.lights[index].icon_label();
+                                        let mut on = self.lights[index].on;
+                                        let mut b = self.lights[index].brightness;
+                                        let mut k = self.lights[index].kelvin;
+                                        let collapsed =
+                                            self.preferences.collapsed_lights.contains(&id);
+
+                                        let card = egui::Frame::none()
+                                            .fill(theme.card)
+                                            .stroke(egui::Stroke::new(1.0, theme.border))
+                                            .rounding(6.0)
+                                            .inner_margin(8.0)
+                                            .show(ui, |ui| {
+                                                ui.set_width(card_w - 4.0);
+                                                ui.horizontal(|ui| {
+                                                    drag_handle(
+                                                        ui,
+                                                        egui::Id::new(("light", &id)),
+                                                        DraggedLight(index),
+                                                        &theme,
+                                                    );
+                                                    if power_button(
+                                                        ui,
+                                                        &mut on,
+                                                        26.0,
+                                                        power_tex.as_ref(),
+                                                        &theme,
+                                                    ) {
+                                                        self.set_light_on(index, on);
+                                                    }
+                                                    ui.add_space(4.0);
+                                                    ui.label(highlighted(
+                                                        &label,
+                                                        &self.filter,
+                                                        11.0,
+                                                        theme.text_primary,
+                                                        &theme,
+                                                    ));
+                                                    let timer = self
+                                                        .next_timer(&self.lights[index])
+                                                        .map(|(timer, left)| {
+                                                            (timer.clone(), left)
+                                                        });
+                                                    if let Some((timer, left)) = timer {
+                                                        ui.add_space(4.0);
+                                                        if timer_chip(ui, &timer, left, &theme)
+                                                            .clicked()
+                                                        {
+                                                            self.cancel_timer(timer.id);
+                                                        }
+                                                        ui.ctx().request_repaint_after(
+                                                            Duration::from_secs(1),
+                                                        );
+                                                    }
+                                                    if self.lights[index].has_schedule {
+                                                        let light = &self.lights[index];
+                                                        let request = schedule_menu(
+                                                            ui,
+                                                            &id,
+                                                            light.schedule_paused,
+                                                            light.schedule_skip_next,
+                                                            &theme,
+                                                        );
+                                                        if let Some((paused, skip)) = request {
+                                                            self.set_schedule_override(
+                                                                &id,
+                                                                paused,
+                                                                skip,
+                                                            );
+                                                        }
+                                                    }
+                                                    ui.with_layout(
+                                                        egui::Layout::right_to_left(
+                                                            egui::Align::Center,
+                                                        ),
+                                                        |ui| {
+                                                            if collapse_toggle(
+                                                                ui, collapsed, &theme,
+                                                            ) {
+                                                                self.toggle_collapsed(&id);
+                                                            }
+                                                            let favorite =
+                                                                self.lights[index].favorite;
+                                                            if star_button(ui, favorite, &theme) {
+                                                                self.change_light_setting(
+                                                                    &id,
+                                                                    api::LightSetting::Favorite(
+                                                                        !favorite,
+                                                                    ),
+                                                                );
+                                                            }
+                                                            if collapsed {
+                                                                let summary = if on {
+                                                                    format!("{b}% · {k}K")
+                                                                } else {
+                                                                    "off".to_string()
+                                                                };
+                                                                ui.label(
+                                                                    egui::RichText::new(summary)
+                                                                        .size(9.0)
+                                                                        .color(
+                                                                            theme.text_secondary,
+                                                                        ),
+                                                                );
+                                                            }
+                                                        },
+                                                    );
+                                                });
+                                                if let Some(room) = &self.lights[index].room {
+                                                    let subtitle = ui.label(
+                                                        egui::RichText::new(room)
+                                                            .size(9.0)
+                                                            .color(theme.text_secondary),
+                                                    );
+                                                    if let Some(notes) = &self.lights[index].notes {
+                                                        subtitle.on_hover_text(notes);
+                                                    }
+                                                }
+                                                if self.lights[index].restart_required
+                                                    && restart_notice(ui)
+                                                {
+                                                    self.reset_light(&id);
+                                                }
+                                                if collapsed {
+                                                    return;
+                                                }
+                                                ui.add_space(2.0);
+                                                let capabilities = self.lights[index].capabilities;
+                                                let kelvin_range = self.lights[index].kelvin_range;
+                                                let brightness_limits =
+                                                    self.lights[index].brightness_limits;
+                                                let (b_changed, k_changed) = slider_pair(
+                                                    ui,
+                                                    card_w,
+                                                    |ui, sw| {
+                                                        capabilities.brightness
+                                                            && brightness_slider(
+                                                                ui,
+                                                                &mut b,
+                                                                brightness_limits,
+                                                                sw,
+                                                                slider_height,
+                                                                bright_grad.as_ref(),
+                                                                &theme,
+                                                            )
+                                                    },
+                                                    |ui, sw| {
+                                                        capabilities.temperature
+                                                            && temperature_slider(
+                                                                ui,
+                                                                &mut k,
+                                                                kelvin_range,
+                                                                sw,
+                                                                slider_height,
+                                                                temp_grad.as_ref(),
+                                                                &theme,
+                                                            )
+                                                    },
+                                                );
+                                                if b_changed {
+                                                    self.lights[index].brightness = b;
+                                                    let url = format!(
+                                                        "{}/v1/lights/{}",
+                                                        self.api_url,
+                                                        urlencoding::encode(&id)
+                                                    );
+                                                    self.queue_update(
+                                                        &format!("b_{}", id),
+                                                        url,
+                                                        UpdateRequest {
+                                                            on: None,
+                                                            brightness: Some(b),
+                                                            kelvin: None,
+                                                            mired: None,
+                                                        },
+                                                    );
+                                                }
+                                                if k_changed {
+                                                    self.lights[index].kelvin = k;
+                                                    let url = format!(
+                                                        "{}/v1/lights/{}",
+                                                        self.api_url,
+                                                        urlencoding::encode(&id)
+                                                    );
+                                                    self.queue_update(
+                                                        &format!("k_{}", id),
+                                                        url,
+                                                        UpdateRequest {
+                                                            on: None,
+                                                            brightness: None,
+                                                            kelvin: Some(k),
+                                                            mired: None,
+                                                        },
+                                                    );
+                                                }
+                                                if let Some(preset) =
+                                                    preset_chips(ui, &self.presets, &theme)
+                                                {
+                                                    self.apply_light_preset(index, &preset);
+                                                }
+                                            });
+                                        self.card_focus(ui, &card.response, index);
+                                        if let Some(from) =
+                                            drop_target::<DraggedLight>(ui, &card.response, &theme)
+                                        {
+                                            moved_light = Some((from.0, index));
+                                        }
+                                    });
+                                }
+                            });
+                            ui.add_space(3.0);
+                        }
+                        if let Some((from, to)) = moved_light {
+                            self.move_light(from, to);
+                        }
+
+                        if self.lights.iter().filter(|l| l.enabled).count() == 0
+                            && self.modal_state == ModalState::None
+                        {
+                            ui.vertical_centered(|ui| {
+                                ui.label(
+                                    egui::RichText::new("No lights. Click + to discover.")
+                                        .size(10.0)
+                                        .color(theme.text_secondary),
+                                );
+                            });
+                        }
+                    }
 
-                match self.active_tab {
-                    Tab::Lights => {
-                        if self.modal_state == ModalState::Discover {
+                    Tab::Groups => {
+                        if self.modal_state == ModalState::CreateGroup {
                             egui::Frame::none()
-                                .fill(colors::BG_CARD)
-                                .stroke(egui::Stroke::new(1.0, colors::BORDER))
+                                .fill(theme.card)
+                                .stroke(egui::Stroke::new(1.0, theme.border))
                                 .rounding(6.0)
                                 .inner_margin(8.0)
                                 .show(ui, |ui| {
                                     ui.set_width(w - 4.0);
                                     ui.horizontal(|ui| {
                                         ui.label(
-                                            egui::RichText::new("Manage Lights")
+                                            egui::RichText::new("Create Group")
                                                 .size(12.0)
                                                 .strong()
-                                                .color(colors::TEXT_PRIMARY),
+                                                .color(theme.text_primary),
                                         );
                                         ui.with_layout(
                                             egui::Layout::right_to_left(egui::Align::Center),
                                             |ui| {
-                                                if ui.small_button("Done").clicked() {
+                                                if ui.small_button("×").clicked() {
                                                     self.modal_state = ModalState::None;
                                                 }
                                             },
                                         );
                                     });
-                                    if ui.small_button("Scan").clicked() {
-                                        self.refresh_discovery();
-                                    }
-                                    let mut pending: Vec<(String, bool)> = Vec::new();
-                                    let mut pending_aliases: Vec<(String, String)> = Vec::new();
-                                    for idx in 0..self.lights.len() {
-                                        let id = self.lights[idx].id.clone();
-                                        let mut en = self.lights[idx].enabled;
-                                        let alias = self
-                                            .editing_aliases
-                                            .entry(id.clone())
-                                            .or_insert_with(|| self.lights[idx].label.clone());
+                                    let mut accepted = None;
+                                    for suggestion in &self.group_suggestions {
                                         ui.horizontal(|ui| {
-                                            if ui.checkbox(&mut en, "").changed() {
-                                                self.lights[idx].enabled = en;
-                                                pending.push((id.clone(), en));
+                                            if ui.small_button("Create").clicked() {
+                                                accepted = Some(suggestion.clone());
                                             }
-                                            let r = ui.add(
-                                                egui::TextEdit::singleline(alias)
-                                                    .desired_width(w - 40.0),
+                                            ui.label(
+                                                egui::RichText::new(&suggestion.name)
+                                                    .size(10.0)
+                                                    .strong()
+                                                    .color(theme.text_primary),
+                                            );
+                                            ui.label(
+                                                egui::RichText::new(suggestion.aliases.join(", "))
+                                                    .size(9.0)
+                                                    .color(theme.text_secondary),
                                             );
-                                            if r.lost_focus() {
-                                                pending_aliases.push((id.clone(), alias.clone()));
-                                            }
                                         });
                                     }
-                                    for (id, en) in pending {
-                                        self.set_light_enabled(&id, en);
+                                    // Stay open: the other suggestions may be wanted too.
+                                    if let Some(suggestion) = accepted {
+                                        self.save_group(suggestion.name, suggestion.members);
                                     }
-                                    for (id, al) in pending_aliases {
-                                        self.set_light_alias(&id, &al);
+                                    if !self.group_suggestions.is_empty() {
+                                        ui.separator();
+                                    }
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut self.new_group_name)
+                                            .hint_text("Name")
+                                            .desired_width(w - 16.0),
+                                    );
+                                    for light in &self.lights {
+                                        if !light.matches_filter(&self.filter) {
+                                            continue;
+                                        }
+                                        let mut sel = self.new_group_members.contains(&light.id);
+                                        let label = highlighted(
+                                            &light.label,
+                                            &self.filter,
+                                            12.0,
+                                            theme.text_primary,
+                                            &theme,
+                                        );
+                                        if ui.checkbox(&mut sel, label).changed() {
+                                            if sel {
+                                                self.new_group_members.insert(light.id.clone());
+                                            } else {
+                                                self.new_group_members.remove(&light.id);
+                                            }
+                                        }
                                     }
+                                    let can = !self.new_group_name.trim().is_empty()
+                                        && !self.new_group_members.is_empty();
+                                    ui.add_enabled_ui(can, |ui| {
+                                        if ui.small_button("Save").clicked() {
+                                            let name = self.new_group_name.trim().to_string();
+                                            let members: Vec<_> =
+                                                self.new_group_members.iter().cloned().collect();
+                                            self.save_group(name, members);
+                                            self.new_group_name.clear();
+                                            self.new_group_members.clear();
+                                            self.modal_state = ModalState::None;
+                                        }
+                                    });
                                 });
                             ui.add_space(4.0);
                         }
 
-                        // All lights
+                        let (columns, card_w) = card_columns(w);
+                        let indices = self.visible_groups();
+                        let mut moved_group = None;
+                        for row in indices.chunks(columns) {
+                            ui.horizontal_top(|ui| {
+                                for &gi in row {
+                                    // Deleting a group reloads the list mid-row.
+                                    if gi >= self.groups.len() {
+                                        break;
+                                    }
+                                    ui.vertical(|ui| {
+                                        let name = self.groups[gi].name.clone();
+                                        let members = self.groups[gi].members.clone();
+                                        let member_count = members.len();
+                                        if !self.group_controls.contains_key(&name) {
+                                            self.group_controls.insert(
+                                                name.clone(),
+                                                GroupControl {
+                                                    on: true,
+                                                    brightness: 50,
+                                                    kelvin: 4500,
+                                                },
+                                            );
+                                        }
+                                        let ctrl = self
+                                            .group_controls
+                                            .get_mut(&name)
+                                            .expect("group_controls missing entry for group");
+                                        let mut on = ctrl.on;
+                                        let mut b = ctrl.brightness;
+                                        let mut k = ctrl.kelvin;
+
+                                        let card = egui::Frame::none()
+                                            .fill(theme.card)
+                                            .stroke(egui::Stroke::new(1.0, theme.border))
+                                            .rounding(6.0)
+                                            .inner_margin(8.0)
+                                            .show(ui, |ui| {
+                                                ui.set_width(card_w - 4.0);
+                                                ui.horizontal(|ui| {
+                                                    drag_handle(
+                                                        ui,
+                                                        egui::Id::new(("group", &name)),
+                                                        DraggedGroup(gi),
+                                                        &theme,
+                                                    );
+                                                    if power_button(
+                                                        ui,
+                                                        &mut on,
+                                                        26.0,
+                                                        power_tex.as_ref(),
+                                                        &theme,
+                                                    ) {
+                                                        self.set_group_on(&name, on);
+                                                    }
+                                                    ui.add_space(4.0);
+                                                    ui.label(highlighted(
+                                                        &name,
+                                                        &self.filter,
+                                                        11.0,
+                                                        theme.text_primary,
+                                                        &theme,
+                                                    ));
+                                                    let expanded =
+                                                        self.expanded_groups.contains(&name);
+                                                    let arrow = if expanded { "▾" } else { "▸" };
+                                                    let count = ui
+                                                        .add(
+                                                            egui::Button::new(
+                                                                egui::RichText::new(format!(
+                                                                    "{arrow} {member_count}"
+                                                                ))
+                                                                .size(9.0)
+                                                                .color(theme.text_secondary),
+                                                            )
+                                                            .frame(false),
+                                                        )
+                                                        .on_hover_text(if expanded {
+                                                            "Hide members"
+                                                        } else {
+                                                            "Show members"
+                                                        });
+                                                    if count.clicked() {
+                                                        if expanded {
+                                                            self.expanded_groups.remove(&name);
+                                                        } else {
+                                                            self.expanded_groups
+                                                                .insert(name.clone());
+                                                        }
+                                                    }
+                                                    ui.with_layout(
+                                                        egui::Layout::right_to_left(
+                                                            egui::Align::Center,
+                                                        ),
+                                                        |ui| {
+                                                            if ui.small_button("×").clicked() {
+                                                                self.delete_group(&name);
+                                                            }
+                                                        },
+                                                    );
+                                                });
+                                                ui.add_space(2.0);
+                                                let (b_changed, k_changed) = slider_pair(
+                                                    ui,
+                                                    card_w,
+                                                    |ui, sw| {
+                                                        brightness_slider(
+                                                            ui,
+                                                            &mut b,
+                                                            FULL_BRIGHTNESS,
+                                                            sw,
+                                                            slider_height,
+                                                            bright_grad.as_ref(),
+                                                            &theme,
+                                                        )
+                                                    },
+                                                    |ui, sw| {
+                                                        temperature_slider(
+                                                            ui,
+                                                            &mut k,
+                                                            DEFAULT_KELVIN_RANGE,
+                                                            sw,
+                                                            slider_height,
+                                                            temp_grad.as_ref(),
+                                                            &theme,
+                                                        )
+                                                    },
+                                                );
+                                                if b_changed {
+                                                    if let Some(c) =
+                                                        self.group_controls.get_mut(&name)
+                                                    {
+                                                        c.brightness = b;
+                                                    }
+                                                    let url = format!(
+                                                        "{}/v1/groups/{}",
+                                                        self.api_url,
+                                                        urlencoding::encode(&name)
+                                                    );
+                                                    self.queue_update(
+                                                        &format!("gb_{}", name),
+                                                        url,
+                                                        UpdateRequest {
+                                                            on: None,
+                                                            brightness: Some(b),
+                                                            kelvin: None,
+                                                            mired: None,
+                                                        },
+                                                    );
+                                                }
+                                                if k_changed {
+                                                    if let Some(c) =
+                                                        self.group_controls.get_mut(&name)
+                                                    {
+                                                        c.kelvin = k;
+                                                    }
+                                                    let url = format!(
+                                                        "{}/v1/groups/{}",
+                                                        self.api_url,
+                                                        urlencoding::encode(&name)
+                                                    );
+                                                    self.queue_update(
+                                                        &format!("gk_{}", name),
+                                                        url,
+                                                        UpdateRequest {
+                                                            on: None,
+                                                            brightness: None,
+                                                            kelvin: Some(k),
+                                                            mired: None,
+                                                        },
+                                                    );
+                                                }
+                                                if let Some(preset) =
+                                                    preset_chips(ui, &self.presets, &theme)
+                                                {
+                                                    self.apply_group_preset(&name, &preset);
+                                                }
+                                                if self.expanded_groups.contains(&name) {
+                                                    ui.separator();
+                                                    self.group_members(
+                                                        ui,
+                                                        &members,
+                                                        power_tex.as_ref(),
+                                                        &theme,
+                                                    );
+                                                }
+                                            });
+                                        self.card_focus(ui, &card.response, gi);
+                                        if let Some(from) =
+                                            drop_target::<DraggedGroup>(ui, &card.response, &theme)
+                                        {
+                                            moved_group = Some((from.0, gi));
+                                        }
+                                    });
+                                }
+                            });
+                            ui.add_space(3.0);
+                        }
+                        if let Some((from, to)) = moved_group {
+                            self.move_group(from, to);
+                        }
+
+                        if self.groups.is_empty() && self.modal_state == ModalState::None {
+                            ui.vertical_centered(|ui| {
+                                ui.label(
+                                    egui::RichText::new("No groups. Click + to create.")
+                                        .size(10.0)
+                                        .color(theme.text_secondary),
+                                );
+                            });
+                        }
+                    }
+
+                    Tab::Scenes => self.scenes_tab(ui, w, &theme),
+
+                    Tab::Settings => {
                         egui::Frame::none()
-                            .fill(colors::BG_CARD)
-                            .stroke(egui::Stroke::new(1.0, colors::BORDER))
+                            .fill(theme.card)
+                            .stroke(egui::Stroke::new(1.0, theme.border))
                             .rounding(6.0)
-                            .inner_margin(8.0)
+                            .inner_margin(12.0)
                             .show(ui, |ui| {
                                 ui.set_width(w - 4.0);
+                                ui.label(
+                                    egui::RichText::new("Settings")
+                                        .size(13.0)
+                                        .strong()
+                                        .color(theme.text_primary),
+                                );
+                                ui.add_space(8.0);
+
+                                // Autostart toggle
                                 ui.horizontal(|ui| {
-                                    if power_button(ui, &mut self.all_on, 26.0, power_tex.as_ref())
+                                    let mut autostart = self.autostart_enabled;
+                                    if ui.checkbox(&mut autostart, "").changed()
+                                        && set_autostart(autostart).is_ok()
                                     {
-                                        let state = self.all_on;
-                                        for l in &mut self.lights {
-                                            if l.enabled {
-                                                l.on = state;
-                                            }
-                                        }
-                                        self.queue_update(
-                                            "all_power",
-                                            self.url_all.clone(),
-                                            UpdateRequest {
-                                                on: Some(if state { 1 } else { 0 }),
-                                                brightness: None,
-                                                kelvin: None,
-                                                mired: None,
-                                            },
-                                        );
+                                        self.autostart_enabled = autostart;
                                     }
-                                    ui.add_space(4.0);
                                     ui.label(
-                                        egui::RichText::new("All Lights")
+                                        egui::RichText::new("Start on login")
                                             .size(11.0)
-                                            .strong()
-                                            .color(colors::TEXT_PRIMARY),
+                                            .color(theme.text_primary),
                                     );
                                 });
-                                ui.add_space(2.0);
-                                let sw = w - 16.0;
-                                let mut b = self.all_brightness;
-                                let mut k = self.all_kelvin;
-                                if brightness_slider(ui, &mut b, sw, bright_grad.as_ref()) {
-                                    self.all_brightness = b;
-                                    for l in &mut self.lights {
-                                        if l.enabled {
-                                            l.brightness = b;
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Launch LimeLight automatically when you log in",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(6.0);
+
+                                ui.horizontal(|ui| {
+                                    let mut settings = self.daemon_settings;
+                                    if ui.checkbox(&mut settings.stop_on_quit, "").changed()
+                                        && save_daemon_settings(settings).is_ok()
+                                    {
+                                        self.daemon_settings = settings;
+                                    }
+                                    ui.label(
+                                        egui::RichText::new("Stop keylightd on quit")
+                                            .size(11.0)
+                                            .color(theme.text_primary),
+                                    );
+                                });
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Untick to leave the daemon running for scripts and \
+                                         plugins after LimeLight quits",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(6.0);
+
+                                ui.horizontal(|ui| {
+                                    let mut preferences = self.preferences.clone();
+                                    if ui.checkbox(&mut preferences.close_to_tray, "").changed()
+                                        && preferences::save(&preferences).is_ok()
+                                    {
+                                        self.preferences = preferences;
+                                    }
+                                    ui.label(
+                                        egui::RichText::new("Close to tray")
+                                            .size(11.0)
+                                            .color(theme.text_primary),
+                                    );
+                                });
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Closing the window hides it while the tray icon is up; \
+                                         untick to quit instead",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(6.0);
+
+                                ui.horizontal(|ui| {
+                                    ui.label(
+                                        egui::RichText::new("Check the daemon every")
+                                            .size(11.0)
+                                            .color(theme.text_primary),
+                                    );
+                                    let slider = egui::Slider::new(
+                                        &mut self.preferences.poll_interval_secs,
+                                        preferences::MIN_POLL_SECS..=preferences::MAX_POLL_SECS,
+                                    )
+                                    .suffix("s");
+                                    let response = ui.add(slider);
+                                    if response.changed() {
+                                        let poll = self.preferences.poll_interval();
+                                        for connection in &self.connections {
+                                            connection.set_poll_interval(poll);
                                         }
                                     }
-                                    self.queue_update(
-                                        "all_b",
-                                        self.url_all.clone(),
-                                        UpdateRequest {
-                                            on: None,
-                                            brightness: Some(b),
-                                            kelvin: None,
-                                            mired: None,
-                                        },
+                                    // Write once a drag ends rather than on every step.
+                                    if response.drag_stopped()
+                                        || (response.changed() && !response.dragged())
+                                    {
+                                        if let Err(e) = preferences::save(&self.preferences) {
+                                            eprintln!("failed to save preferences: {e}");
+                                        }
+                                    }
+                                });
+                                ui.add_space(6.0);
+
+                                ui.horizontal(|ui| {
+                                    let mut preferences = self.preferences.clone();
+                                    if ui.checkbox(&mut preferences.compact, "").changed()
+                                        && preferences::save(&preferences).is_ok()
+                                    {
+                                        self.preferences = preferences;
+                                    }
+                                    ui.label(
+                                        egui::RichText::new("Compact mode")
+                                            .size(11.0)
+                                            .color(theme.text_primary),
+                                    );
+                                });
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Thinner sliders, so more lights fit without scrolling",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(6.0);
+
+                                ui.label(
+                                    egui::RichText::new("Notifications")
+                                        .size(11.0)
+                                        .color(theme.text_primary),
+                                );
+                                let mut preferences = self.preferences.clone();
+                                let automations = ui
+                                    .checkbox(
+                                        &mut preferences.notify_automations,
+                                        egui::RichText::new(
+                                            "When a call or schedule changes the lights",
+                                        )
+                                        .size(10.0),
+                                    )
+                                    .changed();
+                                let errors = ui
+                                    .checkbox(
+                                        &mut preferences.notify_errors,
+                                        egui::RichText::new("When a light stops answering")
+                                            .size(10.0),
+                                    )
+                                    .changed();
+                                let changed = automations || errors;
+                                if changed && preferences::save(&preferences).is_ok() {
+                                    self.notifier.set_categories(
+                                        preferences.notify_automations,
+                                        preferences.notify_errors,
                                     );
+                                    self.preferences = preferences;
                                 }
-                                ui.add_space(1.0);
-                                if temperature_slider(ui, &mut k, sw, temp_grad.as_ref()) {
-                                    self.all_kelvin = k;
-                                    for l in &mut self.lights {
-                                        if l.enabled {
-                                            l.kelvin = k;
+
+                                ui.add_space(12.0);
+                                ui.separator();
+                                ui.add_space(8.0);
+
+                                // Theme
+                                ui.label(
+                                    egui::RichText::new("Appearance")
+                                        .size(11.0)
+                                        .strong()
+                                        .color(theme.text_primary),
+                                );
+                                ui.add_space(4.0);
+                                ui.horizontal(|ui| {
+                                    for preference in ThemePreference::ALL {
+                                        if ui
+                                            .selectable_label(
+                                                self.theme_preference == preference,
+                                                egui::RichText::new(preference.label()).size(10.0),
+                                            )
+                                            .clicked()
+                                            && self.theme_preference != preference
+                                        {
+                                            self.theme_preference = preference;
+                                            if let Err(e) = theme::save_preference(preference) {
+                                                eprintln!("failed to save theme: {e}");
+                                            }
+                                        }
+                                    }
+                                });
+
+                                ui.add_space(12.0);
+                                ui.separator();
+                                ui.add_space(8.0);
+
+                                // Ambient mode
+                                ui.label(
+                                    egui::RichText::new("Ambient mode")
+                                        .size(11.0)
+                                        .strong()
+                                        .color(theme.text_primary),
+                                );
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Tint a Light Strip to the average color of your screen",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(4.0);
+                                let strips: Vec<(String, String)> = self
+                                    .lights
+                                    .iter()
+                                    .filter(|l| l.capabilities.color && l.enabled)
+                                    .map(|l| (l.id.clone(), l.label.clone()))
+                                    .collect();
+                                if strips.is_empty() {
+                                    ui.label(
+                                        egui::RichText::new("No color-capable lights found")
+                                            .size(10.0)
+                                            .color(theme.text_secondary),
+                                    );
+                                } else {
+                                    if self
+                                        .ambient_light
+                                        .as_ref()
+                                        .is_none_or(|id| !strips.iter().any(|(s, _)| s == id))
+                                    {
+                                        self.ambient_light = Some(strips[0].0.clone());
+                                    }
+                                    let mut restart = false;
+                                    ui.horizontal(|ui| {
+                                        let mut enabled = self.ambient.is_some();
+                                        if ui.checkbox(&mut enabled, "").changed() {
+                                            if enabled {
+                                                restart = true;
+                                            } else {
+                                                self.ambient = None;
+                                            }
+                                        }
+                                        let selected = strips
+                                            .iter()
+                                            .find(|(id, _)| Some(id) == self.ambient_light.as_ref())
+                                            .map(|(_, label)| label.as_str())
+                                            .unwrap_or_default();
+                                        egui::ComboBox::from_id_salt("ambient_light")
+                                            .selected_text(selected)
+                                            .show_ui(ui, |ui| {
+                                                for (id, label) in &strips {
+                                                    if ui
+                                                        .selectable_label(
+                                                            self.ambient_light.as_ref() == Some(id),
+                                                            label,
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        self.ambient_light = Some(id.clone());
+                                                        restart |= self.ambient.is_some();
+                                                    }
+                                                }
+                                            });
+                                        let slider = egui::Slider::new(
+                                            &mut self.ambient_interval_secs,
+                                            ambient::MIN_INTERVAL_SECS..=ambient::MAX_INTERVAL_SECS,
+                                        )
+                                        .suffix("s");
+                                        if ui.add(slider).drag_stopped() {
+                                            restart |= self.ambient.is_some();
+                                        }
+                                    });
+                                    if restart {
+                                        if let Some(id) = self.ambient_light.clone() {
+                                            self.ambient = Some(ambient::AmbientMode::start(
+                                                Arc::clone(&self.client),
+                                                &self.api_url,
+                                                id,
+                                                Duration::from_secs(self.ambient_interval_secs),
+                                            ));
                                         }
                                     }
-                                    self.queue_update(
-                                        "all_k",
-                                        self.url_all.clone(),
-                                        UpdateRequest {
-                                            on: None,
-                                            brightness: None,
-                                            kelvin: Some(k),
-                                            mired: None,
-                                        },
-                                    );
+                                    if let Some(status) =
+                                        self.ambient.as_ref().and_then(|a| a.status())
+                                    {
+                                        ui.label(
+                                            egui::RichText::new(status)
+                                                .size(9.0)
+                                                .color(theme.text_secondary),
+                                        );
+                                    }
                                 }
-                            });
-                        ui.add_space(3.0);
 
-                        // Individual lights
-                        for index in 0..self.lights.len() {
-                            if !self.lights[index].enabled {
-                                continue;
-                            }
-                            let id = self.lights[index].id.clone();
-                            let label = self.lights[index].label.clone();
-                            let mut on = self.lights[index].on;
-                            let mut b = self.lights[index].brightness;
-                            let mut k = self.lights[index].kelvin;
+                                ui.add_space(12.0);
+                                ui.separator();
+                                ui.add_space(8.0);
 
-                            egui::Frame::none()
-                                .fill(colors::BG_CARD)
-                                .stroke(egui::Stroke::new(1.0, colors::BORDER))
-                                .rounding(6.0)
-                                .inner_margin(8.0)
-                                .show(ui, |ui| {
-                                    ui.set_width(w - 4.0);
-                                    ui.horizontal(|ui| {
-                                        if power_button(ui, &mut on, 26.0, power_tex.as_ref()) {
-                                            self.lights[index].on = on;
-                                            let url = format!(
-                                                "{}/v1/lights/{}",
-                                                self.api_url,
-                                                urlencoding::encode(&id)
-                                            );
-                                            self.queue_update(
-                                                &format!("p_{}", id),
-                                                url,
-                                                UpdateRequest {
-                                                    on: Some(if on { 1 } else { 0 }),
-                                                    brightness: None,
-                                                    kelvin: None,
-                                                    mired: None,
-                                                },
-                                            );
-                                            self.sync_all_state();
+                                // Screen match
+                                ui.label(
+                                    egui::RichText::new("Match screen")
+                                        .size(11.0)
+                                        .strong()
+                                        .color(theme.text_primary),
+                                );
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Nudge a group's color temperature toward your screen's",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(4.0);
+                                if self.groups.is_empty() {
+                                    ui.label(
+                                        egui::RichText::new("No groups configured")
+                                            .size(10.0)
+                                            .color(theme.text_secondary),
+                                    );
+                                } else {
+                                    let mut changed = false;
+                                    ui.horizontal_wrapped(|ui| {
+                                        let settings = &mut self.screen_match_settings;
+                                        for group in &self.groups {
+                                            let mut enabled = settings.groups.contains(&group.name);
+                                            if ui.checkbox(&mut enabled, &group.name).changed() {
+                                                if enabled {
+                                                    settings.groups.push(group.name.clone());
+                                                } else {
+                                                    settings.groups.retain(|g| g != &group.name);
+                                                }
+                                                changed = true;
+                                            }
                                         }
-                                        ui.add_space(4.0);
+                                    });
+                                    ui.horizontal(|ui| {
                                         ui.label(
-                                            egui::RichText::new(&label)
-                                                .size(11.0)
-                                                .strong()
-                                                .color(colors::TEXT_PRIMARY),
+                                            egui::RichText::new("Every")
+                                                .size(10.0)
+                                                .color(theme.text_secondary),
                                         );
+                                        let slider = egui::Slider::new(
+                                            &mut self.screen_match_settings.interval_secs,
+                                            screen_match::MIN_INTERVAL_SECS
+                                                ..=screen_match::MAX_INTERVAL_SECS,
+                                        )
+                                        .suffix("s");
+                                        changed |= ui.add(slider).drag_stopped();
                                     });
-                                    ui.add_space(2.0);
-                                    let sw = w - 16.0;
-                                    if brightness_slider(ui, &mut b, sw, bright_grad.as_ref()) {
-                                        self.lights[index].brightness = b;
-                                        let url = format!(
-                                            "{}/v1/lights/{}",
-                                            self.api_url,
-                                            urlencoding::encode(&id)
-                                        );
-                                        self.queue_update(
-                                            &format!("b_{}", id),
-                                            url,
-                                            UpdateRequest {
-                                                on: None,
-                                                brightness: Some(b),
-                                                kelvin: None,
-                                                mired: None,
-                                            },
-                                        );
+                                    if changed {
+                                        if let Err(e) =
+                                            screen_match::save_settings(&self.screen_match_settings)
+                                        {
+                                            eprintln!("failed to save screen match: {e}");
+                                        }
+                                        self.start_screen_match();
                                     }
-                                    ui.add_space(1.0);
-                                    if temperature_slider(ui, &mut k, sw, temp_grad.as_ref()) {
-                                        self.lights[index].kelvin = k;
-                                        let url = format!(
-                                            "{}/v1/lights/{}",
-                                            self.api_url,
-                                            urlencoding::encode(&id)
-                                        );
-                                        self.queue_update(
-                                            &format!("k_{}", id),
-                                            url,
-                                            UpdateRequest {
-                                                on: None,
-                                                brightness: None,
-                                                kelvin: Some(k),
-                                                mired: None,
-                                            },
+                                    if let Some(status) =
+                                        self.screen_match.as_ref().and_then(|m| m.status())
+                                    {
+                                        ui.label(
+                                            egui::RichText::new(status)
+                                                .size(9.0)
+                                                .color(theme.text_secondary),
                                         );
                                     }
-                                });
-                            ui.add_space(3.0);
-                        }
+                                }
 
-                        if self.lights.iter().filter(|l| l.enabled).count() == 0
-                            && self.modal_state == ModalState::None
-                        {
-                            ui.vertical_centered(|ui| {
+                                ui.add_space(12.0);
+                                ui.separator();
+                                ui.add_space(8.0);
+
+                                // Global shortcuts
                                 ui.label(
-                                    egui::RichText::new("No lights. Click + to discover.")
-                                        .size(10.0)
-                                        .color(colors::TEXT_SECONDARY),
+                                    egui::RichText::new("Keyboard shortcuts")
+                                        .size(11.0)
+                                        .strong()
+                                        .color(theme.text_primary),
                                 );
-                            });
-                        }
-                    }
-
-                    Tab::Groups => {
-                        if self.modal_state == ModalState::CreateGroup {
-                            egui::Frame::none()
-                                .fill(colors::BG_CARD)
-                                .stroke(egui::Stroke::new(1.0, colors::BORDER))
-                                .rounding(6.0)
-                                .inner_margin(8.0)
-                                .show(ui, |ui| {
-                                    ui.set_width(w - 4.0);
+                                ui.label(
+                                    egui::RichText::new(
+                                        "Click a shortcut, then press keys. Backspace clears, Esc cancels",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(4.0);
+                                let mut remove = None;
+                                for (i, binding) in self.hotkey_bindings.iter().enumerate() {
                                     ui.horizontal(|ui| {
                                         ui.label(
-                                            egui::RichText::new("Create Group")
-                                                .size(12.0)
-                                                .strong()
-                                                .color(colors::TEXT_PRIMARY),
+                                            egui::RichText::new(binding.action.label())
+                                                .size(10.0)
+                                                .color(theme.text_primary),
                                         );
                                         ui.with_layout(
                                             egui::Layout::right_to_left(egui::Align::Center),
                                             |ui| {
-                                                if ui.small_button("×").clicked() {
-                                                    self.modal_state = ModalState::None;
+                                                if ui.small_button("✕").clicked() {
+                                                    remove = Some(i);
+                                                }
+                                                let text = if self.recording_hotkey == Some(i) {
+                                                    "Press keys…"
+                                                } else if binding.keys.is_empty() {
+                                                    "Unassigned"
+                                                } else {
+                                                    binding.keys.as_str()
+                                                };
+                                                if ui
+                                                    .small_button(
+                                                        egui::RichText::new(text).size(10.0),
+                                                    )
+                                                    .clicked()
+                                                {
+                                                    self.recording_hotkey = Some(i);
                                                 }
                                             },
                                         );
                                     });
-                                    ui.add(
-                                        egui::TextEdit::singleline(&mut self.new_group_name)
-                                            .hint_text("Name")
-                                            .desired_width(w - 16.0),
-                                    );
-                                    for light in &self.lights {
-                                        let mut sel = self.new_group_members.contains(&light.id);
-                                        if ui.checkbox(&mut sel, &light.label).changed() {
-                                            if sel {
-                                                self.new_group_members.insert(light.id.clone());
-                                            } else {
-                                                self.new_group_members.remove(&light.id);
+                                }
+                                if let Some(i) = remove {
+                                    self.hotkey_bindings.remove(i);
+                                    self.recording_hotkey = None;
+                                    self.apply_hotkey_bindings();
+                                }
+                                ui.horizontal(|ui| {
+                                    let mut actions = vec![
+                                        hotkeys::HotkeyAction::ToggleAll,
+                                        hotkeys::HotkeyAction::BrightnessUp,
+                                        hotkeys::HotkeyAction::BrightnessDown,
+                                    ];
+                                    actions.extend(self.groups.iter().map(|g| {
+                                        hotkeys::HotkeyAction::ToggleGroup(g.name.clone())
+                                    }));
+                                    actions.extend(self.scenes.iter().map(|s| {
+                                        hotkeys::HotkeyAction::ApplyScene(s.name.clone())
+                                    }));
+                                    egui::ComboBox::from_id_salt("new_hotkey_action")
+                                        .selected_text(self.new_hotkey_action.label())
+                                        .width(w - 80.0)
+                                        .show_ui(ui, |ui| {
+                                            for action in actions {
+                                                let label = action.label();
+                                                ui.selectable_value(
+                                                    &mut self.new_hotkey_action,
+                                                    action,
+                                                    label,
+                                                );
                                             }
-                                        }
+                                        });
+                                    if ui.small_button("Add").clicked() {
+                                        self.hotkey_bindings.push(hotkeys::HotkeyBinding {
+                                            action: self.new_hotkey_action.clone(),
+                                            keys: String::new(),
+                                        });
+                                        self.recording_hotkey =
+                                            Some(self.hotkey_bindings.len() - 1);
                                     }
-                                    let can = !self.new_group_name.trim().is_empty()
-                                        && !self.new_group_members.is_empty();
-                                    ui.add_enabled_ui(can, |ui| {
-                                        if ui.small_button("Save").clicked() {
-                                            let name = self.new_group_name.trim().to_string();
-                                            let members: Vec<_> =
-                                                self.new_group_members.iter().cloned().collect();
-                                            self.save_group(name, members);
-                                            self.new_group_name.clear();
-                                            self.new_group_members.clear();
-                                            self.modal_state = ModalState::None;
-                                        }
-                                    });
                                 });
-                            ui.add_space(4.0);
-                        }
 
-                        for gi in 0..self.groups.len() {
-                            let name = self.groups[gi].name.clone();
-                            let member_count = self.groups[gi].members.len();
-                            if !self.group_controls.contains_key(&name) {
-                                self.group_controls.insert(
-                                    name.clone(),
-                                    GroupControl {
-                                        on: true,
-                                        brightness: 50,
-                                        kelvin: 4500,
-                                    },
-                                );
-                            }
-                            let ctrl = self
-                                .group_controls
-                                .get_mut(&name)
-                                .expect("group_controls missing entry for group");
-                            let mut on = ctrl.on;
-                            let mut b = ctrl.brightness;
-                            let mut k = ctrl.kelvin;
+                                ui.add_space(12.0);
+                                ui.separator();
+                                ui.add_space(8.0);
 
-                            egui::Frame::none()
-                                .fill(colors::BG_CARD)
-                                .stroke(egui::Stroke::new(1.0, colors::BORDER))
-                                .rounding(6.0)
-                                .inner_margin(8.0)
-                                .show(ui, |ui| {
-                                    ui.set_width(w - 4.0);
+                                // Daemons on other computers
+                                ui.label(
+                                    egui::RichText::new("Daemons")
+                                        .size(11.0)
+                                        .strong()
+                                        .color(theme.text_primary),
+                                );
+                                ui.label(
+                                    egui::RichText::new(
+                                        "keylightd on other computers, to switch to from the \
+                                         header. Daemons started with serve --listen 0.0.0.0 \
+                                         are found on the network; others only listen locally, \
+                                         so forward their port, e.g. \
+                                         ssh -L 9125:127.0.0.1:9124 studio",
+                                    )
+                                    .size(9.0)
+                                    .color(theme.text_secondary),
+                                );
+                                ui.add_space(4.0);
+                                let mut remove = None;
+                                for (index, host) in self.hosts.iter().enumerate().skip(1) {
                                     ui.horizontal(|ui| {
-                                        if power_button(ui, &mut on, 26.0, power_tex.as_ref()) {
-                                            if let Some(c) = self.group_controls.get_mut(&name) {
-                                                c.on = on;
-                                            }
-                                            let url = format!(
-                                                "{}/v1/groups/{}",
-                                                self.api_url,
-                                                urlencoding::encode(&name)
-                                            );
-                                            self.queue_update(
-                                                &format!("gp_{}", name),
-                                                url,
-                                                UpdateRequest {
-                                                    on: Some(if on { 1 } else { 0 }),
-                                                    brightness: None,
-                                                    kelvin: None,
-                                                    mired: None,
-                                                },
-                                            );
-                                        }
-                                        ui.add_space(4.0);
+                                        connection_dot(
+                                            ui,
+                                            &self.connections[index].connection(),
+                                            &theme,
+                                        );
                                         ui.label(
-                                            egui::RichText::new(&name)
-                                                .size(11.0)
-                                                .strong()
-                                                .color(colors::TEXT_PRIMARY),
+                                            egui::RichText::new(&host.name)
+                                                .size(10.0)
+                                                .color(theme.text_primary),
                                         );
                                         ui.label(
-                                            egui::RichText::new(format!(
-                                                "({})",
-                                                member_count
-                                            ))
-                                            .size(9.0)
-                                            .color(colors::TEXT_SECONDARY),
+                                            egui::RichText::new(&host.url)
+                                                .size(9.0)
+                                                .color(theme.text_secondary),
                                         );
                                         ui.with_layout(
                                             egui::Layout::right_to_left(egui::Align::Center),
                                             |ui| {
-                                                if ui.small_button("×").clicked() {
-                                                    self.delete_group(&name);
+                                                if ui.small_button("✕").clicked() {
+                                                    remove = Some(index);
                                                 }
                                             },
                                         );
                                     });
-                                    ui.add_space(2.0);
-                                    let sw = w - 16.0;
-                                    if brightness_slider(ui, &mut b, sw, bright_grad.as_ref()) {
-                                        if let Some(c) = self.group_controls.get_mut(&name) {
-                                            c.brightness = b;
-                                        }
-                                        let url = format!(
-                                            "{}/v1/groups/{}",
-                                            self.api_url,
-                                            urlencoding::encode(&name)
-                                        );
-                                        self.queue_update(
-                                            &format!("gb_{}", name),
-                                            url,
-                                            UpdateRequest {
-                                                on: None,
-                                                brightness: Some(b),
-                                                kelvin: None,
-                                                mired: None,
-                                            },
+                                }
+                                if let Some(index) = remove {
+                                    self.remove_host(index);
+                                }
+                                let found: Vec<hosts::Host> = self
+                                    .nearby
+                                    .as_ref()
+                                    .map(|nearby| nearby.hosts())
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .filter(|host| !self.hosts.iter().any(|h| h.url == host.url))
+                                    .collect();
+                                let mut picked = None;
+                                for host in found {
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            egui::RichText::new(&host.name)
+                                                .size(10.0)
+                                                .color(theme.text_primary),
                                         );
-                                    }
-                                    ui.add_space(1.0);
-                                    if temperature_slider(ui, &mut k, sw, temp_grad.as_ref()) {
-                                        if let Some(c) = self.group_controls.get_mut(&name) {
-                                            c.kelvin = k;
-                                        }
-                                        let url = format!(
-                                            "{}/v1/groups/{}",
-                                            self.api_url,
-                                            urlencoding::encode(&name)
+                                        ui.label(
+                                            egui::RichText::new(format!(
+                                                "{} · on the network",
+                                                host.url
+                                            ))
+                                            .size(9.0)
+                                            .color(theme.text_secondary),
                                         );
-                                        self.queue_update(
-                                            &format!("gk_{}", name),
-                                            url,
-                                            UpdateRequest {
-                                                on: None,
-                                                brightness: None,
-                                                kelvin: Some(k),
-                                                mired: None,
+                                        ui.with_layout(
+                                            egui::Layout::right_to_left(egui::Align::Center),
+                                            |ui| {
+                                                if ui.small_button("Add").clicked() {
+                                                    picked = Some(host.clone());
+                                                }
                                             },
                                         );
+                                    });
+                                }
+                                if let Some(host) = picked {
+                                    self.new_host_name = host.name;
+                                    self.new_host_url = host.url;
+                                    self.add_host(ui.ctx());
+                                }
+                                ui.horizontal(|ui| {
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut self.new_host_name)
+                                            .hint_text("Name")
+                                            .desired_width(70.0),
+                                    );
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut self.new_host_url)
+                                            .hint_text("127.0.0.1:9125")
+                                            .desired_width(w - 150.0),
+                                    );
+                                    if ui.small_button("Add").clicked() {
+                                        self.add_host(ui.ctx());
                                     }
                                 });
-                            ui.add_space(3.0);
-                        }
+                                if let Some(error) = &self.host_error {
+                                    ui.label(
+                                        egui::RichText::new(error)
+                                            .size(9.0)
+                                            .color(theme.text_secondary),
+                                    );
+                                }
 
-                        if self.groups.is_empty() && self.modal_state == ModalState::None {
-                            ui.vertical_centered(|ui| {
-                                ui.label(
-                                    egui::RichText::new("No groups. Click + to create.")
-                                        .size(10.0)
-                                        .color(colors::TEXT_SECONDARY),
-                                );
-                            });
-                        }
-                    }
+                                ui.add_space(12.0);
+                                ui.separator();
+                                ui.add_space(8.0);
 
-                    Tab::Settings => {
-                        egui::Frame::none()
-                            .fill(colors::BG_CARD)
-                            .stroke(egui::Stroke::new(1.0, colors::BORDER))
-                            .rounding(6.0)
-                            .inner_margin(12.0)
-                            .show(ui, |ui| {
-                                ui.set_width(w - 4.0);
+                                // Diagnostics
                                 ui.label(
-                                    egui::RichText::new("Settings")
-                                        .size(13.0)
+                                    egui::RichText::new("Diagnostics")
+                                        .size(11.0)
                                         .strong()
-                                        .color(colors::TEXT_PRIMARY),
+                                        .color(theme.text_primary),
                                 );
-                                ui.add_space(8.0);
-
-                                // Autostart toggle
-                                ui.horizontal(|ui| {
-                                    let mut autostart = self.autostart_enabled;
-                                    if ui.checkbox(&mut autostart, "").changed()
-                                        && set_autostart(autostart).is_ok()
-                                    {
-                                        self.autostart_enabled = autostart;
-                                    }
-                                    ui.label(
-                                        egui::RichText::new("Start on login")
-                                            .size(11.0)
-                                            .color(colors::TEXT_PRIMARY),
-                                    );
-                                });
                                 ui.label(
                                     egui::RichText::new(
-                                        "Launch LimeLight automatically when you log in",
+                                        "Settings, daemon status, lights and recent daemon logs \
+                                         in one zip to attach to a bug report",
                                     )
                                     .size(9.0)
-                                    .color(colors::TEXT_SECONDARY),
+                                    .color(theme.text_secondary),
                                 );
+                                ui.add_space(4.0);
+                                let exporting = self.diagnostics.is_some();
+                                let export = ui.add_enabled(
+                                    !exporting,
+                                    egui::Button::new(
+                                        egui::RichText::new("Export diagnostics…").size(10.0),
+                                    )
+                                    .small(),
+                                );
+                                if export.clicked() {
+                                    self.diagnostics_status = None;
+                                    self.diagnostics = Some(diagnostics::spawn(
+                                        ui.ctx().clone(),
+                                        Arc::clone(&self.client),
+                                        self.api_url.clone(),
+                                    ));
+                                }
+                                if let Some(status) = &self.diagnostics_status {
+                                    ui.label(
+                                        egui::RichText::new(status)
+                                            .size(9.0)
+                                            .color(theme.text_secondary),
+                                    );
+                                }
 
                                 ui.add_space(12.0);
                                 ui.separator();
@@ -1340,23 +5033,28 @@ impl eframe::App for KeylightApp {
                                     egui::RichText::new("About")
                                         .size(11.0)
                                         .strong()
-                                        .color(colors::TEXT_PRIMARY),
+                                        .color(theme.text_primary),
                                 );
                                 ui.add_space(4.0);
                                 ui.label(
                                     egui::RichText::new("LimeLight v0.1.0")
                                         .size(10.0)
-                                        .color(colors::TEXT_SECONDARY),
+                                        .color(theme.text_secondary),
                                 );
                                 ui.label(
                                     egui::RichText::new("Elgato Key Light Controller for Linux")
                                         .size(10.0)
-                                        .color(colors::TEXT_SECONDARY),
+                                        .color(theme.text_secondary),
                                 );
                             });
                     }
                 }
             });
+
+        // Clicks, drag releases and typed values are final; don't make them wait for the batch.
+        if ctx.input(|i| i.pointer.any_released() || i.key_pressed(egui::Key::Enter)) {
+            self.flush_updates();
+        }
     }
 }
 
@@ -1395,13 +5093,15 @@ fn spawn_daemon() -> Option<std::process::Child> {
 }
 
 fn main() -> eframe::Result<()> {
-    let api_url = std::env::var("KEYLIGHT_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.into());
+    let start_minimized = std::env::args().any(|arg| arg == "--start-minimized");
+    let api_url = hosts::local().url;
 
-    // Start daemon if not already running
-    let mut daemon_process: Option<std::process::Child> = None;
+    // Start daemon if not already running. Only a daemon started here is restarted
+    // when it exits; one started elsewhere is left to whoever started it.
+    let mut daemon_process: Option<connection::DaemonProcess> = None;
     if !daemon_is_running(&api_url) {
         eprintln!("Starting keylightd daemon...");
-        daemon_process = spawn_daemon();
+        daemon_process = Some(Arc::new(Mutex::new(spawn_daemon())));
         // Give daemon time to start
         thread::sleep(Duration::from_millis(500));
     }
@@ -1410,6 +5110,9 @@ fn main() -> eframe::Result<()> {
     let icon = eframe::icon_data::from_png_bytes(include_bytes!("../../../../public/Limecon.png"))
         .unwrap_or_default();
 
+    // Shared with the app so queued updates can be flushed after the window closes.
+    let pending_updates: PendingUpdates = Arc::default();
+
     let result = eframe::run_native(
         "LimeLight",
         eframe::NativeOptions {
@@ -1424,15 +5127,38 @@ fn main() -> eframe::Result<()> {
                 .with_icon(icon),
             ..Default::default()
         },
-        Box::new(|cc| {
-            configure_egui(&cc.egui_ctx);
-            Ok(Box::new(KeylightApp::new()))
+        Box::new({
+            let pending_updates = Arc::clone(&pending_updates);
+            let daemon_process = daemon_process.clone();
+            move |cc| {
+                configure_egui(&cc.egui_ctx);
+                Ok(Box::new(KeylightApp::new(
+                    &cc.egui_ctx,
+                    pending_updates,
+                    start_minimized,
+                    daemon_process,
+                )))
+            }
         }),
     );
 
-    // Clean up daemon when app exits
-    if let Some(mut child) = daemon_process {
-        let _ = child.kill();
+    let client = Client::builder().timeout(Duration::from_secs(2)).build();
+    if let Ok(client) = &client {
+        flush_pending_on_exit(client, &pending_updates);
+    }
+
+    // Stop the daemon we started, unless it should outlive the tray.
+    let child = daemon_process
+        .as_ref()
+        .and_then(|daemon| daemon.lock().ok()?.take())
+        .filter(|_| load_daemon_settings().stop_on_quit);
+    if let Some(mut child) = child {
+        match &client {
+            Ok(client) => stop_daemon(client, &api_url, child),
+            Err(_) => {
+                let _ = child.kill();
+            }
+        }
     }
 
     result
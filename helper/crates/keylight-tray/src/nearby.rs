@@ -0,0 +1,87 @@
+//! keylightd instances announcing themselves on the network (`serve --listen`), found
+//! over mDNS so Settings can offer them instead of making the user type an address.
+
+use eframe::egui;
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::hosts::Host;
+
+const SERVICE: &str = "_keylightd._tcp.local.";
+
+/// Daemons found so far, by mDNS instance name; dropping it stops the browse.
+pub struct NearbyDaemons {
+    found: Arc<Mutex<Vec<(String, Host)>>>,
+    daemon: ServiceDaemon,
+}
+
+impl NearbyDaemons {
+    pub fn start(ctx: egui::Context) -> Result<Self, mdns_sd::Error> {
+        let daemon = ServiceDaemon::new()?;
+        let events = daemon.browse(SERVICE)?;
+        let found = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::clone(&found);
+        thread::spawn(move || {
+            while let Ok(event) = events.recv() {
+                let mut found = shared.lock().unwrap_or_else(|p| p.into_inner());
+                match event {
+                    ServiceEvent::ServiceResolved(info) => {
+                        let Some(ip) = preferred_address(
+                            info.get_addresses().iter().map(|addr| addr.to_string()),
+                        ) else {
+                            continue;
+                        };
+                        let fullname = info.get_fullname().to_string();
+                        let name = info
+                            .get_property_val_str("name")
+                            .map(str::to_string)
+                            .unwrap_or_else(|| instance_name(&fullname).to_string());
+                        let url = match ip {
+                            IpAddr::V4(ip) => format!("http://{ip}:{}", info.get_port()),
+                            IpAddr::V6(ip) => format!("http://[{ip}]:{}", info.get_port()),
+                        };
+                        found.retain(|(known, _)| *known != fullname);
+                        found.push((fullname, Host { name, url }));
+                    }
+                    ServiceEvent::ServiceRemoved(_, fullname) => {
+                        found.retain(|(known, _)| *known != fullname);
+                    }
+                    _ => continue,
+                }
+                ctx.request_repaint();
+            }
+        });
+        Ok(Self { found, daemon })
+    }
+
+    /// Found daemons, by name.
+    pub fn hosts(&self) -> Vec<Host> {
+        let found = self.found.lock().unwrap_or_else(|p| p.into_inner());
+        let mut hosts: Vec<Host> = found.iter().map(|(_, host)| host.clone()).collect();
+        hosts.sort_by(|a, b| a.name.cmp(&b.name));
+        hosts
+    }
+}
+
+impl Drop for NearbyDaemons {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// `studio._keylightd._tcp.local.` → `studio`.
+fn instance_name(fullname: &str) -> &str {
+    fullname
+        .strip_suffix(SERVICE)
+        .unwrap_or(fullname)
+        .trim_end_matches('.')
+}
+
+/// An IPv4 address if there is one, since link-local IPv6 needs a scope to be usable.
+fn preferred_address(addresses: impl Iterator<Item = String>) -> Option<IpAddr> {
+    let mut addresses: Vec<IpAddr> = addresses.filter_map(|addr| addr.parse().ok()).collect();
+    addresses.sort_by_key(|addr| (!addr.is_ipv4(), *addr));
+    addresses.into_iter().next()
+}
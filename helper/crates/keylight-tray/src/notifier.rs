@@ -0,0 +1,223 @@
+//! Desktop notifications for what the daemon posts on `GET /v1/notices`: automations
+//! that changed the lights (a call started or ended, a schedule ran) and lights that
+//! stopped answering. Sent through the freedesktop Notifications interface on the
+//! session bus, for the daemon the window controls; each category can be turned off
+//! in Settings.
+
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long each poll waits on the daemon for a notice.
+const WAIT_SECS: u64 = 25;
+/// Wait before asking again after the daemon didn't answer.
+const RETRY: Duration = Duration::from_secs(5);
+const APP_ICON: &str = "io.github.chimi6.limelight-linux-elgato-lights-controller";
+
+#[derive(Deserialize)]
+struct NoticeBatch {
+    revision: u64,
+    #[serde(default)]
+    notices: Vec<Notice>,
+}
+
+#[derive(Deserialize)]
+struct Notice {
+    category: String,
+    kind: String,
+    #[serde(default)]
+    scene: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+}
+
+struct Shared {
+    api_url: String,
+    automations: bool,
+    errors: bool,
+}
+
+/// Background notice poller; it runs for as long as the window does.
+pub struct Notifier {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Notifier {
+    pub fn start(client: Arc<Client>, api_url: &str, automations: bool, errors: bool) -> Self {
+        let shared = Arc::new(Mutex::new(Shared {
+            api_url: api_url.to_string(),
+            automations,
+            errors,
+        }));
+        let poller = Arc::clone(&shared);
+        thread::spawn(move || run(&client, &poller));
+        Self { shared }
+    }
+
+    /// Follow another daemon; its earlier notices aren't shown.
+    pub fn set_api_url(&self, api_url: &str) {
+        self.lock().api_url = api_url.to_string();
+    }
+
+    pub fn set_categories(&self, automations: bool, errors: bool) {
+        let mut shared = self.lock();
+        shared.automations = automations;
+        shared.errors = errors;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn run(client: &Client, shared: &Mutex<Shared>) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("failed to start notifications runtime: {e}");
+            return;
+        }
+    };
+    let bus = match runtime.block_on(zbus::Connection::session()) {
+        Ok(bus) => bus,
+        Err(e) => {
+            eprintln!("notifications unavailable: {e}");
+            return;
+        }
+    };
+    let mut polled_url = String::new();
+    let mut since = None;
+    loop {
+        let api_url = shared
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .api_url
+            .clone();
+        if api_url != polled_url {
+            polled_url = api_url.clone();
+            since = None;
+        }
+        let Some(batch) = poll(client, &api_url, since) else {
+            thread::sleep(RETRY);
+            continue;
+        };
+        // The first answer only says where to start, so nothing old pops up.
+        if since.is_some() {
+            for notice in &batch.notices {
+                let shown = {
+                    let shared = shared
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    match notice.category.as_str() {
+                        "automation" => shared.automations,
+                        "error" => shared.errors,
+                        _ => false,
+                    }
+                };
+                if !shown {
+                    continue;
+                }
+                let Some((summary, body)) = describe(client, &api_url, notice) else {
+                    continue;
+                };
+                if let Err(e) = runtime.block_on(notify(&bus, &summary, &body)) {
+                    eprintln!("failed to show notification: {e}");
+                }
+            }
+        }
+        since = Some(batch.revision);
+    }
+}
+
+fn poll(client: &Client, api_url: &str, since: Option<u64>) -> Option<NoticeBatch> {
+    let url = match since {
+        Some(since) => format!("{api_url}/v1/notices?since={since}&wait={WAIT_SECS}"),
+        None => format!("{api_url}/v1/notices"),
+    };
+    client
+        .get(url)
+        .timeout(Duration::from_secs(WAIT_SECS + 5))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .ok()
+}
+
+/// Summary and body for a notice; `None` for kinds this version doesn't know.
+fn describe(client: &Client, api_url: &str, notice: &Notice) -> Option<(String, String)> {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    let (summary, body) = match notice.kind.as_str() {
+        "call_started" => (
+            "Call started",
+            format!("Lights set to '{}'", text(&notice.scene)),
+        ),
+        "call_ended" => ("Call ended", "Lights are back to how they were".into()),
+        "schedule_applied" => (
+            "Schedule ran",
+            format!("'{}' changed the lights", text(&notice.name)),
+        ),
+        "light_unreachable" => (
+            "Light unreachable",
+            format!(
+                "{} stopped answering",
+                light_label(client, api_url, &text(&notice.id))
+            ),
+        ),
+        _ => return None,
+    };
+    Some((summary.to_string(), body))
+}
+
+/// A light's alias or name, falling back to its id.
+fn light_label(client: &Client, api_url: &str, id: &str) -> String {
+    let lights: Option<Vec<Value>> = client
+        .get(format!("{api_url}/v1/lights"))
+        .send()
+        .and_then(|r| r.json())
+        .ok();
+    lights
+        .into_iter()
+        .flatten()
+        .find(|light| light["id"] == id)
+        .and_then(|light| {
+            light["alias"]
+                .as_str()
+                .or_else(|| light["name"].as_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| id.to_string())
+}
+
+async fn notify(bus: &zbus::Connection, summary: &str, body: &str) -> zbus::Result<()> {
+    let actions: Vec<&str> = Vec::new();
+    let hints: HashMap<&str, zbus::zvariant::Value> = HashMap::new();
+    bus.call_method(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        Some("org.freedesktop.Notifications"),
+        "Notify",
+        &(
+            "LimeLight",
+            0u32,
+            APP_ICON,
+            summary,
+            body,
+            actions,
+            hints,
+            -1i32,
+        ),
+    )
+    .await?;
+    Ok(())
+}
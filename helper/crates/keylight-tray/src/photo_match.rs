@@ -0,0 +1,103 @@
+//! "Match photo": pick a reference image through the FileChooser portal and estimate
+//! its color temperature. Same estimate as `keylightd match`.
+
+use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+/// Longest side the photo is scaled to before sampling.
+const SAMPLE_SIZE: u32 = 256;
+
+/// Ask for a photo and estimate it on a background thread. `Ok(None)` means the
+/// dialog was cancelled.
+pub fn spawn(ctx: egui::Context) -> mpsc::Receiver<Result<Option<u16>, String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = pick_photo().and_then(|path| match path {
+            Some(path) => estimate_kelvin_from_file(&path).map(Some),
+            None => Ok(None),
+        });
+        let _ = tx.send(result);
+        ctx.request_repaint();
+    });
+    rx
+}
+
+fn pick_photo() -> Result<Option<PathBuf>, String> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+    runtime.block_on(async {
+        use ashpd::desktop::file_chooser::{FileFilter, SelectedFiles};
+
+        let request = SelectedFiles::open_file()
+            .title("Match lights to photo")
+            .accept_label("Match")
+            .modal(true)
+            .multiple(false)
+            .filter(FileFilter::new("Images").mimetype("image/*"))
+            .send()
+            .await
+            .map_err(|e| format!("file chooser unavailable: {e}"))?;
+        match request.response() {
+            Ok(files) => Ok(files.uris().first().and_then(|uri| uri.to_file_path().ok())),
+            Err(ashpd::Error::Response(ashpd::desktop::ResponseError::Cancelled)) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    })
+}
+
+fn estimate_kelvin_from_file(path: &Path) -> Result<u16, String> {
+    let img = image::open(path).map_err(|e| format!("unable to read photo: {e}"))?;
+    let rgb = if img.width() > SAMPLE_SIZE || img.height() > SAMPLE_SIZE {
+        img.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8()
+    } else {
+        img.to_rgb8()
+    };
+    // Gray-world average, skipping clipped highlights and near-black pixels.
+    let mut sum = [0.0f64; 3];
+    let mut count = 0u32;
+    for pixel in rgb.pixels() {
+        if pixel.0.iter().any(|&c| c >= 250) {
+            continue;
+        }
+        let linear = pixel.0.map(srgb_to_linear);
+        if 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2] < 0.05 {
+            continue;
+        }
+        for (total, value) in sum.iter_mut().zip(linear) {
+            *total += value;
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return Err("photo has no usable pixels".into());
+    }
+    kelvin_from_linear(sum.map(|total| total / count as f64))
+}
+
+/// Correlated color temperature of an average linear sRGB color.
+pub fn kelvin_from_linear([r, g, b]: [f64; 3]) -> Result<u16, String> {
+    // Linear sRGB -> CIE xy -> McCamy's CCT approximation.
+    let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+    let (cx, cy) = (x / (x + y + z), y / (x + y + z));
+    let n = (cx - 0.3320) / (0.1858 - cy);
+    let cct = 449.0 * n.powi(3) + 3525.0 * n.powi(2) + 6823.3 * n + 5520.33;
+    if !cct.is_finite() {
+        return Err("unable to estimate color temperature".into());
+    }
+    Ok(cct.clamp(1000.0, 20000.0).round() as u16)
+}
+
+pub fn srgb_to_linear(channel: u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
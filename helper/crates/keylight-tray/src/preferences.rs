@@ -0,0 +1,57 @@
+//! Window preferences that don't belong to one daemon: whether closing the window
+//! hides it to the tray, how often the daemon's status is checked, compact mode,
+//! which light cards are collapsed and which notifications are shown. Kept under
+//! `[preferences]` in `sublime.toml`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use super::settings;
+
+pub const MIN_POLL_SECS: u64 = 1;
+pub const MAX_POLL_SECS: u64 = 60;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Preferences {
+    /// Closing the window hides it while the tray icon is up, instead of quitting.
+    pub close_to_tray: bool,
+    /// Seconds between status checks while the daemon answers.
+    pub poll_interval_secs: u64,
+    /// Thinner sliders, so more cards fit without scrolling.
+    pub compact: bool,
+    /// Ids of lights whose cards show only their header row.
+    pub collapsed_lights: BTreeSet<String>,
+    /// Notify when an automation (a call, a schedule) changes the lights.
+    pub notify_automations: bool,
+    /// Notify when a light stops answering.
+    pub notify_errors: bool,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            close_to_tray: true,
+            poll_interval_secs: 5,
+            compact: false,
+            collapsed_lights: BTreeSet::new(),
+            notify_automations: true,
+            notify_errors: true,
+        }
+    }
+}
+
+impl Preferences {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs.clamp(MIN_POLL_SECS, MAX_POLL_SECS))
+    }
+}
+
+pub fn load() -> Preferences {
+    settings::load().preferences
+}
+
+pub fn save(preferences: &Preferences) -> Result<(), std::io::Error> {
+    settings::update(|settings| settings.preferences = preferences.clone())
+}
@@ -0,0 +1,195 @@
+//! Screen match: estimate the color temperature of what's on screen and nudge the
+//! kelvin of the groups that opted in toward it, a poor man's bias lighting.
+//!
+//! Screenshots come from the same portal as ambient mode, and cost as much (see
+//! there), so they're at least 15 seconds apart; the lights only drift halfway to
+//! each sample anyway. Unlike "Match photo", every pixel counts: a white document
+//! is exactly what the lights should follow.
+
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ambient::capture_screen;
+use crate::photo_match::{kelvin_from_linear, srgb_to_linear};
+use crate::settings;
+
+pub const MIN_INTERVAL_SECS: u64 = 15;
+pub const MAX_INTERVAL_SECS: u64 = 120;
+
+/// Longest side the screenshot is scaled to before averaging.
+const SAMPLE_SIZE: u32 = 64;
+/// Share of the gap to the screen's temperature closed per sample, so switching
+/// windows for a moment doesn't swing the lights.
+const NUDGE: f64 = 0.5;
+/// Changes smaller than this (kelvin) aren't worth a request.
+const KELVIN_STEP: u16 = 100;
+
+/// Groups that follow the screen, saved under `[screen_match]` in `sublime.toml`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScreenMatchSettings {
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for ScreenMatchSettings {
+    fn default() -> Self {
+        Self {
+            groups: Vec::new(),
+            interval_secs: default_interval_secs(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    30
+}
+
+pub fn load_settings() -> ScreenMatchSettings {
+    settings::load().screen_match
+}
+
+pub fn save_settings(screen_match: &ScreenMatchSettings) -> Result<(), std::io::Error> {
+    settings::update(|settings| settings.screen_match = screen_match.clone())
+}
+
+/// A running screen match worker; dropping it stops the worker.
+pub struct ScreenMatch {
+    stop: Arc<AtomicBool>,
+    status: Arc<Mutex<Option<String>>>,
+}
+
+impl ScreenMatch {
+    pub fn start(
+        client: Arc<Client>,
+        api_url: &str,
+        settings: &ScreenMatchSettings,
+        range: (u16, u16),
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(Mutex::new(None));
+        let urls = settings
+            .groups
+            .iter()
+            .map(|name| format!("{}/v1/groups/{}", api_url, urlencoding::encode(name)))
+            .collect();
+        let worker = Worker {
+            client,
+            urls,
+            range,
+            interval: Duration::from_secs(
+                settings
+                    .interval_secs
+                    .clamp(MIN_INTERVAL_SECS, MAX_INTERVAL_SECS),
+            ),
+            stop: Arc::clone(&stop),
+            status: Arc::clone(&status),
+        };
+        thread::spawn(move || worker.run());
+        Self { stop, status }
+    }
+
+    /// Last error from the worker, if the most recent sample failed.
+    pub fn status(&self) -> Option<String> {
+        self.status.lock().ok().and_then(|s| s.clone())
+    }
+}
+
+impl Drop for ScreenMatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Worker {
+    client: Arc<Client>,
+    urls: Vec<String>,
+    range: (u16, u16),
+    interval: Duration,
+    stop: Arc<AtomicBool>,
+    status: Arc<Mutex<Option<String>>>,
+}
+
+impl Worker {
+    fn run(self) {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(rt) => rt,
+            Err(e) => {
+                self.set_status(Some(format!("failed to start runtime: {e}")));
+                return;
+            }
+        };
+        let mut last: Option<u16> = None;
+        while !self.stop.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            match runtime.block_on(capture_kelvin()) {
+                Ok(screen) => {
+                    let target = screen.clamp(self.range.0, self.range.1);
+                    let next = match last {
+                        Some(prev) => nudge(prev, target),
+                        None => target,
+                    };
+                    if last.is_none_or(|prev| prev.abs_diff(next) >= KELVIN_STEP) {
+                        match self.send(next) {
+                            Ok(()) => {
+                                last = Some(next);
+                                self.set_status(None);
+                            }
+                            Err(e) => self.set_status(Some(e)),
+                        }
+                    }
+                }
+                Err(e) => self.set_status(Some(e)),
+            }
+            // Sleep in short steps so turning the mode off takes effect promptly.
+            while started.elapsed() < self.interval && !self.stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+
+    fn send(&self, kelvin: u16) -> Result<(), String> {
+        for url in &self.urls {
+            self.client
+                .put(url)
+                .json(&serde_json::json!({ "kelvin": kelvin }))
+                .send()
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn set_status(&self, status: Option<String>) {
+        if let Ok(mut slot) = self.status.lock() {
+            *slot = status;
+        }
+    }
+}
+
+fn nudge(from: u16, to: u16) -> u16 {
+    (from as f64 + (to as f64 - from as f64) * NUDGE).round() as u16
+}
+
+async fn capture_kelvin() -> Result<u16, String> {
+    let rgb = capture_screen(SAMPLE_SIZE).await?;
+    let mut sum = [0.0f64; 3];
+    for pixel in rgb.pixels() {
+        for (total, value) in sum.iter_mut().zip(pixel.0.map(srgb_to_linear)) {
+            *total += value;
+        }
+    }
+    let count = (rgb.width() * rgb.height()).max(1) as f64;
+    if sum.iter().all(|&total| total / count < 0.01) {
+        return Err("screen is too dark to estimate".into());
+    }
+    kelvin_from_linear(sum.map(|total| total / count))
+}
@@ -0,0 +1,88 @@
+//! Every tray setting in one file, `~/.config/limelight/sublime.toml`: daemon hosts,
+//! theme, shortcuts, window preferences, what quitting does to the daemon, and the
+//! groups that follow the screen.
+
+use keylight_core::files;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::hosts::HostSettings;
+use super::hotkeys::{self, HotkeyBinding};
+use super::preferences::Preferences;
+use super::screen_match::ScreenMatchSettings;
+use super::theme::ThemePreference;
+use super::DaemonSettings;
+
+pub const FILE_NAME: &str = "sublime.toml";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub theme: ThemePreference,
+    pub hosts: HostSettings,
+    pub preferences: Preferences,
+    pub daemon: DaemonSettings,
+    pub screen_match: ScreenMatchSettings,
+    pub hotkeys: Vec<HotkeyBinding>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: ThemePreference::default(),
+            hosts: HostSettings::default(),
+            preferences: Preferences::default(),
+            daemon: DaemonSettings::default(),
+            screen_match: ScreenMatchSettings::default(),
+            hotkeys: hotkeys::default_bindings(),
+        }
+    }
+}
+
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join("limelight"))
+}
+
+pub fn load() -> Settings {
+    let Some(dir) = dir() else {
+        return Settings::default();
+    };
+    read(&dir.join(FILE_NAME)).unwrap_or_else(|e| {
+        eprintln!("{e}; using the default settings");
+        Settings::default()
+    })
+}
+
+/// The settings saved at `path`, or the defaults when there's no file yet.
+fn read(path: &Path) -> Result<Settings, std::io::Error> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => toml_edit::de::from_str(&text).map_err(|e| {
+            let message = format!("invalid {}: {e}", path.display());
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Settings::default()),
+        Err(e) => Err(std::io::Error::new(
+            e.kind(),
+            format!("failed to read {}: {e}", path.display()),
+        )),
+    }
+}
+
+/// Load the settings, let `change` edit them and save the result. A file that
+/// can't be read is left alone rather than replaced by defaults plus `change`.
+pub fn update(change: impl FnOnce(&mut Settings)) -> Result<(), std::io::Error> {
+    let dir = dir()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No config dir"))?;
+    let path = dir.join(FILE_NAME);
+    let mut settings = read(&path)?;
+    change(&mut settings);
+    save_to(&path, &settings)
+}
+
+fn save_to(path: &Path, settings: &Settings) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        files::create_private_dir(parent)?;
+    }
+    let text = toml_edit::ser::to_string_pretty(settings).map_err(std::io::Error::other)?;
+    files::write_private_file(path, text.as_bytes())
+}
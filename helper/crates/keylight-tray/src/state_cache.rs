@@ -0,0 +1,39 @@
+//! Last light states the window saw, per daemon, so cards open with them instead of
+//! defaults while `GET /v1/lights/states` is still asking every light. Kept in
+//! `~/.local/state/limelight/light-states.json` and replaced by what the daemon
+//! reports as soon as it answers.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CachedState {
+    pub on: bool,
+    pub brightness: u8,
+    pub kelvin: u16,
+}
+
+/// Light id to state, per daemon API URL.
+pub type StateCache = HashMap<String, HashMap<String, CachedState>>;
+
+fn cache_path() -> Option<std::path::PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::cache_dir)
+        .map(|p| p.join("limelight").join("light-states.json"))
+}
+
+pub fn load() -> StateCache {
+    cache_path()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(cache: &StateCache) -> Result<(), std::io::Error> {
+    let path = cache_path()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No state dir"))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec(cache)?)
+}
@@ -0,0 +1,154 @@
+//! Color themes and the light/dark preference, following the desktop's color scheme
+//! through the freedesktop Settings portal when set to System.
+
+use eframe::egui::{self, Color32};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use super::settings;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    pub dark: bool,
+    pub bg: Color32,
+    pub card: Color32,
+    pub accent: Color32,
+    pub accent_light: Color32,
+    pub text_primary: Color32,
+    pub text_secondary: Color32,
+    pub border: Color32,
+    pub power_on: Color32,
+    pub power_off: Color32,
+    pub warm: Color32,
+    pub cool: Color32,
+    pub bright_high: Color32,
+    pub bright_low: Color32,
+}
+
+impl Theme {
+    pub const LIGHT: Theme = Theme {
+        dark: false,
+        bg: Color32::from_rgb(245, 250, 255),
+        card: Color32::from_rgb(255, 255, 255),
+        accent: Color32::from_rgb(70, 150, 220),
+        accent_light: Color32::from_rgb(100, 175, 235),
+        text_primary: Color32::from_rgb(30, 50, 80),
+        text_secondary: Color32::from_rgb(120, 140, 160),
+        border: Color32::from_rgb(200, 220, 240),
+        power_on: Color32::from_rgb(80, 190, 110),
+        power_off: Color32::from_rgb(160, 170, 180),
+        warm: Color32::from_rgb(255, 170, 70),
+        cool: Color32::from_rgb(140, 195, 255),
+        bright_high: Color32::from_rgb(255, 252, 240),
+        bright_low: Color32::from_rgb(50, 55, 65),
+    };
+
+    pub const DARK: Theme = Theme {
+        dark: true,
+        bg: Color32::from_rgb(28, 32, 38),
+        card: Color32::from_rgb(40, 45, 53),
+        accent: Color32::from_rgb(90, 165, 235),
+        accent_light: Color32::from_rgb(120, 185, 245),
+        text_primary: Color32::from_rgb(225, 232, 240),
+        text_secondary: Color32::from_rgb(140, 155, 172),
+        border: Color32::from_rgb(60, 68, 80),
+        power_on: Color32::from_rgb(80, 190, 110),
+        power_off: Color32::from_rgb(88, 96, 108),
+        warm: Color32::from_rgb(255, 170, 70),
+        cool: Color32::from_rgb(140, 195, 255),
+        bright_high: Color32::from_rgb(255, 252, 240),
+        bright_low: Color32::from_rgb(20, 22, 26),
+    };
+
+    /// Set egui's base visuals to match; widgets we don't paint ourselves use these.
+    pub fn apply(&self, ctx: &egui::Context) {
+        let mut visuals = if self.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        visuals.panel_fill = self.bg;
+        ctx.set_visuals(visuals);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemePreference {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl ThemePreference {
+    pub const ALL: [ThemePreference; 3] = [
+        ThemePreference::System,
+        ThemePreference::Light,
+        ThemePreference::Dark,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemePreference::System => "System",
+            ThemePreference::Light => "Light",
+            ThemePreference::Dark => "Dark",
+        }
+    }
+
+    pub fn resolve(self, system_dark: bool) -> Theme {
+        match self {
+            ThemePreference::System if system_dark => Theme::DARK,
+            ThemePreference::System | ThemePreference::Light => Theme::LIGHT,
+            ThemePreference::Dark => Theme::DARK,
+        }
+    }
+}
+
+pub fn load_preference() -> ThemePreference {
+    settings::load().theme
+}
+
+pub fn save_preference(preference: ThemePreference) -> Result<(), std::io::Error> {
+    settings::update(|settings| settings.theme = preference)
+}
+
+/// Track the portal's `color-scheme` setting. The flag stays false (light) when the
+/// portal isn't available.
+pub fn watch_system_scheme(ctx: egui::Context) -> Arc<AtomicBool> {
+    let dark = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&dark);
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build();
+        match runtime {
+            Ok(rt) => {
+                if let Err(e) = rt.block_on(listen_portal(&flag, &ctx)) {
+                    eprintln!("color-scheme portal unavailable: {e}");
+                }
+            }
+            Err(e) => eprintln!("failed to start color-scheme runtime: {e}"),
+        }
+    });
+    dark
+}
+
+async fn listen_portal(dark: &AtomicBool, ctx: &egui::Context) -> ashpd::Result<()> {
+    use ashpd::desktop::settings::{ColorScheme, Settings};
+    use futures_util::StreamExt;
+
+    let update = |scheme: ColorScheme| {
+        dark.store(matches!(scheme, ColorScheme::PreferDark), Ordering::Relaxed);
+        ctx.request_repaint();
+    };
+    let settings = Settings::new().await?;
+    update(settings.color_scheme().await?);
+    let mut changes = settings.receive_color_scheme_changed().await?;
+    while let Some(scheme) = changes.next().await {
+        update(scheme);
+    }
+    Ok(())
+}
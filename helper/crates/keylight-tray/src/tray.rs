@@ -0,0 +1,281 @@
+//! StatusNotifierItem icon with a quick-action menu.
+
+use eframe::egui;
+use ksni::blocking::TrayMethods;
+use ksni::menu::{StandardItem, SubMenu};
+use ksni::MenuItem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::{PendingUpdates, UpdateRequest};
+
+pub type TrayHandle = ksni::blocking::Handle<TrayIcon>;
+
+/// Flags the tray menu raises for the window to pick up on its next frame.
+#[derive(Default)]
+pub struct TraySignals {
+    /// Quit was chosen; let the next close request through.
+    pub quit: AtomicBool,
+    /// Lights were switched from the menu; the window's state is stale.
+    pub refresh: AtomicBool,
+    /// A favorite scene was picked; the window applies it.
+    pub apply_scene: Mutex<Option<String>>,
+}
+
+pub struct TrayIcon {
+    ctx: egui::Context,
+    pending: PendingUpdates,
+    signals: Arc<TraySignals>,
+    /// Daemon the menu acts on; follows the host picked in the window.
+    pub api_url: String,
+    icon: Vec<ksni::Icon>,
+    pub groups: Vec<String>,
+    /// Enabled lights as `(id, label with icon)`.
+    pub lights: Vec<(String, String)>,
+    /// Starred lights, like `lights`, and starred scene names, listed at the top.
+    pub favorites: Vec<(String, String)>,
+    pub scenes: Vec<String>,
+}
+
+impl TrayIcon {
+    pub fn new(
+        ctx: egui::Context,
+        pending: PendingUpdates,
+        signals: Arc<TraySignals>,
+        api_url: String,
+    ) -> Self {
+        Self {
+            ctx,
+            pending,
+            signals,
+            api_url,
+            icon: load_icon(),
+            groups: Vec::new(),
+            lights: Vec::new(),
+            favorites: Vec::new(),
+            scenes: Vec::new(),
+        }
+    }
+
+    /// Register with the session's StatusNotifierWatcher. Returns `None` when
+    /// there is no tray host (e.g. GNOME without the AppIndicator extension).
+    pub fn spawn(self) -> Option<TrayHandle> {
+        match TrayMethods::spawn(self) {
+            Ok(handle) => Some(handle),
+            Err(e) => {
+                eprintln!("tray icon unavailable: {e}");
+                None
+            }
+        }
+    }
+
+    fn show_window(&self) {
+        self.ctx
+            .send_viewport_cmd(egui::ViewportCommand::Visible(true));
+        self.ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        self.ctx.request_repaint();
+    }
+
+    fn set_power(&self, key: String, url: String, on: bool) {
+        self.pending.queue(
+            key,
+            url,
+            UpdateRequest {
+                on: Some(if on { 1 } else { 0 }),
+                brightness: None,
+                kelvin: None,
+                mired: None,
+            },
+        );
+        self.pending.flush();
+        self.signals.refresh.store(true, Ordering::Relaxed);
+        self.ctx.request_repaint();
+    }
+
+    fn set_all(&self, on: bool) {
+        self.set_power("all_power".into(), format!("{}/v1/all", self.api_url), on);
+    }
+
+    fn set_group(&self, name: &str, on: bool) {
+        let url = format!("{}/v1/groups/{}", self.api_url, urlencoding::encode(name));
+        self.set_power(format!("gp_{}", name), url, on);
+    }
+
+    fn set_light(&self, id: &str, on: bool) {
+        let url = format!("{}/v1/lights/{}", self.api_url, urlencoding::encode(id));
+        self.set_power(format!("p_{}", id), url, on);
+    }
+
+    fn apply_scene(&self, name: &str) {
+        *self
+            .signals
+            .apply_scene
+            .lock()
+            .unwrap_or_else(|p| p.into_inner()) = Some(name.to_string());
+        self.ctx.request_repaint();
+    }
+}
+
+/// Submenu per entry with On and Off items.
+fn on_off_submenu(
+    entries: &[(String, String)],
+    set: fn(&TrayIcon, &str, bool),
+) -> Vec<MenuItem<TrayIcon>> {
+    entries
+        .iter()
+        .map(|(key, label)| {
+            let (on, off) = (key.clone(), key.clone());
+            SubMenu {
+                label: label.clone(),
+                submenu: vec![
+                    StandardItem {
+                        label: "On".into(),
+                        activate: Box::new(move |tray: &mut TrayIcon| set(tray, &on, true)),
+                        ..Default::default()
+                    }
+                    .into(),
+                    StandardItem {
+                        label: "Off".into(),
+                        activate: Box::new(move |tray: &mut TrayIcon| set(tray, &off, false)),
+                        ..Default::default()
+                    }
+                    .into(),
+                ],
+                ..Default::default()
+            }
+            .into()
+        })
+        .collect()
+}
+
+impl ksni::Tray for TrayIcon {
+    fn id(&self) -> String {
+        "limelight".into()
+    }
+
+    fn title(&self) -> String {
+        "LimeLight".into()
+    }
+
+    fn icon_name(&self) -> String {
+        "io.github.chimi6.limelight-linux-elgato-lights-controller".into()
+    }
+
+    fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+        self.icon.clone()
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.show_window();
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let mut items: Vec<MenuItem<Self>> = vec![
+            StandardItem {
+                label: "Open LimeLight".into(),
+                activate: Box::new(|tray: &mut Self| tray.show_window()),
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            StandardItem {
+                label: "All On".into(),
+                activate: Box::new(|tray: &mut Self| tray.set_all(true)),
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: "All Off".into(),
+                activate: Box::new(|tray: &mut Self| tray.set_all(false)),
+                ..Default::default()
+            }
+            .into(),
+        ];
+
+        if !self.favorites.is_empty() || !self.scenes.is_empty() {
+            items.push(MenuItem::Separator);
+            let favorites: Vec<(String, String)> = self
+                .favorites
+                .iter()
+                .map(|(id, label)| (id.clone(), format!("★ {label}")))
+                .collect();
+            items.extend(on_off_submenu(&favorites, Self::set_light));
+            for name in &self.scenes {
+                let scene = name.clone();
+                items.push(
+                    StandardItem {
+                        label: format!("★ {name}"),
+                        activate: Box::new(move |tray: &mut Self| tray.apply_scene(&scene)),
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            }
+            items.push(MenuItem::Separator);
+        }
+
+        if !self.lights.is_empty() {
+            items.push(
+                SubMenu {
+                    label: "Lights".into(),
+                    submenu: on_off_submenu(&self.lights, Self::set_light),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        if !self.groups.is_empty() {
+            let groups: Vec<(String, String)> = self
+                .groups
+                .iter()
+                .map(|name| (name.clone(), name.clone()))
+                .collect();
+            let submenu = on_off_submenu(&groups, Self::set_group);
+            items.push(
+                SubMenu {
+                    label: "Groups".into(),
+                    submenu,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        items.push(MenuItem::Separator);
+        items.push(
+            StandardItem {
+                label: "Quit".into(),
+                activate: Box::new(|tray: &mut Self| {
+                    tray.signals.quit.store(true, Ordering::Relaxed);
+                    tray.ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    tray.ctx.request_repaint();
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+        items
+    }
+}
+
+/// Limecon.png as the ARGB32 pixmap SNI expects, for hosts that can't
+/// resolve the icon name (e.g. when not installed via the .desktop file).
+fn load_icon() -> Vec<ksni::Icon> {
+    let Ok(img) = image::load_from_memory(include_bytes!("../../../../public/Limecon.png")) else {
+        return Vec::new();
+    };
+    let img = img
+        .resize(64, 64, image::imageops::FilterType::Lanczos3)
+        .to_rgba8();
+    let (width, height) = img.dimensions();
+    let mut data = img.into_raw();
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.rotate_right(1);
+    }
+    vec![ksni::Icon {
+        width: width as i32,
+        height: height as i32,
+        data,
+    }]
+}
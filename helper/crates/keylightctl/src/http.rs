@@ -0,0 +1,149 @@
+//! Just enough HTTP/1.0 over a `TcpStream` to talk to keylightd on plain http. The
+//! daemon sends compact JSON with a Content-Length and closes the connection, so
+//! there's no chunking, TLS or keep-alive to handle.
+
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Group updates with staggering or soft start can take a few seconds.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the daemon listens, from a URL like `http://127.0.0.1:9124`.
+#[derive(Debug, PartialEq)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    /// Path prefix, for a daemon behind a reverse proxy; empty otherwise.
+    prefix: String,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("{url}: only http:// URLs are supported"))?;
+        let (authority, prefix) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], rest[slash..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        // IPv6 literals are bracketed: `[::1]:9124`.
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => bracketed
+                .split_once(']')
+                .map(|(host, rest)| (host, rest.strip_prefix(':')))
+                .ok_or_else(|| format!("{url}: unclosed '['"))?,
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(format!("{url}: missing host"));
+        }
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .map_err(|_| format!("{url}: invalid port '{port}'"))?,
+            None => 80,
+        };
+        Ok(Self {
+            host: host.into(),
+            port,
+            prefix: prefix.into(),
+        })
+    }
+
+    /// Send a request and return the JSON body of a 2xx response. Error responses
+    /// become their `error` message.
+    pub fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, String> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("resolving {}: {e}", self.host))?
+            .next()
+            .ok_or_else(|| format!("resolving {}: no addresses", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            .map_err(|e| format!("keylightd isn't reachable at {addr}: {e}"))?;
+        stream
+            .set_read_timeout(Some(READ_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let request = format!(
+            "{method} {}{path} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{body}",
+            self.prefix,
+            self.host,
+            body.len()
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| format!("sending request: {e}"))?;
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .map_err(|e| format!("reading response: {e}"))?;
+        parse_response(&response)
+    }
+}
+
+fn parse_response(response: &[u8]) -> Result<Value, String> {
+    let text = String::from_utf8_lossy(response);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .ok_or("malformed response from keylightd")?;
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or("malformed status line from keylightd")?;
+    let json = if body.trim().is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(body).map_err(|e| format!("invalid JSON from keylightd: {e}"))?
+    };
+    if (200..300).contains(&status) {
+        Ok(json)
+    } else {
+        Err(json
+            .get("error")
+            .and_then(Value::as_str)
+            .map_or_else(|| format!("keylightd answered {status}"), str::to_string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_parse_host_port_and_prefix() {
+        let endpoint = Endpoint::parse("http://127.0.0.1:9124").unwrap();
+        assert_eq!(
+            endpoint,
+            Endpoint {
+                host: "127.0.0.1".into(),
+                port: 9124,
+                prefix: String::new()
+            }
+        );
+        let endpoint = Endpoint::parse("http://[::1]:9124/lights/").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port), ("::1", 9124));
+        assert_eq!(endpoint.prefix, "/lights");
+        assert_eq!(Endpoint::parse("http://studio").unwrap().port, 80);
+        assert!(Endpoint::parse("https://studio").is_err());
+        assert!(Endpoint::parse("http://studio:port").is_err());
+    }
+
+    #[test]
+    fn error_responses_carry_the_daemon_message() {
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\n{\"on\":true}";
+        assert_eq!(parse_response(ok).unwrap()["on"], true);
+        let missing = b"HTTP/1.1 404 Not Found\r\n\r\n{\"error\":\"No group named 'x'\"}";
+        assert_eq!(parse_response(missing).unwrap_err(), "No group named 'x'");
+        let bare = b"HTTP/1.1 500 Internal Server Error\r\n\r\n";
+        assert_eq!(parse_response(bare).unwrap_err(), "keylightd answered 500");
+    }
+}
@@ -0,0 +1,281 @@
+//! `keylightctl`: a thin client for a running keylightd, for keybindings, scripts
+//! and minimal containers. It only speaks the `/v1` API: no discovery, no config
+//! files, and no dependencies beyond JSON.
+
+mod http;
+
+use http::Endpoint;
+use serde_json::{json, Value};
+use std::process::ExitCode;
+
+const DEFAULT_API_URL: &str = "http://127.0.0.1:9124";
+
+const USAGE: &str = "\
+Usage: keylightctl [--url URL] <command>
+
+Commands:
+  status                       Daemon status and the state of every light
+  toggle <light>|--group <name>|--all
+                               Switch on if everything targeted is off, else off
+  set <light>|--group <name>|--all [--on|--off] [--brightness N] [--kelvin K]
+                               Change power, brightness (0-100) or temperature
+  scene [<name>]               Restore a saved snapshot; without a name, list them
+
+The daemon is at KEYLIGHT_API_URL, else http://127.0.0.1:9124; --url overrides both.
+Lights are matched by id, name or alias.";
+
+#[derive(Debug, PartialEq)]
+enum Target {
+    Light(String),
+    Group(String),
+    All,
+}
+
+impl Target {
+    fn path(&self) -> String {
+        match self {
+            Target::Light(id) => format!("/v1/lights/{}", urlencoding::encode(id)),
+            Target::Group(name) => format!("/v1/groups/{}", urlencoding::encode(name)),
+            Target::All => "/v1/all".into(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Status,
+    Toggle(Target),
+    Set { target: Target, update: Value },
+    Scene(Option<String>),
+}
+
+/// Parse everything after the program name into the daemon URL and a command.
+fn parse_args(args: &[String]) -> Result<(Option<String>, Command), String> {
+    let mut url = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => url = Some(args.next().ok_or("--url needs a value")?.clone()),
+            _ => rest.push(arg.as_str()),
+        }
+    }
+    let (command, rest) = rest.split_first().ok_or("missing command")?;
+    let command = match *command {
+        "status" if rest.is_empty() => Command::Status,
+        "toggle" => {
+            let (target, rest) = parse_target(rest)?;
+            if let Some(extra) = rest.first() {
+                return Err(format!("unexpected argument '{extra}'"));
+            }
+            Command::Toggle(target)
+        }
+        "set" => {
+            let (target, rest) = parse_target(rest)?;
+            Command::Set {
+                target,
+                update: parse_update(rest)?,
+            }
+        }
+        "scene" => match rest {
+            [] => Command::Scene(None),
+            [name] => Command::Scene(Some(name.to_string())),
+            _ => return Err("scene takes at most one name".into()),
+        },
+        "status" => return Err("status takes no arguments".into()),
+        other => return Err(format!("unknown command '{other}'")),
+    };
+    Ok((url, command))
+}
+
+fn parse_target<'a>(args: &'a [&'a str]) -> Result<(Target, &'a [&'a str]), String> {
+    match args {
+        ["--all", rest @ ..] => Ok((Target::All, rest)),
+        ["--group", name, rest @ ..] => Ok((Target::Group(name.to_string()), rest)),
+        ["--group"] => Err("--group needs a name".into()),
+        [light, rest @ ..] if !light.starts_with("--") => {
+            Ok((Target::Light(light.to_string()), rest))
+        }
+        _ => Err("missing target: a light, --group <name> or --all".into()),
+    }
+}
+
+fn parse_update(args: &[&str]) -> Result<Value, String> {
+    let mut update = serde_json::Map::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--on" => {
+                update.insert("on".into(), json!(1));
+            }
+            "--off" => {
+                update.insert("on".into(), json!(0));
+            }
+            "--brightness" | "--kelvin" => {
+                let value = args.next().ok_or(format!("{arg} needs a value"))?;
+                let number: u16 = value
+                    .parse()
+                    .map_err(|_| format!("{arg}: '{value}' is not a number"))?;
+                if *arg == "--brightness" && number > 100 {
+                    return Err("--brightness must be 0-100".into());
+                }
+                update.insert(arg.trim_start_matches("--").into(), json!(number));
+            }
+            other => return Err(format!("unexpected argument '{other}'")),
+        }
+    }
+    if update.is_empty() {
+        return Err("set needs --on, --off, --brightness or --kelvin".into());
+    }
+    Ok(Value::Object(update))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let (url, command) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("keylightctl: {err}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let url = url
+        .or_else(|| std::env::var("KEYLIGHT_API_URL").ok())
+        .unwrap_or_else(|| DEFAULT_API_URL.into());
+    let result = Endpoint::parse(&url).and_then(|endpoint| run(&endpoint, command));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("keylightctl: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Output is `key=value` lines, like keylightd's, so scripts can read it.
+fn run(endpoint: &Endpoint, command: Command) -> Result<(), String> {
+    match command {
+        Command::Status => {
+            let status = endpoint.request("GET", "/v1/status", None)?;
+            println!("version={}", text(&status["version"]));
+            println!("uptime_secs={}", text(&status["uptime_secs"]));
+            println!("lights={}", text(&status["lights"]));
+            let automations: Vec<String> = status["automations"]
+                .as_array()
+                .map(|names| names.iter().map(text).collect())
+                .unwrap_or_default();
+            println!("automations={}", automations.join(","));
+            let dnd = &status["dnd"];
+            if dnd["active"].as_bool() == Some(true) {
+                println!("dnd_remaining_secs={}", text(&dnd["remaining_secs"]));
+            }
+            let states = endpoint.request("GET", "/v1/lights/states", None)?;
+            for state in states.as_array().into_iter().flatten() {
+                println!(
+                    "id={}, on={}, brightness={}, kelvin={}",
+                    text(&state["id"]),
+                    u8::from(state["on"].as_bool() == Some(true)),
+                    text(&state["brightness"]),
+                    text(&state["kelvin"])
+                );
+            }
+        }
+        Command::Toggle(Target::Light(id)) => {
+            let path = format!("/v1/lights/{}/toggle", urlencoding::encode(&id));
+            let summary = endpoint.request("POST", &path, None)?;
+            println!("on={}", u8::from(summary["on"].as_bool() == Some(true)));
+        }
+        Command::Toggle(target) => {
+            let on = !any_on(endpoint, &target)?;
+            endpoint.request("PUT", &target.path(), Some(&json!({"on": u8::from(on)})))?;
+            println!("on={}", u8::from(on));
+        }
+        Command::Set { target, update } => {
+            endpoint.request("PUT", &target.path(), Some(&update))?;
+        }
+        Command::Scene(Some(name)) => {
+            let path = format!("/v1/snapshots/{}/restore", urlencoding::encode(&name));
+            endpoint.request("POST", &path, None)?;
+        }
+        Command::Scene(None) => {
+            let snapshots = endpoint.request("GET", "/v1/snapshots", None)?;
+            for (name, snapshot) in snapshots.as_object().into_iter().flatten() {
+                let lights = snapshot["lights"].as_array().map_or(0, Vec::len);
+                println!("name={name}, lights={lights}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether any light of a group (or of all enabled lights) is on.
+fn any_on(endpoint: &Endpoint, target: &Target) -> Result<bool, String> {
+    let members: Option<Vec<String>> = match target {
+        Target::Group(name) => {
+            let path = format!("/v1/groups/{}/resolved", urlencoding::encode(name));
+            let resolved = endpoint.request("GET", &path, None)?;
+            let ids = resolved["members"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|member| member.get("ip").is_some())
+                .filter_map(|member| member["light"]["id"].as_str().map(str::to_string))
+                .collect();
+            Some(ids)
+        }
+        _ => None,
+    };
+    let states = endpoint.request("GET", "/v1/lights/states", None)?;
+    Ok(states.as_array().into_iter().flatten().any(|state| {
+        let targeted = members.as_ref().is_none_or(|ids| {
+            state["id"]
+                .as_str()
+                .is_some_and(|id| ids.iter().any(|m| m == id))
+        });
+        targeted && state["on"].as_bool() == Some(true)
+    }))
+}
+
+/// A JSON value as plain text: strings without quotes, `null` as empty.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Command, String> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse_args(&args).map(|(_, command)| command)
+    }
+
+    #[test]
+    fn commands_parse_targets_and_updates() {
+        assert_eq!(parse("status"), Ok(Command::Status));
+        assert_eq!(
+            parse("toggle --group desk"),
+            Ok(Command::Toggle(Target::Group("desk".into())))
+        );
+        assert_eq!(
+            parse("--url http://10.0.0.2:9124 set left --on --brightness 40"),
+            Ok(Command::Set {
+                target: Target::Light("left".into()),
+                update: json!({"on": 1, "brightness": 40}),
+            })
+        );
+        assert_eq!(parse("scene"), Ok(Command::Scene(None)));
+        assert!(parse("set --all").is_err());
+        assert!(parse("set --all --brightness 140").is_err());
+        assert!(parse("toggle --brightness 10").is_err());
+        assert!(parse("dim").is_err());
+    }
+}
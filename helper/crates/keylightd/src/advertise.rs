@@ -0,0 +1,68 @@
+//! Announces the API as `_keylightd._tcp` over mDNS while `serve` listens beyond
+//! loopback, so the tray and other clients on the network can find the daemon
+//! instead of being given its address.
+
+use mdns_sd::{Error as MdnsError, ServiceDaemon, ServiceInfo};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::logs::log_line;
+
+pub const SERVICE: &str = "_keylightd._tcp.local.";
+
+/// The mDNS daemon answering for the API and the registered name, kept so the
+/// announcement can be withdrawn on shutdown.
+static ADVERTISED: Mutex<Option<(ServiceDaemon, String)>> = Mutex::new(None);
+
+/// This computer's name, as the instance name and in the `name` TXT record.
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "keylightd".into())
+}
+
+/// Start announcing the API on `listen`:`port`. An unspecified address (`0.0.0.0`)
+/// is announced with every address of this computer. Called once by `serve`.
+pub fn start(listen: IpAddr, port: u16) {
+    if let Err(err) = register(listen, port) {
+        log_line!("advertising the API over mDNS: {err}");
+    }
+}
+
+fn register(listen: IpAddr, port: u16) -> Result<(), MdnsError> {
+    let name = hostname();
+    let version = env!("CARGO_PKG_VERSION");
+    let properties = [("name", name.as_str()), ("version", version)];
+    let host = format!("{name}.local.");
+    let info = if listen.is_unspecified() {
+        ServiceInfo::new(SERVICE, &name, &host, "", port, &properties[..])?.enable_addr_auto()
+    } else {
+        ServiceInfo::new(SERVICE, &name, &host, listen, port, &properties[..])?
+    };
+    let fullname = info.get_fullname().to_string();
+    let daemon = ServiceDaemon::new()?;
+    daemon.register(info)?;
+    log_line!("advertising the API as {fullname}");
+    *ADVERTISED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some((daemon, fullname));
+    Ok(())
+}
+
+/// Tell the network the API is going away, waiting briefly for that to be sent.
+pub fn withdraw() {
+    let advertised = ADVERTISED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take();
+    let Some((daemon, fullname)) = advertised else {
+        return;
+    };
+    if let Ok(status) = daemon.unregister(&fullname) {
+        let _ = status.recv_timeout(Duration::from_secs(1));
+    }
+    let _ = daemon.shutdown();
+}
@@ -0,0 +1,269 @@
+//! Auto brightness: reads an ambient light sensor through iio sysfs and dims the
+//! lights as the room gets brighter (and the other way round).
+//!
+//! Configured by an `[auto_brightness]` section in `config.toml`; without one, or
+//! without a sensor, nothing runs.
+
+use keylight_core::store::SavedLight;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use super::dnd;
+use super::logs::log_line;
+use super::profiles::{self, Automation};
+use super::schedule::{target_records, ScheduleTarget};
+use super::status;
+use super::{load_config, set_light, LightUpdate};
+
+const IIO_DEVICES: &str = "/sys/bus/iio/devices";
+/// Smaller changes than this aren't sent, so sensor noise doesn't keep the lights busy.
+const MIN_STEP: u8 = 2;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AutoBrightnessConfig {
+    pub target: ScheduleTarget,
+    /// Brightness at or below `dark_lux`.
+    #[serde(default = "default_dark_brightness")]
+    pub dark_brightness: u8,
+    /// Brightness at or above `bright_lux`.
+    #[serde(default = "default_bright_brightness")]
+    pub bright_brightness: u8,
+    #[serde(default = "default_dark_lux")]
+    pub dark_lux: f64,
+    #[serde(default = "default_bright_lux")]
+    pub bright_lux: f64,
+    #[serde(default)]
+    pub curve: Curve,
+    /// Weight of each new reading in the running average (0-1); lower reacts slower.
+    #[serde(default = "default_smoothing")]
+    pub smoothing: f64,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// iio device directory; the first one with an illuminance channel when unset.
+    #[serde(default)]
+    pub sensor: Option<PathBuf>,
+}
+
+/// How lux between `dark_lux` and `bright_lux` maps onto brightness.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    /// Even steps per doubling of lux, which is closer to how bright a room looks.
+    #[default]
+    Log,
+    Linear,
+}
+
+fn default_dark_brightness() -> u8 {
+    70
+}
+
+fn default_bright_brightness() -> u8 {
+    15
+}
+
+fn default_dark_lux() -> f64 {
+    5.0
+}
+
+fn default_bright_lux() -> f64 {
+    1000.0
+}
+
+fn default_smoothing() -> f64 {
+    0.3
+}
+
+fn default_interval_secs() -> u64 {
+    5
+}
+
+impl AutoBrightnessConfig {
+    fn brightness_for(&self, lux: f64) -> u8 {
+        let (low, high) = (self.dark_lux.max(0.0), self.bright_lux.max(0.0));
+        let position = match self.curve {
+            Curve::Linear => (lux - low) / (high - low),
+            Curve::Log => {
+                ((lux + 1.0).ln() - (low + 1.0).ln()) / ((high + 1.0).ln() - (low + 1.0).ln())
+            }
+        };
+        let position = if position.is_finite() {
+            position.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let dark = self.dark_brightness.min(100) as f64;
+        let bright = self.bright_brightness.min(100) as f64;
+        (dark + (bright - dark) * position).round() as u8
+    }
+}
+
+/// First iio device exposing an illuminance channel.
+fn find_sensor(root: &Path) -> Option<PathBuf> {
+    let mut devices: Vec<PathBuf> = fs::read_dir(root)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|dir| {
+            dir.join("in_illuminance_input").exists() || dir.join("in_illuminance_raw").exists()
+        })
+        .collect();
+    devices.sort();
+    devices.into_iter().next()
+}
+
+/// Current reading in lux: `in_illuminance_input` if the driver provides it,
+/// otherwise `(raw + offset) * scale`.
+fn read_lux(sensor: &Path) -> io::Result<f64> {
+    let read = |name: &str| -> io::Result<Option<f64>> {
+        match fs::read_to_string(sensor.join(name)) {
+            Ok(text) => text.trim().parse().map(Some).map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{name}: {err}"))
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    };
+    if let Some(lux) = read("in_illuminance_input")? {
+        return Ok(lux);
+    }
+    let raw = read("in_illuminance_raw")?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "sensor has no illuminance channel")
+    })?;
+    let offset = read("in_illuminance_offset")?.unwrap_or(0.0);
+    let scale = read("in_illuminance_scale")?.unwrap_or(1.0);
+    Ok((raw + offset) * scale)
+}
+
+/// Start auto brightness if `[auto_brightness]` is configured. Called once by `serve`.
+pub fn spawn(client: Client) {
+    let config = match load_config() {
+        Ok(config) => config.auto_brightness,
+        Err(err) => {
+            log_line!("auto brightness: {err}");
+            return;
+        }
+    };
+    let Some(config) = config else {
+        return;
+    };
+    let Some(sensor) = config
+        .sensor
+        .clone()
+        .or_else(|| find_sensor(Path::new(IIO_DEVICES)))
+    else {
+        log_line!("auto brightness: no ambient light sensor found under {IIO_DEVICES}");
+        return;
+    };
+    let spawned = thread::Builder::new()
+        .name("auto-brightness".into())
+        .spawn(move || run(client, config, sensor));
+    match spawned {
+        Ok(_) => status::automation_started("auto_brightness"),
+        Err(err) => log_line!("failed to start auto brightness: {err}"),
+    }
+}
+
+fn run(client: Client, config: AutoBrightnessConfig, sensor: PathBuf) {
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    let smoothing = config.smoothing.clamp(0.01, 1.0);
+    let mut average: Option<f64> = None;
+    let mut sent: Option<u8> = None;
+    loop {
+        match read_lux(&sensor) {
+            Ok(lux) => {
+                let lux = match average {
+                    Some(previous) => previous + (lux - previous) * smoothing,
+                    None => lux,
+                };
+                average = Some(lux);
+                let brightness = config.brightness_for(lux);
+                if dnd::active() || profiles::disabled(Automation::AutoBrightness) {
+                    // Re-apply once the window ends, whatever changed meanwhile.
+                    sent = None;
+                } else if sent.is_none_or(|sent| sent.abs_diff(brightness) >= MIN_STEP) {
+                    apply(&client, &config.target, brightness);
+                    sent = Some(brightness);
+                }
+            }
+            Err(err) => log_line!("auto brightness: {}: {err}", sensor.display()),
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn apply(client: &Client, target: &ScheduleTarget, brightness: u8) {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            log_line!("auto brightness: {err}");
+            return;
+        }
+    };
+    let update = LightUpdate {
+        brightness: Some(brightness),
+        ..Default::default()
+    };
+    for light in target_records(&config, target) {
+        if !light.capabilities.brightness {
+            continue;
+        }
+        let Some(ip) = light.address() else {
+            continue;
+        };
+        if let Err(err) = set_light(client, light.backend, &ip, &update) {
+            log_line!("auto brightness on {}: {err}", light.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(curve: Curve) -> AutoBrightnessConfig {
+        AutoBrightnessConfig {
+            target: ScheduleTarget::All,
+            dark_brightness: 80,
+            bright_brightness: 20,
+            dark_lux: 0.0,
+            bright_lux: 1000.0,
+            curve,
+            smoothing: 0.3,
+            interval_secs: 5,
+            sensor: None,
+        }
+    }
+
+    #[test]
+    fn brighter_rooms_get_dimmer_lights() {
+        let linear = config(Curve::Linear);
+        assert_eq!(linear.brightness_for(0.0), 80);
+        assert_eq!(linear.brightness_for(500.0), 50);
+        assert_eq!(linear.brightness_for(5000.0), 20);
+
+        let log = config(Curve::Log);
+        assert_eq!(log.brightness_for(0.0), 80);
+        assert_eq!(log.brightness_for(1000.0), 20);
+        // A dim room already moves the lights well down the range.
+        assert!(log.brightness_for(30.0) < linear.brightness_for(30.0));
+    }
+
+    #[test]
+    fn raw_readings_are_scaled() {
+        let dir = std::env::temp_dir().join(format!("keylightd-iio-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("in_illuminance_raw"), "250\n").unwrap();
+        fs::write(dir.join("in_illuminance_scale"), "0.5\n").unwrap();
+        let lux = read_lux(&dir).unwrap();
+        let found = find_sensor(dir.parent().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(lux, 125.0);
+        assert!(found.is_some());
+    }
+}
@@ -0,0 +1,234 @@
+//! Per-light corrections from `[[calibration]]` in `config.toml`, so lights of
+//! different models look alike at the same setting.
+//!
+//! Temperature: the same mired renders differently per model (a Ring Light next to a
+//! Key Light at 4500K looks cooler), so an entry maps the kelvin asked for to the
+//! kelvin sent. Brightness: with `brightness_mode = "lumens"` a percentage means the
+//! same light output on every such light, namely that share of the dimmest one's
+//! maximum, rather than a share of each light's own. States read back are mapped
+//! the other way.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Config, LightRecord};
+
+/// Rated output by accessory-info `productName`, for entries without `max_lumens`.
+const RATED_LUMENS: &[(&str, u32)] = &[
+    ("Elgato Key Light", 2800),
+    ("Elgato Key Light Air", 1400),
+    ("Elgato Key Light Mini", 800),
+    ("Elgato Ring Light", 2500),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Calibration {
+    /// Light id, name or alias.
+    pub light: String,
+    /// Added after scaling.
+    #[serde(default)]
+    pub kelvin_offset: i32,
+    #[serde(default = "default_scale")]
+    pub kelvin_scale: f64,
+    #[serde(default)]
+    pub brightness_mode: BrightnessMode,
+    /// The light's output at 100%; looked up by model when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_lumens: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BrightnessMode {
+    /// A share of the light's own maximum, as the device takes it.
+    #[default]
+    Percent,
+    /// A share of the dimmest lumens-mode light's maximum.
+    Lumens,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Calibration {
+    /// Kelvin to send the light when `kelvin` is asked for. The result is clamped to
+    /// the light's range afterwards, like any temperature.
+    pub fn to_device(&self, kelvin: u16) -> u16 {
+        let kelvin = f64::from(kelvin) * self.scale() + f64::from(self.kelvin_offset);
+        kelvin.round().clamp(0.0, f64::from(u16::MAX)) as u16
+    }
+
+    /// The kelvin that was asked for to get `kelvin` on the light.
+    pub fn requested_kelvin(&self, kelvin: u16) -> u16 {
+        let kelvin = (f64::from(kelvin) - f64::from(self.kelvin_offset)) / self.scale();
+        kelvin.round().clamp(0.0, f64::from(u16::MAX)) as u16
+    }
+
+    /// A scale of 0 or less would map every temperature to one; it's ignored.
+    fn scale(&self) -> f64 {
+        if self.kelvin_scale > 0.0 {
+            self.kelvin_scale
+        } else {
+            1.0
+        }
+    }
+
+    /// Maximum output of `light` when it's in lumens mode and that's known.
+    fn lumens(&self, light: &LightRecord) -> Option<u32> {
+        if self.brightness_mode != BrightnessMode::Lumens {
+            return None;
+        }
+        let rated = || {
+            let product = light
+                .accessory_info
+                .as_ref()?
+                .get("productName")?
+                .as_str()?;
+            RATED_LUMENS
+                .iter()
+                .find(|(name, _)| *name == product)
+                .map(|(_, lumens)| *lumens)
+        };
+        self.max_lumens.or_else(rated).filter(|lumens| *lumens > 0)
+    }
+}
+
+/// The calibration for `light`, matched by id, name or alias.
+pub fn for_light<'a>(
+    calibrations: &'a [Calibration],
+    light: &LightRecord,
+) -> Option<&'a Calibration> {
+    calibrations.iter().find(|calibration| {
+        calibration.light == light.id
+            || calibration.light == light.name
+            || light.alias.as_deref() == Some(calibration.light.as_str())
+    })
+}
+
+/// Device percent per requested percent for a light in lumens mode.
+fn brightness_factor(config: &Config, light: &LightRecord) -> Option<f64> {
+    let own = for_light(&config.calibration, light)?.lumens(light)?;
+    let dimmest = config
+        .lights
+        .iter()
+        .filter_map(|other| for_light(&config.calibration, other)?.lumens(other))
+        .min()?;
+    Some(f64::from(dimmest) / f64::from(own))
+}
+
+/// Brightness and kelvin to send `light` for a request.
+pub fn to_device(
+    config: &Config,
+    light: &LightRecord,
+    brightness: Option<u8>,
+    kelvin: Option<u16>,
+) -> (Option<u8>, Option<u16>) {
+    let calibration = for_light(&config.calibration, light);
+    let brightness = match (brightness, brightness_factor(config, light)) {
+        // Dimmed but never off: a light that's on stays visibly on.
+        (Some(percent), Some(factor)) if percent > 0 => {
+            Some((f64::from(percent) * factor).round().clamp(1.0, 100.0) as u8)
+        }
+        _ => brightness,
+    };
+    let kelvin = match (kelvin, calibration) {
+        (Some(kelvin), Some(calibration)) => Some(calibration.to_device(kelvin)),
+        _ => kelvin,
+    };
+    (brightness, kelvin)
+}
+
+/// What `light` reports, in the terms requests use.
+pub fn reported(
+    config: &Config,
+    light: &LightRecord,
+    brightness: Option<u8>,
+    kelvin: Option<u16>,
+) -> (Option<u8>, Option<u16>) {
+    let calibration = for_light(&config.calibration, light);
+    let brightness = match (brightness, brightness_factor(config, light)) {
+        (Some(percent), Some(factor)) => {
+            Some((f64::from(percent) / factor).round().clamp(0.0, 100.0) as u8)
+        }
+        _ => brightness,
+    };
+    let kelvin = match (kelvin, calibration) {
+        (Some(kelvin), Some(calibration)) => Some(calibration.requested_kelvin(kelvin)),
+        _ => kelvin,
+    };
+    (brightness, kelvin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kelvin_is_mapped_to_the_device_and_back() {
+        let ring = Calibration {
+            light: "ring".into(),
+            kelvin_offset: -200,
+            kelvin_scale: 1.0,
+            brightness_mode: BrightnessMode::Percent,
+            max_lumens: None,
+        };
+        assert_eq!(ring.to_device(4500), 4300);
+        assert_eq!(ring.requested_kelvin(4300), 4500);
+
+        let scaled = Calibration {
+            light: "strip".into(),
+            kelvin_offset: 100,
+            kelvin_scale: 0.9,
+            ..ring.clone()
+        };
+        assert_eq!(scaled.to_device(5000), 4600);
+        assert_eq!(scaled.requested_kelvin(4600), 5000);
+
+        let broken = Calibration {
+            kelvin_scale: 0.0,
+            ..ring
+        };
+        assert_eq!(broken.to_device(4500), 4300);
+    }
+
+    #[test]
+    fn lumens_mode_matches_output_to_the_dimmest_light() {
+        let light = |id: &str, product: &str| -> LightRecord {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "alias": null,
+                "name": id,
+                "hostname": format!("{id}.local"),
+                "port": 9123,
+                "addresses": [],
+                "last_seen_unix": 0,
+                "accessory_info": { "productName": product }
+            }))
+            .unwrap()
+        };
+        let lumens = |light: &str, max_lumens| Calibration {
+            light: light.into(),
+            kelvin_offset: 0,
+            kelvin_scale: 1.0,
+            brightness_mode: BrightnessMode::Lumens,
+            max_lumens,
+        };
+        let config = Config {
+            lights: vec![
+                light("panel", "Elgato Key Light"),
+                light("mini", "Elgato Key Light Mini"),
+                light("other", "Elgato Light Strip"),
+            ],
+            calibration: vec![lumens("panel", Some(2000)), lumens("mini", None)],
+            ..Default::default()
+        };
+        let [panel, mini, other] = [0, 1, 2].map(|index| &config.lights[index]);
+
+        assert_eq!(to_device(&config, panel, Some(50), None), (Some(20), None));
+        assert_eq!(to_device(&config, panel, Some(1), None), (Some(1), None));
+        assert_eq!(to_device(&config, panel, Some(0), None), (Some(0), None));
+        assert_eq!(reported(&config, panel, Some(20), None), (Some(50), None));
+        assert_eq!(to_device(&config, mini, Some(50), None), (Some(50), None));
+        assert_eq!(to_device(&config, other, Some(50), None), (Some(50), None));
+    }
+}
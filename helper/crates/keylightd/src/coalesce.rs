@@ -0,0 +1,191 @@
+//! Per-device command queue. Updates for a light that arrive while a command to it is
+//! in flight, or within its minimum interval, are merged into the next command
+//! instead of each becoming a request of its own.
+//!
+//! Slider drags produce a burst of partial updates; Key Light firmware gets flaky when
+//! every one of them is a PUT. Whoever finds the device idle sends for everyone
+//! waiting, and all callers merged into a command get its response.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{KeylightError, LightState, LightUpdate, LightsPayload};
+
+type Outcome = Result<LightsPayload<LightState>, KeylightError>;
+
+#[derive(Default)]
+struct Queue {
+    /// Merged updates waiting for the next command.
+    pending: Option<LightUpdate>,
+    /// Number of the command `pending` will go out as.
+    next: u64,
+    /// Callers waiting on each command that hasn't been collected.
+    waiting: HashMap<u64, usize>,
+    /// Outcome of finished commands, until every waiting caller took it.
+    finished: HashMap<u64, Outcome>,
+    /// A caller is sending, or waiting out the interval to send.
+    busy: bool,
+    /// Earliest time the next command may start.
+    not_before: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Device {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+impl Device {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, queue: MutexGuard<'a, Queue>) -> MutexGuard<'a, Queue> {
+        self.changed
+            .wait(queue)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Queues by device address.
+static DEVICES: Mutex<BTreeMap<String, Arc<Device>>> = Mutex::new(BTreeMap::new());
+
+fn device(ip: &str) -> Arc<Device> {
+    let mut devices = DEVICES.lock().unwrap_or_else(PoisonError::into_inner);
+    Arc::clone(devices.entry(ip.to_string()).or_default())
+}
+
+/// Send `update` to the device at `ip` through `deliver`, merged with whatever else
+/// is queued for it, at most one command per `interval`.
+pub fn send(
+    ip: &str,
+    interval: Duration,
+    update: &LightUpdate,
+    deliver: impl Fn(&LightUpdate) -> Outcome,
+) -> Outcome {
+    let device = device(ip);
+    let mut queue = device.lock();
+    queue
+        .pending
+        .get_or_insert_with(LightUpdate::default)
+        .merge(update);
+    let command = queue.next;
+    *queue.waiting.entry(command).or_default() += 1;
+
+    loop {
+        if let Some(outcome) = collect(&mut queue, command) {
+            return outcome;
+        }
+        if queue.busy {
+            queue = device.wait(queue);
+            continue;
+        }
+
+        // Nobody is sending: this caller sends for everyone queued so far.
+        queue.busy = true;
+        let start = queue.not_before.unwrap_or_else(Instant::now);
+        drop(queue);
+        thread::sleep(start.saturating_duration_since(Instant::now()));
+        queue = device.lock();
+        let merged = queue.pending.take().unwrap_or_default();
+        let sending = queue.next;
+        queue.next += 1;
+        drop(queue);
+
+        let outcome = deliver(&merged);
+
+        queue = device.lock();
+        queue.finished.insert(sending, outcome);
+        queue.busy = false;
+        queue.not_before = Some(Instant::now() + interval);
+        device.changed.notify_all();
+    }
+}
+
+/// Take this caller's share of a finished command's outcome.
+fn collect(queue: &mut Queue, command: u64) -> Option<Outcome> {
+    let outcome = queue.finished.get(&command)?.clone();
+    let waiting = queue.waiting.entry(command).or_default();
+    *waiting = waiting.saturating_sub(1);
+    if *waiting == 0 {
+        queue.waiting.remove(&command);
+        queue.finished.remove(&command);
+    }
+    Some(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn echo(update: &LightUpdate) -> Outcome {
+        Ok(LightsPayload {
+            number_of_lights: 1,
+            lights: vec![LightState {
+                on: update.on.unwrap_or(0),
+                brightness: update.brightness.unwrap_or(0),
+                temperature: update.temperature.unwrap_or(0),
+                hue: None,
+                saturation: None,
+            }],
+        })
+    }
+
+    #[test]
+    fn updates_during_a_command_are_merged_into_the_next() {
+        let sent = AtomicUsize::new(0);
+        let slow_echo = |update: &LightUpdate| {
+            sent.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(100));
+            echo(update)
+        };
+        let interval = Duration::from_millis(10);
+        let brightness = |value| LightUpdate {
+            brightness: Some(value),
+            ..Default::default()
+        };
+        thread::scope(|scope| {
+            let first = scope.spawn(|| send("test-merge", interval, &brightness(10), slow_echo));
+            thread::sleep(Duration::from_millis(30));
+            let later: Vec<_> = [20, 30, 40]
+                .map(|value| {
+                    let update = brightness(value);
+                    let slow_echo = &slow_echo;
+                    scope.spawn(move || send("test-merge", interval, &update, slow_echo))
+                })
+                .into_iter()
+                .collect();
+            let first = first.join().unwrap().unwrap();
+            assert_eq!(first.lights[0].brightness, 10);
+            let merged: Vec<u8> = later
+                .into_iter()
+                .map(|handle| handle.join().unwrap().unwrap().lights[0].brightness)
+                .collect();
+            // All three land in one command, so they share the last-merged value.
+            assert!(merged.iter().all(|&value| value == merged[0]));
+        });
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn later_fields_win_and_color_replaces_temperature() {
+        let mut merged = LightUpdate {
+            on: Some(1),
+            temperature: Some(200),
+            ..Default::default()
+        };
+        merged.merge(&LightUpdate {
+            brightness: Some(30),
+            hue: Some(120.0),
+            saturation: Some(80.0),
+            ..Default::default()
+        });
+        assert_eq!(merged.on, Some(1));
+        assert_eq!(merged.brightness, Some(30));
+        assert_eq!(merged.temperature, None);
+        assert_eq!(merged.hue, Some(120.0));
+    }
+}
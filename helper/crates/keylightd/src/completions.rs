@@ -0,0 +1,145 @@
+//! Shell completions (`keylightd completions <shell>`) and the man page
+//! (`keylightd man`). Completions are generated at the time of the Tab press by
+//! `keylightd` itself, so `--id`, `--group` and `--preset` offer the lights, groups
+//! and presets in the config instead of making you type long mDNS names.
+
+use clap::{CommandFactory, ValueEnum};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use std::error::Error;
+use std::ffi::OsStr;
+
+use super::{load_config, Cli, Config};
+
+/// Environment variable the registered shell function sets when asking for
+/// candidates.
+const COMPLETE_VAR: &str = "COMPLETE";
+const BIN: &str = "keylightd";
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        }
+    }
+}
+
+/// Answer a completion request from the shell and exit; returns when this run isn't
+/// one. Call before parsing the command line.
+pub fn complete_if_asked() {
+    clap_complete::CompleteEnv::with_factory(Cli::command)
+        .var(COMPLETE_VAR)
+        .complete();
+}
+
+/// Print the script that hooks `keylightd` into `shell`'s completion.
+pub fn print_registration(shell: Shell) -> Result<(), Box<dyn Error>> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell.name())
+        .ok_or_else(|| format!("No completions for {}", shell.name()))?;
+    completer.write_registration(COMPLETE_VAR, BIN, BIN, BIN, &mut std::io::stdout())?;
+    Ok(())
+}
+
+/// Print the roff man page.
+pub fn print_man() -> Result<(), Box<dyn Error>> {
+    clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())?;
+    Ok(())
+}
+
+pub fn lights(current: &OsStr) -> Vec<CompletionCandidate> {
+    candidates(current, light_names)
+}
+
+pub fn groups(current: &OsStr) -> Vec<CompletionCandidate> {
+    candidates(current, |config| {
+        config
+            .groups
+            .iter()
+            .map(|group| (group.name.clone(), None))
+            .collect()
+    })
+}
+
+pub fn presets(current: &OsStr) -> Vec<CompletionCandidate> {
+    candidates(current, |config| {
+        config
+            .presets
+            .iter()
+            .map(|preset| (preset.name.clone(), None))
+            .collect()
+    })
+}
+
+fn candidates(
+    current: &OsStr,
+    names: impl Fn(&Config) -> Vec<(String, Option<String>)>,
+) -> Vec<CompletionCandidate> {
+    let (Some(current), Ok(config)) = (current.to_str(), load_config()) else {
+        return Vec::new();
+    };
+    names(&config)
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(current))
+        .map(|(name, help)| CompletionCandidate::new(name).help(help.map(Into::into)))
+        .collect()
+}
+
+/// Every name a light answers to: its alias, shown with the light's name, and its id.
+fn light_names(config: &Config) -> Vec<(String, Option<String>)> {
+    let mut names = Vec::new();
+    for light in &config.lights {
+        if let Some(alias) = &light.alias {
+            names.push((alias.clone(), Some(light.name.clone())));
+        }
+        names.push((light.id.clone(), light.alias.clone()));
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LightRecord;
+
+    #[test]
+    fn lights_complete_by_alias_and_id() {
+        let light: LightRecord = serde_json::from_value(serde_json::json!({
+            "id": "Elgato Key Light ABCD._elg._tcp.local.",
+            "alias": "left",
+            "name": "Elgato Key Light ABCD._elg._tcp.local.",
+            "hostname": "abcd.local",
+            "port": 9123,
+            "addresses": ["192.168.1.20"],
+            "last_seen_unix": 0
+        }))
+        .unwrap();
+        let config = Config {
+            lights: vec![light],
+            ..Default::default()
+        };
+        assert_eq!(
+            light_names(&config),
+            vec![
+                (
+                    "left".to_string(),
+                    Some("Elgato Key Light ABCD._elg._tcp.local.".to_string())
+                ),
+                (
+                    "Elgato Key Light ABCD._elg._tcp.local.".to_string(),
+                    Some("left".to_string())
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! Call detection: polls PipeWire (through `pw-dump`) for capture streams opened by
+//! conferencing apps and applies a snapshot while one is running.
+//!
+//! Watching capture streams rather than `/dev/video*` also catches browser calls,
+//! which on PipeWire desktops often reach the camera through the portal.
+
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+
+use super::dnd;
+use super::load_config;
+use super::logs::log_line;
+use super::notices::{self, Notice};
+use super::profiles::{self, Automation};
+use super::snapshot::SceneSwitcher;
+use super::status;
+
+/// Snapshot of the lights taken before a call scene is applied.
+const PREVIOUS_SNAPSHOT: &str = "call-previous";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ConferencingConfig {
+    /// Snapshot applied during a call, unless the matching rule names another.
+    pub scene: String,
+    #[serde(default = "default_poll_secs")]
+    pub poll_secs: u64,
+    /// Checked in order; the first rule matching a running stream wins.
+    #[serde(default = "default_rules")]
+    pub rules: Vec<AppRule>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AppRule {
+    /// Case-insensitive substring of the stream's application name or binary.
+    pub app: String,
+    #[serde(default)]
+    pub media: Media,
+    #[serde(default)]
+    pub scene: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Media {
+    #[default]
+    Any,
+    Audio,
+    Video,
+}
+
+fn default_poll_secs() -> u64 {
+    5
+}
+
+/// Zoom and Teams count on any capture; browsers only when they use the camera, since
+/// plenty of sites ask for the microphone.
+fn default_rules() -> Vec<AppRule> {
+    let rule = |app: &str, media| AppRule {
+        app: app.to_string(),
+        media,
+        scene: None,
+    };
+    vec![
+        rule("zoom", Media::Any),
+        rule("teams", Media::Any),
+        rule("chrome", Media::Video),
+        rule("chromium", Media::Video),
+        rule("firefox", Media::Video),
+    ]
+}
+
+/// A running audio or video capture stream.
+#[derive(Debug, Clone, PartialEq)]
+struct CaptureStream {
+    app: String,
+    binary: String,
+    video: bool,
+}
+
+impl AppRule {
+    fn matches(&self, stream: &CaptureStream) -> bool {
+        let media = match self.media {
+            Media::Any => true,
+            Media::Audio => !stream.video,
+            Media::Video => stream.video,
+        };
+        let app = self.app.to_lowercase();
+        media
+            && (stream.app.to_lowercase().contains(&app)
+                || stream.binary.to_lowercase().contains(&app))
+    }
+}
+
+impl ConferencingConfig {
+    fn scene_for(&self, streams: &[CaptureStream]) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| streams.iter().any(|stream| rule.matches(stream)))
+            .map(|rule| rule.scene.as_deref().unwrap_or(&self.scene))
+    }
+}
+
+/// Running capture streams in `pw-dump` output.
+fn capture_streams(dump: &Value) -> Vec<CaptureStream> {
+    let Some(objects) = dump.as_array() else {
+        return Vec::new();
+    };
+    objects
+        .iter()
+        .filter(|object| {
+            object.get("type").and_then(Value::as_str) == Some("PipeWire:Interface:Node")
+        })
+        .filter_map(|node| {
+            let info = node.get("info")?;
+            if info.get("state").and_then(Value::as_str) != Some("running") {
+                return None;
+            }
+            let props = info.get("props")?;
+            let prop = |key: &str| {
+                props
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let video = match prop("media.class").as_str() {
+                "Stream/Input/Audio" => false,
+                "Stream/Input/Video" => true,
+                _ => return None,
+            };
+            Some(CaptureStream {
+                app: prop("application.name"),
+                binary: prop("application.process.binary"),
+                video,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum PollError {
+    /// `pw-dump` couldn't be started, so polling again won't help.
+    #[error("can't run pw-dump ({0})")]
+    Unavailable(io::Error),
+    #[error("pw-dump exited with {0}")]
+    Failed(std::process::ExitStatus),
+    #[error("unreadable pw-dump output: {0}")]
+    Output(serde_json::Error),
+}
+
+fn poll() -> Result<Vec<CaptureStream>, PollError> {
+    let output = Command::new("pw-dump")
+        .output()
+        .map_err(PollError::Unavailable)?;
+    if !output.status.success() {
+        return Err(PollError::Failed(output.status));
+    }
+    let dump = serde_json::from_slice(&output.stdout).map_err(PollError::Output)?;
+    Ok(capture_streams(&dump))
+}
+
+/// Whether any app is capturing from a camera, for profile rules.
+pub fn camera_in_use() -> Result<bool, PollError> {
+    Ok(poll()?.iter().any(|stream| stream.video))
+}
+
+/// Start call detection if `[conferencing]` is configured. Called once by `serve`.
+pub fn spawn(client: Client) {
+    let config = match load_config() {
+        Ok(config) => config.conferencing,
+        Err(err) => {
+            log_line!("conferencing: {err}");
+            return;
+        }
+    };
+    let Some(config) = config else {
+        return;
+    };
+    let spawned = thread::Builder::new()
+        .name("conferencing".into())
+        .spawn(move || run(client, config));
+    match spawned {
+        Ok(_) => status::automation_started("conferencing"),
+        Err(err) => log_line!("failed to start call detection: {err}"),
+    }
+}
+
+fn run(client: Client, config: ConferencingConfig) {
+    let interval = Duration::from_secs(config.poll_secs.max(1));
+    let mut scenes = SceneSwitcher::new(PREVIOUS_SNAPSHOT);
+    loop {
+        match poll() {
+            // Picked up again by the first poll after do-not-disturb, or a profile that
+            // turns call detection off, ends.
+            Ok(_) if dnd::active() || profiles::disabled(Automation::Conferencing) => {}
+            Ok(streams) => {
+                let scene = config.scene_for(&streams);
+                match scenes.switch(&client, scene) {
+                    Ok(true) => notices::post(match scene {
+                        Some(scene) => Notice::CallStarted {
+                            scene: scene.to_string(),
+                        },
+                        None => Notice::CallEnded,
+                    }),
+                    Ok(false) => {}
+                    Err(err) => log_line!("conferencing: {err}"),
+                }
+            }
+            Err(err @ PollError::Unavailable(_)) => {
+                log_line!("conferencing: {err}; call detection is off");
+                status::automation_stopped("conferencing");
+                return;
+            }
+            Err(err) => log_line!("conferencing: {err}"),
+        }
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(class: &str, app: &str, binary: &str, state: &str) -> Value {
+        json!({
+            "id": 60,
+            "type": "PipeWire:Interface:Node",
+            "info": {
+                "state": state,
+                "props": {
+                    "media.class": class,
+                    "application.name": app,
+                    "application.process.binary": binary,
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn only_running_capture_streams_count() {
+        let dump = json!([
+            node("Stream/Input/Audio", "ZOOM VoiceEngine", "zoom", "running"),
+            node("Stream/Input/Video", "Firefox", "firefox", "idle"),
+            node("Stream/Output/Audio", "Firefox", "firefox", "running"),
+            node("Audio/Source", "", "", "running"),
+        ]);
+        assert_eq!(
+            capture_streams(&dump),
+            vec![CaptureStream {
+                app: "ZOOM VoiceEngine".into(),
+                binary: "zoom".into(),
+                video: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn browsers_need_the_camera() {
+        let config = ConferencingConfig {
+            scene: "call".into(),
+            poll_secs: 5,
+            rules: default_rules(),
+        };
+        let mic = CaptureStream {
+            app: "Google Chrome".into(),
+            binary: "chrome".into(),
+            video: false,
+        };
+        assert_eq!(config.scene_for(std::slice::from_ref(&mic)), None);
+        let camera = CaptureStream { video: true, ..mic };
+        assert_eq!(config.scene_for(&[camera]), Some("call"));
+    }
+
+    #[test]
+    fn rule_scene_overrides_default() {
+        let config = ConferencingConfig {
+            scene: "call".into(),
+            poll_secs: 5,
+            rules: vec![AppRule {
+                app: "Teams".into(),
+                media: Media::Any,
+                scene: Some("work-call".into()),
+            }],
+        };
+        let teams = CaptureStream {
+            app: "teams-for-linux".into(),
+            binary: "teams-for-linux".into(),
+            video: false,
+        };
+        assert_eq!(config.scene_for(&[teams]), Some("work-call"));
+    }
+}
@@ -0,0 +1,189 @@
+//! Cross-origin access for browser dashboards, from the `[cors]` section. A page on
+//! another origin can only read responses naming its origin in
+//! `Access-Control-Allow-Origin`, and asks with an `OPTIONS` preflight before most
+//! requests. With no `allowed_origins`, the default, the API is for the pages
+//! keylightd serves itself and for clients that aren't browsers.
+
+use serde::Deserialize;
+use std::sync::{Mutex, PoisonError};
+use tiny_http::{Header, Response, StatusCode};
+
+use super::json_client_error;
+
+/// Methods the API answers, for `Allow` and preflights.
+const METHODS: &str = "GET, POST, PUT, DELETE, OPTIONS";
+/// Request headers an allowed page may send beyond the ones browsers always allow.
+const ALLOWED_HEADERS: &str = "Content-Type, If-None-Match";
+/// Error for a page on an origin that isn't allowed.
+pub const REFUSED: &str = "Origin not allowed; add it to allowed_origins under [cors].";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins such as `http://localhost:3000` whose pages may call the API, or
+    /// `"*"` for any.
+    pub allowed_origins: Vec<String>,
+    /// How long a browser may reuse a preflight answer.
+    pub max_age_secs: u64,
+}
+
+const DEFAULT: CorsConfig = CorsConfig {
+    allowed_origins: Vec::new(),
+    max_age_secs: 600,
+};
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+static CONFIGURED: Mutex<CorsConfig> = Mutex::new(DEFAULT);
+
+/// Apply the `[cors]` section of the config. Called on every config load, like
+/// `device::configure`, so origins can be added without a restart.
+pub fn configure(config: &CorsConfig) {
+    *CONFIGURED.lock().unwrap_or_else(PoisonError::into_inner) = config.clone();
+}
+
+fn configured() -> CorsConfig {
+    CONFIGURED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Where a request comes from, as far as browsers are concerned.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Access {
+    /// No `Origin` (curl, the tray, scripts), or a page keylightd served.
+    #[default]
+    SameOrigin,
+    /// A page on one of `allowed_origins`; responses name it.
+    Allowed(String),
+    /// A page on any other origin. It can't read responses, and changes from it
+    /// are refused.
+    Refused,
+}
+
+impl Access {
+    /// Judge a request by its `Origin` and `Host` headers.
+    pub fn check(origin: Option<&str>, host: Option<&str>) -> Access {
+        Self::check_against(&configured(), origin, host)
+    }
+
+    fn check_against(config: &CorsConfig, origin: Option<&str>, host: Option<&str>) -> Access {
+        let Some(origin) = origin else {
+            return Access::SameOrigin;
+        };
+        let same_host = origin
+            .split_once("://")
+            .is_some_and(|(_, authority)| Some(authority) == host);
+        if same_host {
+            return Access::SameOrigin;
+        }
+        let normalized = origin.trim_end_matches('/');
+        let allowed = config.allowed_origins.iter().any(|allowed| {
+            allowed == "*"
+                || allowed
+                    .trim_end_matches('/')
+                    .eq_ignore_ascii_case(normalized)
+        });
+        if allowed && origin != "null" {
+            Access::Allowed(origin.to_string())
+        } else {
+            Access::Refused
+        }
+    }
+
+    /// Whether the request may change anything. Browsers send simple cross-origin
+    /// requests (a form POST, say) without a preflight, so refusing only to show
+    /// the answer would still let any page switch the lights.
+    pub fn may_change(&self) -> bool {
+        *self != Access::Refused
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+/// Headers a response to a request with `access` carries. Once origins are
+/// configured, every response depends on `Origin`, so it's listed under `Vary`
+/// even where nothing was granted.
+pub fn headers(access: &Access) -> Vec<Header> {
+    let mut headers = Vec::new();
+    if let Access::Allowed(origin) = access {
+        headers.push(header("Access-Control-Allow-Origin", origin));
+    }
+    if !configured().allowed_origins.is_empty() {
+        headers.push(header("Vary", "Origin"));
+    }
+    headers
+}
+
+/// Answer an `OPTIONS` request: a preflight from an allowed page gets the methods
+/// and headers it may use, one from any other page a 403, and a plain `OPTIONS`
+/// the methods in `Allow`.
+pub fn preflight(access: &Access, is_preflight: bool) -> Response<std::io::Cursor<Vec<u8>>> {
+    let vary = header(
+        "Vary",
+        "Origin, Access-Control-Request-Method, Access-Control-Request-Headers",
+    );
+    match access {
+        Access::Refused if is_preflight => {
+            json_client_error(StatusCode(403), REFUSED).with_header(vary)
+        }
+        Access::Allowed(origin) if is_preflight => Response::from_data(Vec::new())
+            .with_status_code(StatusCode(204))
+            .with_header(header("Access-Control-Allow-Origin", origin))
+            .with_header(header("Access-Control-Allow-Methods", METHODS))
+            .with_header(header("Access-Control-Allow-Headers", ALLOWED_HEADERS))
+            .with_header(header(
+                "Access-Control-Max-Age",
+                &configured().max_age_secs.to_string(),
+            ))
+            .with_header(vary),
+        _ => Response::from_data(Vec::new())
+            .with_status_code(StatusCode(204))
+            .with_header(header("Allow", METHODS)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_listed_origins_are_allowed() {
+        let host = Some("127.0.0.1:9124");
+        let config = CorsConfig {
+            allowed_origins: vec!["http://localhost:3000/".into()],
+            ..CorsConfig::default()
+        };
+        let check = |origin| Access::check_against(&config, origin, host);
+        assert_eq!(check(None), Access::SameOrigin);
+        assert_eq!(check(Some("http://127.0.0.1:9124")), Access::SameOrigin);
+        assert_eq!(
+            check(Some("http://localhost:3000")),
+            Access::Allowed("http://localhost:3000".into())
+        );
+        assert_eq!(check(Some("http://evil.example")), Access::Refused);
+        assert!(!Access::Refused.may_change());
+
+        let default = CorsConfig::default();
+        let origin = Some("http://localhost:3000");
+        assert_eq!(
+            Access::check_against(&default, origin, host),
+            Access::Refused
+        );
+        let any = CorsConfig {
+            allowed_origins: vec!["*".into()],
+            ..CorsConfig::default()
+        };
+        assert_eq!(
+            Access::check_against(&any, Some("null"), host),
+            Access::Refused
+        );
+    }
+}
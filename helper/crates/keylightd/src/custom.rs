@@ -0,0 +1,335 @@
+//! User-defined HTTP lights (Tasmota, ESPHome, DIY firmware), configured in
+//! `config.toml` as `[[custom_lights]]` with a URL template per action.
+//!
+//! Templates may use `{host}`, `{brightness}` (0-100), `{brightness_255}`, `{kelvin}`
+//! and `{mired}`. Each must point at `{host}`, which has to be a LAN address, so a
+//! config entry can't make the daemon call out to arbitrary servers.
+//!
+//! These devices have no common state endpoint, so the state reported for them is
+//! whatever the daemon last sent.
+
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use super::error::KeylightError;
+use super::logs::log_line;
+use super::{
+    load_config, validate_manual_ip, Backend, Capabilities, Config, KelvinRange, LightRecord,
+    LightState, LightUpdate,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    /// ESPHome's REST API only accepts POST for actions.
+    Post,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomLight {
+    pub id: String,
+    pub name: String,
+    /// LAN IP address, substituted for `{host}`.
+    pub host: String,
+    pub on_url: String,
+    pub off_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_url: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default_method")]
+    pub method: HttpMethod,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kelvin_range: Option<KelvinRange>,
+}
+
+fn is_default_method(method: &HttpMethod) -> bool {
+    *method == HttpMethod::Get
+}
+
+/// Last state sent to each custom light, keyed by host.
+static LAST_SENT: Mutex<BTreeMap<String, (u8, u8, u16)>> = Mutex::new(BTreeMap::new());
+
+impl CustomLight {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() || self.name.trim().is_empty() {
+            return Err("id and name must not be empty".into());
+        }
+        validate_manual_ip(&self.host).map_err(|err| format!("host: {err}"))?;
+        let templates = [
+            ("on_url", Some(&self.on_url), &[][..]),
+            ("off_url", Some(&self.off_url), &[][..]),
+            (
+                "brightness_url",
+                self.brightness_url.as_ref(),
+                &["{brightness}", "{brightness_255}"][..],
+            ),
+            (
+                "temperature_url",
+                self.temperature_url.as_ref(),
+                &["{kelvin}", "{mired}"][..],
+            ),
+        ];
+        for (field, template, placeholders) in templates {
+            let Some(template) = template else {
+                continue;
+            };
+            if !template.starts_with("http://{host}") && !template.starts_with("https://{host}") {
+                return Err(format!("{field} must start with http://{{host}}"));
+            }
+            // `{host}@other/` or `{host}.other/` starts the same but goes elsewhere.
+            let url = reqwest::Url::parse(&self.render(template, 100, 250))
+                .map_err(|err| format!("{field}: {err}"))?;
+            if url.host_str() != Some(self.host.as_str()) {
+                return Err(format!("{field} must point at {{host}}"));
+            }
+            if !placeholders.is_empty() && !placeholders.iter().any(|p| template.contains(p)) {
+                return Err(format!(
+                    "{field} must contain {}",
+                    placeholders.join(" or ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            brightness: self.brightness_url.is_some(),
+            temperature: self.temperature_url.is_some(),
+            color: false,
+        }
+    }
+
+    fn render(&self, template: &str, brightness: u8, mired: u16) -> String {
+        let range = self.kelvin_range.unwrap_or(KelvinRange::DEFAULT);
+        template
+            .replace("{host}", &self.host)
+            .replace(
+                "{brightness_255}",
+                &(brightness as u32 * 255 / 100).to_string(),
+            )
+            .replace("{brightness}", &brightness.to_string())
+            .replace("{kelvin}", &range.mired_to_kelvin(mired).to_string())
+            .replace("{mired}", &mired.to_string())
+    }
+
+    fn send(&self, client: &Client, url: &str) -> Result<(), KeylightError> {
+        let request = match self.method {
+            HttpMethod::Get => client.get(url),
+            HttpMethod::Post => client.post(url),
+        };
+        request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| KeylightError::from_request(&self.host, err))?;
+        Ok(())
+    }
+}
+
+/// Validate `[[custom_lights]]` and mirror them into `config.lights`, keeping alias
+/// and enabled state from earlier runs. Invalid entries are skipped with a warning;
+/// records for entries that were removed from the config are dropped.
+pub fn merge_into(config: &mut Config) {
+    let mut valid = Vec::new();
+    for light in &config.custom_lights {
+        match light.validate() {
+            Err(err) => log_line!("ignoring custom light '{}': {err}", light.id),
+            Ok(())
+                if config
+                    .lights
+                    .iter()
+                    .any(|l| l.id == light.id && l.backend != Backend::Custom) =>
+            {
+                log_line!(
+                    "ignoring custom light '{}': id is already used by a discovered light",
+                    light.id
+                );
+            }
+            Ok(()) => valid.push(light.clone()),
+        }
+    }
+    config
+        .lights
+        .retain(|l| l.backend != Backend::Custom || valid.iter().any(|c| c.id == l.id));
+    for light in valid {
+        let existing = config.lights.iter_mut().find(|l| l.id == light.id);
+        let record = LightRecord {
+            id: light.id.clone(),
+            alias: existing.as_ref().and_then(|l| l.alias.clone()),
+            name: light.name.clone(),
+            hostname: light.host.clone(),
+            port: if light.on_url.starts_with("https://") {
+                443
+            } else {
+                80
+            },
+            addresses: vec![light.host.clone()],
+            last_seen_unix: existing.as_ref().map_or(0, |l| l.last_seen_unix),
+            enabled: existing.as_ref().is_none_or(|l| l.enabled),
+            accessory_info: None,
+            schedule_paused: existing.as_ref().is_some_and(|l| l.schedule_paused),
+            schedule_skip_next: existing.as_ref().is_some_and(|l| l.schedule_skip_next),
+            backend: Backend::Custom,
+            capabilities: light.capabilities(),
+            kelvin_range: light.kelvin_range,
+            soft_start: existing.as_ref().is_some_and(|l| l.soft_start),
+            icon: existing.as_ref().and_then(|l| l.icon.clone()),
+            room: existing.as_ref().and_then(|l| l.room.clone()),
+            notes: existing.as_ref().and_then(|l| l.notes.clone()),
+            favorite: existing.as_ref().is_some_and(|l| l.favorite),
+            brightness_limits: existing.as_ref().and_then(|l| l.brightness_limits),
+            sort_index: existing.as_ref().and_then(|l| l.sort_index),
+            zone: None,
+        };
+        match existing {
+            Some(slot) => *slot = record,
+            None => config.lights.push(record),
+        }
+    }
+}
+
+/// Run the templates `update` needs: power first, then brightness and temperature.
+pub fn set(client: &Client, ip: &str, update: &LightUpdate) -> Result<LightState, KeylightError> {
+    let config = load_config()?;
+    let light = config
+        .custom_lights
+        .iter()
+        .find(|light| light.host == ip)
+        .ok_or_else(|| KeylightError::not_found("custom light", ip))?;
+    let (mut on, mut brightness, mut temperature) = last_sent(ip).unwrap_or((0, 100, 0));
+    if update.on == Some(0) {
+        light.send(
+            client,
+            &light.render(&light.off_url, brightness, temperature),
+        )?;
+        on = 0;
+    } else {
+        if update.on == Some(1) {
+            light.send(
+                client,
+                &light.render(&light.on_url, brightness, temperature),
+            )?;
+            on = 1;
+        }
+        if let (Some(value), Some(template)) = (update.brightness, &light.brightness_url) {
+            light.send(client, &light.render(template, value, temperature))?;
+            brightness = value;
+        }
+        if let (Some(value), Some(template)) = (update.temperature, &light.temperature_url) {
+            light.send(client, &light.render(template, brightness, value))?;
+            temperature = value;
+        }
+    }
+    if let Ok(mut sent) = LAST_SENT.lock() {
+        sent.insert(ip.to_string(), (on, brightness, temperature));
+    }
+    Ok(LightState {
+        on,
+        brightness,
+        temperature,
+        hue: None,
+        saturation: None,
+    })
+}
+
+fn last_sent(ip: &str) -> Option<(u8, u8, u16)> {
+    LAST_SENT.lock().ok()?.get(ip).copied()
+}
+
+pub fn get_state(ip: &str) -> Option<LightState> {
+    let (on, brightness, temperature) = last_sent(ip)?;
+    Some(LightState {
+        on,
+        brightness,
+        temperature,
+        hue: None,
+        saturation: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasmota() -> CustomLight {
+        CustomLight {
+            id: "desk".into(),
+            name: "Desk panel".into(),
+            host: "192.168.1.40".into(),
+            on_url: "http://{host}/cm?cmnd=Power%20On".into(),
+            off_url: "http://{host}/cm?cmnd=Power%20Off".into(),
+            brightness_url: Some("http://{host}/cm?cmnd=Dimmer%20{brightness}".into()),
+            temperature_url: Some("http://{host}/cm?cmnd=CT%20{mired}".into()),
+            method: HttpMethod::Get,
+            kelvin_range: None,
+        }
+    }
+
+    #[test]
+    fn templates_render_placeholders() {
+        let light = tasmota();
+        assert!(light.validate().is_ok());
+        assert_eq!(
+            light.render(light.brightness_url.as_ref().unwrap(), 40, 250),
+            "http://192.168.1.40/cm?cmnd=Dimmer%2040"
+        );
+        assert_eq!(
+            light.render("http://{host}/b={brightness_255}&k={kelvin}", 100, 250),
+            "http://192.168.1.40/b=255&k=4000"
+        );
+    }
+
+    #[test]
+    fn templates_must_target_the_lan_host() {
+        let mut light = tasmota();
+        light.on_url = "http://example.com/on".into();
+        assert!(light.validate().is_err());
+
+        let mut light = tasmota();
+        light.host = "8.8.8.8".into();
+        assert!(light.validate().is_err());
+
+        let mut light = tasmota();
+        light.brightness_url = Some("http://{host}/cm?cmnd=Dimmer%2050".into());
+        assert!(light.validate().is_err());
+
+        for url in [
+            "http://{host}@evil.example/on",
+            "http://{host}.evil.example/on",
+        ] {
+            let mut light = tasmota();
+            light.on_url = url.into();
+            assert!(light.validate().is_err(), "{url}");
+        }
+        let mut light = tasmota();
+        light.on_url = "http://{host}:8080?cmnd=Power%20On".into();
+        assert!(light.validate().is_ok());
+    }
+
+    #[test]
+    fn merge_keeps_user_settings_and_drops_removed_lights() {
+        let mut config = Config {
+            custom_lights: vec![tasmota()],
+            ..Default::default()
+        };
+        merge_into(&mut config);
+        assert_eq!(config.lights.len(), 1);
+        assert_eq!(config.lights[0].backend, Backend::Custom);
+        assert!(config.lights[0].capabilities.brightness);
+
+        config.lights[0].alias = Some("desk".into());
+        config.lights[0].enabled = false;
+        merge_into(&mut config);
+        assert_eq!(config.lights[0].alias.as_deref(), Some("desk"));
+        assert!(!config.lights[0].enabled);
+
+        config.custom_lights.clear();
+        merge_into(&mut config);
+        assert!(config.lights.is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+//! Timeouts and retries for HTTP requests to lights, from the `[device]` section.
+//! A client in a hurry (a key press that should fail fast rather than hang) can
+//! lower them for one API request with `?timeout_ms=` and `?retries=`.
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct DeviceConfig {
+    /// Time allowed to open the connection. Read when the process starts.
+    pub connect_timeout_ms: u64,
+    /// Time allowed for a whole request, connecting included.
+    pub timeout_ms: u64,
+    /// Further attempts after a timeout, a refused connection or a 5xx answer.
+    pub retries: u32,
+    /// Pause before the first retry; each further one waits twice as long.
+    pub retry_backoff_ms: u64,
+}
+
+const DEFAULT: DeviceConfig = DeviceConfig {
+    connect_timeout_ms: 3000,
+    timeout_ms: 3000,
+    retries: 0,
+    retry_backoff_ms: 200,
+};
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+/// Most retries one API request may ask for with `?retries=`.
+pub const MAX_RETRIES: u32 = 5;
+
+/// Longest pause between two attempts, however many retries came before.
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+static CONFIGURED: Mutex<DeviceConfig> = Mutex::new(DEFAULT);
+
+/// Apply the `[device]` section of the config. Called on every config load, like
+/// `limits::configure`, so timeouts and retries change without a restart.
+pub fn configure(config: &DeviceConfig) {
+    *CONFIGURED.lock().unwrap_or_else(PoisonError::into_inner) = *config;
+}
+
+/// The HTTP client for lights, with the connect and request timeouts of `config`.
+pub fn client(config: &DeviceConfig) -> reqwest::Result<Client> {
+    Client::builder()
+        .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .build()
+}
+
+/// `[device]` settings replaced for the API request being answered.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Overrides {
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+}
+
+thread_local! {
+    /// Set by the thread answering a request, and handed on to the threads it fans
+    /// out to, so `send` doesn't need it passed through every call.
+    static OVERRIDES: Cell<Overrides> = const {
+        Cell::new(Overrides {
+            timeout: None,
+            retries: None,
+        })
+    };
+}
+
+impl Overrides {
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let mut overrides = Self::default();
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let invalid = || format!("Invalid {key} '{value}'");
+            match key {
+                "timeout_ms" => {
+                    let ms: u64 = value.parse().map_err(|_| invalid())?;
+                    if ms == 0 {
+                        return Err(invalid());
+                    }
+                    overrides.timeout = Some(Duration::from_millis(ms));
+                }
+                "retries" => {
+                    let retries: u32 = value.parse().map_err(|_| invalid())?;
+                    if retries > MAX_RETRIES {
+                        return Err(format!("retries can be at most {MAX_RETRIES}"));
+                    }
+                    overrides.retries = Some(retries);
+                }
+                _ => {}
+            }
+        }
+        Ok(overrides)
+    }
+
+    pub fn install(self) {
+        OVERRIDES.with(|overrides| overrides.set(self));
+    }
+
+    pub fn current() -> Self {
+        OVERRIDES.with(Cell::get)
+    }
+}
+
+/// Send the request `build` makes, trying again as `[device]` (or this request's
+/// overrides) allow. The request is rebuilt for each attempt.
+pub fn send(build: impl Fn() -> RequestBuilder) -> reqwest::Result<Response> {
+    let config = *CONFIGURED.lock().unwrap_or_else(PoisonError::into_inner);
+    let overrides = Overrides::current();
+    let timeout = overrides
+        .timeout
+        .unwrap_or(Duration::from_millis(config.timeout_ms));
+    let retries = overrides.retries.unwrap_or(config.retries);
+    let mut backoff = Duration::from_millis(config.retry_backoff_ms).min(MAX_BACKOFF);
+    let mut attempt = 0;
+    loop {
+        let result = build()
+            .timeout(timeout)
+            .send()
+            .and_then(|response| response.error_for_status());
+        match result {
+            Err(err) if attempt < retries && retryable(&err) => {
+                attempt += 1;
+                thread::sleep(backoff);
+                backoff = next_backoff(backoff);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The pause after `backoff`: twice as long, up to `MAX_BACKOFF`.
+fn next_backoff(backoff: Duration) -> Duration {
+    backoff.saturating_mul(2).min(MAX_BACKOFF)
+}
+
+/// Failures a second attempt may get past; a 4xx would only be refused again.
+fn retryable(err: &reqwest::Error) -> bool {
+    err.is_timeout()
+        || err.is_connect()
+        || err.status().is_some_and(|status| status.is_server_error())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_come_from_the_query() {
+        assert_eq!(Overrides::parse("pretty=true"), Ok(Overrides::default()));
+        let overrides = Overrides::parse("timeout_ms=500&retries=2").unwrap();
+        assert_eq!(overrides.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(overrides.retries, Some(2));
+        assert!(Overrides::parse("timeout_ms=0").is_err());
+        assert!(Overrides::parse("retries=-1").is_err());
+        assert_eq!(
+            Overrides::parse("retries=5").unwrap().retries,
+            Some(MAX_RETRIES)
+        );
+        assert!(Overrides::parse("retries=6").is_err());
+        assert!(Overrides::parse("retries=4294967295").is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_two_seconds() {
+        assert_eq!(
+            next_backoff(Duration::from_millis(200)),
+            Duration::from_millis(400)
+        );
+        assert_eq!(next_backoff(Duration::from_millis(1500)), MAX_BACKOFF);
+        assert_eq!(next_backoff(Duration::MAX), MAX_BACKOFF);
+    }
+}
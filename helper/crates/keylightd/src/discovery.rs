@@ -0,0 +1,188 @@
+//! Continuous discovery while `serve` runs: an mDNS browse stays open so lights that
+//! come online or change address are picked up without a manual refresh.
+//!
+//! The browse is restarted when an interface gains or loses an address (Wi-Fi
+//! reconnects, VPN toggles), and the whole mDNS daemon is rebuilt when it stops
+//! answering, so a network hiccup doesn't leave discovery deaf until a restart.
+//!
+//! A light is stored as soon as it's announced; asking it for its details happens on
+//! a thread of its own, so a slow light doesn't hold up the announcements after it.
+
+use flume::RecvTimeoutError;
+use keylight_core::discovery::{Browse, Found, ELGATO_SERVICE};
+use mdns_sd::{DaemonEvent, DaemonStatus, Error as MdnsError, ServiceDaemon, ServiceEvent};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::logs::log_line;
+use super::status;
+use super::{
+    load_config, lock_config_edits, probe_light, save_config, save_state, upsert_record,
+    KeylightError, LightProbe,
+};
+
+/// How often the mDNS daemon is asked whether it's still running.
+const HEALTH_CHECK: Duration = Duration::from_secs(30);
+/// Pause before rebuilding a failed daemon, so a missing network doesn't spin.
+const RETRY: Duration = Duration::from_secs(10);
+/// Announcements repeat; a light whose addresses didn't change is saved at most this often.
+const RESAVE: Duration = Duration::from_secs(300);
+
+/// Where lights are looked for, from the `[discovery]` section.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// mDNS service types browsed for Elgato-compatible lights, for clones that
+    /// announce themselves under another name. `.local.` may be left off.
+    pub services: Vec<String>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            services: vec![ELGATO_SERVICE.into()],
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    /// The service types in full, like `_elg._tcp.local.`.
+    pub fn service_types(&self) -> Vec<String> {
+        self.services
+            .iter()
+            .map(|service| {
+                let service = service.trim().trim_end_matches('.');
+                let service = service.strip_suffix(".local").unwrap_or(service);
+                format!("{service}.local.")
+            })
+            .collect()
+    }
+}
+
+/// Start continuous discovery. Called once by `serve`.
+pub fn spawn(client: Client) {
+    let spawned = thread::Builder::new()
+        .name("discovery".into())
+        .spawn(move || run(client));
+    if let Err(err) = spawned {
+        log_line!("failed to start discovery: {err}");
+    }
+}
+
+fn run(client: Client) {
+    let mut seen = HashMap::new();
+    let mut recovering = false;
+    loop {
+        let services = load_config().unwrap_or_default().discovery.service_types();
+        let result = ServiceDaemon::new().and_then(|daemon| {
+            let result = watch(&client, &daemon, &services, &mut seen, recovering);
+            let _ = daemon.shutdown();
+            result
+        });
+        if let Err(err) = result {
+            log_line!(
+                "discovery: {err}; recreating the mDNS daemon in {}s",
+                RETRY.as_secs()
+            );
+        }
+        recovering = true;
+        thread::sleep(RETRY);
+    }
+}
+
+/// Follow announcements until the daemon stops working; the error says why.
+fn watch(
+    client: &Client,
+    daemon: &ServiceDaemon,
+    services: &[String],
+    seen: &mut HashMap<String, (String, Instant)>,
+    recovering: bool,
+) -> Result<(), MdnsError> {
+    let monitor = daemon.monitor()?;
+    let mut browser = Browse::start(daemon, services)?;
+    if recovering {
+        log_line!("discovery: mDNS daemon recreated, browsing again");
+    }
+    let mut last_check = Instant::now();
+    loop {
+        match browser.recv_timeout(Duration::from_secs(1)) {
+            Ok(ServiceEvent::ServiceResolved(info)) => remember(client, seen, &Found::from(&*info)),
+            Ok(ServiceEvent::SearchStopped(_)) => {
+                return Err(MdnsError::Msg("browse stopped".into()))
+            }
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(MdnsError::Msg("mDNS daemon went away".into()))
+            }
+        }
+
+        let mut network_changed = false;
+        while let Ok(event) = monitor.try_recv() {
+            match event {
+                DaemonEvent::IpAdd(_) | DaemonEvent::IpDel(_) => network_changed = true,
+                DaemonEvent::Error(err) => log_line!("discovery: {err}"),
+                _ => {}
+            }
+        }
+        if network_changed {
+            log_line!("discovery: network changed, browsing again");
+            browser.stop(daemon)?;
+            browser = Browse::start(daemon, services)?;
+        }
+
+        if last_check.elapsed() >= HEALTH_CHECK {
+            last_check = Instant::now();
+            match daemon.status()?.recv_timeout(Duration::from_secs(2)) {
+                Ok(DaemonStatus::Running) => {}
+                Ok(status) => return Err(MdnsError::Msg(format!("mDNS daemon is {status:?}"))),
+                Err(_) => return Err(MdnsError::Msg("mDNS daemon stopped answering".into())),
+            }
+        }
+    }
+}
+
+/// Persist a resolved light, unless it was saved recently with the same addresses.
+fn remember(client: &Client, seen: &mut HashMap<String, (String, Instant)>, light: &Found) {
+    let addresses = light.addresses.join(",");
+    if seen
+        .get(&light.id)
+        .is_some_and(|(known, at)| *known == addresses && at.elapsed() < RESAVE)
+    {
+        return;
+    }
+    match store(light, None) {
+        Ok(()) => {
+            status::discovered();
+            seen.insert(light.id.clone(), (addresses, Instant::now()));
+        }
+        Err(err) => log_line!("discovery: saving {}: {err}", light.id),
+    }
+    let client = client.clone();
+    let found = light.clone();
+    let spawned = thread::Builder::new()
+        .name("discovery-probe".into())
+        .spawn(move || {
+            let probe = probe_light(&client, &found);
+            if let Err(err) = store(&found, Some(&probe)) {
+                log_line!("discovery: saving {}: {err}", found.id);
+            }
+        });
+    if let Err(err) = spawned {
+        log_line!("discovery: failed to probe {}: {err}", light.id);
+    }
+}
+
+/// Save `light` into the light records, with what `probe` learned when given.
+pub fn store(light: &Found, probe: Option<&LightProbe>) -> Result<(), KeylightError> {
+    let _editing = lock_config_edits();
+    let mut config = load_config()?;
+    let groups_changed = upsert_record(&mut config, light, probe);
+    save_state(&config)?;
+    if groups_changed {
+        save_config(&config)?;
+    }
+    Ok(())
+}
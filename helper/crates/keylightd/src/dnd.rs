@@ -0,0 +1,62 @@
+//! "Do not disturb": a window during which automations (schedules, OBS, call
+//! detection, auto brightness) leave the lights alone without being switched off.
+//! It lives in memory only, so a daemon restart ends it.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest window accepted, so a typo doesn't silence automations for months.
+pub const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+static UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DndStatus {
+    pub active: bool,
+    /// Whole seconds left, rounded up; 0 when inactive.
+    pub remaining_secs: u64,
+}
+
+/// Suspend automations for `duration` from now, replacing any running window.
+/// A zero duration ends it.
+pub fn start(duration: Duration) -> DndStatus {
+    if let Ok(mut until) = UNTIL.lock() {
+        *until = (!duration.is_zero()).then(|| Instant::now() + duration.min(MAX_DURATION));
+    }
+    status()
+}
+
+pub fn status() -> DndStatus {
+    let remaining = UNTIL
+        .lock()
+        .ok()
+        .and_then(|until| *until)
+        .map(|until| until.saturating_duration_since(Instant::now()))
+        .filter(|remaining| !remaining.is_zero());
+    DndStatus {
+        active: remaining.is_some(),
+        remaining_secs: remaining.map_or(0, |left| {
+            left.as_secs() + u64::from(left.subsec_nanos() > 0)
+        }),
+    }
+}
+
+/// Whether automations should hold off right now.
+pub fn active() -> bool {
+    status().active
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_is_capped_and_zero_ends_it() {
+        let started = start(Duration::from_secs(u64::MAX / 2));
+        assert!(started.active);
+        assert_eq!(started.remaining_secs, MAX_DURATION.as_secs());
+        assert!(!start(Duration::ZERO).active);
+        assert_eq!(status().remaining_secs, 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! The failures callers act on differently. The config files, the light backends,
+//! the background loops and everything the API handlers call return these, so a
+//! handler's status code follows from the error alone. Loops that only log a failure
+//! of something other than a light or our files (mDNS, OBS, PipeWire, the light
+//! sensor) keep that library's error.
+//!
+//! Only the CLI's own code (`run`, `watch`, `tui`, `smoke` and the output helpers)
+//! returns `Box<dyn Error>`; `main` picks these back out with `downcast_ref` for a
+//! hint, and anything boxed that isn't one of these becomes `Other`.
+
+use keylight_core::store::{FileError, TargetError};
+use std::error::Error as StdError;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error)]
+pub enum KeylightError {
+    /// `config.toml`, `state.json` or another file of ours couldn't be read,
+    /// parsed or written.
+    #[error("{}: {reason}", path.display())]
+    Config { path: PathBuf, reason: String },
+    /// The light didn't answer: refused, timed out, or skipped while offline.
+    #[error("{ip} is unreachable: {reason}")]
+    Unreachable { ip: String, reason: String },
+    /// The light answered, but with an error status or a body we can't read.
+    #[error("{ip} sent an unexpected response: {reason}")]
+    Protocol { ip: String, reason: String },
+    #[error("No persisted light found with id '{0}'")]
+    LightNotFound(String),
+    #[error("No group named '{0}'")]
+    GroupNotFound(String),
+    /// A schedule, snapshot, preset, profile or room looked up by name; `what` says which.
+    #[error("No {what} named '{name}'")]
+    NotFound { what: &'static str, name: String },
+    /// An alias that would name a second light, which `store::find` can't tell
+    /// apart. `id` is the light already known by `alias`.
+    #[error("'{alias}' already names light {id}")]
+    AliasTaken { alias: String, id: String },
+    /// A request or argument we refuse as given; the message says why.
+    #[error("{0}")]
+    Invalid(String),
+    /// Anything else: no home directory to keep files in, mDNS failing to browse, or
+    /// an error the CLI boxed.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl KeylightError {
+    pub fn config(path: impl Into<PathBuf>, reason: impl ToString) -> Self {
+        Self::Config {
+            path: path.into(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn invalid(reason: impl ToString) -> Self {
+        Self::Invalid(reason.to_string())
+    }
+
+    pub fn not_found(what: &'static str, name: impl ToString) -> Self {
+        Self::NotFound {
+            what,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn unreachable(ip: &str, reason: impl ToString) -> Self {
+        Self::Unreachable {
+            ip: ip.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn protocol(ip: &str, reason: impl ToString) -> Self {
+        Self::Protocol {
+            ip: ip.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Sort a failed request to the light at `ip` into unreachable or protocol.
+    pub fn from_request(ip: &str, err: reqwest::Error) -> Self {
+        if err.is_connect() || err.is_timeout() {
+            Self::unreachable(ip, err)
+        } else {
+            Self::protocol(ip, err)
+        }
+    }
+
+    /// Status an API response for this error gets.
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::Config { .. } | Self::Other(_) => 500,
+            Self::Unreachable { .. } | Self::Protocol { .. } => 502,
+            Self::LightNotFound(_) | Self::GroupNotFound(_) | Self::NotFound { .. } => 404,
+            Self::AliasTaken { .. } => 409,
+            Self::Invalid(_) => 400,
+        }
+    }
+
+    /// What to try next, printed under the error by the CLI.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Config { .. } => Some("check the file's permissions and TOML/JSON syntax"),
+            Self::Unreachable { .. } => Some(
+                "check the light is powered and on this network; \
+                 `keylightd discover` picks up a new address",
+            ),
+            Self::Protocol { .. } => Some("the light may need a restart or a firmware update"),
+            Self::LightNotFound(_) => {
+                Some("`keylightd list` shows saved lights; `keylightd discover` finds new ones")
+            }
+            Self::GroupNotFound(_) => Some("groups are listed under [[groups]] in config.toml"),
+            Self::NotFound { what: "schedule", .. } => {
+                Some("schedules are listed under [[schedules]] in config.toml")
+            }
+            Self::NotFound { what: "snapshot", .. } => Some("`keylightd snapshot list` shows them"),
+            Self::AliasTaken { .. } => Some("pick another alias or rename the other light first"),
+            Self::NotFound { .. } | Self::Invalid(_) | Self::Other(_) => None,
+        }
+    }
+}
+
+/// Keeps the kind of an error that was boxed on the way up.
+impl From<Box<dyn StdError>> for KeylightError {
+    fn from(err: Box<dyn StdError>) -> Self {
+        match err.downcast::<KeylightError>() {
+            Ok(typed) => *typed,
+            Err(err) => Self::Other(err.to_string()),
+        }
+    }
+}
+
+impl From<FileError> for KeylightError {
+    fn from(err: FileError) -> Self {
+        Self::Config {
+            path: err.path,
+            reason: err.reason,
+        }
+    }
+}
+
+impl From<TargetError> for KeylightError {
+    fn from(err: TargetError) -> Self {
+        match err {
+            TargetError::LightNotFound(ident) => Self::LightNotFound(ident),
+            TargetError::GroupNotFound(name) => Self::GroupNotFound(name),
+            err => Self::Invalid(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_map_to_status_codes() {
+        let boxed: Box<dyn std::error::Error> = KeylightError::LightNotFound("desk".into()).into();
+        let err = boxed.downcast_ref::<KeylightError>().unwrap();
+        assert_eq!(err.status(), 404);
+        assert_eq!(err.to_string(), "No persisted light found with id 'desk'");
+        assert_eq!(KeylightError::invalid("bad").status(), 400);
+        assert_eq!(KeylightError::config("/tmp/x", "denied").status(), 500);
+        assert_eq!(
+            KeylightError::config("/tmp/x", "denied").to_string(),
+            "/tmp/x: denied"
+        );
+        assert!(KeylightError::invalid("bad").hint().is_none());
+        let unreachable = KeylightError::unreachable("10.0.0.2", "no reply from the WiZ light");
+        assert_eq!(unreachable.status(), 502);
+        assert_eq!(
+            unreachable.to_string(),
+            "10.0.0.2 is unreachable: no reply from the WiZ light"
+        );
+        assert_eq!(
+            KeylightError::protocol("10.0.0.2", "bad reply").status(),
+            502
+        );
+    }
+
+    #[test]
+    fn boxed_errors_keep_their_kind() {
+        let boxed: Box<dyn std::error::Error> = KeylightError::not_found("schedule", "wake").into();
+        let err = KeylightError::from(boxed);
+        assert_eq!(err.status(), 404);
+        assert_eq!(err.to_string(), "No schedule named 'wake'");
+        let other = KeylightError::from(Box::<dyn std::error::Error>::from("boom"));
+        assert_eq!((other.status(), other.to_string()), (500, "boom".to_string()));
+    }
+}
@@ -0,0 +1,303 @@
+//! Revisioned light-state events for `GET /v1/events`.
+//!
+//! Every observed change bumps a global revision and is logged as a sparse event:
+//! only the fields that differ from the last known state. Clients fetch
+//! `/v1/events/snapshot` once, then long-poll `/v1/events?since=<revision>`; if they
+//! fall further behind than the retained log they get 410 and start over from a
+//! fresh snapshot.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::hooks::{self, HookEvent};
+
+/// Events kept for catching up; enough for a few seconds of slider drags on every light.
+const RETAINED_EVENTS: usize = 512;
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(25);
+pub const MAX_WAIT: Duration = Duration::from_secs(60);
+
+static LOG: Mutex<EventLog> = Mutex::new(EventLog::new());
+static CHANGED: Condvar = Condvar::new();
+/// Only `serve` has listeners; one-shot CLI commands skip the bookkeeping.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LightFields {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kelvin: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hue: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+}
+
+impl LightFields {
+    /// Fields of `observed` that are set and differ from `self`.
+    fn changed_in(&self, observed: &LightFields) -> LightFields {
+        fn diff<T: PartialEq + Copy>(known: Option<T>, observed: Option<T>) -> Option<T> {
+            observed.filter(|value| known != Some(*value))
+        }
+        LightFields {
+            on: diff(self.on, observed.on),
+            brightness: diff(self.brightness, observed.brightness),
+            kelvin: diff(self.kelvin, observed.kelvin),
+            hue: diff(self.hue, observed.hue),
+            saturation: diff(self.saturation, observed.saturation),
+        }
+    }
+
+    fn merge(&mut self, changed: &LightFields) {
+        self.on = changed.on.or(self.on);
+        self.brightness = changed.brightness.or(self.brightness);
+        self.kelvin = changed.kelvin.or(self.kelvin);
+        self.hue = changed.hue.or(self.hue);
+        self.saturation = changed.saturation.or(self.saturation);
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == LightFields::default()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StateEvent {
+    pub revision: u64,
+    pub id: String,
+    #[serde(flatten)]
+    pub changed: LightFields,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StateSnapshot {
+    pub revision: u64,
+    pub lights: BTreeMap<String, LightFields>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EventBatch {
+    /// Pass this as `since` on the next poll.
+    pub revision: u64,
+    pub events: Vec<StateEvent>,
+}
+
+/// `since` is older than the retained log (or from before a daemon restart).
+#[derive(Debug)]
+pub struct Expired;
+
+struct EventLog {
+    revision: u64,
+    lights: BTreeMap<String, LightFields>,
+    recent: VecDeque<StateEvent>,
+}
+
+impl EventLog {
+    const fn new() -> Self {
+        Self {
+            revision: 0,
+            lights: BTreeMap::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, id: &str, observed: &LightFields) -> bool {
+        let known = self.lights.entry(id.to_string()).or_default();
+        let changed = known.changed_in(observed);
+        if changed.is_empty() {
+            return false;
+        }
+        known.merge(&changed);
+        self.revision += 1;
+        self.recent.push_back(StateEvent {
+            revision: self.revision,
+            id: id.to_string(),
+            changed,
+        });
+        if self.recent.len() > RETAINED_EVENTS {
+            self.recent.pop_front();
+        }
+        true
+    }
+
+    fn since(&self, since: u64) -> Result<Vec<StateEvent>, Expired> {
+        let oldest = self
+            .recent
+            .front()
+            .map_or(self.revision + 1, |event| event.revision);
+        if since > self.revision || since + 1 < oldest {
+            return Err(Expired);
+        }
+        Ok(self
+            .recent
+            .iter()
+            .filter(|event| event.revision > since)
+            .cloned()
+            .collect())
+    }
+
+    fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            revision: self.revision,
+            lights: self.lights.clone(),
+        }
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Record the state a light reported; wakes pollers if anything changed and runs
+/// `light_on`/`light_off` hooks when a light we already knew about switched.
+pub fn record(id: &str, observed: &LightFields) {
+    if !enabled() {
+        return;
+    }
+    let switched = {
+        let Ok(mut log) = LOG.lock() else {
+            return;
+        };
+        let known = log.lights.get(id).is_some_and(|light| light.on.is_some());
+        if !log.record(id, observed) {
+            return;
+        }
+        CHANGED.notify_all();
+        log.recent
+            .back()
+            .and_then(|event| event.changed.on)
+            .filter(|_| known)
+    };
+    match switched {
+        Some(true) => hooks::fire(HookEvent::LightOn { id: id.to_string() }),
+        Some(false) => hooks::fire(HookEvent::LightOff { id: id.to_string() }),
+        None => {}
+    }
+}
+
+pub fn snapshot() -> StateSnapshot {
+    LOG.lock()
+        .map(|log| log.snapshot())
+        .unwrap_or_else(|poisoned| poisoned.into_inner().snapshot())
+}
+
+/// Current revision and the last known state of one light.
+pub fn light(id: &str) -> (u64, Option<LightFields>) {
+    let log = LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    (log.revision, log.lights.get(id).cloned())
+}
+
+/// Events after `since`, waiting up to `wait` for the first one. An empty batch
+/// means nothing changed in time.
+pub fn wait_since(since: u64, wait: Duration) -> Result<EventBatch, Expired> {
+    let deadline = Instant::now() + wait.min(MAX_WAIT);
+    let mut log = LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    loop {
+        let events = log.since(since)?;
+        let now = Instant::now();
+        if !events.is_empty() || now >= deadline {
+            return Ok(EventBatch {
+                revision: log.revision,
+                events,
+            });
+        }
+        log = CHANGED
+            .wait_timeout(log, deadline - now)
+            .map(|(log, _)| log)
+            .unwrap_or_else(|poisoned| poisoned.into_inner().0);
+    }
+}
+
+/// `since` and `wait` (seconds) from a `/v1/events` query string.
+pub fn parse_query(query: &str) -> Result<(u64, Duration), String> {
+    let mut since = None;
+    let mut wait = DEFAULT_WAIT;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match key {
+            "since" => {
+                since = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid since '{value}'"))?,
+                )
+            }
+            "wait" => {
+                wait = Duration::from_secs(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid wait '{value}'"))?,
+                )
+            }
+            _ => {}
+        }
+    }
+    let since =
+        since.ok_or("since is required; fetch /v1/events/snapshot for a starting revision")?;
+    Ok((since, wait))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(on: bool, brightness: u8, kelvin: u16) -> LightFields {
+        LightFields {
+            on: Some(on),
+            brightness: Some(brightness),
+            kelvin: Some(kelvin),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn events_carry_only_changed_fields() {
+        let mut log = EventLog::new();
+        assert!(log.record("left", &fields(true, 40, 4500)));
+        assert!(!log.record("left", &fields(true, 40, 4500)));
+        assert!(log.record("left", &fields(true, 55, 4500)));
+
+        let events = log.since(1).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].revision, 2);
+        assert_eq!(
+            serde_json::to_value(&events[0]).unwrap(),
+            serde_json::json!({"revision": 2, "id": "left", "brightness": 55})
+        );
+
+        let snapshot = log.snapshot();
+        assert_eq!(snapshot.revision, 2);
+        assert_eq!(snapshot.lights["left"], fields(true, 55, 4500));
+    }
+
+    #[test]
+    fn stale_or_future_revisions_expire() {
+        let mut log = EventLog::new();
+        assert!(log.since(0).unwrap().is_empty());
+        for i in 0..RETAINED_EVENTS + 10 {
+            log.record("left", &fields(true, (i % 100) as u8, 4500));
+        }
+        assert!(log.since(0).is_err());
+        assert!(log.since(log.revision + 1).is_err());
+        assert!(log.since(log.revision).unwrap().is_empty());
+    }
+
+    #[test]
+    fn query_requires_since() {
+        assert!(parse_query("wait=5").is_err());
+        assert_eq!(
+            parse_query("since=7&wait=5").unwrap(),
+            (7, Duration::from_secs(5))
+        );
+        assert_eq!(parse_query("since=0").unwrap(), (0, DEFAULT_WAIT));
+    }
+}
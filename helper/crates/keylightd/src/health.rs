@@ -0,0 +1,225 @@
+//! Failure streaks for Elgato lights. Key Lights occasionally wedge and stop
+//! answering HTTP until they're power-cycled; counting consecutive failed requests
+//! lets the tray say so instead of just showing the light as missing.
+//!
+//! A streak also works as a circuit breaker: after `OFFLINE_AFTER` failures the
+//! light's requests fail at once instead of each waiting out the timeout, so
+//! `/v1/all` stays fast while a light is unplugged. The pause doubles with every
+//! failed retry, up to `MAX_BACKOFF`; a background probe (and any request once the
+//! pause is over) tries the light again, and one answer closes the breaker.
+
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::logs::log_line;
+use super::split_zone;
+
+/// Consecutive failures after which a light is reported as needing a restart.
+const RESTART_THRESHOLD: u32 = 3;
+/// A wedged light tends to accept the connection and then hang.
+const RESET_TIMEOUT: Duration = Duration::from_secs(5);
+/// Consecutive failures after which requests skip the light for a while.
+const OFFLINE_AFTER: u32 = 2;
+const FIRST_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+/// How often the background probe looks for lights due a retry.
+const PROBE_TICK: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Streak {
+    failures: u32,
+    /// A soft reset was tried and didn't get through.
+    reset_failed: bool,
+    last_failure: Option<Instant>,
+}
+
+/// Streaks by address; only the running daemon's requests count.
+static STREAKS: Mutex<BTreeMap<String, Streak>> = Mutex::new(BTreeMap::new());
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LightHealth {
+    pub id: String,
+    /// Consecutive requests to the light that failed.
+    pub failures: u32,
+    /// The light looks wedged: soft-reset it, or unplug it for a few seconds.
+    pub restart_required: bool,
+    /// Requests to the light fail at once until it answers a retry again.
+    pub offline: bool,
+}
+
+impl Streak {
+    fn restart_required(&self) -> bool {
+        self.reset_failed || self.failures >= RESTART_THRESHOLD
+    }
+
+    fn offline(&self) -> bool {
+        self.failures >= OFFLINE_AFTER
+    }
+
+    /// How long requests skip the light after its last failure.
+    fn backoff(&self) -> Duration {
+        let doublings = self.failures.saturating_sub(OFFLINE_AFTER).min(8);
+        (FIRST_BACKOFF * 2u32.pow(doublings)).min(MAX_BACKOFF)
+    }
+
+    /// Whether a request at `now` should fail without trying the light.
+    fn skips(&self, now: Instant) -> bool {
+        self.offline()
+            && self
+                .last_failure
+                .is_some_and(|at| now.saturating_duration_since(at) < self.backoff())
+    }
+
+    fn fail(&mut self) {
+        self.failures += 1;
+        self.last_failure = Some(Instant::now());
+    }
+}
+
+/// Count a request to `ip`; any success ends the streak.
+pub fn note(ip: &str, ok: bool) {
+    let Ok(mut streaks) = STREAKS.lock() else {
+        return;
+    };
+    if ok {
+        streaks.remove(ip);
+    } else {
+        streaks.entry(ip.to_string()).or_default().fail();
+    }
+}
+
+/// The light at `ip` is offline and not due a retry yet, so a request to it would
+/// only wait out the timeout.
+pub fn skip(ip: &str) -> bool {
+    STREAKS
+        .lock()
+        .ok()
+        .and_then(|streaks| streaks.get(ip).map(|streak| streak.skips(Instant::now())))
+        .unwrap_or(false)
+}
+
+/// Start the background retries of offline lights.
+pub fn spawn(client: Client) {
+    let spawned = thread::Builder::new()
+        .name("health".into())
+        .spawn(move || loop {
+            thread::sleep(PROBE_TICK);
+            probe_due(&client);
+        });
+    if let Err(err) = spawned {
+        log_line!("failed to start health probes: {err}");
+    }
+}
+
+/// Ask each offline light whose pause is over for its state once.
+fn probe_due(client: &Client) {
+    let now = Instant::now();
+    let due: Vec<String> = match STREAKS.lock() {
+        Ok(streaks) => streaks
+            .iter()
+            .filter(|(_, streak)| streak.offline() && !streak.skips(now))
+            .map(|(ip, _)| ip.clone())
+            .collect(),
+        Err(_) => return,
+    };
+    for ip in due {
+        let answered = client
+            .get(format!("http://{}:9123/elgato/lights", split_zone(&ip).0))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .is_ok();
+        if answered {
+            log_line!("{ip} answers again");
+        }
+        note(&ip, answered);
+    }
+}
+
+/// Health of the light with persisted `id`, reachable at `ip`.
+pub fn status(id: &str, ip: &str) -> LightHealth {
+    let streak = STREAKS
+        .lock()
+        .ok()
+        .and_then(|streaks| streaks.get(ip).copied())
+        .unwrap_or_default();
+    LightHealth {
+        id: id.to_string(),
+        failures: streak.failures,
+        restart_required: streak.restart_required(),
+        offline: streak.offline(),
+    }
+}
+
+/// Ask an Elgato light to restart itself. A light that can't even take this request
+/// stays flagged until it answers again, since only a power cycle will help.
+pub fn reset(client: &Client, ip: &str) -> Result<(), String> {
+    let result = client
+        .post(format!("http://{}:9123/elgato/restart", split_zone(ip).0))
+        .timeout(RESET_TIMEOUT)
+        .send()
+        .and_then(|response| response.error_for_status());
+    let Ok(mut streaks) = STREAKS.lock() else {
+        return result.map(|_| ()).map_err(|err| err.to_string());
+    };
+    match result {
+        Ok(_) => {
+            streaks.remove(ip);
+            Ok(())
+        }
+        Err(err) => {
+            let streak = streaks.entry(ip.to_string()).or_default();
+            streak.fail();
+            streak.reset_failed = true;
+            Err(err.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streak_flags_after_threshold_and_clears_on_success() {
+        let ip = "192.0.2.77";
+        for _ in 0..RESTART_THRESHOLD - 1 {
+            note(ip, false);
+        }
+        assert!(!status("left", ip).restart_required);
+        note(ip, false);
+        let health = status("left", ip);
+        assert_eq!(health.failures, RESTART_THRESHOLD);
+        assert!(health.restart_required);
+        note(ip, true);
+        assert_eq!(status("left", ip).failures, 0);
+    }
+
+    #[test]
+    fn offline_lights_are_skipped_for_a_growing_pause() {
+        let at = Instant::now();
+        let streak = |failures| Streak {
+            failures,
+            reset_failed: false,
+            last_failure: Some(at),
+        };
+        assert!(!streak(OFFLINE_AFTER - 1).skips(at));
+        assert!(streak(OFFLINE_AFTER).skips(at + FIRST_BACKOFF / 2));
+        assert!(!streak(OFFLINE_AFTER).skips(at + FIRST_BACKOFF));
+        assert!(streak(OFFLINE_AFTER + 1).skips(at + FIRST_BACKOFF));
+        assert_eq!(streak(OFFLINE_AFTER + 20).backoff(), MAX_BACKOFF);
+
+        let ip = "192.0.2.78";
+        for _ in 0..OFFLINE_AFTER {
+            note(ip, false);
+        }
+        assert!(skip(ip) && status("left", ip).offline);
+        note(ip, true);
+        assert!(!skip(ip));
+    }
+}
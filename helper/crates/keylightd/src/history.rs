@@ -0,0 +1,204 @@
+//! Recent updates made through the API, so `POST /v1/undo` can put the lights the
+//! last one touched back the way they were. Kept in memory only; a restarted daemon
+//! has nothing to undo.
+
+use keylight_core::store::SavedLight;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::events::LightFields;
+use super::logs::log_line;
+use super::{load_config, set_light, KeylightError, UpdateRequest};
+
+/// Entries kept; older updates can't be undone.
+const LIMIT: usize = 50;
+/// Updates to the same target this close together, like the steps of a slider drag,
+/// are one entry, so undo goes back to before the drag.
+const MERGE_WINDOW: Duration = Duration::from_secs(3);
+
+static HISTORY: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::new());
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Entry {
+    pub applied_unix: u64,
+    /// `light:<id>`, `group:<name>` or `all`.
+    pub target: String,
+    pub update: UpdateRequest,
+    /// Each light's state before the update, by persisted id, in the terms requests
+    /// use. Lights whose state wasn't known are missing.
+    pub previous: BTreeMap<String, LightFields>,
+    #[serde(skip)]
+    at: Instant,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct UndoReport {
+    pub target: String,
+    pub restored: Vec<String>,
+    /// Lights removed since, or that didn't answer.
+    pub skipped: Vec<String>,
+}
+
+/// Remember that `update` was applied to `target`, whose lights were in `previous`.
+pub fn record(target: String, update: &UpdateRequest, previous: BTreeMap<String, LightFields>) {
+    let entry = Entry {
+        applied_unix: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        target,
+        update: update.clone(),
+        previous,
+        at: Instant::now(),
+    };
+    let mut history = HISTORY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    push(&mut history, entry);
+}
+
+fn push(history: &mut VecDeque<Entry>, entry: Entry) {
+    if let Some(last) = history.back_mut() {
+        if last.target == entry.target && entry.at.duration_since(last.at) < MERGE_WINDOW {
+            // Keep the states from before the first step.
+            for (id, fields) in entry.previous {
+                last.previous.entry(id).or_insert(fields);
+            }
+            last.applied_unix = entry.applied_unix;
+            last.update = entry.update;
+            last.at = entry.at;
+            return;
+        }
+    }
+    history.push_back(entry);
+    if history.len() > LIMIT {
+        history.pop_front();
+    }
+}
+
+/// Recorded updates, newest first.
+pub fn list() -> Vec<Entry> {
+    let history = HISTORY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    history.iter().rev().cloned().collect()
+}
+
+/// Put the lights the newest update touched back how they were, and forget it.
+/// `None` when there is nothing to undo.
+pub fn undo(client: &Client) -> Result<Option<UndoReport>, KeylightError> {
+    let entry = HISTORY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .pop_back();
+    let Some(entry) = entry else {
+        return Ok(None);
+    };
+    let config = load_config()?;
+    let mut report = UndoReport {
+        target: entry.target,
+        ..Default::default()
+    };
+    for (id, fields) in &entry.previous {
+        let light = config.lights.iter().find(|light| light.id == *id);
+        let Some((light, ip)) = light.and_then(|light| Some((light, light.address()?)))
+        else {
+            report.skipped.push(id.clone());
+            continue;
+        };
+        let update = to_request(fields)
+            .calibrated(&config, Some(light))
+            .to_light_update_in(light.supported_kelvin());
+        match set_light(client, light.backend, &ip, &update) {
+            Ok(_) => report.restored.push(id.clone()),
+            Err(err) => {
+                log_line!("undoing {id}: {err}");
+                report.skipped.push(id.clone());
+            }
+        }
+    }
+    Ok(Some(report))
+}
+
+/// The request that brings a light back to `fields`. A light in color mode also
+/// reports a temperature, so color is only restored when no temperature was seen.
+fn to_request(fields: &LightFields) -> UpdateRequest {
+    let color = fields.kelvin.is_none();
+    UpdateRequest {
+        on: fields.on.map(u8::from),
+        brightness: fields.brightness,
+        kelvin: fields.kelvin,
+        mired: None,
+        hue: fields.hue.filter(|_| color),
+        saturation: fields.saturation.filter(|_| color),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(target: &str, brightness: u8, at: Instant) -> Entry {
+        Entry {
+            applied_unix: 0,
+            target: target.into(),
+            update: UpdateRequest {
+                brightness: Some(brightness),
+                ..Default::default()
+            },
+            previous: BTreeMap::from([(
+                "left".to_string(),
+                LightFields {
+                    brightness: Some(brightness - 10),
+                    ..Default::default()
+                },
+            )]),
+            at,
+        }
+    }
+
+    #[test]
+    fn quick_updates_to_one_target_undo_together() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = VecDeque::new();
+        push(&mut history, entry("light:left", 20, at(0)));
+        push(&mut history, entry("light:left", 30, at(1)));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].update.brightness, Some(30));
+        assert_eq!(history[0].previous["left"].brightness, Some(10));
+
+        push(&mut history, entry("all", 40, at(2)));
+        push(&mut history, entry("all", 50, at(10)));
+        assert_eq!(history.len(), 3);
+
+        for step in 0..LIMIT as u64 {
+            push(&mut history, entry("light:left", 60, at(20 + 20 * step)));
+        }
+        assert_eq!(history.len(), LIMIT);
+    }
+
+    #[test]
+    fn color_is_restored_only_without_a_temperature() {
+        let white = LightFields {
+            on: Some(true),
+            kelvin: Some(4000),
+            hue: Some(120.0),
+            saturation: Some(50.0),
+            ..Default::default()
+        };
+        let request = to_request(&white);
+        assert_eq!(
+            (request.on, request.kelvin, request.hue),
+            (Some(1), Some(4000), None)
+        );
+
+        let color = LightFields {
+            kelvin: None,
+            ..white
+        };
+        assert_eq!(to_request(&color).hue, Some(120.0));
+    }
+}
@@ -0,0 +1,173 @@
+//! User hooks: every executable in `<config dir>/hooks.d/` is run on light and scene
+//! events, with the event name as its only argument and a JSON object on stdin.
+//!
+//! Hooks run in the background and their exit status is ignored; a slow or failing
+//! script never holds up a light update.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use super::config_dir;
+use super::logs::log_line;
+use super::notices::{self, Notice};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HookEvent {
+    LightOn { id: String },
+    LightOff { id: String },
+    SceneApplied { name: String },
+    LightOffline { id: String },
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::LightOn { .. } => "light_on",
+            HookEvent::LightOff { .. } => "light_off",
+            HookEvent::SceneApplied { .. } => "scene_applied",
+            HookEvent::LightOffline { .. } => "light_offline",
+        }
+    }
+}
+
+/// Lights last seen unreachable, so `light_offline` fires once per outage.
+static OFFLINE: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn hooks_dir() -> Option<PathBuf> {
+    config_dir().ok().map(|dir| dir.join("hooks.d"))
+}
+
+/// Executable regular files in `dir`, in name order. Dotfiles and editor backups
+/// (`*~`) are skipped so a half-edited script doesn't run.
+fn hook_scripts(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut scripts: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            !name.starts_with('.') && !name.ends_with('~')
+        })
+        .filter(|entry| entry.metadata().is_ok_and(|meta| is_executable(&meta)))
+        .map(|entry| entry.path())
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.is_file() && meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(meta: &std::fs::Metadata) -> bool {
+    meta.is_file()
+}
+
+/// Run every hook for `event`. Returns once they're started.
+pub fn fire(event: HookEvent) {
+    let Some(dir) = hooks_dir() else {
+        return;
+    };
+    let scripts = hook_scripts(&dir);
+    if scripts.is_empty() {
+        return;
+    }
+    let payload = match serde_json::to_vec(&event) {
+        Ok(payload) => payload,
+        Err(err) => {
+            log_line!("unable to encode hook event: {err}");
+            return;
+        }
+    };
+    for script in scripts {
+        let child = Command::new(&script)
+            .arg(event.name())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                log_line!("hook {} failed to start: {err}", script.display());
+                continue;
+            }
+        };
+        // The payload is far below a pipe buffer, so this doesn't block on the script.
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(&payload);
+        }
+        // Reap it in the background so finished hooks don't linger as zombies.
+        std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+    }
+}
+
+/// Track reachability from state polls; fires `light_offline` and posts a notice
+/// when a light that answered before stops answering.
+pub fn note_reachable(id: &str, reachable: bool) {
+    let went_offline = {
+        let Ok(mut offline) = OFFLINE.lock() else {
+            return;
+        };
+        if reachable {
+            offline.remove(id);
+            false
+        } else {
+            offline.insert(id.to_string())
+        }
+    };
+    if went_offline {
+        fire(HookEvent::LightOffline { id: id.to_string() });
+        notices::post(Notice::LightUnreachable { id: id.to_string() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_names_the_event() {
+        let event = HookEvent::SceneApplied {
+            name: "evening".into(),
+        };
+        assert_eq!(event.name(), "scene_applied");
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"event": "scene_applied", "name": "evening"})
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_executable_scripts_run() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("keylightd-hooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, mode) in [
+            ("10-notify", 0o755),
+            ("20-readme.txt", 0o644),
+            (".hidden", 0o755),
+            ("05-log~", 0o755),
+        ] {
+            let path = dir.join(name);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let scripts = hook_scripts(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(scripts, vec![dir.join("10-notify")]);
+    }
+}
@@ -0,0 +1,164 @@
+//! Last known state of every light, kept across daemon restarts.
+//!
+//! After a power outage lights come back with their own power-on behavior (usually
+//! on at full brightness, or off). `serve` mirrors the event cache into the state dir
+//! so `POST /v1/lights/restore`, or `serve --restore-on-start`, can put them back.
+
+use keylight_core::store::SavedLight;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use super::events::{self, LightFields};
+use super::logs::log_line;
+use super::{
+    create_private_dir, load_config, read_optional, set_light, state_dir, write_private_file,
+    KeylightError, LightUpdate,
+};
+
+/// Quiet period before writing, so a slider drag is one write rather than dozens.
+const SETTLE: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct RestoreReport {
+    pub restored: Vec<String>,
+    /// Lights with no saved state, removed since, or that didn't answer.
+    pub skipped: Vec<String>,
+}
+
+fn last_state_path() -> Result<PathBuf, KeylightError> {
+    Ok(state_dir()?.join("last-state.json"))
+}
+
+fn load() -> Result<BTreeMap<String, LightFields>, KeylightError> {
+    let path = last_state_path()?;
+    match read_optional(&path)? {
+        Some(bytes) => {
+            serde_json::from_slice(&bytes).map_err(|err| KeylightError::config(&path, err))
+        }
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Merge what the daemon has seen into the saved file. Lights not seen since the
+/// restart keep the state saved for them before it.
+fn save(observed: BTreeMap<String, LightFields>) -> Result<(), KeylightError> {
+    let mut saved = load()?;
+    saved.extend(observed);
+    let path = last_state_path()?;
+    let in_file = |err: &dyn std::fmt::Display| KeylightError::config(&path, err);
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent).map_err(|err| in_file(&err))?;
+    }
+    let bytes = serde_json::to_vec_pretty(&saved).map_err(|err| in_file(&err))?;
+    write_private_file(&path, &bytes).map_err(|err| in_file(&err))
+}
+
+/// Start mirroring observed states to disk. Called once by `serve`.
+pub fn spawn() {
+    let spawned = thread::Builder::new().name("last-state".into()).spawn(run);
+    if let Err(err) = spawned {
+        log_line!("failed to start saving light state: {err}");
+    }
+}
+
+fn run() {
+    let mut since = events::snapshot().revision;
+    loop {
+        // An expired revision only means we fell behind; the snapshot has it all.
+        let changed = match events::wait_since(since, events::MAX_WAIT) {
+            Ok(batch) => !batch.events.is_empty(),
+            Err(_) => true,
+        };
+        if !changed {
+            continue;
+        }
+        thread::sleep(SETTLE);
+        let snapshot = events::snapshot();
+        since = snapshot.revision;
+        if let Err(err) = save(snapshot.lights) {
+            log_line!("saving light state: {err}");
+        }
+    }
+}
+
+/// What to send to bring a light back to `saved`. A light in color mode also
+/// reports a temperature, so color only wins when no temperature was seen.
+fn to_update(saved: &LightFields, kelvin_to_mired: impl Fn(u16) -> u16) -> Option<LightUpdate> {
+    let mut update = LightUpdate {
+        on: saved.on.map(u8::from),
+        brightness: saved.brightness,
+        temperature: saved.kelvin.map(kelvin_to_mired),
+        ..Default::default()
+    };
+    if update.temperature.is_none() {
+        update.hue = saved.hue;
+        update.saturation = saved.saturation;
+    }
+    let empty = update.on.is_none()
+        && update.brightness.is_none()
+        && update.temperature.is_none()
+        && update.hue.is_none();
+    (!empty).then_some(update)
+}
+
+/// Re-apply the saved state to every enabled light. Unlike snapshot restore this
+/// carries on past lights that don't answer: after an outage some may still be
+/// booting.
+pub fn restore(client: &Client) -> Result<RestoreReport, KeylightError> {
+    let saved = load()?;
+    let config = load_config()?;
+    let mut report = RestoreReport::default();
+    for light in config.lights.iter().filter(|light| light.enabled) {
+        let range = light.supported_kelvin();
+        let update = saved
+            .get(&light.id)
+            .and_then(|fields| to_update(fields, |kelvin| range.kelvin_to_mired(kelvin)));
+        let (Some(update), Some(ip)) = (update, light.address()) else {
+            report.skipped.push(light.id.clone());
+            continue;
+        };
+        match set_light(client, light.backend, &ip, &update) {
+            Ok(_) => report.restored.push(light.id.clone()),
+            Err(err) => {
+                log_line!("restoring {}: {err}", light.id);
+                report.skipped.push(light.id.clone());
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature_wins_over_color_and_empty_state_is_skipped() {
+        let saved = LightFields {
+            on: Some(true),
+            brightness: Some(40),
+            kelvin: Some(5000),
+            hue: Some(120.0),
+            saturation: Some(80.0),
+        };
+        let update = to_update(&saved, |kelvin| (1_000_000 / kelvin as u32) as u16).unwrap();
+        assert_eq!(update.on, Some(1));
+        assert_eq!(update.brightness, Some(40));
+        assert_eq!(update.temperature, Some(200));
+        assert_eq!(update.hue, None);
+
+        let color_only = LightFields {
+            kelvin: None,
+            ..saved
+        };
+        let update = to_update(&color_only, |_| unreachable!()).unwrap();
+        assert_eq!(update.hue, Some(120.0));
+        assert_eq!(update.saturation, Some(80.0));
+
+        assert!(to_update(&LightFields::default(), |_| 0).is_none());
+    }
+}
@@ -0,0 +1,304 @@
+//! LIFX lights: the LAN binary protocol over UDP port 56700. Discovery broadcasts
+//! `GetService`; control sends `SetPower`/`SetColor` straight to the light's address.
+//!
+//! Messages are a 36-byte little-endian header followed by the payload; see
+//! <https://lan.developer.lifx.com/docs/header-description>.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::error::KeylightError;
+use super::logs::log_line;
+use super::{Backend, Capabilities, DiscoveredLight, KelvinRange, LightState, LightUpdate};
+
+pub const PORT: u16 = 56700;
+pub const KELVIN_RANGE: KelvinRange = KelvinRange {
+    min: 2500,
+    max: 9000,
+};
+
+const HEADER_LEN: usize = 36;
+const REPLY_TIMEOUT: Duration = Duration::from_millis(700);
+const ATTEMPTS: usize = 3;
+/// Identifies our requests; lights echo it back.
+const SOURCE: u32 = 0x4c4d_4c54;
+
+const GET_SERVICE: u16 = 2;
+const STATE_SERVICE: u16 = 3;
+const LIGHT_GET: u16 = 101;
+const LIGHT_SET_COLOR: u16 = 102;
+const LIGHT_STATE: u16 = 107;
+const LIGHT_SET_POWER: u16 = 117;
+const ACKNOWLEDGEMENT: u16 = 45;
+
+/// Hue, saturation, brightness and kelvin in LIFX units (0..=65535, kelvin as-is).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hsbk {
+    hue: u16,
+    saturation: u16,
+    brightness: u16,
+    kelvin: u16,
+}
+
+struct LifxState {
+    color: Hsbk,
+    power: bool,
+    label: String,
+}
+
+fn message(kind: u16, tagged: bool, ack: bool, sequence: u8, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + payload.len());
+    buf.extend_from_slice(&((HEADER_LEN + payload.len()) as u16).to_le_bytes());
+    // Protocol 1024, addressable, tagged when addressed to every light.
+    let flags = 1024u16 | 0x1000 | if tagged { 0x2000 } else { 0 };
+    buf.extend_from_slice(&flags.to_le_bytes());
+    buf.extend_from_slice(&SOURCE.to_le_bytes());
+    // Target (all zero: whoever receives it), reserved.
+    buf.extend_from_slice(&[0; 8]);
+    buf.extend_from_slice(&[0; 6]);
+    buf.push(if ack { 0x02 } else { 0x01 });
+    buf.push(sequence);
+    buf.extend_from_slice(&[0; 8]);
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(&[0; 2]);
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// Message type, target MAC and payload of a reply, if it's well-formed and ours.
+fn parse(reply: &[u8]) -> Option<(u16, [u8; 6], &[u8])> {
+    if reply.len() < HEADER_LEN {
+        return None;
+    }
+    let size = u16::from_le_bytes([reply[0], reply[1]]) as usize;
+    let source = u32::from_le_bytes(reply[4..8].try_into().ok()?);
+    if size != reply.len() || source != SOURCE {
+        return None;
+    }
+    let mac = reply[8..14].try_into().ok()?;
+    let kind = u16::from_le_bytes([reply[32], reply[33]]);
+    Some((kind, mac, &reply[HEADER_LEN..]))
+}
+
+/// Send a message to one light and wait for a reply of type `expect`.
+fn request(ip: &str, kind: u16, payload: &[u8], expect: u16) -> Result<Vec<u8>, KeylightError> {
+    let unreachable = |err: io::Error| KeylightError::unreachable(ip, err);
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(unreachable)?;
+    socket
+        .set_read_timeout(Some(REPLY_TIMEOUT))
+        .map_err(unreachable)?;
+    let mut buf = [0u8; 512];
+    for sequence in 0..ATTEMPTS as u8 {
+        let ack = expect == ACKNOWLEDGEMENT;
+        socket
+            .send_to(&message(kind, true, ack, sequence, payload), (ip, PORT))
+            .map_err(unreachable)?;
+        while let Ok((len, _)) = socket.recv_from(&mut buf) {
+            if let Some((reply_kind, _, body)) = parse(&buf[..len]) {
+                if reply_kind == expect {
+                    return Ok(body.to_vec());
+                }
+            }
+        }
+    }
+    Err(KeylightError::unreachable(
+        ip,
+        "no reply from the LIFX light",
+    ))
+}
+
+fn get(ip: &str) -> Result<LifxState, KeylightError> {
+    let body = request(ip, LIGHT_GET, &[], LIGHT_STATE)?;
+    parse_light_state(&body)
+        .ok_or_else(|| KeylightError::protocol(ip, "malformed LIFX state reply"))
+}
+
+fn parse_light_state(body: &[u8]) -> Option<LifxState> {
+    if body.len() < 52 {
+        return None;
+    }
+    let word = |at: usize| u16::from_le_bytes([body[at], body[at + 1]]);
+    let label = String::from_utf8_lossy(&body[12..44])
+        .trim_end_matches('\0')
+        .to_string();
+    Some(LifxState {
+        color: Hsbk {
+            hue: word(0),
+            saturation: word(2),
+            brightness: word(4),
+            kelvin: word(6),
+        },
+        power: word(10) != 0,
+        label,
+    })
+}
+
+fn to_light_state(state: &LifxState) -> LightState {
+    let percent = |value: u16| (value as f64 * 100.0 / 65535.0).round();
+    let color = state.color.saturation > 0;
+    LightState {
+        on: u8::from(state.power),
+        brightness: percent(state.color.brightness) as u8,
+        temperature: if color {
+            0
+        } else {
+            KELVIN_RANGE.kelvin_to_mired(state.color.kelvin)
+        },
+        hue: color.then(|| (state.color.hue as f64 * 360.0 / 65535.0).round()),
+        saturation: color.then(|| percent(state.color.saturation)),
+    }
+}
+
+pub fn get_state(ip: &str) -> Option<LightState> {
+    get(ip).ok().map(|state| to_light_state(&state))
+}
+
+/// Apply `update` and read back the resulting state. `SetColor` always carries all
+/// four components, so unchanged ones are taken from the light's current color.
+pub fn set(ip: &str, update: &LightUpdate) -> Result<LightState, KeylightError> {
+    let current = get(ip)?;
+    let color = merge_color(current.color, update);
+    if color != current.color {
+        let mut payload = vec![0u8];
+        for value in [color.hue, color.saturation, color.brightness, color.kelvin] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+        payload.extend_from_slice(&0u32.to_le_bytes());
+        request(ip, LIGHT_SET_COLOR, &payload, ACKNOWLEDGEMENT)?;
+    }
+    if let Some(on) = update.on {
+        let level: u16 = if on == 1 { u16::MAX } else { 0 };
+        let mut payload = level.to_le_bytes().to_vec();
+        payload.extend_from_slice(&0u32.to_le_bytes());
+        request(ip, LIGHT_SET_POWER, &payload, ACKNOWLEDGEMENT)?;
+    }
+    get(ip).map(|state| to_light_state(&state))
+}
+
+fn merge_color(current: Hsbk, update: &LightUpdate) -> Hsbk {
+    let scale = |value: f64, max: f64| ((value / max).clamp(0.0, 1.0) * 65535.0).round() as u16;
+    let mut color = current;
+    if let Some(brightness) = update.brightness {
+        color.brightness = scale(brightness as f64, 100.0);
+    }
+    if let Some(mired) = update.temperature {
+        color.kelvin = KELVIN_RANGE.mired_to_kelvin(mired);
+        // A white temperature means leaving color mode.
+        color.saturation = 0;
+    }
+    if let Some(hue) = update.hue {
+        color.hue = scale(hue, 360.0);
+    }
+    if let Some(saturation) = update.saturation {
+        color.saturation = scale(saturation, 100.0);
+    }
+    color
+}
+
+/// Broadcast `GetService` and collect every light that answers before `timeout`.
+pub fn discover(timeout: Duration) -> Vec<DiscoveredLight> {
+    match discover_inner(timeout) {
+        Ok(found) => found,
+        Err(err) => {
+            log_line!("LIFX discovery failed: {err}");
+            Vec::new()
+        }
+    }
+}
+
+fn discover_inner(timeout: Duration) -> io::Result<Vec<DiscoveredLight>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    socket.send_to(
+        &message(GET_SERVICE, true, false, 0, &[]),
+        (Ipv4Addr::BROADCAST, PORT),
+    )?;
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<DiscoveredLight> = Vec::new();
+    let mut buf = [0u8; 512];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let Ok((len, SocketAddr::V4(from))) = socket.recv_from(&mut buf) else {
+            break;
+        };
+        let Some((STATE_SERVICE, mac, _)) = parse(&buf[..len]) else {
+            continue;
+        };
+        let id = format!(
+            "lifx-{}",
+            mac.iter().map(|b| format!("{b:02x}")).collect::<String>()
+        );
+        if found.iter().any(|known| known.id == id) {
+            continue;
+        }
+        let ip = from.ip().to_string();
+        let name = get(&ip)
+            .ok()
+            .map(|state| state.label)
+            .filter(|label| !label.is_empty())
+            .unwrap_or_else(|| format!("LIFX {ip}"));
+        found.push(DiscoveredLight {
+            id,
+            name,
+            ip,
+            port: PORT,
+            backend: Backend::Lifx,
+            capabilities: Capabilities {
+                brightness: true,
+                temperature: true,
+                color: true,
+            },
+            kelvin_range: Some(KELVIN_RANGE),
+            accessory_info: None,
+        });
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_layout_matches_protocol() {
+        let msg = message(LIGHT_SET_POWER, true, true, 7, &[0xff, 0xff, 0, 0, 0, 0]);
+        assert_eq!(msg.len(), 42);
+        assert_eq!(u16::from_le_bytes([msg[0], msg[1]]), 42);
+        assert_eq!(u16::from_le_bytes([msg[2], msg[3]]), 0x3400);
+        assert_eq!(msg[22], 0x02);
+        assert_eq!(msg[23], 7);
+        assert_eq!(u16::from_le_bytes([msg[32], msg[33]]), LIGHT_SET_POWER);
+    }
+
+    #[test]
+    fn replies_from_other_clients_are_ignored() {
+        let mut reply = message(LIGHT_STATE, false, false, 0, &[0; 52]);
+        assert_eq!(parse(&reply).map(|(kind, _, _)| kind), Some(LIGHT_STATE));
+        reply[4] ^= 1;
+        assert!(parse(&reply).is_none());
+    }
+
+    #[test]
+    fn temperature_update_leaves_color_mode() {
+        let current = Hsbk {
+            hue: 1000,
+            saturation: 65535,
+            brightness: 0,
+            kelvin: 3500,
+        };
+        let update = LightUpdate {
+            brightness: Some(100),
+            temperature: Some(250),
+            ..Default::default()
+        };
+        let merged = merge_color(current, &update);
+        assert_eq!(merged.saturation, 0);
+        assert_eq!(merged.brightness, 65535);
+        assert_eq!(merged.kelvin, 4000);
+        assert_eq!(merged.hue, 1000);
+    }
+}
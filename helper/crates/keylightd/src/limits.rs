@@ -0,0 +1,210 @@
+//! Per-backend request concurrency and pacing, configured under `[backends.<name>]`.
+//!
+//! Every device request goes through `Backend::acquire`, so the server, the scheduler
+//! and group fan-out all share one budget per backend.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::logs::log_line;
+
+/// Device protocol a light is controlled through.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Elgato's HTTP API on port 9123, found over mDNS.
+    #[default]
+    Elgato,
+    /// WiZ JSON-over-UDP on port 38899.
+    Wiz,
+    /// LIFX LAN binary protocol on UDP port 56700.
+    Lifx,
+    /// `[[custom_lights]]` URL templates (Tasmota, ESPHome, ...).
+    Custom,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 4] = [
+        Backend::Elgato,
+        Backend::Wiz,
+        Backend::Lifx,
+        Backend::Custom,
+    ];
+
+    /// Key used under `[backends.<name>]` in `config.toml`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Elgato => "elgato",
+            Backend::Wiz => "wiz",
+            Backend::Lifx => "lifx",
+            Backend::Custom => "custom",
+        }
+    }
+
+    /// Used when `config.toml` doesn't say otherwise. Key Lights handle a handful of
+    /// parallel requests fine and need no pacing. WiZ bulbs drop packets when flooded,
+    /// and LIFX asks clients to stay under 20 messages a second. Custom lights are
+    /// mostly ESP boards with single-threaded web servers.
+    fn default_limits(self) -> (usize, Duration) {
+        match self {
+            Backend::Elgato => (8, Duration::ZERO),
+            Backend::Wiz => (4, Duration::from_millis(20)),
+            Backend::Lifx => (4, Duration::from_millis(50)),
+            Backend::Custom => (2, Duration::ZERO),
+        }
+    }
+
+    /// Minimum gap between two commands to the same light unless `config.toml` says
+    /// otherwise; updates arriving in between are merged (see `coalesce`).
+    fn default_device_interval(self) -> Duration {
+        match self {
+            Backend::Custom => Duration::from_millis(100),
+            _ => Duration::from_millis(50),
+        }
+    }
+
+    pub fn device_interval(self) -> Duration {
+        self.limiter()
+            .lock()
+            .limits
+            .device_interval_ms
+            .map(Duration::from_millis)
+            .unwrap_or_else(|| self.default_device_interval())
+    }
+
+    fn limiter(self) -> &'static Limiter {
+        static ELGATO: Limiter = Limiter::new();
+        static WIZ: Limiter = Limiter::new();
+        static LIFX: Limiter = Limiter::new();
+        static CUSTOM: Limiter = Limiter::new();
+        match self {
+            Backend::Elgato => &ELGATO,
+            Backend::Wiz => &WIZ,
+            Backend::Lifx => &LIFX,
+            Backend::Custom => &CUSTOM,
+        }
+    }
+
+    /// Block until a request slot is free and the pacing interval has passed.
+    /// The slot is released when the returned permit is dropped.
+    pub fn acquire(self) -> Permit {
+        let limiter = self.limiter();
+        let mut slot = limiter.lock();
+        let (default_max, default_interval) = self.default_limits();
+        let max_concurrent = slot.limits.max_concurrent.unwrap_or(default_max).max(1);
+        let min_interval = slot
+            .limits
+            .min_interval_ms
+            .map(Duration::from_millis)
+            .unwrap_or(default_interval);
+        while slot.in_flight >= max_concurrent {
+            slot = limiter
+                .freed
+                .wait(slot)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        slot.in_flight += 1;
+        let now = Instant::now();
+        let start = slot.next_start.map_or(now, |next| next.max(now));
+        slot.next_start = Some(start + min_interval);
+        drop(slot);
+        thread::sleep(start - now);
+        Permit { limiter }
+    }
+}
+
+/// Limits for one backend. Unset fields use the backend's defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackendLimits {
+    /// Maximum requests in flight at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<usize>,
+    /// Minimum gap between the start of two requests, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_interval_ms: Option<u64>,
+    /// Minimum gap between two commands to the same light, in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_interval_ms: Option<u64>,
+}
+
+/// Apply the `[backends]` section of the config. Called on every config load so
+/// hand edits take effect without restarting `serve`.
+pub fn configure(backends: &BTreeMap<String, BackendLimits>) {
+    for backend in Backend::ALL {
+        let limits = backends.get(backend.name()).cloned().unwrap_or_default();
+        let limiter = backend.limiter();
+        let mut slot = limiter.lock();
+        if slot.limits != limits {
+            slot.limits = limits;
+            // A raised limit may let waiters through.
+            limiter.freed.notify_all();
+        }
+    }
+    for name in backends.keys() {
+        if !Backend::ALL.iter().any(|backend| backend.name() == name) {
+            log_line!("ignoring limits for unknown backend '{name}'");
+        }
+    }
+}
+
+struct Slot {
+    limits: BackendLimits,
+    in_flight: usize,
+    next_start: Option<Instant>,
+}
+
+struct Limiter {
+    slot: Mutex<Slot>,
+    freed: Condvar,
+}
+
+impl Limiter {
+    const fn new() -> Self {
+        Self {
+            slot: Mutex::new(Slot {
+                limits: BackendLimits {
+                    max_concurrent: None,
+                    min_interval_ms: None,
+                    device_interval_ms: None,
+                },
+                in_flight: 0,
+                next_start: None,
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slot> {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub struct Permit {
+    limiter: &'static Limiter,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.lock().in_flight -= 1;
+        self.limiter.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_round_trip_without_unset_fields() {
+        let limits: BackendLimits = serde_json::from_str(r#"{"max_concurrent": 2}"#).unwrap();
+        assert_eq!(limits.max_concurrent, Some(2));
+        assert_eq!(limits.min_interval_ms, None);
+        assert_eq!(
+            serde_json::to_value(&limits).unwrap(),
+            serde_json::json!({"max_concurrent": 2})
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Conditional requests, paging and field projection for the list endpoints the
+//! tray polls. Their 200 responses carry an `ETag` (a hash of the body), and a
+//! request whose `If-None-Match` still matches gets an empty `304` instead, so an
+//! unchanged list isn't sent again. `?offset=` and `?limit=` page through arrays,
+//! with the full length in `X-Total-Count`, and `?fields=id,alias` keeps only the
+//! named keys of each item.
+
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// GET routes answered with a list; the only ones that take the parameters above.
+pub const ENDPOINTS: [&str; 10] = [
+    "/v1/lights",
+    "/v1/lights/states",
+    "/v1/lights/health",
+    "/v1/groups",
+    "/v1/rooms",
+    "/v1/scenes",
+    "/v1/favorites",
+    "/v1/schedules",
+    "/v1/presets",
+    "/v1/timers",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ListQuery {
+    /// Keys to keep in each item; all of them when absent.
+    pub fields: Option<Vec<String>>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    /// The request's `If-None-Match` header.
+    pub if_none_match: Option<String>,
+}
+
+impl ListQuery {
+    pub fn parse(query: &str, if_none_match: Option<&str>) -> Result<Self, String> {
+        let mut list = Self {
+            if_none_match: if_none_match.map(str::to_string),
+            ..Self::default()
+        };
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid {key} '{value}'"))
+            };
+            match key {
+                "fields" => {
+                    let fields: Vec<String> = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|field| !field.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    list.fields = (!fields.is_empty()).then_some(fields);
+                }
+                "offset" => list.offset = number()?,
+                "limit" => list.limit = Some(number()?),
+                _ => {}
+            }
+        }
+        Ok(list)
+    }
+
+    /// The page of `value` asked for, and the full length when it is an array.
+    pub fn apply(&self, value: Value) -> (Value, Option<usize>) {
+        match value {
+            Value::Array(items) => {
+                let total = items.len();
+                let page = items
+                    .into_iter()
+                    .skip(self.offset)
+                    .take(self.limit.unwrap_or(usize::MAX))
+                    .map(|item| self.project(item))
+                    .collect();
+                (Value::Array(page), Some(total))
+            }
+            other => (self.project(other), None),
+        }
+    }
+
+    fn project(&self, value: Value) -> Value {
+        match (&self.fields, value) {
+            (Some(fields), Value::Object(mut object)) => {
+                object.retain(|key, _| fields.iter().any(|field| field == key));
+                Value::Object(object)
+            }
+            (_, value) => value,
+        }
+    }
+
+    /// Whether the client already has the body tagged `etag`.
+    pub fn not_modified(&self, etag: &str) -> bool {
+        self.if_none_match.as_deref().is_some_and(|header| {
+            header
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+        })
+    }
+}
+
+/// Strong `ETag` of a response body.
+pub fn etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pages_and_projects_lists() {
+        let lights = json!([
+            {"id": "a", "alias": "left", "on": 1},
+            {"id": "b", "alias": "right", "on": 0},
+            {"id": "c", "alias": null, "on": 1}
+        ]);
+        let list = ListQuery::parse("fields=id,on&offset=1&limit=1", None).unwrap();
+        assert_eq!(
+            list.apply(lights.clone()),
+            (json!([{"id": "b", "on": 0}]), Some(3))
+        );
+        assert_eq!(
+            ListQuery::default().apply(lights.clone()),
+            (lights, Some(3))
+        );
+        assert!(ListQuery::parse("limit=ten", None).is_err());
+    }
+
+    #[test]
+    fn matching_etags_are_not_modified() {
+        let tag = etag(b"[]");
+        assert_eq!(tag, etag(b"[]"));
+        assert_ne!(tag, etag(b"[1]"));
+        let list = ListQuery::parse("", Some(&format!("\"other\", W/{tag}"))).unwrap();
+        assert!(list.not_modified(&tag));
+        assert!(!ListQuery::default().not_modified(&tag));
+        assert!(ListQuery::parse("", Some("*")).unwrap().not_modified(&tag));
+    }
+}
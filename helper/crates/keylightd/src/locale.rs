@@ -0,0 +1,142 @@
+//! Locale-aware number formatting for everything the CLI prints for people: light
+//! states, lists, progress and the terminal UI. `--json` output stays plain so
+//! scripts can keep parsing it, as does anything run under `LC_ALL=C`.
+
+use std::sync::OnceLock;
+
+/// How the user's locale writes numbers, picked from `LC_ALL`, `LC_NUMERIC` or `LANG`
+/// in the order the C library checks them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Thousands separator; `None` in the C locale.
+    grouping: Option<char>,
+    /// Separator before the fractional part.
+    decimal: char,
+}
+
+impl NumberFormat {
+    const PLAIN: Self = Self {
+        grouping: None,
+        decimal: '.',
+    };
+
+    /// Format for a POSIX locale name such as `de_DE.UTF-8`. Languages not listed
+    /// write numbers the English way.
+    pub fn for_locale(locale: &str) -> Self {
+        let name = locale.split(['.', '@']).next().unwrap_or_default();
+        let (language, territory) = name.split_once('_').unwrap_or((name, ""));
+        let (grouping, decimal) = match (language, territory) {
+            ("" | "C" | "POSIX", _) => return Self::PLAIN,
+            ("de" | "fr" | "it", "CH") => ('\'', '.'),
+            ("de" | "nl" | "it" | "es" | "pt" | "da" | "el" | "id" | "tr", _) => ('.', ','),
+            (
+                "fr" | "ru" | "uk" | "pl" | "cs" | "sk" | "fi" | "sv" | "nb" | "nn" | "no" | "hu"
+                | "bg" | "et" | "lv" | "lt",
+                _,
+            ) => ('\u{a0}', ','),
+            _ => (',', '.'),
+        };
+        Self {
+            grouping: Some(grouping),
+            decimal,
+        }
+    }
+
+    /// `value` with thousands separators.
+    pub fn integer(&self, value: impl Into<i64>) -> String {
+        self.group(&value.into().to_string())
+    }
+
+    /// `value` rounded to `places` decimals, with the locale's separators.
+    pub fn decimal(&self, value: f64, places: usize) -> String {
+        let text = format!("{value:.places$}");
+        let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        let mut out = self.group(whole);
+        if !fraction.is_empty() {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    /// Digits of a whole number, optionally signed, with thousands separators.
+    fn group(&self, whole: &str) -> String {
+        let digits = whole.trim_start_matches('-');
+        let mut out = String::with_capacity(whole.len() + digits.len() / 3);
+        if digits.len() < whole.len() {
+            out.push('-');
+        }
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                if let Some(separator) = self.grouping {
+                    out.push(separator);
+                }
+            }
+            out.push(digit);
+        }
+        out
+    }
+}
+
+/// Format for the environment this process was started in.
+pub fn current() -> NumberFormat {
+    static FORMAT: OnceLock<NumberFormat> = OnceLock::new();
+    *FORMAT.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|key| std::env::var(key).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        NumberFormat::for_locale(&locale)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separators_follow_locale() {
+        assert_eq!(
+            NumberFormat::for_locale("en_US.UTF-8").integer(6500),
+            "6,500"
+        );
+        assert_eq!(
+            NumberFormat::for_locale("de_DE.UTF-8").integer(6500),
+            "6.500"
+        );
+        assert_eq!(
+            NumberFormat::for_locale("fr_FR").integer(6500),
+            "6\u{a0}500"
+        );
+        assert_eq!(
+            NumberFormat::for_locale("de_CH.UTF-8").integer(6500),
+            "6'500"
+        );
+        assert_eq!(NumberFormat::for_locale("C").integer(6500), "6500");
+    }
+
+    #[test]
+    fn groups_every_three_digits() {
+        let format = NumberFormat::for_locale("en_GB");
+        assert_eq!(format.integer(0), "0");
+        assert_eq!(format.integer(999), "999");
+        assert_eq!(format.integer(1_234_567), "1,234,567");
+        assert_eq!(format.integer(-4200), "-4,200");
+    }
+
+    #[test]
+    fn decimals_use_the_locale_separator() {
+        assert_eq!(
+            NumberFormat::for_locale("en_US").decimal(1234.56, 1),
+            "1,234.6"
+        );
+        assert_eq!(
+            NumberFormat::for_locale("de_DE").decimal(1234.56, 1),
+            "1.234,6"
+        );
+        assert_eq!(NumberFormat::for_locale("fr_FR").decimal(-0.25, 2), "-0,25");
+        assert_eq!(NumberFormat::for_locale("de_CH").decimal(12.5, 1), "12.5");
+        assert_eq!(NumberFormat::for_locale("C").decimal(1234.5, 0), "1234");
+    }
+}
@@ -0,0 +1,60 @@
+//! Recent daemon log lines, kept in memory for `GET /v1/logs`. The tray starts the
+//! daemon with stderr discarded, so this is the only place a bug report can get them.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Lines kept; enough to cover a few minutes of a misbehaving light.
+const RETAINED_LINES: usize = 200;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Log a daemon message to stderr and keep it for `GET /v1/logs`.
+macro_rules! log_line {
+    ($($arg:tt)*) => {
+        $crate::logs::push(format!($($arg)*))
+    };
+}
+pub(crate) use log_line;
+
+pub fn push(message: String) {
+    eprintln!("[keylightd] {message}");
+    let Ok(mut recent) = RECENT.lock() else {
+        return;
+    };
+    if recent.len() == RETAINED_LINES {
+        recent.pop_front();
+    }
+    let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+    recent.push_back(format!("{now} {message}"));
+}
+
+/// Retained lines, oldest first.
+pub fn recent() -> Vec<String> {
+    RECENT
+        .lock()
+        .map(|recent| recent.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_latest_lines() {
+        for n in 0..RETAINED_LINES + 5 {
+            log_line!("test line {n}");
+        }
+        // Other tests may log in between, so only look at this test's lines.
+        let recent = recent();
+        let ours: Vec<_> = recent
+            .iter()
+            .filter(|line| line.contains("test line"))
+            .collect();
+        assert_eq!(recent.len(), RETAINED_LINES);
+        assert!(!ours.iter().any(|line| line.ends_with("test line 4")));
+        let last = format!("test line {}", RETAINED_LINES + 4);
+        assert!(ours.last().is_some_and(|line| line.ends_with(&last)));
+    }
+}
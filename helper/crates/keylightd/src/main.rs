@@ -1,22 +1,101 @@
+mod advertise;
+mod auto_brightness;
+mod calibration;
+mod coalesce;
+mod completions;
+mod conferencing;
+mod cors;
+mod custom;
+mod device;
+mod discovery;
+mod dnd;
+mod error;
+mod events;
+mod health;
+mod history;
+mod hooks;
+mod last_state;
+mod lifx;
+mod limits;
+mod listing;
+mod locale;
+mod logs;
+mod notices;
+mod obs;
+mod presets;
+mod profiles;
+mod ramps;
+mod rooms;
+mod router;
+mod schedule;
+mod smoke;
+mod snapshot;
+mod soft_start;
+mod status;
+mod subnet;
+mod suggestions;
+mod summary;
+mod timers;
+mod tui;
+mod verify;
+mod watch;
+mod web;
+mod whitepoint;
+mod wiz;
+
 use clap::{Parser, Subcommand};
-use flume::RecvTimeoutError;
-use mdns_sd::{ServiceDaemon, ServiceEvent};
+use clap_complete::engine::ArgValueCompleter;
+use error::KeylightError;
+use keylight_core::protocol::{
+    kelvin_range_from_value, mired_to_kelvin, split_zone, supports_color, KelvinRange, LightState,
+    LightUpdate, LightsPayload,
+};
+use keylight_core::discovery::Found;
+use keylight_core::files::{self, create_private_dir};
+use keylight_core::store::{SavedGroup, SavedLight, Target};
+use keylight_core::{elgato, store};
+use limits::{Backend, BackendLimits};
+use logs::log_line;
 use reqwest::blocking::Client;
+use router::Segments;
+use schedule::Schedule;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock};
 use std::time::{Duration, Instant};
 use tiny_http::{Method, Response, Server, StatusCode};
 
-const KELVIN_MIN: u16 = 2900;
-const KELVIN_MAX: u16 = 7000;
-const MIRED_MIN: u16 = (1_000_000u32 / KELVIN_MAX as u32) as u16;
-const MIRED_MAX: u16 = (1_000_000u32 / KELVIN_MIN as u32) as u16;
-
 const MAX_API_BODY_BYTES: usize = 64 * 1024; // 64KiB
+/// How long a client gets to send a request body over 1 KiB before it is answered
+/// 408. tiny_http reads smaller bodies itself before handing the request over.
+const BODY_READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Body reads in flight, counting ones still waiting on a client that was already
+/// answered 408; past this, requests with a body are answered 503.
+const MAX_BODY_READERS: usize = 16;
+/// API requests answered at once; further ones wait for a free worker.
+const API_WORKERS: usize = 4;
+/// Long polls (events, notices, summaries) waiting at once; past this, further ones
+/// are answered 503 rather than each getting a thread.
+const MAX_LONG_POLLS: usize = 64;
+
+/// Icon names clients draw themselves; anything else set as an icon is an emoji.
+const LIGHT_ICONS: [&str; 4] = ["panel", "ring", "strip", "bulb"];
+/// Longest emoji accepted as an icon, in chars; ZWJ sequences need a few.
+const MAX_ICON_CHARS: usize = 8;
+/// Longest room name and notes accepted for a light, in chars.
+const MAX_ROOM_CHARS: usize = 64;
+const MAX_NOTES_CHARS: usize = 2000;
+
+/// On-disk config schema version. Bump this and add a step to `migrate_config`
+/// whenever the persisted shape changes.
+const CONFIG_VERSION: u32 = 2;
 
 fn default_enabled() -> bool {
     true
@@ -25,6 +104,17 @@ fn default_enabled() -> bool {
 #[derive(Parser, Debug)]
 #[command(name = "keylightd", version, about = "Elgato Key Light control spike")]
 struct Cli {
+    /// Suppress progress output on stderr (for scripts)
+    #[arg(long, global = true, default_value_t = false)]
+    quiet: bool,
+    /// Print results as JSON with the REST API's field names (list, get, group-list,
+    /// preset-list, favorites, meta, discover, refresh, set; watch prints a JSON object
+    /// per line)
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+    /// Print resolved addresses, request bodies and response times on stderr
+    #[arg(long, global = true, default_value_t = false)]
+    verbose: bool,
     #[command(subcommand)]
     command: Command,
 }
@@ -37,7 +127,7 @@ enum Command {
         #[arg(long)]
         ip: Option<String>,
         /// Persisted light id (from `list`)
-        #[arg(long)]
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
         id: Option<String>,
     },
     /// Fetch device info from /elgato/accessory-info
@@ -46,7 +136,7 @@ enum Command {
         #[arg(long)]
         ip: Option<String>,
         /// Persisted light id (from `list`)
-        #[arg(long)]
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
         id: Option<String>,
     },
     /// Discover Elgato lights on the local network via mDNS
@@ -54,51 +144,134 @@ enum Command {
         /// How long to wait for responses (seconds)
         #[arg(long, default_value_t = 3)]
         timeout: u64,
+        /// Store lights as announced, without asking each one for its accessory info,
+        /// temperature range and zones; what's known from earlier is kept
+        #[arg(long)]
+        skip_info: bool,
+        /// Also probe every address in this IPv4 range (e.g. 192.168.1.0/24, at most a
+        /// /20) on port 9123, for lights that don't announce themselves over mDNS
+        #[arg(long)]
+        subnet: Option<subnet::Subnet>,
     },
     /// Refresh persisted lights by re-running discovery
     Refresh {
         /// How long to wait for responses (seconds)
         #[arg(long, default_value_t = 3)]
         timeout: u64,
+        /// Store lights as announced, without asking each one for its accessory info,
+        /// temperature range and zones; what's known from earlier is kept
+        #[arg(long)]
+        skip_info: bool,
+        /// Also probe every address in this IPv4 range (e.g. 192.168.1.0/24, at most a
+        /// /20) on port 9123, for lights that don't announce themselves over mDNS
+        #[arg(long)]
+        subnet: Option<subnet::Subnet>,
     },
     /// Run the local HTTP API server
     Serve {
-        /// Port to bind on localhost
+        /// Port to bind on
         #[arg(long, default_value_t = 9124)]
         port: u16,
+        /// Address to listen on. Anything but loopback exposes the API, which has no
+        /// authentication, to the network, and announces it there over mDNS
+        #[arg(long, default_value = "127.0.0.1")]
+        listen: IpAddr,
+        /// Re-apply the last state each light was seen in before serving, e.g. after
+        /// a power outage reset them to their power-on behavior
+        #[arg(long)]
+        restore_on_start: bool,
     },
     /// Show persisted lights from the last discovery
     List,
     /// Assign a friendly name to a persisted light
     Name {
         /// Persisted light id (from `list`)
-        #[arg(long)]
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
         id: String,
         /// Friendly name (e.g. leftlight)
         #[arg(long)]
         name: String,
+        /// Show the change without saving it
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Set the room and notes of a persisted light; an empty value clears one
+    Meta {
+        /// Persisted light id (from `list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
+        id: String,
+        /// Room or location (e.g. office)
+        #[arg(long, alias = "location")]
+        room: Option<String>,
+        /// Free-form notes
+        #[arg(long)]
+        notes: Option<String>,
+    },
+    /// Star a light or scene so it's listed among the favorites
+    Favorite {
+        /// Persisted light id (from `list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
+        id: Option<String>,
+        /// Scene name (from `snapshot list`)
+        #[arg(long)]
+        scene: Option<String>,
+        /// Unstar instead
+        #[arg(long, default_value_t = false)]
+        remove: bool,
     },
+    /// List starred lights and scenes
+    Favorites,
     /// Add or update a group of lights
     GroupAdd {
         /// Group name (e.g. office)
         #[arg(long)]
         name: String,
         /// Members by id/name/alias (repeat for multiple)
-        #[arg(long = "id", required = true)]
+        #[arg(long = "id", required = true, add = ArgValueCompleter::new(completions::lights))]
         members: Vec<String>,
+        /// Delay between members when switching the group on; 0 switches them together
+        #[arg(long)]
+        stagger_ms: Option<u64>,
+        /// Show the group and how its members resolve without saving it
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
     },
     /// List configured groups
     GroupList,
+    /// Remove lights from a group, leaving the other members
+    GroupRemove {
+        /// Group name (from `group-list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::groups))]
+        name: String,
+        /// Members to remove by id/name/alias (repeat for multiple)
+        #[arg(long = "id", required = true, add = ArgValueCompleter::new(completions::lights))]
+        members: Vec<String>,
+    },
+    /// Rename a group; schedules that target it follow
+    GroupRename {
+        /// Group name (from `group-list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::groups))]
+        name: String,
+        /// New group name
+        #[arg(long)]
+        to: String,
+    },
+    /// Delete a group (its lights are kept)
+    GroupDelete {
+        /// Group name (from `group-list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::groups))]
+        name: String,
+    },
     /// Update light state via /elgato/lights
     Set {
         /// Device IP address (e.g. 192.168.1.61)
         #[arg(long)]
         ip: Option<String>,
         /// Persisted light id (from `list`)
-        #[arg(long)]
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
         id: Option<String>,
         /// Group name (from `group-list`)
-        #[arg(long)]
+        #[arg(long, add = ArgValueCompleter::new(completions::groups))]
         group: Option<String>,
         /// Target all persisted lights
         #[arg(long, default_value_t = false)]
@@ -115,40 +288,194 @@ enum Command {
         /// Color temperature in mired (143-344)
         #[arg(long)]
         mired: Option<u16>,
+        /// Hue in degrees (0-360), for color-capable lights
+        #[arg(long)]
+        hue: Option<f64>,
+        /// Saturation percentage (0-100), for color-capable lights
+        #[arg(long)]
+        saturation: Option<f64>,
+        /// Preset name (from `preset-list`); --brightness and --kelvin override it
+        #[arg(long, add = ArgValueCompleter::new(completions::presets))]
+        preset: Option<String>,
+        /// Show which lights would get which request bodies without sending anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Read each light back afterwards and report values it didn't take
+        #[arg(long, default_value_t = false)]
+        verify: bool,
+    },
+    /// List brightness/temperature presets
+    PresetList,
+    /// Estimate a photo's color temperature and set lights to the closest supported kelvin
+    Match {
+        /// Reference photo (JPEG, PNG, ...)
+        #[arg(long)]
+        image: PathBuf,
+        /// Device IP address (e.g. 192.168.1.61)
+        #[arg(long)]
+        ip: Option<String>,
+        /// Persisted light id (from `list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
+        id: Option<String>,
+        /// Group name (from `group-list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::groups))]
+        group: Option<String>,
+        /// Target all persisted lights
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Capture or restore the live state of all lights
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// Exercise the API end to end against fake lights and report what passed
+    Smoke {
+        /// Serve two fake Key Lights on 127.0.0.2 and 127.0.0.3 (required)
+        #[arg(long, default_value_t = false)]
+        mock: bool,
+        /// Delay each answer of the fake lights, e.g. `300`, or `smoke-left=300` for one
+        #[arg(long, value_name = "[LIGHT=]MS")]
+        latency_ms: Vec<String>,
+        /// Up to this much more delay, varying per request
+        #[arg(long, value_name = "[LIGHT=]MS")]
+        jitter_ms: Vec<String>,
+        /// Share of requests (0 to 1) the fake lights answer with a 500
+        #[arg(long, value_name = "[LIGHT=]RATE")]
+        failure_rate: Vec<String>,
+    },
+    /// Print each change to the lights' state as it happens, until interrupted
+    Watch {
+        /// Device IP address (e.g. 192.168.1.61)
+        #[arg(long)]
+        ip: Option<String>,
+        /// Persisted light id (from `list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::lights))]
+        id: Option<String>,
+        /// Group name (from `group-list`)
+        #[arg(long, add = ArgValueCompleter::new(completions::groups))]
+        group: Option<String>,
+        /// Watch all persisted lights (the default)
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        /// Seconds between reads
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
     },
+    /// Control lights from a terminal interface (arrow keys, Space, Tab)
+    Tui,
+    /// Print the shell completion script (e.g. `keylightd completions bash`)
+    Completions { shell: completions::Shell },
+    /// Print the man page (e.g. `keylightd man > keylightd.1`)
+    Man,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct LightsPayload<T> {
-    number_of_lights: u8,
-    lights: Vec<T>,
+#[derive(Subcommand, Debug)]
+enum SnapshotAction {
+    /// Read every enabled light and save its state under a name
+    Save {
+        /// Snapshot name (e.g. before-stream)
+        name: String,
+    },
+    /// Send a saved snapshot back to the lights
+    Restore {
+        /// Snapshot name (from `snapshot list`)
+        name: String,
+    },
+    /// List saved snapshots
+    List,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct LightState {
-    on: u8,
-    brightness: u8,
-    temperature: u16,
-}
-
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
-#[serde(rename_all = "camelCase")]
-struct LightUpdate {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    on: Option<u8>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    brightness: Option<u8>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<u16>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Default)]
 struct Config {
+    #[serde(default)]
+    version: u32,
     lights: Vec<LightRecord>,
     #[serde(default)]
     groups: Vec<Group>,
+    #[serde(default)]
+    schedules: Vec<Schedule>,
+    /// Request limits keyed by backend name (`elgato`).
+    #[serde(default)]
+    backends: BTreeMap<String, BackendLimits>,
+    /// Hand-defined HTTP lights; mirrored into `lights` on load.
+    #[serde(default)]
+    custom_lights: Vec<custom::CustomLight>,
+    /// OBS connection and the snapshots to apply while recording or streaming.
+    #[serde(default, skip_serializing)]
+    obs: Option<obs::ObsConfig>,
+    /// Conferencing apps to watch for and the snapshot to apply during calls.
+    #[serde(default, skip_serializing)]
+    conferencing: Option<conferencing::ConferencingConfig>,
+    /// Ambient light sensor driven brightness for a set of lights.
+    #[serde(default, skip_serializing)]
+    auto_brightness: Option<auto_brightness::AutoBrightnessConfig>,
+    /// What `serve` does before it starts answering.
+    #[serde(default, skip_serializing)]
+    serve: ServeConfig,
+    /// mDNS service types to browse.
+    #[serde(default, skip_serializing)]
+    discovery: discovery::DiscoveryConfig,
+    /// Timeouts and retries of requests to lights.
+    #[serde(default, skip_serializing)]
+    device: device::DeviceConfig,
+    /// Browser origins besides keylightd's own that may use the API.
+    #[serde(default, skip_serializing)]
+    cors: cors::CorsConfig,
+    /// Named brightness and temperature settings; built-in ones when unset.
+    #[serde(default = "presets::defaults", skip_serializing)]
+    presets: Vec<presets::Preset>,
+    /// Per-light kelvin corrections, so mixed models match at the same temperature.
+    #[serde(default, skip_serializing)]
+    calibration: Vec<calibration::Calibration>,
+    /// Activities bundling a scene and automation switches, with rules to pick them.
+    #[serde(default, skip_serializing)]
+    profiles: Vec<profiles::Profile>,
+}
+
+/// Startup behavior of `serve`, from the `[serve]` section.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+struct ServeConfig {
+    /// Run a full discovery (mDNS plus WiZ/LIFX broadcasts) when `serve` starts.
+    discover_on_start: bool,
+    discovery_timeout_secs: u64,
+    /// Hold API requests until that discovery has finished, so the first
+    /// `GET /v1/lights` already includes lights that are new since the last run.
+    wait_for_discovery: bool,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            discover_on_start: true,
+            discovery_timeout_secs: 3,
+            wait_for_discovery: false,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            lights: Vec::new(),
+            groups: Vec::new(),
+            schedules: Vec::new(),
+            backends: BTreeMap::new(),
+            custom_lights: Vec::new(),
+            obs: None,
+            conferencing: None,
+            auto_brightness: None,
+            serve: ServeConfig::default(),
+            discovery: discovery::DiscoveryConfig::default(),
+            device: device::DeviceConfig::default(),
+            cors: cors::CorsConfig::default(),
+            presets: presets::defaults(),
+            calibration: Vec::new(),
+            profiles: Vec::new(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -162,17 +489,134 @@ struct LightRecord {
     last_seen_unix: u64,
     #[serde(default = "default_enabled")]
     enabled: bool,
-    #[serde(default)]
+    /// Persisted in the cache dir rather than `state.json`; refetched on discovery.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     accessory_info: Option<Value>,
+    /// Ignore all schedules for this light until cleared.
+    #[serde(default)]
+    schedule_paused: bool,
+    /// Skip only the next schedule run that would touch this light.
+    #[serde(default)]
+    schedule_skip_next: bool,
+    /// Protocol the light speaks; lights persisted before WiZ/LIFX support are Elgato.
+    #[serde(default)]
+    backend: Backend,
+    #[serde(default)]
+    capabilities: Capabilities,
+    /// Temperature range the device reports; absent means `KelvinRange::DEFAULT`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kelvin_range: Option<KelvinRange>,
+    /// Ramp brightness when switching on or off instead of snapping.
+    #[serde(default)]
+    soft_start: bool,
+    /// One of `LIGHT_ICONS` or an emoji, shown next to the name in the tray.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    /// Room or other location the light is in, e.g. "office".
+    #[serde(default, alias = "location", skip_serializing_if = "Option::is_none")]
+    room: Option<String>,
+    /// Free-form notes, e.g. where the light is mounted or its power supply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    /// Starred to be shown among the favorites.
+    #[serde(default)]
+    favorite: bool,
+    /// Brightness range updates are clamped to; absent means 0-100.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    brightness_limits: Option<BrightnessLimits>,
+    /// Place in light lists, set by `PUT /v1/lights/{id}/position`. Lights without
+    /// one follow in discovery order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sort_index: Option<u32>,
+    /// Zone of a multi-zone light (a Ring Light's inner and outer ring) this record
+    /// controls. Such a light is stored as one record per zone, with ids ending in
+    /// `#0`, `#1`, and so on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zone: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct BrightnessLimits {
+    min: u8,
+    max: u8,
+}
+
+impl BrightnessLimits {
+    /// Check limits from the API; missing bounds are 0 and 100, and the full range
+    /// clears the limits.
+    fn new(min: Option<u8>, max: Option<u8>) -> Result<Option<Self>, &'static str> {
+        let (min, max) = (min.unwrap_or(0), max.unwrap_or(100));
+        if max > 100 {
+            return Err("Brightness limits must be within 0-100.");
+        }
+        if min > max {
+            return Err("The minimum brightness must not be above the maximum.");
+        }
+        Ok((min > 0 || max < 100).then_some(Self { min, max }))
+    }
+}
+
+impl LightRecord {
+    fn supported_kelvin(&self) -> KelvinRange {
+        self.kelvin_range.unwrap_or(KelvinRange::DEFAULT)
+    }
+
+    /// Id of the device; the record's own id unless it's one zone of a light.
+    fn device_id(&self) -> &str {
+        match self.zone {
+            Some(_) => self.id.rsplit_once('#').map_or(&self.id, |(id, _)| id),
+            None => &self.id,
+        }
+    }
+}
+
+impl SavedLight for LightRecord {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn addresses(&self) -> &[String] {
+        &self.addresses
+    }
+
+    fn zone(&self) -> Option<u8> {
+        self.zone
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Group {
     name: String,
     members: Vec<String>,
+    /// Delay between members when the group is switched on, so many panels on one
+    /// circuit don't all draw inrush current at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stagger_ms: Option<u64>,
 }
 
-#[derive(Serialize, Debug)]
+impl SavedGroup for Group {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn members(&self) -> &[String] {
+        &self.members
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
 struct LightStateResponse {
     id: String,
     on: bool,
@@ -180,23 +624,76 @@ struct LightStateResponse {
     kelvin: u16,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+impl LightStateResponse {
+    /// State of the light at `ip`, named by its persisted id; manual `--ip` targets
+    /// keep the address as their id.
+    fn for_ip(config: &Config, ip: &str, state: &LightState) -> Self {
+        match light_for_ip(config, ip) {
+            Some(light) => Self::for_light(config, light, state),
+            None => Self {
+                id: ip.to_string(),
+                on: state.on == 1,
+                brightness: state.brightness,
+                kelvin: mired_to_kelvin(state.temperature),
+            },
+        }
+    }
+
+    /// State of a persisted light in the terms requests use, undoing its
+    /// `[[calibration]]`.
+    fn for_light(config: &Config, light: &LightRecord, state: &LightState) -> Self {
+        let kelvin = light.supported_kelvin().mired_to_kelvin(state.temperature);
+        let (brightness, kelvin) =
+            calibration::reported(config, light, Some(state.brightness), Some(kelvin));
+        Self {
+            id: light.id.clone(),
+            on: state.on == 1,
+            brightness: brightness.unwrap_or(state.brightness),
+            kelvin: kelvin.unwrap_or_default(),
+        }
+    }
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {err}");
+        if let Some(hint) = err
+            .downcast_ref::<KeylightError>()
+            .and_then(KeylightError::hint)
+        {
+            eprintln!("Hint: {hint}");
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    completions::complete_if_asked();
     let cli = Cli::parse();
-    let client = Client::builder().timeout(Duration::from_secs(3)).build()?;
+    warn_on_insecure_permissions();
+    let client = device::client(
+        &load_config()
+            .map(|config| config.device)
+            .unwrap_or_default(),
+    )?;
     match cli.command {
         Command::Get { ip, id } => {
             let ip = resolve_ip(ip, id)?;
-            let base_url = format!("http://{}:9123/elgato", ip);
-            let payload: LightsPayload<LightState> = client
-                .get(format!("{}/lights", base_url))
-                .send()?
-                .error_for_status()?
-                .json()?;
-            print_lights(&payload);
+            let config = load_config().unwrap_or_default();
+            let state = fetch_light_state(&client, backend_for_ip(&config, &ip), &ip)
+                .ok_or_else(|| format!("Unable to read light state from {ip}"))?;
+            if cli.json {
+                print_json(&LightStateResponse::for_ip(&config, &ip, &state))?;
+            } else {
+                print_lights(&LightsPayload {
+                    number_of_lights: 1,
+                    lights: vec![state],
+                });
+            }
         }
         Command::Info { ip, id } => {
             let ip = resolve_ip(ip, id)?;
-            let base_url = format!("http://{}:9123/elgato", ip);
+            let base_url = format!("http://{}:9123/elgato", split_zone(&ip).0);
             let info: Value = client
                 .get(format!("{}/accessory-info", base_url))
                 .send()?
@@ -204,18 +701,35 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .json()?;
             println!("{}", serde_json::to_string_pretty(&info)?);
         }
-        Command::Discover { timeout } => {
-            discover_lights(&client, Duration::from_secs(timeout))?;
+        Command::Discover {
+            timeout,
+            skip_info,
+            subnet,
         }
-        Command::Refresh { timeout } => {
-            discover_lights(&client, Duration::from_secs(timeout))?;
+        | Command::Refresh {
+            timeout,
+            skip_info,
+            subnet,
+        } => {
+            let options = DiscoverOptions {
+                timeout: Duration::from_secs(timeout),
+                probe: !skip_info,
+                subnet,
+            };
+            discover_lights(&client, &options, cli.quiet, cli.json)?;
         }
-        Command::Serve { port } => {
-            run_api_server(&client, port)?;
+        Command::Serve {
+            port,
+            listen,
+            restore_on_start,
+        } => {
+            run_api_server(&client, SocketAddr::new(listen, port), restore_on_start)?;
         }
         Command::List => {
             let config = load_config()?;
-            if config.lights.is_empty() {
+            if cli.json {
+                print_json(&config.lights)?;
+            } else if config.lights.is_empty() {
                 println!("No persisted lights found. Run `discover` first.");
             } else {
                 for light in config.lights {
@@ -225,10 +739,11 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .and_then(|value| serde_json::to_string(value).ok())
                         .unwrap_or_else(|| "-".to_string());
                     println!(
-                        "id={}, alias={}, name={}, host={}, port={}, addresses=[{}], last_seen_unix={}, accessory_info={}",
+                        "id={}, alias={}, name={}, room={}, host={}, port={}, addresses=[{}], last_seen_unix={}, accessory_info={}",
                         light.id,
                         light.alias.as_deref().unwrap_or("-"),
                         light.name,
+                        light.room.as_deref().unwrap_or("-"),
                         light.hostname,
                         light.port,
                         light.addresses.join(", "),
@@ -238,8 +753,11 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
         }
-        Command::Name { id, name } => {
+        Command::Name { id, name, dry_run } => {
             let mut config = load_config()?;
+            if let Some(light) = store::find(&config.lights, &id) {
+                check_alias(&config, &light.id, name.trim())?;
+            }
             let record_id = {
                 let record = config
                     .lights
@@ -247,43 +765,152 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .find(|light| {
                         light.id == id || light.name == id || light.alias.as_deref() == Some(&id)
                     })
-                    .ok_or_else(|| format!("No persisted light found with id '{}'", id))?;
+                    .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+                if dry_run {
+                    println!(
+                        "Would change alias for {} from {} to {}",
+                        record.id,
+                        record.alias.as_deref().unwrap_or("-"),
+                        name
+                    );
+                    return Ok(());
+                }
                 record.alias = Some(name);
                 record.id.clone()
             };
-            save_config(&config)?;
+            save_state(&config)?;
             println!("Updated alias for {}", record_id);
         }
-        Command::GroupAdd { name, members } => {
-            let mut config = load_config()?;
-            let mut members = members;
-            members.sort();
-            members.dedup();
-            let group = Group {
-                name: name.clone(),
-                members,
-            };
-            match config.groups.iter_mut().find(|group| group.name == name) {
-                Some(existing) => *existing = group,
-                None => config.groups.push(group),
+        Command::Favorite { id, scene, remove } => match (id, scene) {
+            (Some(id), None) => {
+                let record = set_light_favorite(id, !remove)?;
+                let verb = if remove { "Unstarred" } else { "Starred" };
+                println!("{verb} light {}", record.id);
             }
-            save_config(&config)?;
+            (None, Some(scene)) => {
+                let scene = snapshot::set_favorite(&scene, !remove)?;
+                let verb = if remove { "Unstarred" } else { "Starred" };
+                println!("{verb} scene {}", scene.name);
+            }
+            _ => return Err("Provide exactly one of --id or --scene".into()),
+        },
+        Command::Favorites => {
+            let favorites = favorites()?;
+            if cli.json {
+                print_json(&favorites)?;
+            } else if favorites.lights.is_empty() && favorites.scenes.is_empty() {
+                println!(
+                    "No favorites. Star one with `favorite --id <light>` or `--scene <name>`."
+                );
+            } else {
+                for light in &favorites.lights {
+                    println!(
+                        "light={}, alias={}, room={}",
+                        light.id,
+                        light.alias.as_deref().unwrap_or("-"),
+                        light.room.as_deref().unwrap_or("-")
+                    );
+                }
+                for scene in &favorites.scenes {
+                    let lights = locale::current().integer(scene.lights as i64);
+                    println!("scene={}, lights={lights}", scene.name);
+                }
+            }
+        }
+        Command::Meta { id, room, notes } => {
+            if room.is_none() && notes.is_none() {
+                return Err("Pass --room and/or --notes".into());
+            }
+            let record = set_light_meta(id, MetaRequest { room, notes })?;
+            if cli.json {
+                print_json(&record)?;
+            } else {
+                println!(
+                    "Updated {}: room={}, notes={}",
+                    record.id,
+                    record.room.as_deref().unwrap_or("-"),
+                    record.notes.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        Command::GroupAdd {
+            name,
+            members,
+            stagger_ms,
+            dry_run,
+        } => {
+            if dry_run || cli.verbose {
+                let config = load_config().unwrap_or_default();
+                for line in explain_members(&config, &members) {
+                    if dry_run {
+                        println!("{line}");
+                    } else {
+                        eprintln!("{line}");
+                    }
+                }
+            }
+            if dry_run {
+                println!("Would save group '{}'", name);
+                return Ok(());
+            }
+            save_group(name.clone(), members, stagger_ms)?;
             println!("Saved group '{}'", name);
         }
+        Command::GroupRemove { name, members } => {
+            let group = remove_group_members(&name, &members)?;
+            println!(
+                "Updated group '{}', members=[{}]",
+                name,
+                group.members.join(", ")
+            );
+        }
+        Command::GroupRename { name, to } => {
+            let schedules = locale::current().integer(rename_group(&name, &to)? as i64);
+            println!("Renamed group '{name}' to '{to}' ({schedules} schedule(s) updated)");
+        }
+        Command::GroupDelete { name } => {
+            delete_group(name.clone())?;
+            println!("Deleted group '{}'", name);
+        }
         Command::GroupList => {
             let config = load_config()?;
-            if config.groups.is_empty() {
+            if cli.json {
+                print_json(&config.groups)?;
+            } else if config.groups.is_empty() {
                 println!("No groups configured. Use `group-add` first.");
             } else {
+                let format = locale::current();
                 for group in config.groups {
                     println!(
-                        "group={}, members=[{}]",
+                        "group={}, members=[{}], stagger_ms={}",
                         group.name,
-                        group.members.join(", ")
+                        group.members.join(", "),
+                        format.integer(group.stagger_ms.unwrap_or(0) as i64)
                     );
                 }
             }
         }
+        Command::PresetList => {
+            let config = load_config()?;
+            if cli.json {
+                print_json(&config.presets)?;
+                return Ok(());
+            }
+            if config.presets.is_empty() {
+                println!("No presets configured. Add [[presets]] to config.toml.");
+            }
+            let format = locale::current();
+            for preset in config.presets {
+                let brightness = preset.brightness.map(|v| format.integer(v));
+                let kelvin = preset.kelvin.map(|v| format.integer(v));
+                println!(
+                    "preset={}, brightness={}, kelvin={}",
+                    preset.name,
+                    brightness.unwrap_or_default(),
+                    kelvin.unwrap_or_default()
+                );
+            }
+        }
         Command::Set {
             ip,
             id,
@@ -293,10 +920,23 @@ fn main() -> Result<(), Box<dyn Error>> {
             brightness,
             kelvin,
             mired,
+            hue,
+            saturation,
+            preset,
+            dry_run,
+            verify,
         } => {
-            if on.is_none() && brightness.is_none() && kelvin.is_none() && mired.is_none() {
+            if preset.is_none()
+                && on.is_none()
+                && brightness.is_none()
+                && kelvin.is_none()
+                && mired.is_none()
+                && hue.is_none()
+                && saturation.is_none()
+            {
                 return Err(
-                    "set requires at least one of --on, --brightness, --kelvin, --mired".into(),
+                    "set requires at least one of --preset, --on, --brightness, --kelvin, --mired, --hue, --saturation"
+                        .into(),
                 );
             }
             if let Some(value) = on {
@@ -304,84 +944,481 @@ fn main() -> Result<(), Box<dyn Error>> {
                     return Err("--on must be 0 or 1".into());
                 }
             }
-            let temperature = mired
-                .map(clamp_mired)
-                .or_else(|| kelvin.map(kelvin_to_mired));
-            let update = LightUpdate {
-                on,
-                brightness: brightness.map(|v| v.min(100)),
-                temperature,
+            let config = load_config().unwrap_or_default();
+            let base = match &preset {
+                Some(name) => presets::find(&config.presets, name)?.update(),
+                None => UpdateRequest::default(),
+            };
+            let request = UpdateRequest {
+                on: on.or(base.on),
+                brightness: brightness.or(base.brightness),
+                kelvin: kelvin.or(base.kelvin.filter(|_| mired.is_none())),
+                mired,
+                hue,
+                saturation,
             };
+            let stagger = power_on_stagger(&config, group.as_deref(), &request);
+            if dry_run || cli.verbose {
+                // Members that won't be sent anything, and why.
+                let members = group
+                    .as_deref()
+                    .and_then(|name| config.groups.iter().find(|g| g.name == name))
+                    .map(|group| explain_members(&config, &group.members))
+                    .unwrap_or_default();
+                for line in members {
+                    if dry_run {
+                        println!("{line}");
+                    } else {
+                        eprintln!("{line}");
+                    }
+                }
+            }
+            let targets = resolve_targets(ip, id, group, all)?;
+            let mut progress = Progress::new(cli.quiet || dry_run, Some(targets.len()));
+            let mut states = Vec::new();
+            let mut outcomes = Vec::new();
+            let mut unverified = 0;
+            for (index, ip) in targets.into_iter().enumerate() {
+                let range = kelvin_range_for_ip(&config, &ip);
+                let update = request
+                    .calibrated(&config, light_for_ip(&config, &ip))
+                    .to_light_update_in(range);
+                let light = light_for_ip(&config, &ip);
+                let backend = backend_for_ip(&config, &ip);
+                let sent = within_limits(light, &update);
+                let body = serde_json::to_string(&sent)?;
+                let ramp = update.on.is_some() && light.is_some_and(|light| light.soft_start);
+                if dry_run {
+                    println!(
+                        "ip={}, id={}, backend={}, body={}{}",
+                        ip,
+                        light.map_or("-", |light| light.id.as_str()),
+                        backend.name(),
+                        body,
+                        if ramp { ", soft_start=true" } else { "" }
+                    );
+                    continue;
+                }
+                if index > 0 {
+                    std::thread::sleep(stagger);
+                }
+                if cli.verbose {
+                    eprintln!("{ip}: sending {body}");
+                }
+                let started = Instant::now();
+                let result = set_light(&client, backend, &ip, &update);
+                if cli.verbose {
+                    let ms = started.elapsed().as_secs_f64() * 1000.0;
+                    eprintln!("{ip}: answered in {} ms", locale::current().decimal(ms, 1));
+                }
+                let response = match result {
+                    Ok(response) => response,
+                    // Keep going: one unplugged light shouldn't leave the rest unchanged.
+                    Err(err) => {
+                        progress.step(&ip, &format!("failed: {err}"));
+                        outcomes.push((ip, Err(err.to_string())));
+                        continue;
+                    }
+                };
+                progress.step(&ip, "ok");
+                if cli.json {
+                    states.extend(
+                        response
+                            .lights
+                            .iter()
+                            .map(|state| LightStateResponse::for_ip(&config, &ip, state)),
+                    );
+                } else {
+                    print_lights(&response);
+                }
+                if verify {
+                    let verification = verify::read_back(&client, backend, &ip, &sent, range);
+                    // Like failures, reported even with --quiet.
+                    for line in verification.lines() {
+                        eprintln!("{ip}: {line}");
+                    }
+                    if !verification.matched {
+                        unverified += 1;
+                    }
+                }
+                outcomes.push((ip, Ok(())));
+            }
+            if cli.json && !dry_run {
+                print_json(&states)?;
+            }
+            let failed = outcomes
+                .iter()
+                .filter(|(_, outcome)| outcome.is_err())
+                .count();
+            let format = locale::current();
+            let total = format.integer(outcomes.len() as i64);
+            if failed == 0 && unverified > 0 {
+                let unverified = format.integer(unverified as i64);
+                eprintln!("{unverified} of {total} lights didn't take every value");
+                std::process::exit(4);
+            }
+            if failed == 0 {
+                if outcomes.len() > 1 {
+                    progress.note(&format!("{total} of {total} lights updated"));
+                }
+                return Ok(());
+            }
+            // Failures are reported even with --quiet.
+            for (ip, outcome) in &outcomes {
+                match outcome {
+                    Ok(()) => eprintln!("{ip}: ok"),
+                    Err(err) => eprintln!("{ip}: failed: {err}"),
+                }
+            }
+            let updated = format.integer((outcomes.len() - failed) as i64);
+            eprintln!("{updated} of {total} lights updated");
+            std::process::exit(set_exit_code(outcomes.len(), failed));
+        }
+        Command::Match {
+            image,
+            ip,
+            id,
+            group,
+            all,
+        } => {
+            let estimated = whitepoint::estimate_kelvin_from_file(&image)?;
+            println!("estimated_kelvin={}", locale::current().integer(estimated));
+            if ip.is_none() && id.is_none() && group.is_none() && !all {
+                // Estimate only; nothing selected to apply it to.
+                return Ok(());
+            }
             let targets = resolve_targets(ip, id, group, all)?;
+            let config = load_config().unwrap_or_default();
+            let mut progress = Progress::new(cli.quiet, Some(targets.len()));
             for ip in targets {
-                let response = set_light(&client, &ip, &update)?;
-                print_lights(&response);
+                let range = kelvin_range_for_ip(&config, &ip);
+                let kelvin = range.clamp_kelvin(estimated);
+                let update = LightUpdate {
+                    temperature: Some(range.kelvin_to_mired(kelvin)),
+                    ..Default::default()
+                };
+                match set_light(&client, backend_for_ip(&config, &ip), &ip, &update) {
+                    Ok(_) => {
+                        let kelvin = locale::current().integer(kelvin);
+                        progress.step(&ip, &format!("set to {kelvin}K"))
+                    }
+                    Err(err) => {
+                        progress.step(&ip, &format!("failed: {err}"));
+                        return Err(err.into());
+                    }
+                }
+            }
+        }
+        Command::Snapshot { action } => match action {
+            SnapshotAction::Save { name } => {
+                let saved = snapshot::save(&client, &name, cli.quiet)?;
+                let lights = locale::current().integer(saved.lights.len() as i64);
+                println!("Saved snapshot '{name}' ({lights} lights)");
+            }
+            SnapshotAction::Restore { name } => {
+                snapshot::restore(&client, &name, cli.quiet)?;
+                println!("Restored snapshot '{}'", name);
+            }
+            SnapshotAction::List => {
+                let snapshots = snapshot::list()?;
+                if snapshots.is_empty() {
+                    println!("No snapshots saved. Use `snapshot save <name>` first.");
+                }
+                let format = locale::current();
+                for (name, saved) in snapshots {
+                    println!(
+                        "snapshot={}, lights={}, taken_unix={}",
+                        name,
+                        format.integer(saved.lights.len() as i64),
+                        saved.taken_unix
+                    );
+                }
+            }
+        },
+        Command::Smoke {
+            mock,
+            latency_ms,
+            jitter_ms,
+            failure_rate,
+        } => {
+            if !mock {
+                return Err("smoke only runs against fake lights; pass --mock".into());
             }
+            let faults = smoke::FaultOptions {
+                latency_ms: &latency_ms,
+                jitter_ms: &jitter_ms,
+                failure_rate: &failure_rate,
+            };
+            smoke::run(&client, &faults)?;
+        }
+        Command::Watch {
+            ip,
+            id,
+            group,
+            all,
+            interval,
+        } => {
+            let all = all || (ip.is_none() && id.is_none() && group.is_none());
+            let targets = resolve_targets(ip, id, group, all)?;
+            watch::run(&client, &targets, Duration::from_secs(interval), cli.json)?;
         }
+        Command::Tui => tui::run(&client)?,
+        Command::Completions { shell } => completions::print_registration(shell)?,
+        Command::Man => completions::print_man()?,
     }
 
     Ok(())
 }
 
-fn discover_lights(client: &Client, timeout: Duration) -> Result<(), Box<dyn Error>> {
-    let daemon = ServiceDaemon::new()?;
-    let receiver = daemon.browse("_elg._tcp.local.")?;
-    let deadline = std::time::Instant::now() + timeout;
-    let mut found_any = false;
-    let mut config = load_config().unwrap_or_default();
+/// Progress lines for long CLI operations. Written to stderr so stdout stays parseable.
+struct Progress {
+    quiet: bool,
+    total: Option<usize>,
+    done: usize,
+}
 
-    while std::time::Instant::now() < deadline {
-        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
-        match receiver.recv_timeout(remaining) {
-            Ok(event) => match event {
-                ServiceEvent::ServiceResolved(info) => {
-                    found_any = true;
-                    let addrs = info
-                        .get_addresses()
-                        .iter()
-                        .map(|addr| addr.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    println!(
-                        "name={}, host={}, port={}, addresses=[{}]",
-                        info.get_fullname(),
-                        info.get_hostname(),
-                        info.get_port(),
-                        addrs
-                    );
-                    upsert_record(client, &mut config, &info);
-                }
-                ServiceEvent::SearchStopped(_) => break,
-                _ => {}
-            },
-            Err(RecvTimeoutError::Timeout) => break,
-            Err(err) => return Err(err.into()),
+impl Progress {
+    fn new(quiet: bool, total: Option<usize>) -> Self {
+        Self {
+            quiet,
+            total,
+            done: 0,
+        }
+    }
+
+    fn note(&self, message: &str) {
+        if !self.quiet {
+            eprintln!("{message}");
+        }
+    }
+
+    fn step(&mut self, target: &str, outcome: &str) {
+        self.done += 1;
+        match self.total {
+            Some(total) => self.note(&format!("[{}/{}] {target}: {outcome}", self.done, total)),
+            None => self.note(&format!("[{}] {target}: {outcome}", self.done)),
+        }
+    }
+}
+
+/// What `discover_lights` looks for.
+struct DiscoverOptions {
+    timeout: Duration,
+    /// Ask each light found over mDNS for its details once browsing is over, all at
+    /// the same time, so slow lights don't cut into the time left for late
+    /// announcements.
+    probe: bool,
+    /// IPv4 range to scan for lights that don't announce themselves.
+    subnet: Option<subnet::Subnet>,
+}
+
+impl DiscoverOptions {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            probe: true,
+            subnet: None,
         }
     }
+}
+
+/// Find lights on the network and persist them. With `json`, the records of the
+/// lights found are printed as one array at the end instead of a line per light.
+fn discover_lights(
+    client: &Client,
+    options: &DiscoverOptions,
+    quiet: bool,
+    json: bool,
+) -> Result<(), KeylightError> {
+    let timeout = options.timeout;
+    let services = load_config().unwrap_or_default().discovery.service_types();
+    let mut found = Vec::new();
+    let mut groups_changed = false;
+    let mut progress = Progress::new(quiet, None);
+    let scanning = options
+        .subnet
+        .map(|subnet| format!(", scanning {subnet}"))
+        .unwrap_or_default();
+    progress.note(&format!(
+        "Browsing for {} services and WiZ/LIFX lights{scanning} ({}s)...",
+        services.join(", "),
+        timeout.as_secs()
+    ));
+    // The UDP backends broadcast and listen on their own sockets alongside mDNS.
+    let udp_scans = [
+        std::thread::spawn(move || wiz::discover(timeout)),
+        std::thread::spawn(move || lifx::discover(timeout)),
+    ];
+    let subnet_scan = options
+        .subnet
+        .map(|subnet| std::thread::spawn(move || subnet::scan(subnet)));
+
+    let resolved = keylight_core::discovery::browse(&services, timeout, |light| {
+        if !json {
+            println!(
+                "name={}, host={}, port={}, addresses=[{}]",
+                light.id,
+                light.hostname,
+                light.port,
+                light.addresses.join(", ")
+            );
+        }
+        progress.step(&light.id, "resolved");
+    })
+    .map_err(|err| KeylightError::Other(format!("mDNS browse failed: {err}")))?;
+    found.extend(resolved.iter().map(|light| light.id.clone()));
 
-    if !found_any {
-        println!("No _elg._tcp.local. services discovered within timeout.");
+    let probes = if options.probe {
+        probe_lights(client, &resolved)
     } else {
-        save_config(&config)?;
+        Vec::new()
+    };
+    let answered: Vec<_> = udp_scans
+        .into_iter()
+        .flat_map(|scan| scan.join().unwrap_or_default())
+        .collect();
+    let scanned = subnet_scan.map(|scan| scan.join().unwrap_or_default());
+
+    // Browsing took a while; load the config only now, under the edit lock, so edits
+    // made meanwhile aren't saved over.
+    let _editing = lock_config_edits();
+    let mut config = load_config().unwrap_or_default();
+    for (index, light) in resolved.iter().enumerate() {
+        groups_changed |= upsert_record(&mut config, light, probes.get(index));
+    }
+    for light in answered {
+        found.push(light.id.clone());
+        if !json {
+            println!(
+                "name={}, backend={}, address={}",
+                light.name,
+                light.backend.name(),
+                light.ip
+            );
+        }
+        progress.step(&light.name, "resolved");
+        upsert_discovered(&mut config, light);
+    }
+    for light in scanned.unwrap_or_default() {
+        // Lights that also announce themselves are stored under their mDNS name.
+        let announced = config.lights.iter().any(|item| {
+            item.backend == Backend::Elgato
+                && item.id != light.id
+                && item.addresses.contains(&light.ip)
+        });
+        if announced {
+            continue;
+        }
+        found.push(light.id.clone());
+        if !json {
+            println!("name={}, backend=elgato, address={}", light.name, light.ip);
+        }
+        progress.step(&light.name, "resolved");
+        upsert_discovered(&mut config, light);
     }
 
-    daemon.stop_browse("_elg._tcp.local.")?;
+    status::discovered();
+    if !found.is_empty() {
+        save_state(&config)?;
+    }
+    if groups_changed {
+        save_config(&config)?;
+    }
+    if json {
+        let records: Vec<&LightRecord> = config
+            .lights
+            .iter()
+            .filter(|light| found.iter().any(|id| id == light.device_id()))
+            .collect();
+        print_json(&records)?;
+    } else if found.is_empty() {
+        println!("No lights discovered within timeout.");
+    }
     Ok(())
 }
 
-fn run_api_server(client: &Client, port: u16) -> Result<(), Box<dyn Error>> {
-    let server = Server::http(("127.0.0.1", port)).map_err(|err| -> Box<dyn Error> {
-        format!("Failed to bind 127.0.0.1:{port} (is the port already in use?): {err}").into()
+fn run_api_server(
+    client: &Client,
+    listen: SocketAddr,
+    restore_on_start: bool,
+) -> Result<(), Box<dyn Error>> {
+    let server = Server::http(listen).map_err(|err| -> Box<dyn Error> {
+        format!("Failed to bind {listen} (is the port already in use?): {err}").into()
     })?;
-    println!("keylightd API listening on http://127.0.0.1:{port}");
+    println!("keylightd API listening on http://{listen}");
+    status::started();
+    if !listen.ip().is_loopback() {
+        advertise::start(listen.ip(), listen.port());
+    }
+
+    // Requests queue on the bound socket meanwhile, so clients see a slow first
+    // answer rather than a refused connection.
+    let serve = load_config().map(|config| config.serve).unwrap_or_default();
+    if serve.discover_on_start {
+        let timeout = Duration::from_secs(serve.discovery_timeout_secs.max(1));
+        if serve.wait_for_discovery {
+            log_line!(
+                "discovering lights for {}s before serving",
+                timeout.as_secs()
+            );
+            if let Err(err) = discover_lights(client, &DiscoverOptions::new(timeout), true, false) {
+                log_line!("startup discovery: {err}");
+            }
+        } else {
+            let client = client.clone();
+            std::thread::spawn(move || {
+                let options = DiscoverOptions::new(timeout);
+                if let Err(err) = discover_lights(&client, &options, true, false) {
+                    log_line!("startup discovery: {err}");
+                }
+            });
+        }
+    }
+
+    schedule::spawn_runner(client.clone());
+    obs::spawn(client.clone());
+    conferencing::spawn(client.clone());
+    auto_brightness::spawn(client.clone());
+    discovery::spawn(client.clone());
+    events::enable();
+    last_state::spawn();
+    timers::spawn(client.clone());
+    profiles::spawn(client.clone());
+    health::spawn(client.clone());
+    if restore_on_start {
+        match last_state::restore(client) {
+            Ok(report) => log_line!(
+                "restored {} light(s), skipped {}",
+                report.restored.len(),
+                report.skipped.len()
+            ),
+            Err(err) => log_line!("restoring last state: {err}"),
+        }
+    }
+
+    serve_requests(&server, client);
+    Ok(())
+}
 
+/// Answer API requests until the server is dropped. The loop only rate-limits;
+/// each request is then answered by one of `API_WORKERS` threads, so a slow light
+/// or a discovery doesn't hold up every other client.
+fn serve_requests(server: &Server, client: &Client) {
     let mut rate_limiter = RateLimiter::new();
+    let (jobs, pending) = flume::unbounded::<tiny_http::Request>();
+    for _ in 0..API_WORKERS {
+        let pending = pending.clone();
+        let client = client.clone();
+        std::thread::spawn(move || {
+            for request in pending.iter() {
+                answer_request(&client, request);
+            }
+        });
+    }
 
-    for mut request in server.incoming_requests() {
+    for request in server.incoming_requests() {
         let method = request.method().clone();
-        let url = request.url().to_string();
-        let (path, _query) = url.split_once('?').unwrap_or((url.as_str(), ""));
-
+        let path = request.url().split('?').next().unwrap_or_default();
         if !rate_limiter.allow(&method, path) {
             request
                 .respond(json_client_error(
@@ -391,56 +1428,384 @@ fn run_api_server(client: &Client, port: u16) -> Result<(), Box<dyn Error>> {
                 .ok();
             continue;
         }
+        if jobs.send(request).is_err() {
+            break;
+        }
+    }
+}
 
-        let body = match read_body_limited(&mut request) {
-            Ok(body) => body,
-            Err(BodyReadError::TooLarge) => {
-                request
-                    .respond(json_client_error(
-                        StatusCode(413),
-                        "Request body too large.",
-                    ))
-                    .ok();
-                continue;
+fn answer_request(client: &Client, request: tiny_http::Request) {
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+    let format = match ResponseFormat::for_request(&method, path, query, &request) {
+        Ok(format) => format,
+        Err(err) => {
+            ResponseFormat::default().install();
+            request
+                .respond(json_client_error(StatusCode(400), &err))
+                .ok();
+            return;
+        }
+    };
+    format.clone().install();
+    match device::Overrides::parse(query) {
+        Ok(overrides) => overrides.install(),
+        Err(err) => {
+            request
+                .respond(json_client_error(StatusCode(400), &err))
+                .ok();
+            return;
+        }
+    }
+
+    if method == Method::Options {
+        let is_preflight = request
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("Access-Control-Request-Method"));
+        request
+            .respond(cors::preflight(&format.access, is_preflight))
+            .ok();
+        return;
+    }
+    if !format.access.may_change() && !matches!(method, Method::Get | Method::Head) {
+        request
+            .respond(json_client_error(StatusCode(403), cors::REFUSED))
+            .ok();
+        return;
+    }
+
+    let Some((request, body)) = read_body_within(request, BODY_READ_TIMEOUT, &BODY_READERS)
+    else {
+        log_line!("{method} {path}: request body not received in time");
+        return;
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(BodyReadError::TooLarge) => {
+            request
+                .respond(json_client_error(
+                    StatusCode(413),
+                    "Request body too large.",
+                ))
+                .ok();
+            return;
+        }
+        Err(BodyReadError::Busy) => {
+            request
+                .respond(json_client_error(
+                    StatusCode(503),
+                    "Too many request bodies still arriving; try again shortly.",
+                ))
+                .ok();
+            return;
+        }
+        Err(BodyReadError::InvalidUtf8) => {
+            request
+                .respond(json_client_error(
+                    StatusCode(400),
+                    "Request body must be valid UTF-8.",
+                ))
+                .ok();
+            return;
+        }
+        Err(BodyReadError::Io(err)) => {
+            request
+                .respond(json_server_error(
+                    StatusCode(500),
+                    "reading request body",
+                    err,
+                ))
+                .ok();
+            return;
+        }
+    };
+
+    // Long polls wait on their own thread so they don't hold up a worker.
+    if method == Method::Get && path == "/v1/events" {
+        match events::parse_query(query) {
+            Ok((since, wait)) => {
+                wait_on_thread(request, format, move || {
+                    match events::wait_since(since, wait) {
+                        Ok(batch) => json_response(StatusCode(200), &batch),
+                        Err(events::Expired) => json_client_error(
+                            StatusCode(410),
+                            "Revision expired; fetch /v1/events/snapshot and resume from its revision",
+                        ),
+                    }
+                });
             }
-            Err(BodyReadError::InvalidUtf8) => {
+            Err(err) => {
                 request
-                    .respond(json_client_error(
-                        StatusCode(400),
-                        "Request body must be valid UTF-8.",
-                    ))
+                    .respond(json_client_error(StatusCode(400), &err))
                     .ok();
-                continue;
             }
-            Err(BodyReadError::Io(err)) => {
+        }
+        return;
+    }
+    if method == Method::Get && path == "/v1/notices" {
+        match notices::parse_query(query) {
+            Ok((since, wait)) => {
+                wait_on_thread(request, format, move || {
+                    json_response(StatusCode(200), &notices::wait_since(since, wait))
+                });
+            }
+            Err(err) => {
                 request
-                    .respond(json_server_error(
-                        StatusCode(500),
-                        "reading request body",
-                        err,
-                    ))
+                    .respond(json_client_error(StatusCode(400), &err))
                     .ok();
-                continue;
             }
-        };
+        }
+        return;
+    }
+
+    // Summaries query the light, and may long-poll, so they get a thread too.
+    if method == Method::Get {
+        if let ["v1", "lights", id, "summary"] = Segments::parse(path).as_strs().as_slice() {
+            let id = id.to_string();
+            let if_none_match = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("If-None-Match"))
+                .map(|header| header.value.as_str().to_string());
+            match summary::parse_wait(query) {
+                Ok(wait) => {
+                    let client = client.clone();
+                    wait_on_thread(request, format, move || {
+                        let etag = if_none_match.as_deref();
+                        let result = match (etag, wait) {
+                            (Some(etag), Some(wait)) => {
+                                summary::wait_for_change(&client, &id, etag, wait)
+                            }
+                            _ => summary::current(&client, &id).map(|summary| {
+                                Some(summary).filter(|s| etag != Some(s.etag().as_str()))
+                            }),
+                        };
+                        summary_response(result, etag)
+                    });
+                }
+                Err(err) => {
+                    request
+                        .respond(json_client_error(StatusCode(400), &err))
+                        .ok();
+                }
+            }
+            return;
+        }
+    }
 
-        let response = handle_api_request(client, &method, path, &body);
-        request.respond(response).ok();
+    if method == Method::Post && path == "/v1/shutdown" {
+        let from_browser = request
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv("Origin"));
+        if !shutdown_allowed(request.remote_addr(), from_browser) {
+            request
+                .respond(json_client_error(
+                    StatusCode(403),
+                    "Shutdown is only accepted from local clients.",
+                ))
+                .ok();
+            return;
+        }
+        request
+            .respond(json_response(
+                StatusCode(200),
+                &serde_json::json!({"status": "shutting down"}),
+            ))
+            .ok();
+        shut_down();
     }
 
-    Ok(())
+    let response = {
+        let _editing = edits_config(&method, path).then(lock_config_edits);
+        handle_api_request(client, &method, path, query, &body)
+    };
+    request.respond(response).ok();
+}
+
+/// Held by everything that loads, changes and saves the config or light records:
+/// requests, discovery, the scheduler and config migrations. Otherwise one could save
+/// over a change another made after it loaded.
+static CONFIG_EDITS: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    /// Whether this thread holds `CONFIG_EDITS`, so an edit that loads the config
+    /// (and may migrate it) doesn't wait on itself.
+    static EDITING_CONFIG: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Guard from `lock_config_edits`; only the outermost one on a thread holds the lock.
+struct ConfigEdit(Option<MutexGuard<'static, ()>>);
+
+impl Drop for ConfigEdit {
+    fn drop(&mut self) {
+        if self.0.is_some() {
+            EDITING_CONFIG.with(|editing| editing.set(false));
+        }
+    }
+}
+
+/// Holds off other config edits until the guard drops. Taking it again on the same
+/// thread is a no-op.
+fn lock_config_edits() -> ConfigEdit {
+    if EDITING_CONFIG.with(Cell::get) {
+        return ConfigEdit(None);
+    }
+    let guard = CONFIG_EDITS.lock().unwrap_or_else(PoisonError::into_inner);
+    EDITING_CONFIG.with(|editing| editing.set(true));
+    ConfigEdit(Some(guard))
+}
+
+/// Whether a request may change the config or light records. Reads and plain light,
+/// group, room and all-lights updates only talk to lights, so they don't wait.
+fn edits_config(method: &Method, path: &str) -> bool {
+    match (method, Segments::parse(path).as_strs().as_slice()) {
+        (Method::Get | Method::Head | Method::Options, _) => false,
+        (Method::Put, ["v1", "all"]) => false,
+        (Method::Put, ["v1", "lights" | "groups" | "rooms", name]) => name.is_empty(),
+        (Method::Post, ["v1", "lights", _, "toggle"]) => false,
+        _ => true,
+    }
+}
+
+/// Shutdown is for the tray and scripts on this machine, even when the server listens
+/// on the network; requests with an `Origin` come from a browser page, which
+/// shouldn't be able to stop the daemon.
+fn shutdown_allowed(remote: Option<&SocketAddr>, from_browser: bool) -> bool {
+    remote.is_some_and(|addr| addr.ip().is_loopback()) && !from_browser
+}
+
+/// Exit once no config or state file is being written.
+fn shut_down() -> ! {
+    log_line!("shutting down");
+    advertise::withdraw();
+    let _writes = FILE_WRITES.write().unwrap_or_else(PoisonError::into_inner);
+    std::process::exit(0)
 }
 
 #[derive(Debug)]
 enum BodyReadError {
     TooLarge,
     InvalidUtf8,
+    /// Every body reader is taken.
+    Busy,
     Io(std::io::Error),
 }
 
+/// A cap on threads that wait on clients, for work that can't be handed to the
+/// workers without holding one up.
+struct ThreadSlots {
+    busy: AtomicUsize,
+    max: usize,
+}
+
+/// Threads answering long polls.
+static LONG_POLLS: ThreadSlots = ThreadSlots::new(MAX_LONG_POLLS);
+
+/// Threads reading request bodies. One whose client stalls stays blocked until the
+/// client sends or hangs up: tiny_http keeps the socket to itself, so there's no read
+/// timeout to set on it (one on the listener would be inherited, but would also end
+/// tiny_http's accept loop the first time `accept` timed out). Capping the count
+/// bounds what stalled clients can hold instead.
+static BODY_READERS: ThreadSlots = ThreadSlots::new(MAX_BODY_READERS);
+
+impl ThreadSlots {
+    const fn new(max: usize) -> Self {
+        Self {
+            busy: AtomicUsize::new(0),
+            max,
+        }
+    }
+
+    /// A slot, held until the thread given it ends; `None` when all are taken.
+    fn claim(&'static self) -> Option<ThreadSlot> {
+        self.busy
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |busy| {
+                (busy < self.max).then_some(busy + 1)
+            })
+            .ok()
+            .map(|_| ThreadSlot(self))
+    }
+}
+
+struct ThreadSlot(&'static ThreadSlots);
+
+impl Drop for ThreadSlot {
+    fn drop(&mut self) {
+        self.0.busy.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Answer `request` with what `answer` returns, on a thread of its own so the wait
+/// doesn't hold up a worker; 503 when `MAX_LONG_POLLS` are already waiting.
+fn wait_on_thread(
+    request: tiny_http::Request,
+    format: ResponseFormat,
+    answer: impl FnOnce() -> Response<std::io::Cursor<Vec<u8>>> + Send + 'static,
+) {
+    let Some(slot) = LONG_POLLS.claim() else {
+        request
+            .respond(json_client_error(
+                StatusCode(503),
+                "Too many requests waiting for changes; try again shortly.",
+            ))
+            .ok();
+        return;
+    };
+    std::thread::spawn(move || {
+        let _slot = slot;
+        format.install();
+        request.respond(answer()).ok();
+    });
+}
+
+/// The request back with its body, or `None` when the client didn't send the body
+/// within `timeout`. Bodies are read on their own thread so a stalled client can't
+/// hold up the request loop; once it gives up, that thread answers 408 if the body
+/// still arrives, or ends with the connection. Only bodies over 1 KiB can stall
+/// here: tiny_http reads smaller ones on the connection's own thread before the
+/// request reaches the loop at all, so those are already in memory.
+fn read_body_within(
+    mut request: tiny_http::Request,
+    timeout: Duration,
+    readers: &'static ThreadSlots,
+) -> Option<(tiny_http::Request, Result<String, BodyReadError>)> {
+    if request.body_length() == Some(0) {
+        return Some((request, Ok(String::new())));
+    }
+    let Some(slot) = readers.claim() else {
+        return Some((request, Err(BodyReadError::Busy)));
+    };
+    let (done, received) = std::sync::mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let _slot = slot;
+        let body = read_body_limited(&mut request);
+        if let Err(std::sync::mpsc::TrySendError::Disconnected((request, _))) =
+            done.try_send((request, body))
+        {
+            request
+                .respond(json_client_error(
+                    StatusCode(408),
+                    "Request body took too long to arrive.",
+                ))
+                .ok();
+        }
+    });
+    received.recv_timeout(timeout).ok()
+}
+
 fn read_body_limited(request: &mut tiny_http::Request) -> Result<String, BodyReadError> {
     use std::io::Read as _;
 
+    // A declared length over the cap is refused before reading anything.
+    if request
+        .body_length()
+        .is_some_and(|length| length > MAX_API_BODY_BYTES)
+    {
+        return Err(BodyReadError::TooLarge);
+    }
     // Always cap reads to prevent a local DoS.
     let reader = request.as_reader();
     let mut limited = reader.take((MAX_API_BODY_BYTES + 1) as u64);
@@ -525,17 +1890,105 @@ fn handle_api_request(
     client: &Client,
     method: &Method,
     path: &str,
+    query: &str,
     body: &str,
 ) -> Response<std::io::Cursor<Vec<u8>>> {
-    match (method, path) {
-        (Method::Get, "/v1/health") => {
+    let segments = Segments::parse(path);
+    match (method, segments.as_strs().as_slice()) {
+        (Method::Get, [""] | ["index.html"]) => web::index(),
+        (Method::Get, ["v1", "health"]) => {
             json_response(StatusCode(200), &serde_json::json!({"status": "ok"}))
         }
-        (Method::Get, "/v1/lights") => match load_config() {
+        (Method::Get, ["v1", "version"]) => json_response(
+            StatusCode(200),
+            &serde_json::json!({"version": env!("CARGO_PKG_VERSION")}),
+        ),
+        (Method::Get, ["v1", "status"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &status::report(&config)),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Post, ["v1", "dnd"]) => {
+            let request: DndRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => return json_client_error(StatusCode(400), "Invalid JSON body for dnd"),
+            };
+            let status = dnd::start(Duration::from_secs(request.duration_secs));
+            json_response(StatusCode(200), &status)
+        }
+        (Method::Delete, ["v1", "dnd"]) => {
+            json_response(StatusCode(200), &dnd::start(Duration::ZERO))
+        }
+        (Method::Get, ["v1", "timers"]) => json_response(StatusCode(200), &timers::list()),
+        (Method::Post, ["v1", "timers"]) => {
+            let request: TimerRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => return json_client_error(StatusCode(400), "Invalid JSON body for timer"),
+            };
+            if let Err(err) = request.update.check() {
+                return json_client_error(StatusCode(400), &err);
+            }
+            // Refuse unknown lights and groups now rather than when the timer runs.
+            let (id, group, all) = request.target.clone().selection();
+            if let Err(err) = resolve_targets(None, id, group, all) {
+                return error_response(err);
+            }
+            let delay = Duration::from_secs(request.in_secs);
+            match timers::start(request.target, request.update, delay) {
+                Ok(timer) => json_response(StatusCode(200), &timer),
+                Err(err) => json_client_error(StatusCode(400), err),
+            }
+        }
+        (Method::Delete, ["v1", "timers", id]) => match id.parse() {
+            Ok(id) if timers::cancel(id) => {
+                json_response(StatusCode(200), &serde_json::json!({"cancelled": true}))
+            }
+            _ => json_client_error(StatusCode(404), "No pending timer with this id."),
+        },
+        (Method::Get, ["v1", "ramps"]) => json_response(StatusCode(200), &ramps::list()),
+        (Method::Post, ["v1", "ramps"]) => {
+            let request: RampRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => return json_client_error(StatusCode(400), "Invalid JSON body for ramp"),
+            };
+            let (id, group, all) = request.target.clone().selection();
+            if let Err(err) = resolve_targets(None, id, group, all) {
+                return error_response(err);
+            }
+            let config = match load_config() {
+                Ok(config) => config,
+                Err(err) => return json_server_error(StatusCode(500), "loading config", err),
+            };
+            let lights = schedule::target_records(&config, &request.target)
+                .into_iter()
+                .cloned()
+                .collect();
+            match ramps::start(
+                client,
+                request.target,
+                lights,
+                request.brightness,
+                request.kelvin,
+                request.minutes,
+            ) {
+                Ok(ramp) => json_response(StatusCode(200), &ramp),
+                Err(err) => json_client_error(StatusCode(400), err),
+            }
+        }
+        (Method::Delete, ["v1", "ramps", id]) => match id.parse() {
+            Ok(id) if ramps::cancel(id) => {
+                json_response(StatusCode(200), &serde_json::json!({"cancelled": true}))
+            }
+            _ => json_client_error(StatusCode(404), "No running ramp with this id."),
+        },
+        (Method::Get, ["v1", "logs"]) => json_response(
+            StatusCode(200),
+            &serde_json::json!({"lines": logs::recent()}),
+        ),
+        (Method::Get, ["v1", "lights"]) => match load_config() {
             Ok(config) => json_response(StatusCode(200), &config.lights),
             Err(err) => json_server_error(StatusCode(500), "loading config", err),
         },
-        (Method::Post, "/v1/lights") => {
+        (Method::Post, ["v1", "lights"]) => {
             let request: AddLightRequest = match serde_json::from_str(body) {
                 Ok(value) => value,
                 Err(_) => {
@@ -554,7 +2007,7 @@ fn handle_api_request(
                 }
             }
         }
-        (Method::Post, "/v1/lights/refresh") => {
+        (Method::Post, ["v1", "lights", "refresh"]) => {
             let timeout = if body.trim().is_empty() {
                 3u64
             } else {
@@ -562,154 +2015,564 @@ fn handle_api_request(
                     .map(|req| req.timeout)
                     .unwrap_or(3)
             };
-            match discover_lights(client, Duration::from_secs(timeout)) {
+            let options = DiscoverOptions::new(Duration::from_secs(timeout));
+            match discover_lights(client, &options, true, false) {
                 Ok(_) => json_response(StatusCode(200), &serde_json::json!({"refreshed": true})),
                 Err(err) => json_server_error(StatusCode(500), "refresh discovery", err),
             }
         }
-        (Method::Get, "/v1/lights/states") => match get_all_light_states(client) {
+        (Method::Get, ["v1", "events", "snapshot"]) => {
+            json_response(StatusCode(200), &events::snapshot())
+        }
+        (Method::Get, ["v1", "lights", "states"]) => match get_all_light_states(client) {
             Ok(states) => json_response(StatusCode(200), &states),
             Err(err) => json_server_error(StatusCode(500), "getting light states", err),
         },
-        (Method::Get, "/v1/groups") => match load_config() {
-            Ok(config) => json_response(StatusCode(200), &config.groups),
-            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        (Method::Post, ["v1", "lights", "restore"]) => match last_state::restore(client) {
+            Ok(report) => json_response(StatusCode(200), &report),
+            Err(err) => json_server_error(StatusCode(500), "restoring last state", err),
         },
-        (Method::Post, "/v1/groups") => {
-            let request: GroupRequest = match serde_json::from_str(body) {
+        (Method::Get, ["v1", "history"]) => json_response(StatusCode(200), &history::list()),
+        (Method::Post, ["v1", "undo"]) => match history::undo(client) {
+            Ok(Some(report)) => json_response(StatusCode(200), &report),
+            Ok(None) => json_client_error(StatusCode(404), "Nothing to undo."),
+            Err(err) => error_response(err),
+        },
+        (Method::Get, ["v1", "snapshots"]) => match snapshot::list() {
+            Ok(snapshots) => json_response(StatusCode(200), &snapshots),
+            Err(err) => json_server_error(StatusCode(500), "loading snapshots", err),
+        },
+        (Method::Post, ["v1", "snapshots", name, "restore"]) => {
+            restore_snapshot(client, name.to_string(), "restored")
+        }
+        (Method::Get, ["v1", "scenes"]) => match snapshot::scenes() {
+            Ok(scenes) => json_response(StatusCode(200), &scenes),
+            Err(err) => json_server_error(StatusCode(500), "loading snapshots", err),
+        },
+        (Method::Post, ["v1", "scenes"]) => {
+            let request: SceneRequest = match serde_json::from_str(body) {
                 Ok(value) => value,
-                Err(_) => return json_client_error(StatusCode(400), "Invalid JSON body for group"),
+                Err(_) => return json_client_error(StatusCode(400), "Invalid JSON body for scene"),
             };
-            match save_group(request.name, request.members) {
-                Ok(group) => json_response(StatusCode(200), &group),
-                Err(err) => json_client_error(StatusCode(400), &err.to_string()),
-            }
-        }
-        (Method::Delete, path) if path.starts_with("/v1/lights/") => {
-            let raw_id = &path["/v1/lights/".len()..];
-            let id = urlencoding::decode(raw_id)
-                .map(|value| value.into_owned())
-                .unwrap_or_else(|_| raw_id.to_string());
-            match delete_light(id) {
-                Ok(_) => json_response(StatusCode(200), &serde_json::json!({"deleted": true})),
-                Err(err) => json_client_error(StatusCode(404), &err.to_string()),
+            let name = request.name.trim();
+            match snapshot::save(client, name, true) {
+                Ok(saved) => json_response(StatusCode(200), &snapshot::Scene::new(name, &saved)),
+                Err(err) => error_response(err),
             }
         }
-        (Method::Delete, path) if path.starts_with("/v1/groups/") => {
-            let raw_name = &path["/v1/groups/".len()..];
-            let group_name = urlencoding::decode(raw_name)
-                .map(|value| value.into_owned())
-                .unwrap_or_else(|_| raw_name.to_string());
-            match delete_group(group_name) {
-                Ok(_) => json_response(StatusCode(200), &serde_json::json!({"deleted": true})),
-                Err(err) => json_client_error(StatusCode(404), &err.to_string()),
-            }
-        }
-        (Method::Put, path) if path.starts_with("/v1/lights/") => {
-            let raw_id = &path["/v1/lights/".len()..];
-            if let Some(raw_id) = raw_id.strip_suffix("/enabled") {
-                let id = urlencoding::decode(raw_id)
-                    .map(|value| value.into_owned())
-                    .unwrap_or_else(|_| raw_id.to_string());
-                let request: EnabledRequest = match serde_json::from_str(body) {
-                    Ok(value) => value,
-                    Err(_) => {
-                        return json_client_error(
-                            StatusCode(400),
-                            "Invalid JSON body for enabled request",
-                        )
-                    }
-                };
-                match set_light_enabled(id, request.enabled) {
-                    Ok(record) => return json_response(StatusCode(200), &record),
-                    Err(err) => return json_client_error(StatusCode(400), &err.to_string()),
-                }
-            }
-            if let Some(raw_id) = raw_id.strip_suffix("/alias") {
-                let id = urlencoding::decode(raw_id)
-                    .map(|value| value.into_owned())
-                    .unwrap_or_else(|_| raw_id.to_string());
-                let request: AliasRequest = match serde_json::from_str(body) {
-                    Ok(value) => value,
-                    Err(_) => {
-                        return json_client_error(
-                            StatusCode(400),
-                            "Invalid JSON body for alias request",
-                        )
-                    }
-                };
-                match set_light_alias(id, request.alias) {
-                    Ok(record) => return json_response(StatusCode(200), &record),
-                    Err(err) => return json_client_error(StatusCode(400), &err.to_string()),
+        (Method::Put, ["v1", "scenes", "order"]) => {
+            let request: SceneOrderRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(StatusCode(400), "Invalid JSON body for scene order")
                 }
+            };
+            match snapshot::reorder(&request.names).and_then(|()| snapshot::scenes()) {
+                Ok(scenes) => json_response(StatusCode(200), &scenes),
+                Err(err) => error_response(err),
             }
-            let id = urlencoding::decode(raw_id)
-                .map(|value| value.into_owned())
-                .unwrap_or_else(|_| raw_id.to_string());
-            let update: UpdateRequest = match serde_json::from_str(body) {
+        }
+        (Method::Post, ["v1", "scenes", name, "apply"]) => {
+            restore_snapshot(client, name.to_string(), "applied")
+        }
+        (Method::Put, ["v1", "scenes", name, "favorite"]) => {
+            let request: FavoriteRequest = match serde_json::from_str(body) {
                 Ok(value) => value,
                 Err(_) => {
                     return json_client_error(
                         StatusCode(400),
-                        "Invalid JSON body for update request",
-                    );
+                        "Invalid JSON body for favorite request",
+                    )
                 }
             };
-            match apply_update_to_targets(client, Some(id), None, false, update) {
-                Ok(results) => json_response(StatusCode(200), &results),
-                Err(err) => json_client_error(StatusCode(400), &err.to_string()),
+            match snapshot::set_favorite(name, request.favorite) {
+                Ok(scene) => json_response(StatusCode(200), &scene),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Get, ["v1", "favorites"]) => match favorites() {
+            Ok(favorites) => json_response(StatusCode(200), &favorites),
+            Err(err) => json_server_error(StatusCode(500), "loading favorites", err),
+        },
+        (Method::Put, ["v1", "scenes", name]) => {
+            let request: SceneRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => return json_client_error(StatusCode(400), "Invalid JSON body for scene"),
+            };
+            match snapshot::rename(name, &request.name) {
+                Ok(()) => json_response(
+                    StatusCode(200),
+                    &serde_json::json!({"name": request.name.trim()}),
+                ),
+                Err(err) => error_response(err),
             }
         }
-        (Method::Put, path) if path.starts_with("/v1/groups/") => {
-            let raw_name = &path["/v1/groups/".len()..];
-            let group_name = urlencoding::decode(raw_name)
-                .map(|value| value.into_owned())
-                .unwrap_or_else(|_| raw_name.to_string());
-            let update: UpdateRequest = match serde_json::from_str(body) {
+        (Method::Delete, ["v1", "scenes", name]) => match snapshot::delete(name) {
+            Ok(()) => json_response(StatusCode(200), &serde_json::json!({"deleted": true})),
+            Err(err) => error_response(err),
+        },
+        (Method::Get, ["v1", "profiles"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &profiles::status(&config)),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Post, ["v1", "profiles", "active"]) => {
+            let request: ProfileRequest = match serde_json::from_str(body) {
                 Ok(value) => value,
                 Err(_) => {
-                    return json_client_error(
-                        StatusCode(400),
-                        "Invalid JSON body for update request",
-                    )
+                    return json_client_error(StatusCode(400), "Invalid JSON body for profile")
                 }
             };
-            match apply_update_to_targets(client, None, Some(group_name), false, update) {
-                Ok(results) => json_response(StatusCode(200), &results),
-                Err(err) => json_client_error(StatusCode(400), &err.to_string()),
+            let config = match load_config() {
+                Ok(config) => config,
+                Err(err) => return json_server_error(StatusCode(500), "loading config", err),
+            };
+            match profiles::choose(client, &config, request.name.as_deref()) {
+                Ok(()) => json_response(StatusCode(200), &profiles::status(&config)),
+                Err(err) => error_response(err),
             }
         }
-        (Method::Put, "/v1/all") => {
-            let update: UpdateRequest = match serde_json::from_str(body) {
+        (Method::Get, ["v1", "presets"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &config.presets),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Post, ["v1", "presets", name, "apply"]) => {
+            let request: PresetApplyRequest = match serde_json::from_str(body) {
                 Ok(value) => value,
                 Err(_) => {
                     return json_client_error(
                         StatusCode(400),
-                        "Invalid JSON body for update request",
+                        "Invalid JSON body for preset request",
                     )
                 }
             };
-            match apply_update_to_targets(client, None, None, true, update) {
+            let config = match load_config() {
+                Ok(config) => config,
+                Err(err) => return json_server_error(StatusCode(500), "loading config", err),
+            };
+            let update = match presets::find(&config.presets, name) {
+                Ok(preset) => preset.update(),
+                Err(err) => return error_response(err),
+            };
+            let (id, group, all) = request.target.selection();
+            match apply_update_to_targets(client, id, group, all, update, false) {
                 Ok(results) => json_response(StatusCode(200), &results),
-                Err(err) => json_client_error(StatusCode(400), &err.to_string()),
+                Err(err) => error_response(err),
             }
         }
-        _ => json_client_error(StatusCode(404), "Not found"),
-    }
-}
-
-#[derive(Deserialize)]
-struct UpdateRequest {
-    on: Option<u8>,
-    brightness: Option<u8>,
-    kelvin: Option<u16>,
-    mired: Option<u16>,
-}
-
-#[derive(Deserialize)]
-struct RefreshRequest {
-    timeout: u64,
-}
+        (Method::Get, ["v1", "lights", "health"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &lights_health(&config)),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Get, ["v1", "rooms"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &rooms::list(&config)),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Get, ["v1", "groups"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &config.groups),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Get, ["v1", "groups", "suggestions"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &suggestions::suggest(&config)),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Get, ["v1", "groups", group_name, "resolved"]) => {
+            let resolved = load_config().and_then(|config| resolve_group(&config, group_name));
+            match resolved {
+                Ok(resolved) => json_response(StatusCode(200), &resolved),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Get, ["v1", "resolve", ident]) => match load_config() {
+            Ok(config) => {
+                let resolution = resolve_ident(&config, ident);
+                let status = if resolution.matches.is_empty() {
+                    404
+                } else {
+                    200
+                };
+                json_response(StatusCode(status), &resolution)
+            }
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Post, ["v1", "lights", id, "toggle"]) => {
+            summary_response(summary::toggle(client, id).map(Some), None)
+        }
+        (Method::Post, ["v1", "lights", id, "reset"]) => reset_light(client, id),
+        (Method::Post, ["v1", "groups"]) => {
+            let request: GroupRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => return json_client_error(StatusCode(400), "Invalid JSON body for group"),
+            };
+            match save_group(request.name, request.members, request.stagger_ms) {
+                Ok(group) => json_response(StatusCode(200), &group),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Get, ["v1", "schedules"]) => match load_config() {
+            Ok(config) => json_response(StatusCode(200), &config.schedules),
+            Err(err) => json_server_error(StatusCode(500), "loading config", err),
+        },
+        (Method::Post, ["v1", "schedules"]) => {
+            let request: Schedule = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(StatusCode(400), "Invalid JSON body for schedule")
+                }
+            };
+            match save_schedule(request) {
+                Ok(schedule) => json_response(StatusCode(200), &schedule),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Delete, ["v1", "schedules", name]) => match delete_schedule(name.to_string()) {
+            Ok(_) => json_response(StatusCode(200), &serde_json::json!({"deleted": true})),
+            Err(err) => error_response(err),
+        },
+        (Method::Delete, ["v1", "lights", id]) => match delete_light(id.to_string()) {
+            Ok(_) => json_response(StatusCode(200), &serde_json::json!({"deleted": true})),
+            Err(err) => error_response(err),
+        },
+        (Method::Delete, ["v1", "groups", group_name]) => {
+            match delete_group(group_name.to_string()) {
+                Ok(_) => json_response(StatusCode(200), &serde_json::json!({"deleted": true})),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "enabled"]) => {
+            let request: EnabledRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for enabled request",
+                    )
+                }
+            };
+            match set_light_enabled(id.to_string(), request.enabled) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "soft_start"]) => {
+            let request: EnabledRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for soft start request",
+                    )
+                }
+            };
+            match set_light_soft_start(id.to_string(), request.enabled) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "alias"]) => {
+            let request: AliasRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for alias request",
+                    )
+                }
+            };
+            match set_light_alias(id.to_string(), request.alias) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "position"]) => {
+            let request: PositionRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for position request",
+                    )
+                }
+            };
+            match set_light_position(id.to_string(), request.position) {
+                Ok(lights) => json_response(StatusCode(200), &lights),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "icon"]) => {
+            let request: IconRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(StatusCode(400), "Invalid JSON body for icon request")
+                }
+            };
+            let icon = match normalize_icon(request.icon) {
+                Ok(icon) => icon,
+                Err(msg) => return json_client_error(StatusCode(400), msg),
+            };
+            match set_light_icon(id.to_string(), icon) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "favorite"]) => {
+            let request: FavoriteRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for favorite request",
+                    )
+                }
+            };
+            match set_light_favorite(id.to_string(), request.favorite) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "meta"]) => {
+            let request: MetaRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(StatusCode(400), "Invalid JSON body for meta request")
+                }
+            };
+            match set_light_meta(id.to_string(), request) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "brightness_limits"]) => {
+            let request: BrightnessLimitsRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for brightness limits request",
+                    )
+                }
+            };
+            let limits = match BrightnessLimits::new(request.min, request.max) {
+                Ok(limits) => limits,
+                Err(msg) => return json_client_error(StatusCode(400), msg),
+            };
+            match set_light_brightness_limits(id.to_string(), limits) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id, "schedule"]) => {
+            let request: ScheduleOverrideRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for schedule override request",
+                    )
+                }
+            };
+            match set_light_schedule_override(id.to_string(), request) {
+                Ok(record) => json_response(StatusCode(200), &record),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "lights", id]) => {
+            let update = match UpdateRequest::parse(body) {
+                Ok(update) => update,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            let verify = match parse_verify(query) {
+                Ok(verify) => verify,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            match apply_update_to_targets(client, Some(id.to_string()), None, false, update, verify)
+            {
+                Ok(results) => json_response(StatusCode(200), &results),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "groups", group_name, "position"]) => {
+            let request: PositionRequest = match serde_json::from_str(body) {
+                Ok(value) => value,
+                Err(_) => {
+                    return json_client_error(
+                        StatusCode(400),
+                        "Invalid JSON body for position request",
+                    )
+                }
+            };
+            match set_group_position(group_name.to_string(), request.position) {
+                Ok(groups) => json_response(StatusCode(200), &groups),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "groups", group_name]) => {
+            let update = match UpdateRequest::parse(body) {
+                Ok(update) => update,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            let verify = match parse_verify(query) {
+                Ok(verify) => verify,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            match apply_update_to_targets(
+                client,
+                None,
+                Some(group_name.to_string()),
+                false,
+                update,
+                verify,
+            ) {
+                Ok(results) => json_response(StatusCode(200), &results),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "rooms", room]) => {
+            let update = match UpdateRequest::parse(body) {
+                Ok(update) => update,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            let verify = match parse_verify(query) {
+                Ok(verify) => verify,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            match apply_update_to_room(client, room, update, verify) {
+                Ok(results) => json_response(StatusCode(200), &results),
+                Err(err) => error_response(err),
+            }
+        }
+        (Method::Put, ["v1", "all"]) => {
+            let update = match UpdateRequest::parse(body) {
+                Ok(update) => update,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            let verify = match parse_verify(query) {
+                Ok(verify) => verify,
+                Err(err) => return json_client_error(StatusCode(400), &err),
+            };
+            match apply_update_to_targets(client, None, None, true, update, verify) {
+                Ok(results) => json_response(StatusCode(200), &results),
+                Err(err) => error_response(err),
+            }
+        }
+        _ => json_client_error(StatusCode(404), "Not found"),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct UpdateRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kelvin: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mired: Option<u16>,
+    /// 0-360; only color-capable lights act on it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hue: Option<f64>,
+    /// 0-100; only color-capable lights act on it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    saturation: Option<f64>,
+}
+
+impl UpdateRequest {
+    /// Read an update from an API body. Values no light takes are refused rather
+    /// than clamped; a `kelvin` or `mired` outside one light's range is still clamped
+    /// to it, since a group can mix lights with different ranges.
+    fn parse(body: &str) -> Result<UpdateRequest, String> {
+        let update: UpdateRequest = serde_json::from_str(body)
+            .map_err(|err| format!("Invalid JSON body for update request: {err}"))?;
+        update.check()?;
+        Ok(update)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let problems = [
+            (self.on.is_some_and(|on| on > 1), "on must be 0 or 1"),
+            (
+                self.brightness.is_some_and(|v| v > 100),
+                "brightness must be 0-100",
+            ),
+            (
+                self.kelvin.is_some_and(|v| !(1000..=20_000).contains(&v)),
+                "kelvin must be 1000-20000",
+            ),
+            (
+                self.mired.is_some_and(|v| !(50..=1000).contains(&v)),
+                "mired must be 50-1000",
+            ),
+            (
+                self.hue.is_some_and(|v| !(0.0..=360.0).contains(&v)),
+                "hue must be 0-360",
+            ),
+            (
+                self.saturation.is_some_and(|v| !(0.0..=100.0).contains(&v)),
+                "saturation must be 0-100",
+            ),
+        ];
+        match problems.iter().find(|(bad, _)| *bad) {
+            Some((_, problem)) => Err(problem.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// The request with brightness and kelvin corrected for one light's
+    /// `[[calibration]]`; an explicit `mired` is sent as is.
+    fn calibrated(&self, config: &Config, light: Option<&LightRecord>) -> UpdateRequest {
+        let Some(light) = light else {
+            return self.clone();
+        };
+        let (brightness, kelvin) =
+            calibration::to_device(config, light, self.brightness, self.kelvin);
+        UpdateRequest {
+            brightness,
+            kelvin,
+            ..self.clone()
+        }
+    }
+
+    /// Device update for a light supporting `range`; temperatures are clamped to it.
+    fn to_light_update_in(&self, range: KelvinRange) -> LightUpdate {
+        LightUpdate {
+            on: self.on,
+            brightness: self.brightness.map(|v| v.min(100)),
+            temperature: self
+                .mired
+                .map(|mired| range.clamp_mired(mired))
+                .or_else(|| self.kelvin.map(|kelvin| range.kelvin_to_mired(kelvin))),
+            hue: self.hue.map(|v| v.clamp(0.0, 360.0)),
+            saturation: self.saturation.map(|v| v.clamp(0.0, 100.0)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RefreshRequest {
+    timeout: u64,
+}
+
+#[derive(Deserialize)]
+struct DndRequest {
+    duration_secs: u64,
+}
+
+#[derive(Deserialize)]
+struct TimerRequest {
+    target: schedule::ScheduleTarget,
+    update: UpdateRequest,
+    /// Seconds from now.
+    in_secs: u64,
+}
+
+#[derive(Deserialize)]
+struct RampRequest {
+    target: schedule::ScheduleTarget,
+    /// Where the ramp ends.
+    brightness: u8,
+    kelvin: Option<u16>,
+    minutes: u32,
+}
 
 #[derive(Deserialize)]
 struct AddLightRequest {
@@ -720,6 +2583,9 @@ struct AddLightRequest {
 struct GroupRequest {
     name: String,
     members: Vec<String>,
+    /// Left as it was when absent; 0 clears it.
+    #[serde(default)]
+    stagger_ms: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -727,17 +2593,206 @@ struct EnabledRequest {
     enabled: bool,
 }
 
+#[derive(Deserialize)]
+struct FavoriteRequest {
+    favorite: bool,
+}
+
+/// Starred lights and scenes, as `GET /v1/favorites` and `favorites` list them.
+#[derive(Serialize)]
+struct Favorites {
+    lights: Vec<LightRecord>,
+    scenes: Vec<snapshot::Scene>,
+}
+
 #[derive(Deserialize)]
 struct AliasRequest {
     alias: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct IconRequest {
+    icon: Option<String>,
+}
+
+/// Fields left out are kept; an empty string clears one.
+#[derive(Deserialize)]
+struct MetaRequest {
+    #[serde(default, alias = "location")]
+    room: Option<String>,
+    #[serde(default)]
+    notes: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BrightnessLimitsRequest {
+    min: Option<u8>,
+    max: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct SceneRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SceneOrderRequest {
+    names: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ProfileRequest {
+    /// `None` goes back to following the profile rules.
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PresetApplyRequest {
+    target: schedule::ScheduleTarget,
+}
+
+#[derive(Deserialize)]
+struct PositionRequest {
+    position: usize,
+}
+
+#[derive(Deserialize)]
+struct ScheduleOverrideRequest {
+    paused: Option<bool>,
+    skip_next: Option<bool>,
+}
+
+/// Smaller bodies aren't worth compressing.
+const GZIP_MIN_BYTES: usize = 1024;
+
+/// How JSON bodies are written for the request being answered.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ResponseFormat {
+    /// `?pretty=true`: indented, for reading in a terminal.
+    pretty: bool,
+    /// The client accepts `Content-Encoding: gzip`.
+    gzip: bool,
+    /// Paging, projection and `If-None-Match` of a GET to one of `listing::ENDPOINTS`.
+    list: Option<listing::ListQuery>,
+    /// The page asking, when a browser does; decides the CORS headers.
+    access: cors::Access,
+}
+
+thread_local! {
+    /// Set by whichever thread answers a request before it builds responses, so
+    /// `json_response` doesn't have to be handed the format by every route.
+    static RESPONSE_FORMAT: RefCell<ResponseFormat> = const {
+        RefCell::new(ResponseFormat {
+            pretty: false,
+            gzip: false,
+            list: None,
+            access: cors::Access::SameOrigin,
+        })
+    };
+}
+
+impl ResponseFormat {
+    fn parse(query: &str, accept_encoding: Option<&str>) -> Self {
+        let pretty = query
+            .split('&')
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "true")))
+            .any(|(key, value)| key == "pretty" && matches!(value, "true" | "1"));
+        // `gzip;q=0` means the client refuses it.
+        let gzip = accept_encoding.is_some_and(|header| {
+            header.split(',').any(|coding| {
+                let mut params = coding.split(';').map(str::trim);
+                let name = params.next().unwrap_or_default();
+                let refused = params.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q <= 0.0)
+                });
+                (name.eq_ignore_ascii_case("gzip") || name == "*") && !refused
+            })
+        });
+        Self {
+            pretty,
+            gzip,
+            list: None,
+            access: cors::Access::SameOrigin,
+        }
+    }
+
+    fn for_request(
+        method: &Method,
+        path: &str,
+        query: &str,
+        request: &tiny_http::Request,
+    ) -> Result<Self, String> {
+        let header = |name: &'static str| {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv(name))
+                .map(|header| header.value.as_str())
+        };
+        let mut format = Self::parse(query, header("Accept-Encoding"));
+        format.access = cors::Access::check(header("Origin"), header("Host"));
+        if *method == Method::Get && listing::ENDPOINTS.contains(&path) {
+            format.list = Some(listing::ListQuery::parse(query, header("If-None-Match"))?);
+        }
+        Ok(format)
+    }
+
+    fn install(self) {
+        RESPONSE_FORMAT.with(|format| *format.borrow_mut() = self);
+    }
+
+    fn current() -> Self {
+        RESPONSE_FORMAT.with(|format| format.borrow().clone())
+    }
+}
+
+fn gzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Write as _;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T, pretty: bool) -> Vec<u8> {
+    if pretty {
+        serde_json::to_vec_pretty(value)
+    } else {
+        serde_json::to_vec(value)
+    }
+    .unwrap_or_else(|_| b"{}".to_vec())
+}
+
 fn json_response<T: Serialize>(
     status: StatusCode,
     value: &T,
 ) -> Response<std::io::Cursor<Vec<u8>>> {
-    let body = serde_json::to_vec_pretty(value).unwrap_or_else(|_| b"{}".to_vec());
-    Response::from_data(body)
+    let format = ResponseFormat::current();
+    let list = format.list.as_ref().filter(|_| status.0 == 200);
+    let page = list.and_then(|list| Some(list.apply(serde_json::to_value(value).ok()?)));
+    let body = match &page {
+        Some((items, _)) => to_json(items, format.pretty),
+        None => to_json(value, format.pretty),
+    };
+    let etag = page.as_ref().map(|_| listing::etag(&body));
+    let etag_header = |etag: &str| tiny_http::Header::from_bytes(&b"ETag"[..], etag.as_bytes());
+    if let (Some(list), Some(etag)) = (list, &etag) {
+        if list.not_modified(etag) {
+            let response = Response::from_data(Vec::new()).with_status_code(StatusCode(304));
+            return with_cors(match etag_header(etag) {
+                Ok(header) => response.with_header(header),
+                Err(()) => response,
+            });
+        }
+    }
+    let compressed = (format.gzip && body.len() >= GZIP_MIN_BYTES)
+        .then(|| gzip(&body).ok())
+        .flatten();
+    let gzipped = compressed.is_some();
+    let response = Response::from_data(compressed.unwrap_or(body))
         .with_status_code(status)
         .with_header(
             tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
@@ -745,6 +2800,117 @@ fn json_response<T: Serialize>(
         .with_header(
             tiny_http::Header::from_bytes(&b"X-Content-Type-Options"[..], &b"nosniff"[..]).unwrap(),
         )
+        .with_header(tiny_http::Header::from_bytes(&b"Vary"[..], &b"Accept-Encoding"[..]).unwrap());
+    let response = match etag.as_deref().map(etag_header) {
+        Some(Ok(header)) => response.with_header(header),
+        _ => response,
+    };
+    let response = match page.and_then(|(_, total)| total) {
+        Some(total) => response.with_header(
+            tiny_http::Header::from_bytes(&b"X-Total-Count"[..], total.to_string().as_bytes())
+                .unwrap(),
+        ),
+        None => response,
+    };
+    let response = if gzipped {
+        response.with_header(
+            tiny_http::Header::from_bytes(&b"Content-Encoding"[..], &b"gzip"[..]).unwrap(),
+        )
+    } else {
+        response
+    };
+    with_cors(response)
+}
+
+/// `response` with the CORS headers for the page that asked, if any.
+fn with_cors<R: std::io::Read>(mut response: Response<R>) -> Response<R> {
+    for header in cors::headers(&ResponseFormat::current().access) {
+        response.add_header(header);
+    }
+    response
+}
+
+/// Send a saved snapshot back to its lights; `key` names the field echoing it.
+fn restore_snapshot(
+    client: &Client,
+    name: String,
+    key: &str,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    match snapshot::restore(client, &name, true) {
+        Ok(()) => json_response(StatusCode(200), &serde_json::json!({ key: name })),
+        Err(err) => error_response(err),
+    }
+}
+
+/// Soft-reset an Elgato light: 200 with its health once it accepted the restart,
+/// 502 when it didn't answer (it then needs a power cycle).
+fn reset_light(client: &Client, ident: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => return json_server_error(StatusCode(500), "loading config", err),
+    };
+    let (record, ip) = match store::resolve_member(&config.lights, ident) {
+        Ok(found) => found,
+        Err(reason) => return json_client_error(StatusCode(404), &format!("{ident}: {reason}")),
+    };
+    if record.backend != Backend::Elgato {
+        return json_client_error(StatusCode(400), "Only Elgato lights can be reset");
+    }
+    match health::reset(client, &ip) {
+        Ok(()) => json_response(StatusCode(200), &health::status(&record.id, &ip)),
+        Err(err) => json_client_error(
+            StatusCode(502),
+            &format!("Light did not take the restart ({err}); unplug it for a few seconds"),
+        ),
+    }
+}
+
+/// Failure streaks of the enabled Elgato lights.
+fn lights_health(config: &Config) -> Vec<health::LightHealth> {
+    config
+        .lights
+        .iter()
+        .filter(|light| light.enabled && light.backend == Backend::Elgato)
+        .filter_map(|light| Some(health::status(&light.id, &light.address()?)))
+        .collect()
+}
+
+/// 200 with the summary and its ETag, or 304 when the client's `etag` is still current.
+fn summary_response(
+    result: Result<Option<summary::Summary>, KeylightError>,
+    etag: Option<&str>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let (response, etag) = match result {
+        Ok(Some(summary)) => {
+            let etag = summary.etag();
+            (json_response(StatusCode(200), &summary), etag)
+        }
+        Ok(None) => (
+            with_cors(Response::from_data(Vec::new()).with_status_code(StatusCode(304))),
+            etag.unwrap_or_default().to_string(),
+        ),
+        Err(err) => return error_response(err),
+    };
+    match tiny_http::Header::from_bytes(&b"ETag"[..], etag.as_bytes()) {
+        Ok(header) => response.with_header(header),
+        Err(()) => response,
+    }
+}
+
+/// Answer `err` with the status its kind calls for. A light's own id comes along for
+/// a 409; file errors and untyped ones are only logged, like other server errors.
+fn error_response(err: impl Into<KeylightError>) -> Response<std::io::Cursor<Vec<u8>>> {
+    let err = err.into();
+    match &err {
+        KeylightError::AliasTaken { id, .. } => json_response(
+            StatusCode(409),
+            &serde_json::json!({ "error": err.to_string(), "conflict": id }),
+        ),
+        KeylightError::Config { .. } | KeylightError::Other(_) => {
+            json_server_error(StatusCode(500), "handling request", &err)
+        }
+        typed => json_client_error(StatusCode(typed.status()), &typed.to_string()),
+    }
 }
 
 fn json_client_error(status: StatusCode, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
@@ -756,50 +2922,130 @@ fn json_server_error<E: std::fmt::Display>(
     context: &str,
     err: E,
 ) -> Response<std::io::Cursor<Vec<u8>>> {
-    eprintln!("[keylightd] {context}: {err}");
+    log_line!("{context}: {err}");
     json_client_error(status, "Internal server error.")
 }
 
+/// Exit status of `set` when some lights failed: 1 when none were updated, 3 when
+/// only some were (2 is taken by usage errors).
+fn set_exit_code(targets: usize, failed: usize) -> i32 {
+    if failed >= targets {
+        1
+    } else {
+        3
+    }
+}
+
+/// `--json` output: one document on stdout, so it can be piped to `jq`.
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
 fn print_lights(payload: &LightsPayload<LightState>) {
+    let format = locale::current();
     for (index, light) in payload.lights.iter().enumerate() {
         let kelvin = mired_to_kelvin(light.temperature);
         println!(
             "light[{}]: on={}, brightness={}, temperature_mired={}, temperature_kelvin={}",
-            index, light.on, light.brightness, light.temperature, kelvin
+            index,
+            light.on,
+            format.integer(light.brightness),
+            format.integer(light.temperature),
+            format.integer(kelvin)
         );
     }
 }
 
-fn kelvin_to_mired(kelvin: u16) -> u16 {
-    let clamped = kelvin.clamp(KELVIN_MIN, KELVIN_MAX) as u32;
-    let mired = ((1_000_000u32 + clamped / 2) / clamped) as u16;
-    clamp_mired(mired)
+/// Controls a light supports; the tray hides the ones it doesn't.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+struct Capabilities {
+    brightness: bool,
+    temperature: bool,
+    /// Accepts hue/saturation.
+    color: bool,
+}
+
+impl Default for Capabilities {
+    /// A Key Light: dimmable tunable white.
+    fn default() -> Self {
+        Self {
+            brightness: true,
+            temperature: true,
+            color: false,
+        }
+    }
+}
+
+/// A light found by a UDP backend's broadcast discovery.
+#[derive(Debug, Clone)]
+struct DiscoveredLight {
+    id: String,
+    name: String,
+    ip: String,
+    port: u16,
+    backend: Backend,
+    capabilities: Capabilities,
+    kelvin_range: Option<KelvinRange>,
+    /// For Elgato-compatible lights found by a subnet scan.
+    accessory_info: Option<Value>,
+}
+
+fn light_for_ip<'a>(config: &'a Config, ip: &str) -> Option<&'a LightRecord> {
+    let (ip, zone) = split_zone(ip);
+    config
+        .lights
+        .iter()
+        .find(|light| light.zone == zone && light.addresses.iter().any(|addr| addr == ip))
+}
+
+/// Range for the persisted light at `ip`; manual `--ip` targets get the default.
+fn kelvin_range_for_ip(config: &Config, ip: &str) -> KelvinRange {
+    light_for_ip(config, ip)
+        .map(LightRecord::supported_kelvin)
+        .unwrap_or(KelvinRange::DEFAULT)
 }
 
-fn mired_to_kelvin(mired: u16) -> u16 {
-    let clamped = clamp_mired(mired) as u32;
-    ((1_000_000u32 + clamped / 2) / clamped) as u16
+/// Backend for the persisted light at `ip`; manual `--ip` targets are Elgato lights.
+fn backend_for_ip(config: &Config, ip: &str) -> Backend {
+    light_for_ip(config, ip)
+        .map(|light| light.backend)
+        .unwrap_or_default()
 }
 
-fn clamp_mired(mired: u16) -> u16 {
-    mired.clamp(MIRED_MIN, MIRED_MAX)
+/// Range reported by the device, if any. Elgato's own lights don't report one; some
+/// third-party `_elg._tcp` devices list it in `/elgato/lights/settings` or accessory-info.
+fn detect_kelvin_range(
+    client: &Client,
+    ip: &str,
+    accessory_info: Option<&Value>,
+) -> Option<KelvinRange> {
+    let settings = elgato::settings(client, ip).ok();
+    settings
+        .as_ref()
+        .into_iter()
+        .chain(accessory_info)
+        .find_map(kelvin_range_from_value)
 }
 
-fn resolve_ip(ip: Option<String>, id: Option<String>) -> Result<String, Box<dyn Error>> {
+fn resolve_ip(ip: Option<String>, id: Option<String>) -> Result<String, KeylightError> {
     match (ip, id) {
         (Some(ip), None) => {
             // Avoid SSRF-ish behavior when the daemon is used as a helper.
             validate_manual_ip(&ip)
                 .map(|ip| ip.to_string())
-                .map_err(|e| e.into())
+                .map_err(KeylightError::invalid)
         }
         (None, Some(id)) => {
             let config = load_config()?;
-            resolve_ip_from_config(&config, &id)
-                .ok_or_else(|| format!("No persisted light found with id '{}'", id).into())
+            store::resolve_member(&config.lights, &id)
+                .map(|(_, ip)| ip)
+                .map_err(|_| KeylightError::LightNotFound(id))
         }
-        (Some(_), Some(_)) => Err("Use either --ip or --id, not both".into()),
-        (None, None) => Err("You must provide either --ip or --id".into()),
+        (Some(_), Some(_)) => {
+            Err(KeylightError::invalid("Use either --ip or --id, not both"))
+        }
+        (None, None) => Err(KeylightError::invalid("You must provide either --ip or --id")),
     }
 }
 
@@ -834,13 +3080,15 @@ fn resolve_targets(
     id: Option<String>,
     group: Option<String>,
     all: bool,
-) -> Result<Vec<String>, Box<dyn Error>> {
+) -> Result<Vec<String>, KeylightError> {
     let target_count = [ip.is_some(), id.is_some(), group.is_some(), all]
         .iter()
         .filter(|&&value| value)
         .count();
     if target_count != 1 {
-        return Err("Provide exactly one of --ip, --id, --group, or --all".into());
+        return Err(
+            KeylightError::invalid("Provide exactly one of --ip, --id, --group, or --all"),
+        );
     }
 
     if let Some(ip) = ip {
@@ -851,125 +3099,309 @@ fn resolve_targets(
     }
 
     let config = load_config()?;
-    if all {
-        let mut ips = config
+    let target = match group {
+        Some(name) => Target::Group(name),
+        None => Target::All,
+    };
+    Ok(store::resolve(&config.lights, &config.groups, &target)?)
+}
+
+#[derive(Serialize, Debug)]
+struct ResolvedGroup {
+    name: String,
+    members: Vec<ResolvedMember>,
+    /// Addresses an update to the group would go to right now, deduplicated.
+    targets: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct ResolvedMember {
+    member: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    light: Option<LightRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<&'static str>,
+}
+
+/// What `--group` would target, member by member, for troubleshooting groups.
+fn resolve_group(config: &Config, name: &str) -> Result<ResolvedGroup, KeylightError> {
+    let group = config
+        .groups
+        .iter()
+        .find(|group| group.name == name)
+        .ok_or_else(|| KeylightError::GroupNotFound(name.to_string()))?;
+    let mut members = Vec::new();
+    let mut targets = Vec::new();
+    for member in &group.members {
+        let resolved = match store::resolve_member(&config.lights, member) {
+            Ok((record, ip)) => {
+                targets.push(ip.clone());
+                ResolvedMember {
+                    member: member.clone(),
+                    light: Some(record.clone()),
+                    ip: Some(ip),
+                    skipped: None,
+                }
+            }
+            Err(reason) => ResolvedMember {
+                member: member.clone(),
+                // Still show a disabled or unreachable light, just not as a target.
+                light: store::find(&config.lights, member).cloned(),
+                ip: None,
+                skipped: Some(reason),
+            },
+        };
+        members.push(resolved);
+    }
+    targets.sort();
+    targets.dedup();
+    Ok(ResolvedGroup {
+        name: group.name.clone(),
+        members,
+        targets,
+    })
+}
+
+fn fetch_accessory_info(client: &Client, ip: &str) -> Option<Value> {
+    let base_url = format!("http://{}:9123/elgato", ip);
+    device::send(|| client.get(format!("{}/accessory-info", base_url)))
+        .ok()?
+        .json()
+        .ok()
+}
+
+fn fetch_light_state(client: &Client, backend: Backend, ip: &str) -> Option<LightState> {
+    match backend {
+        Backend::Elgato => {}
+        Backend::Wiz => return wiz::get_state(ip),
+        Backend::Lifx => return lifx::get_state(ip),
+        Backend::Custom => return custom::get_state(ip),
+    }
+    if health::skip(ip) {
+        return None;
+    }
+    let (host, zone) = split_zone(ip);
+    let base_url = format!("http://{}:9123/elgato", host);
+    let payload: Option<LightsPayload<LightState>> =
+        device::send(|| client.get(format!("{}/lights", base_url)))
+            .and_then(|response| response.json())
+            .ok();
+    health::note(ip, payload.is_some());
+    payload?.lights.into_iter().nth(zone.map_or(0, usize::from))
+}
+
+fn get_all_light_states(client: &Client) -> Result<Vec<LightStateResponse>, KeylightError> {
+    let config = load_config()?;
+    let config = &config;
+    let overrides = device::Overrides::current();
+
+    // All lights are asked at once, so one slow light costs one timeout, not one each.
+    let states = std::thread::scope(|scope| {
+        let handles = config
             .lights
             .iter()
-            .filter(|light| light.enabled)
-            .filter_map(select_address)
+            .filter(|l| l.enabled)
+            .filter_map(|light| Some((light, light.address()?)))
+            .map(|(light, ip)| {
+                scope.spawn(move || {
+                    overrides.install();
+                    let state = fetch_light_state(client, light.backend, &ip);
+                    // Custom lights can't be polled, so silence from them means nothing.
+                    if light.backend != Backend::Custom {
+                        hooks::note_reachable(&light.id, state.is_some());
+                    }
+                    let state = state?;
+                    events::record(&light.id, &observed_fields(config, light, &state));
+                    Some(LightStateResponse::for_light(config, light, &state))
+                })
+            })
             .collect::<Vec<_>>();
-        if ips.is_empty() {
-            return Err("No persisted lights found. Run `discover` first.".into());
-        }
-        ips.sort();
-        ips.dedup();
-        return Ok(ips);
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok().flatten())
+            .collect()
+    });
+
+    Ok(states)
+}
+
+fn set_light(
+    client: &Client,
+    backend: Backend,
+    ip: &str,
+    update: &LightUpdate,
+) -> Result<LightsPayload<LightState>, KeylightError> {
+    let light = load_config()
+        .ok()
+        .and_then(|config| light_for_ip(&config, ip).cloned());
+    let update = within_limits(light.as_ref(), update);
+    let ramp = update.on.is_some() && light.is_some_and(|light| light.soft_start);
+    if ramp {
+        soft_start::switch(client, backend, ip, &update)
+    } else {
+        send_update(client, backend, ip, &update)
+    }
+}
+
+/// `update` with its brightness kept inside the light's brightness limits.
+fn within_limits(light: Option<&LightRecord>, update: &LightUpdate) -> LightUpdate {
+    let mut update = update.clone();
+    if let Some(limits) = light.and_then(|light| light.brightness_limits) {
+        update.brightness = update
+            .brightness
+            .map(|brightness| brightness.clamp(limits.min, limits.max));
+    }
+    update
+}
+
+/// How each group member resolves, for `--dry-run` and `--verbose`: the light and
+/// address it would be sent to, or why it's skipped.
+fn explain_members(config: &Config, members: &[String]) -> Vec<String> {
+    members
+        .iter()
+        .map(|member| match store::resolve_member(&config.lights, member) {
+            Ok((light, ip)) => format!("member={member}, id={}, ip={ip}", light.id),
+            Err(reason) => format!("member={member}, skipped={reason}"),
+        })
+        .collect()
+}
+
+/// Send one update, merged with any others queued for the same light; `set_light`
+/// goes through here, possibly several times.
+fn send_update(
+    client: &Client,
+    backend: Backend,
+    ip: &str,
+    update: &LightUpdate,
+) -> Result<LightsPayload<LightState>, KeylightError> {
+    coalesce::send(ip, backend.device_interval(), update, |merged| {
+        send_now(client, backend, ip, merged)
+    })
+}
+
+/// One device request, paced by the backend limits.
+fn send_now(
+    client: &Client,
+    backend: Backend,
+    ip: &str,
+    update: &LightUpdate,
+) -> Result<LightsPayload<LightState>, KeylightError> {
+    if backend == Backend::Elgato && health::skip(ip) {
+        return Err(KeylightError::unreachable(
+            ip,
+            "offline; skipped until it answers again",
+        ));
     }
-
-    let group_name = group.unwrap_or_default();
-    let group = config
-        .groups
-        .iter()
-        .find(|group| group.name == group_name)
-        .ok_or_else(|| format!("No group named '{}'", group_name))?;
-    let mut ips = Vec::new();
-    for member in &group.members {
-        if let Some(ip) = resolve_ip_from_config(&config, member) {
-            ips.push(ip);
+    let _permit = backend.acquire();
+    let response = match backend {
+        Backend::Elgato => {
+            let (host, zone) = split_zone(ip);
+            let zone = zone.unwrap_or(0);
+            let base_url = format!("http://{}:9123/elgato", host);
+            // Zones before the one addressed get an empty entry, which leaves them be.
+            let mut lights = vec![LightUpdate::default(); usize::from(zone)];
+            lights.push(update.clone());
+            let payload = LightsPayload {
+                number_of_lights: zone + 1,
+                lights,
+            };
+            let response =
+                device::send(|| client.put(format!("{}/lights", base_url)).json(&payload))
+                    .and_then(|response| response.json::<LightsPayload<LightState>>());
+            health::note(ip, response.is_ok());
+            let mut response = response.map_err(|err| KeylightError::from_request(ip, err))?;
+            // Callers read the first light, so hand them the zone they addressed.
+            let skipped = usize::from(zone).min(response.lights.len());
+            response.lights.drain(..skipped);
+            response
         }
+        Backend::Wiz => LightsPayload {
+            number_of_lights: 1,
+            lights: vec![wiz::set(ip, update)?],
+        },
+        Backend::Lifx => LightsPayload {
+            number_of_lights: 1,
+            lights: vec![lifx::set(ip, update)?],
+        },
+        Backend::Custom => LightsPayload {
+            number_of_lights: 1,
+            lights: vec![custom::set(client, ip, update)?],
+        },
+    };
+    if events::enabled() {
+        record_light_response(ip, &response);
     }
-    ips.sort();
-    ips.dedup();
-    if ips.is_empty() {
-        return Err(format!("Group '{}' has no enabled members", group.name).into());
-    }
-    Ok(ips)
-}
-
-fn select_address(record: &LightRecord) -> Option<String> {
-    select_address_from_list(&record.addresses)
+    Ok(response)
 }
 
-fn select_address_from_list(addresses: &[String]) -> Option<String> {
-    addresses
-        .iter()
-        .find(|addr| addr.contains('.'))
-        .cloned()
-        .or_else(|| addresses.first().cloned())
+/// Feed the state a light echoed back into the event log, keyed by its persisted id.
+fn record_light_response(ip: &str, response: &LightsPayload<LightState>) {
+    let (Ok(config), Some(state)) = (load_config(), response.lights.first()) else {
+        return;
+    };
+    if let Some(light) = light_for_ip(&config, ip) {
+        events::record(&light.id, &observed_fields(&config, light, state));
+    }
 }
 
-fn resolve_ip_from_config(config: &Config, ident: &str) -> Option<String> {
-    let record = config.lights.iter().find(|light| {
-        light.id == ident || light.name == ident || light.alias.as_deref() == Some(ident)
-    })?;
-    if !record.enabled {
-        return None;
-    }
-    select_address(record)
+/// `state` as events and summaries report it, in the terms requests use (undoing the
+/// light's `[[calibration]]`).
+fn observed_fields(
+    config: &Config,
+    light: &LightRecord,
+    state: &LightState,
+) -> events::LightFields {
+    let range = light.supported_kelvin();
+    let mut fields = events::LightFields {
+        on: Some(state.on == 1),
+        brightness: Some(state.brightness),
+        kelvin: (state.temperature != 0).then(|| range.mired_to_kelvin(state.temperature)),
+        hue: state.hue,
+        saturation: state.saturation,
+    };
+    (fields.brightness, fields.kelvin) =
+        calibration::reported(config, light, fields.brightness, fields.kelvin);
+    fields
 }
 
-fn fetch_accessory_info(client: &Client, ip: &str) -> Option<Value> {
-    let base_url = format!("http://{}:9123/elgato", ip);
-    client
-        .get(format!("{}/accessory-info", base_url))
-        .send()
-        .ok()?
-        .error_for_status()
-        .ok()?
-        .json()
-        .ok()
+/// Result of one light's update; `verified` is only there for `?verify=true`.
+#[derive(Serialize, Debug)]
+struct UpdateResult {
+    #[serde(flatten)]
+    payload: LightsPayload<LightState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verified: Option<verify::Verification>,
 }
 
-fn fetch_light_state(client: &Client, ip: &str) -> Option<LightState> {
-    let base_url = format!("http://{}:9123/elgato", ip);
-    let payload: LightsPayload<LightState> = client
-        .get(format!("{}/lights", base_url))
-        .send()
-        .ok()?
-        .error_for_status()
-        .ok()?
-        .json()
-        .ok()?;
-    payload.lights.into_iter().next()
+/// One target's entry in the answer to an update, in the order the lights were
+/// targeted: what the light reported, or why it couldn't be set.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum TargetResult {
+    Updated(UpdateResult),
+    Failed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        ip: String,
+        error: String,
+    },
 }
 
-fn get_all_light_states(client: &Client) -> Result<Vec<LightStateResponse>, Box<dyn Error>> {
-    let config = load_config()?;
-    let mut states = Vec::new();
-
-    for light in config.lights.iter().filter(|l| l.enabled) {
-        if let Some(ip) = select_address(light) {
-            if let Some(state) = fetch_light_state(client, &ip) {
-                states.push(LightStateResponse {
-                    id: light.id.clone(),
-                    on: state.on == 1,
-                    brightness: state.brightness,
-                    kelvin: mired_to_kelvin(state.temperature),
-                });
-            }
+/// `verify` from an update's query string; absent means no read-back.
+fn parse_verify(query: &str) -> Result<bool, String> {
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, "true"));
+        if key == "verify" {
+            return match value {
+                "true" | "1" => Ok(true),
+                "false" | "0" => Ok(false),
+                _ => Err(format!("Invalid verify '{value}'")),
+            };
         }
     }
-
-    Ok(states)
-}
-
-fn set_light(
-    client: &Client,
-    ip: &str,
-    update: &LightUpdate,
-) -> Result<LightsPayload<LightState>, Box<dyn Error>> {
-    let base_url = format!("http://{}:9123/elgato", ip);
-    let payload = LightsPayload {
-        number_of_lights: 1,
-        lights: vec![update.clone()],
-    };
-    let response: LightsPayload<LightState> = client
-        .put(format!("{}/lights", base_url))
-        .json(&payload)
-        .send()?
-        .error_for_status()?
-        .json()?;
-    Ok(response)
+    Ok(false)
 }
 
 fn apply_update_to_targets(
@@ -978,66 +3410,372 @@ fn apply_update_to_targets(
     group: Option<String>,
     all: bool,
     update: UpdateRequest,
-) -> Result<Vec<LightsPayload<LightState>>, Box<dyn Error>> {
-    let update = LightUpdate {
-        on: update.on,
-        brightness: update.brightness.map(|v| v.min(100)),
-        temperature: update
-            .mired
-            .map(clamp_mired)
-            .or_else(|| update.kelvin.map(kelvin_to_mired)),
+    verify: bool,
+) -> Result<Vec<TargetResult>, KeylightError> {
+    let config = load_config()?;
+    let stagger = power_on_stagger(&config, group.as_deref(), &update);
+    let target = match (&id, &group) {
+        (Some(id), _) => format!("light:{id}"),
+        (None, Some(group)) => format!("group:{group}"),
+        (None, None) => "all".to_string(),
     };
     let targets = resolve_targets(None, id, group, all)?;
-    let mut results = Vec::new();
-    for ip in targets {
-        results.push(set_light(client, &ip, &update)?);
+    update_targets(client, &config, target, &targets, stagger, update, verify)
+}
+
+/// Apply `update` to the lights of a room, as to a group.
+fn apply_update_to_room(
+    client: &Client,
+    name: &str,
+    update: UpdateRequest,
+    verify: bool,
+) -> Result<Vec<TargetResult>, KeylightError> {
+    let config = load_config()?;
+    let targets = rooms::targets(&config, name)?;
+    let target = format!("room:{}", name.to_lowercase());
+    update_targets(
+        client,
+        &config,
+        target,
+        &targets,
+        Duration::ZERO,
+        update,
+        verify,
+    )
+}
+
+/// Send `update` to `targets`, recording it for undo under `target`. Fails only
+/// when every target does.
+fn update_targets(
+    client: &Client,
+    config: &Config,
+    target: String,
+    targets: &[String],
+    stagger: Duration,
+    update: UpdateRequest,
+    verify: bool,
+) -> Result<Vec<TargetResult>, KeylightError> {
+    // One thread per light; `set_light` waits on the backend limits, so this only
+    // goes as wide as `[backends]` allows. Staggered groups start each one later.
+    // Each thread first notes its light's state for undo: the daemon has usually
+    // seen it already, and otherwise asks the light, alongside the others.
+    let update = &update;
+    let overrides = device::Overrides::current();
+    let (previous, results): (Vec<_>, Vec<_>) = std::thread::scope(|scope| {
+        let handles = targets
+            .iter()
+            .enumerate()
+            .map(|(index, ip)| {
+                scope.spawn(move || {
+                    overrides.install();
+                    let previous = light_for_ip(config, ip).and_then(|light| {
+                        let fields = events::light(&light.id).1.or_else(|| {
+                            fetch_light_state(client, light.backend, ip)
+                                .map(|state| observed_fields(config, light, &state))
+                        })?;
+                        Some((light.id.clone(), fields))
+                    });
+                    (
+                        previous,
+                        send_to_target(client, config, ip, index, stagger, update, verify),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .zip(targets)
+            .map(|(handle, ip)| {
+                handle.join().unwrap_or_else(|_| {
+                    let failed = KeylightError::Unreachable {
+                        ip: ip.clone(),
+                        reason: "light update thread panicked".into(),
+                    };
+                    (None, Err(failed))
+                })
+            })
+            .unzip()
+    });
+    // Recorded even when some lights failed: undo only re-sends what they had.
+    history::record(target, update, previous.into_iter().flatten().collect());
+    // A dead light doesn't fail the others' update; only all of them failing does.
+    let all_failed = results.iter().all(Result::is_err);
+    let mut entries = Vec::with_capacity(results.len());
+    for (ip, result) in targets.iter().zip(results) {
+        match result {
+            Ok(updated) => entries.push(TargetResult::Updated(updated)),
+            Err(err) if all_failed => return Err(err),
+            Err(err) => entries.push(TargetResult::Failed {
+                id: light_for_ip(config, ip).map(|light| light.id.clone()),
+                ip: ip.clone(),
+                error: err.to_string(),
+            }),
+        }
+    }
+    Ok(entries)
+}
+
+/// The `index`th light of a fan-out: wait out its share of the stagger, then send.
+fn send_to_target(
+    client: &Client,
+    config: &Config,
+    ip: &str,
+    index: usize,
+    stagger: Duration,
+    update: &UpdateRequest,
+    verify: bool,
+) -> Result<UpdateResult, KeylightError> {
+    let index = u32::try_from(index).unwrap_or(u32::MAX);
+    std::thread::sleep(stagger.saturating_mul(index));
+    let range = kelvin_range_for_ip(config, ip);
+    let backend = backend_for_ip(config, ip);
+    let update = update
+        .calibrated(config, light_for_ip(config, ip))
+        .to_light_update_in(range);
+    let payload = set_light(client, backend, ip, &update)?;
+    // Compare with what was sent, after the light's brightness limits.
+    let sent = within_limits(light_for_ip(config, ip), &update);
+    let verified = verify.then(|| verify::read_back(client, backend, ip, &sent, range));
+    Ok(UpdateResult { payload, verified })
+}
+
+/// Pause between group members for `update`: the group's `stagger_ms` when it
+/// switches lights on, nothing otherwise.
+fn power_on_stagger(config: &Config, group: Option<&str>, update: &UpdateRequest) -> Duration {
+    if update.on != Some(1) {
+        return Duration::ZERO;
     }
-    Ok(results)
+    group
+        .and_then(|name| config.groups.iter().find(|group| group.name == name))
+        .and_then(|group| group.stagger_ms)
+        .map(Duration::from_millis)
+        .unwrap_or_default()
 }
 
-fn save_group(name: String, mut members: Vec<String>) -> Result<Group, Box<dyn Error>> {
+/// Create or replace a group. `stagger_ms` of `None` keeps the existing setting.
+fn save_group(
+    name: String,
+    members: Vec<String>,
+    stagger_ms: Option<u64>,
+) -> Result<Group, KeylightError> {
     let mut config = load_config()?;
+    let group = store_group(&mut config, name, members, stagger_ms)?;
+    save_config(&config)?;
+    Ok(group)
+}
+
+/// Longest pause between group members when switching a group on.
+const MAX_STAGGER_MS: u64 = 5000;
+
+/// Checks `save_group` makes before storing: a non-blank name that doesn't differ
+/// from another group's only by case, at least one member, each naming a
+/// persisted light by id, name or alias, and a stagger of at most 5 seconds.
+fn store_group(
+    config: &mut Config,
+    name: String,
+    mut members: Vec<String>,
+    stagger_ms: Option<u64>,
+) -> Result<Group, KeylightError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(KeylightError::invalid("Group name must not be empty"));
+    }
+    if let Some(other) = config
+        .groups
+        .iter()
+        .find(|group| group.name != name && group.name.to_lowercase() == name.to_lowercase())
+    {
+        return Err(KeylightError::Invalid(format!(
+            "A group named '{}' already exists",
+            other.name
+        )));
+    }
+    if stagger_ms.is_some_and(|ms| ms > MAX_STAGGER_MS) {
+        return Err(KeylightError::Invalid(format!(
+            "stagger_ms can be at most {MAX_STAGGER_MS}"
+        )));
+    }
+    members.retain(|member| !member.trim().is_empty());
     members.sort();
     members.dedup();
+    if members.is_empty() {
+        return Err(
+            KeylightError::Invalid(format!("Group '{}' needs at least one member", name)),
+        );
+    }
+    let unknown: Vec<String> = members
+        .iter()
+        .filter(|member| store::find(&config.lights, member).is_none())
+        .map(|member| format!("'{member}'"))
+        .collect();
+    if !unknown.is_empty() {
+        let unknown = unknown.join(", ");
+        return Err(KeylightError::Invalid(format!(
+            "No persisted light has the id, name or alias {unknown}"
+        )));
+    }
+    let existing = config.groups.iter().find(|group| group.name == name);
     let group = Group {
         name: name.clone(),
         members,
+        stagger_ms: match stagger_ms {
+            Some(0) => None,
+            Some(ms) => Some(ms),
+            None => existing.and_then(|group| group.stagger_ms),
+        },
     };
     match config.groups.iter_mut().find(|group| group.name == name) {
         Some(existing) => *existing = group.clone(),
         None => config.groups.push(group.clone()),
     }
+    Ok(group)
+}
+
+fn remove_group_members(name: &str, idents: &[String]) -> Result<Group, KeylightError> {
+    let mut config = load_config()?;
+    let group = remove_members_from(&mut config, name, idents)?;
     save_config(&config)?;
     Ok(group)
 }
 
-fn delete_light(id: String) -> Result<(), Box<dyn Error>> {
+/// Drop `idents` from a group. A member matches an ident naming the same light, so
+/// `--id left` removes a member saved by its mDNS id.
+fn remove_members_from(
+    config: &mut Config,
+    name: &str,
+    idents: &[String],
+) -> Result<Group, KeylightError> {
+    let index = config
+        .groups
+        .iter()
+        .position(|group| group.name == name)
+        .ok_or_else(|| KeylightError::GroupNotFound(name.to_string()))?;
+    let light_id = |ident: &str| store::find(&config.lights, ident).map(|light| light.id.clone());
+    let same_light = |member: &str, ident: &str| {
+        member == ident || light_id(member).is_some_and(|id| Some(id) == light_id(ident))
+    };
+    let members = &config.groups[index].members;
+    if let Some(missing) = idents
+        .iter()
+        .find(|ident| !members.iter().any(|member| same_light(member, ident)))
+    {
+        return Err(KeylightError::Invalid(format!(
+            "'{}' is not a member of group '{}'",
+            missing, name
+        )));
+    }
+    let kept: Vec<String> = members
+        .iter()
+        .filter(|member| !idents.iter().any(|ident| same_light(member, ident)))
+        .cloned()
+        .collect();
+    config.groups[index].members = kept;
+    Ok(config.groups[index].clone())
+}
+
+/// Rename a group; returns how many schedules were pointed at the new name.
+fn rename_group(from: &str, to: &str) -> Result<usize, KeylightError> {
+    let mut config = load_config()?;
+    let schedules = rename_group_in(&mut config, from, to)?;
+    save_config(&config)?;
+    if let Some(auto) = &config.auto_brightness {
+        if auto.target == schedule::ScheduleTarget::Group(from.to_string()) {
+            eprintln!("Note: [auto_brightness] in config.toml still targets '{from}'");
+        }
+    }
+    Ok(schedules)
+}
+
+fn rename_group_in(config: &mut Config, from: &str, to: &str) -> Result<usize, KeylightError> {
+    let to = to.trim();
+    if to.is_empty() {
+        return Err(KeylightError::invalid("Group name must not be empty"));
+    }
+    if let Some(other) = config
+        .groups
+        .iter()
+        .find(|group| group.name != from && group.name.to_lowercase() == to.to_lowercase())
+    {
+        return Err(KeylightError::Invalid(format!(
+            "A group named '{}' already exists",
+            other.name
+        )));
+    }
+    let group = config
+        .groups
+        .iter_mut()
+        .find(|group| group.name == from)
+        .ok_or_else(|| KeylightError::GroupNotFound(from.to_string()))?;
+    group.name = to.to_string();
+    let mut schedules = 0;
+    let old_target = schedule::ScheduleTarget::Group(from.to_string());
+    for entry in config
+        .schedules
+        .iter_mut()
+        .filter(|entry| entry.target == old_target)
+    {
+        entry.target = schedule::ScheduleTarget::Group(to.to_string());
+        schedules += 1;
+    }
+    Ok(schedules)
+}
+
+fn save_schedule(schedule: Schedule) -> Result<Schedule, KeylightError> {
+    schedule.validate().map_err(KeylightError::Invalid)?;
+    let mut config = load_config()?;
+    match config
+        .schedules
+        .iter_mut()
+        .find(|existing| existing.name == schedule.name)
+    {
+        Some(existing) => *existing = schedule.clone(),
+        None => config.schedules.push(schedule.clone()),
+    }
+    save_config(&config)?;
+    Ok(schedule)
+}
+
+fn delete_schedule(name: String) -> Result<(), KeylightError> {
+    let mut config = load_config()?;
+    let original_len = config.schedules.len();
+    config.schedules.retain(|schedule| schedule.name != name);
+    if config.schedules.len() == original_len {
+        return Err(KeylightError::not_found("schedule", name));
+    }
+    save_config(&config)?;
+    Ok(())
+}
+
+fn delete_light(id: String) -> Result<(), KeylightError> {
     let mut config = load_config()?;
     let original_len = config.lights.len();
-    config.lights.retain(|light| {
-        light.id != id && light.name != id && light.alias.as_deref() != Some(&id)
-    });
+    config
+        .lights
+        .retain(|light| light.id != id && light.name != id && light.alias.as_deref() != Some(&id));
     if config.lights.len() == original_len {
-        return Err(format!("No persisted light found with id '{}'", id).into());
+        return Err(KeylightError::LightNotFound(id.to_string()));
     }
-    save_config(&config)?;
+    save_state(&config)?;
     Ok(())
 }
 
-fn delete_group(name: String) -> Result<(), Box<dyn Error>> {
+fn delete_group(name: String) -> Result<(), KeylightError> {
     let mut config = load_config()?;
     let original_len = config.groups.len();
     config.groups.retain(|group| group.name != name);
     if config.groups.len() == original_len {
-        return Err(format!("No group named '{}'", name).into());
+        return Err(KeylightError::GroupNotFound(name.to_string()));
     }
     save_config(&config)?;
     Ok(())
 }
 
-fn add_light_by_ip(client: &Client, ip: String) -> Result<LightRecord, Box<dyn Error>> {
-    let info = fetch_accessory_info(client, &ip)
-        .ok_or_else(|| "Unable to fetch accessory-info from device".to_string())?;
+fn add_light_by_ip(client: &Client, ip: String) -> Result<LightRecord, KeylightError> {
+    let info =
+        fetch_accessory_info(client, &ip).ok_or_else(|| KeylightError::Unreachable {
+            ip: ip.clone(),
+            reason: "Unable to fetch accessory-info from device".into(),
+        })?;
     let serial = info
         .get("serialNumber")
         .and_then(|v| v.as_str())
@@ -1049,6 +3787,11 @@ fn add_light_by_ip(client: &Client, ip: String) -> Result<LightRecord, Box<dyn E
         .or_else(|| info.get("productName").and_then(|v| v.as_str()))
         .unwrap_or("Elgato Light");
     let id = format!("manual-{}", serial);
+    let kelvin_range = detect_kelvin_range(client, &ip, Some(&info));
+    let capabilities = Capabilities {
+        color: supports_color(Some(&info)),
+        ..Default::default()
+    };
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map(|d| d.as_secs())
@@ -1063,137 +3806,1108 @@ fn add_light_by_ip(client: &Client, ip: String) -> Result<LightRecord, Box<dyn E
         last_seen_unix: now,
         enabled: true,
         accessory_info: Some(info),
+        schedule_paused: false,
+        schedule_skip_next: false,
+        backend: Backend::Elgato,
+        capabilities,
+        kelvin_range,
+        soft_start: false,
+        icon: None,
+        room: None,
+        notes: None,
+        favorite: false,
+        brightness_limits: None,
+        sort_index: None,
+        zone: None,
     };
 
     let mut config = load_config()?;
     match config.lights.iter_mut().find(|item| item.id == id) {
-        Some(existing) => *existing = record.clone(),
+        Some(existing) => {
+            *existing = LightRecord {
+                sort_index: existing.sort_index,
+                ..record.clone()
+            }
+        }
         None => config.lights.push(record.clone()),
     }
-    save_config(&config)?;
+    save_state(&config)?;
     Ok(record)
 }
 
-fn set_light_enabled(id: String, enabled: bool) -> Result<LightRecord, Box<dyn Error>> {
+fn set_light_enabled(id: String, enabled: bool) -> Result<LightRecord, KeylightError> {
     let mut config = load_config()?;
     let record_clone = {
         let record = config
             .lights
             .iter_mut()
             .find(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
-            .ok_or_else(|| format!("No persisted light found with id '{}'", id))?;
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
         record.enabled = enabled;
         record.clone()
     };
+    save_state(&config)?;
+    Ok(record_clone)
+}
+
+fn set_light_soft_start(id: String, enabled: bool) -> Result<LightRecord, KeylightError> {
+    let mut config = load_config()?;
+    let record_clone = {
+        let record = config
+            .lights
+            .iter_mut()
+            .find(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+        record.soft_start = enabled;
+        record.clone()
+    };
+    save_state(&config)?;
+    Ok(record_clone)
+}
+
+fn set_light_favorite(id: String, favorite: bool) -> Result<LightRecord, KeylightError> {
+    let mut config = load_config()?;
+    let record_clone = {
+        let record = config
+            .lights
+            .iter_mut()
+            .find(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+        record.favorite = favorite;
+        record.clone()
+    };
+    save_state(&config)?;
+    Ok(record_clone)
+}
+
+/// Starred lights in list order and starred scenes in scene order.
+fn favorites() -> Result<Favorites, KeylightError> {
+    let mut lights = load_config()?.lights;
+    sort_lights(&mut lights);
+    lights.retain(|light| light.favorite);
+    let mut scenes = snapshot::scenes()?;
+    scenes.retain(|scene| scene.favorite);
+    Ok(Favorites { lights, scenes })
+}
+
+/// Err when `alias` is the id, name or alias of a light other than `id`.
+fn check_alias(config: &Config, id: &str, alias: &str) -> Result<(), KeylightError> {
+    match config.lights.iter().find(|light| {
+        light.id != id
+            && (light.id == alias || light.name == alias || light.alias.as_deref() == Some(alias))
+    }) {
+        Some(other) => Err(KeylightError::AliasTaken {
+            alias: alias.to_string(),
+            id: other.id.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn set_light_alias(id: String, alias: Option<String>) -> Result<LightRecord, KeylightError> {
+    let mut config = load_config()?;
+    let alias = alias
+        .map(|alias| alias.trim().to_string())
+        .filter(|alias| !alias.is_empty());
+    let record_id = store::find(&config.lights, &id)
+        .map(|light| light.id.clone())
+        .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+    if let Some(alias) = &alias {
+        check_alias(&config, &record_id, alias)?;
+    }
+    let record_clone = {
+        let record = config
+            .lights
+            .iter_mut()
+            .find(|light| light.id == record_id)
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+        record.alias = alias;
+        record.clone()
+    };
+    save_state(&config)?;
+    Ok(record_clone)
+}
+
+#[derive(Serialize, Debug)]
+struct Resolution {
+    ident: String,
+    /// More than one light answers to `ident`; `matches[0]` is the one used.
+    ambiguous: bool,
+    matches: Vec<IdentMatch>,
+}
+
+#[derive(Serialize, Debug)]
+struct IdentMatch {
+    /// `id`, `name` or `alias`: which field of the light equals the ident.
+    matched_by: &'static str,
+    light: LightRecord,
+}
+
+/// Every light `ident` could mean, in the order `store::find` tries them.
+fn resolve_ident(config: &Config, ident: &str) -> Resolution {
+    let matches: Vec<IdentMatch> = config
+        .lights
+        .iter()
+        .filter_map(|light| {
+            let matched_by = if light.id == ident {
+                "id"
+            } else if light.name == ident {
+                "name"
+            } else if light.alias.as_deref() == Some(ident) {
+                "alias"
+            } else {
+                return None;
+            };
+            Some(IdentMatch {
+                matched_by,
+                light: light.clone(),
+            })
+        })
+        .collect();
+    Resolution {
+        ident: ident.to_string(),
+        ambiguous: matches.len() > 1,
+        matches,
+    }
+}
+
+/// Check an icon from the API: empty clears it, otherwise it must be one of
+/// `LIGHT_ICONS` or a short run of non-alphanumeric characters (an emoji).
+fn normalize_icon(icon: Option<String>) -> Result<Option<String>, &'static str> {
+    let Some(icon) = icon.map(|icon| icon.trim().to_string()) else {
+        return Ok(None);
+    };
+    if icon.is_empty() {
+        return Ok(None);
+    }
+    if LIGHT_ICONS.contains(&icon.as_str()) {
+        return Ok(Some(icon));
+    }
+    let emoji = icon.chars().count() <= MAX_ICON_CHARS
+        && !icon
+            .chars()
+            .any(|c| c.is_alphanumeric() || c.is_whitespace() || c.is_control());
+    if emoji {
+        Ok(Some(icon))
+    } else {
+        Err("Icon must be panel, ring, strip, bulb or an emoji.")
+    }
+}
+
+fn set_light_icon(id: String, icon: Option<String>) -> Result<LightRecord, KeylightError> {
+    let mut config = load_config()?;
+    let record_clone = {
+        let record = config
+            .lights
+            .iter_mut()
+            .find(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+        record.icon = icon;
+        record.clone()
+    };
+    save_state(&config)?;
+    Ok(record_clone)
+}
+
+/// Check a room name or notes from the API: surrounding whitespace is dropped and
+/// empty clears the field.
+fn meta_text(value: String, max_chars: usize, what: &str) -> Result<Option<String>, String> {
+    let value = value.trim();
+    if value.chars().count() > max_chars {
+        return Err(format!("{what} must be at most {max_chars} characters."));
+    }
+    Ok(Some(value.to_string()).filter(|value| !value.is_empty()))
+}
+
+fn set_light_meta(id: String, request: MetaRequest) -> Result<LightRecord, KeylightError> {
+    let room = request
+        .room
+        .map(|room| meta_text(room, MAX_ROOM_CHARS, "Room"))
+        .transpose()
+        .map_err(KeylightError::Invalid)?;
+    let notes = request
+        .notes
+        .map(|notes| meta_text(notes, MAX_NOTES_CHARS, "Notes"))
+        .transpose()
+        .map_err(KeylightError::Invalid)?;
+    let mut config = load_config()?;
+    let record_clone = {
+        let record = config
+            .lights
+            .iter_mut()
+            .find(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+        if let Some(room) = room {
+            record.room = room;
+        }
+        if let Some(notes) = notes {
+            record.notes = notes;
+        }
+        record.clone()
+    };
+    save_state(&config)?;
+    Ok(record_clone)
+}
+
+/// Order lights by `sort_index`; the sort is stable, so lights without one keep
+/// their discovery order after the rest.
+fn sort_lights(lights: &mut [LightRecord]) {
+    lights.sort_by_key(|light| light.sort_index.unwrap_or(u32::MAX));
+}
+
+/// Move `from` to `to` (clamped to the end), shifting the items between.
+fn move_item<T>(items: &mut Vec<T>, from: usize, to: usize) {
+    let item = items.remove(from);
+    items.insert(to.min(items.len()), item);
+}
+
+/// Move a light to `position` in light lists and number every light from there,
+/// so the order no longer depends on discovery. Returns the lights in order.
+fn set_light_position(id: String, position: usize) -> Result<Vec<LightRecord>, KeylightError> {
+    let mut config = load_config()?;
+    let from = config
+        .lights
+        .iter()
+        .position(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
+        .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+    move_item(&mut config.lights, from, position);
+    for (index, light) in config.lights.iter_mut().enumerate() {
+        light.sort_index = Some(index as u32);
+    }
+    save_state(&config)?;
+    Ok(config.lights)
+}
+
+/// Move a group to `position` in `config.toml`, which is the order it's listed in.
+fn set_group_position(name: String, position: usize) -> Result<Vec<Group>, KeylightError> {
+    let mut config = load_config()?;
+    let from = config
+        .groups
+        .iter()
+        .position(|group| group.name == name)
+        .ok_or_else(|| KeylightError::GroupNotFound(name.to_string()))?;
+    move_item(&mut config.groups, from, position);
     save_config(&config)?;
+    Ok(config.groups)
+}
+
+fn set_light_brightness_limits(
+    id: String,
+    limits: Option<BrightnessLimits>,
+) -> Result<LightRecord, KeylightError> {
+    let mut config = load_config()?;
+    let record_clone = {
+        let record = config
+            .lights
+            .iter_mut()
+            .find(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+        record.brightness_limits = limits;
+        record.clone()
+    };
+    save_state(&config)?;
     Ok(record_clone)
 }
 
-fn set_light_alias(id: String, alias: Option<String>) -> Result<LightRecord, Box<dyn Error>> {
+fn set_light_schedule_override(
+    id: String,
+    request: ScheduleOverrideRequest,
+) -> Result<LightRecord, KeylightError> {
     let mut config = load_config()?;
     let record_clone = {
         let record = config
             .lights
             .iter_mut()
             .find(|light| light.id == id || light.name == id || light.alias.as_deref() == Some(&id))
-            .ok_or_else(|| format!("No persisted light found with id '{}'", id))?;
-        record.alias = alias.filter(|s| !s.trim().is_empty());
+            .ok_or_else(|| KeylightError::LightNotFound(id.to_string()))?;
+        if let Some(paused) = request.paused {
+            record.schedule_paused = paused;
+        }
+        if let Some(skip_next) = request.skip_next {
+            record.schedule_skip_next = skip_next;
+        }
         record.clone()
     };
-    save_config(&config)?;
-    Ok(record_clone)
+    save_state(&config)?;
+    Ok(record_clone)
+}
+
+/// What a light found over mDNS says about itself; each field is `None` when it
+/// didn't answer that question.
+#[derive(Debug, Default)]
+struct LightProbe {
+    accessory_info: Option<Value>,
+    kelvin_range: Option<KelvinRange>,
+    zones: Option<u8>,
+}
+
+fn probe_light(client: &Client, light: &Found) -> LightProbe {
+    let Some(ip) = light.address() else {
+        return LightProbe::default();
+    };
+    let accessory_info = fetch_accessory_info(client, &ip);
+    LightProbe {
+        kelvin_range: detect_kelvin_range(client, &ip, accessory_info.as_ref()),
+        zones: elgato::zone_count(client, &ip).ok(),
+        accessory_info,
+    }
+}
+
+/// Probe lights one thread each, so the slowest light sets how long this takes.
+fn probe_lights(client: &Client, lights: &[Found]) -> Vec<LightProbe> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = lights
+            .iter()
+            .map(|light| scope.spawn(move || probe_light(client, light)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    })
 }
 
-fn upsert_record(client: &Client, config: &mut Config, info: &mdns_sd::ResolvedService) {
-    let id = info.get_fullname().to_string();
+/// Persist a light found over mDNS, keeping user settings of a known one. Without a
+/// `probe` the accessory info, temperature range and zones known from before are
+/// kept. Returns whether groups changed, which happens when a light turns out to have
+/// zones.
+fn upsert_record(config: &mut Config, light: &Found, probe: Option<&LightProbe>) -> bool {
+    let id = light.id.clone();
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map(|d| d.as_secs())
         .unwrap_or(0);
-    let existing = config.lights.iter().find(|item| item.id == id);
+    // A light stored by zone carries its settings on the zone records.
+    let existing = config
+        .lights
+        .iter()
+        .find(|item| item.id == id)
+        .or_else(|| config.lights.iter().find(|item| item.device_id() == id));
     let alias = existing.and_then(|item| item.alias.clone());
     let previous_accessory = existing.and_then(|item| item.accessory_info.clone());
     let enabled = existing.map(|item| item.enabled).unwrap_or(false);
-    let addresses = info
-        .get_addresses()
-        .iter()
-        .map(|addr| addr.to_string())
-        .collect::<Vec<_>>();
-    let primary_ip = select_address_from_list(&addresses);
-    let accessory_info = primary_ip
-        .as_deref()
-        .and_then(|ip| fetch_accessory_info(client, ip))
+    let schedule_paused = existing.map(|item| item.schedule_paused).unwrap_or(false);
+    let schedule_skip_next = existing
+        .map(|item| item.schedule_skip_next)
+        .unwrap_or(false);
+    let soft_start = existing.is_some_and(|item| item.soft_start);
+    let icon = existing.and_then(|item| item.icon.clone());
+    let room = existing.and_then(|item| item.room.clone());
+    let notes = existing.and_then(|item| item.notes.clone());
+    let favorite = existing.is_some_and(|item| item.favorite);
+    let brightness_limits = existing.and_then(|item| item.brightness_limits);
+    let sort_index = existing.and_then(|item| item.sort_index);
+    let previous_range = existing.and_then(|item| item.kelvin_range);
+    let accessory_info = probe
+        .and_then(|probe| probe.accessory_info.clone())
         .or(previous_accessory);
+    let kelvin_range = probe
+        .and_then(|probe| probe.kelvin_range)
+        .or(previous_range);
+    let capabilities = Capabilities {
+        color: supports_color(accessory_info.as_ref()),
+        ..Default::default()
+    };
     let record = LightRecord {
         id: id.clone(),
         alias,
-        name: info.get_fullname().to_string(),
-        hostname: info.get_hostname().to_string(),
-        port: info.get_port(),
-        addresses,
+        name: light.id.clone(),
+        hostname: light.hostname.clone(),
+        port: light.port,
+        addresses: light.addresses.clone(),
         last_seen_unix: now,
         enabled,
         accessory_info,
+        schedule_paused,
+        schedule_skip_next,
+        backend: Backend::Elgato,
+        capabilities,
+        kelvin_range,
+        soft_start,
+        icon,
+        room,
+        notes,
+        favorite,
+        brightness_limits,
+        sort_index,
+        zone: None,
     };
 
+    // Keep the known layout when the light doesn't answer.
+    let zones = probe.and_then(|probe| probe.zones).unwrap_or_else(|| {
+        let known = config.lights.iter().filter(|item| item.device_id() == id);
+        known.filter(|item| item.zone.is_some()).count().max(1) as u8
+    });
+    if zones > 1 {
+        return store_zones(config, record, zones);
+    }
+    config
+        .lights
+        .retain(|item| item.id == id || item.device_id() != id);
     match config.lights.iter_mut().find(|item| item.id == id) {
         Some(existing) => *existing = record,
         None => config.lights.push(record),
     }
+    false
 }
 
-fn config_path() -> Result<PathBuf, Box<dyn Error>> {
-    let base = if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
-        PathBuf::from(xdg)
-    } else if let Ok(home) = std::env::var("HOME") {
-        PathBuf::from(home).join(".config")
-    } else {
-        return Err("Unable to determine config directory".into());
-    };
+/// Store `record` as one record per zone. Zones keep their own settings and new ones
+/// start from the light's, except its alias, which names one light only. Groups that
+/// named the light name each of its zones instead; returns whether any did.
+fn store_zones(config: &mut Config, record: LightRecord, zones: u8) -> bool {
+    let ids: Vec<String> = (0..zones)
+        .map(|zone| format!("{}#{zone}", record.id))
+        .collect();
+    let mut groups_changed = false;
+    if let Some(index) = config.lights.iter().position(|item| item.id == record.id) {
+        let whole = config.lights.remove(index);
+        let names = [Some(&whole.id), Some(&whole.name), whole.alias.as_ref()];
+        for group in &mut config.groups {
+            let before = group.members.len();
+            group
+                .members
+                .retain(|member| !names.contains(&Some(member)));
+            if group.members.len() < before {
+                group.members.extend(ids.iter().cloned());
+                groups_changed = true;
+            }
+        }
+    }
+    for (zone, id) in (0..zones).zip(ids) {
+        let fresh = LightRecord {
+            id,
+            alias: None,
+            name: format!("{} #{zone}", record.name),
+            zone: Some(zone),
+            ..record.clone()
+        };
+        match config.lights.iter_mut().find(|item| item.id == fresh.id) {
+            Some(existing) => {
+                *existing = LightRecord {
+                    alias: existing.alias.take(),
+                    enabled: existing.enabled,
+                    schedule_paused: existing.schedule_paused,
+                    schedule_skip_next: existing.schedule_skip_next,
+                    soft_start: existing.soft_start,
+                    icon: existing.icon.take(),
+                    room: existing.room.take(),
+                    notes: existing.notes.take(),
+                    favorite: existing.favorite,
+                    brightness_limits: existing.brightness_limits,
+                    sort_index: existing.sort_index,
+                    ..fresh
+                }
+            }
+            None => config.lights.push(fresh),
+        }
+    }
+    // Zones the light no longer reports.
+    config
+        .lights
+        .retain(|item| item.device_id() != record.id || item.zone.is_some_and(|z| z < zones));
+    groups_changed
+}
+
+/// Persist a light found by WiZ or LIFX discovery, keeping user settings of a known one.
+fn upsert_discovered(config: &mut Config, light: DiscoveredLight) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let existing = config.lights.iter_mut().find(|item| item.id == light.id);
+    match existing {
+        Some(record) => {
+            record.hostname = light.ip.clone();
+            record.port = light.port;
+            record.addresses = vec![light.ip];
+            record.last_seen_unix = now;
+            record.backend = light.backend;
+            record.capabilities = light.capabilities;
+            record.kelvin_range = light.kelvin_range;
+            if light.accessory_info.is_some() {
+                record.accessory_info = light.accessory_info;
+            }
+        }
+        None => config.lights.push(LightRecord {
+            id: light.id,
+            alias: None,
+            name: light.name,
+            hostname: light.ip.clone(),
+            port: light.port,
+            addresses: vec![light.ip],
+            last_seen_unix: now,
+            enabled: false,
+            accessory_info: light.accessory_info,
+            schedule_paused: false,
+            schedule_skip_next: false,
+            backend: light.backend,
+            capabilities: light.capabilities,
+            kelvin_range: light.kelvin_range,
+            soft_start: false,
+            icon: None,
+            room: None,
+            notes: None,
+            favorite: false,
+            brightness_limits: None,
+            sort_index: None,
+            zone: None,
+        }),
+    }
+}
+
+/// Template for a fresh `config.toml`. Keep `version` in sync with `CONFIG_VERSION`.
+const CONFIG_TEMPLATE: &str = r#"# LimeLight keylightd configuration.
+#
+# This file is meant to be edited by hand. keylightd only rewrites the
+# sections it manages through the API (groups, schedules) and leaves the
+# rest, including comments, alone. Discovered lights live in state.json.
+#
+# Groups of lights, by id, name, or alias:
+#
+# [[groups]]
+# name = "office"
+# members = ["left", "right"]
+#
+# Recurring actions at a local time (days are optional):
+#
+# [[schedules]]
+# name = "evening"
+# at = "22:30"
+# days = ["mon", "tue", "wed", "thu", "fri"]
+# target = { group = "office" }
+# update = { on = 0 }
+#
+# A schedule with ramp_minutes fades the lights in from off over that many
+# minutes (1-120), like a sunrise:
+#
+# [[schedules]]
+# name = "wake"
+# at = "06:45"
+# target = { group = "bedroom" }
+# update = { brightness = 80, kelvin = 5000 }
+# ramp_minutes = 30
+#
+# Per-backend request limits, so large group or scene updates don't flood a
+# device. Defaults: elgato allows 8 requests at once with no pacing; wiz
+# allows 4 at 20 ms apart and lifx 4 at 50 ms apart. On top of that, commands
+# to any one light are at least device_interval_ms apart (50, or 100 for custom
+# lights); updates arriving in between are merged into the next command.
+#
+# [backends.elgato]
+# max_concurrent = 8
+# min_interval_ms = 0
+# device_interval_ms = 50
+#
+# mDNS service types browsed for Elgato-compatible lights. Add the type a
+# clone announces itself under if it isn't _elg._tcp; lights that don't
+# announce at all can be found with `discover --subnet 192.168.1.0/24`.
+#
+# [discovery]
+# services = ["_elg._tcp.local."]
+#
+# HTTP lights without a built-in backend (Tasmota, ESPHome, DIY), driven by URL
+# templates. {host} must be a LAN IP; placeholders are {brightness} (0-100),
+# {brightness_255}, {kelvin} and {mired}. Set method = "POST" for ESPHome.
+#
+# [[custom_lights]]
+# id = "desk"
+# name = "Desk panel"
+# host = "192.168.1.40"
+# on_url = "http://{host}/cm?cmnd=Power%20On"
+# off_url = "http://{host}/cm?cmnd=Power%20Off"
+# brightness_url = "http://{host}/cm?cmnd=Dimmer%20{brightness}"
+# temperature_url = "http://{host}/cm?cmnd=CT%20{mired}"
+#
+# OBS (obs-websocket 5, Tools > WebSocket Server Settings): apply a snapshot
+# while recording or streaming and restore the previous state afterwards.
+# Streaming wins when both are active. Read when `serve` starts.
+#
+# [obs]
+# host = "127.0.0.1"
+# port = 4455
+# password = "from OBS"
+# scenes = { recording = "studio", streaming = "studio" }
+#
+# Call detection: while a conferencing app has a PipeWire capture stream
+# running, apply a snapshot, and restore the previous state after the call.
+# Needs `pw-dump`. Without rules, Zoom and Teams count on any capture and
+# Chrome, Chromium and Firefox when they use the camera.
+#
+# [conferencing]
+# scene = "call"
+# poll_secs = 5
+#
+# [[conferencing.rules]]
+# app = "zoom"         # matches the app name or binary, ignoring case
+# media = "any"        # "any", "audio" or "video"
+# scene = "zoom-call"  # optional; defaults to the scene above
+#
+# Auto brightness: read the ambient light sensor (iio, as on most laptops)
+# and dim the lights as the room gets brighter. Readings are averaged so a
+# passing shadow doesn't move them.
+#
+# [auto_brightness]
+# target = { group = "desk" }  # or "all", or { light = "<id>" }
+# dark_brightness = 70         # at or below dark_lux
+# bright_brightness = 15       # at or above bright_lux
+# dark_lux = 5
+# bright_lux = 1000
+# curve = "log"                # or "linear"
+# smoothing = 0.3              # weight of each new reading, 0-1
+# interval_secs = 5
+# sensor = "/sys/bus/iio/devices/iio:device0"  # found automatically if unset
+#
+# Startup of `serve`: a full discovery (including WiZ and LIFX broadcasts)
+# runs once in the background; mDNS announcements are followed after that.
+# With wait_for_discovery, API requests wait until it has finished.
+#
+# [serve]
+# discover_on_start = true
+# discovery_timeout_secs = 3
+# wait_for_discovery = false
+#
+# Requests to lights: how long to wait, and how often to try again after a
+# timeout, refused connection or 5xx (each retry waits twice as long as the
+# one before). connect_timeout_ms is read when keylightd starts. API clients
+# can lower these for one request with ?timeout_ms= and ?retries=.
+#
+# [device]
+# connect_timeout_ms = 3000
+# timeout_ms = 3000
+# retries = 0
+# retry_backoff_ms = 200
+#
+# Browser dashboards on other origins: by default only the pages keylightd
+# serves itself may use the API from a browser. List the origins (scheme, host
+# and port) of any others, or "*" for every page. Pages on other origins can't
+# read answers, and their changes are refused.
+#
+# [cors]
+# allowed_origins = ["http://localhost:3000"]
+# max_age_secs = 600
+#
+# Presets: named brightness/temperature settings, shown as chips on the tray's
+# cards and applied with `keylightd set --preset <name>`. Without any, Video
+# (70%, 5600K), Warm (30%, 3200K) and Bright (100%, 4500K) are used; set
+# `presets = []` to have none.
+#
+# [[presets]]
+# name = "Video"
+# brightness = 70
+# kelvin = 5600
+#
+# Calibration: different models look different at the same setting. For each
+# listed light (by id, name or alias) a requested kelvin is multiplied by
+# kelvin_scale and kelvin_offset is added before it's sent, so a group update
+# looks the same on every light. With brightness_mode = "lumens", brightness
+# is a share of the dimmest lumens-mode light's maximum output instead of the
+# light's own; max_lumens is known for Key Light (2800), Key Light Air (1400),
+# Key Light Mini (800) and Ring Light (2500), so set it for other models.
+# States are reported back in the requested terms. Explicit mired values are
+# sent unchanged.
+#
+# [[calibration]]
+# light = "ring"
+# kelvin_offset = -200
+# kelvin_scale = 1.0
+# brightness_mode = "lumens"  # or "percent" (the default)
+# max_lumens = 2500
+#
+# Profiles: one activity at a time, picked in the tray or through
+# `POST /v1/profiles/active`. Becoming active applies the profile's scene;
+# automations listed in `off` ("schedules", "obs", "conferencing",
+# "auto_brightness") hold off while it is. Without a hand pick, the first
+# profile with a matching rule takes over when it starts matching. A rule
+# checks a time window (which may run past midnight), weekdays and whether
+# some app is using a camera (needs `pw-dump`); unset parts always match.
+#
+# [[profiles]]
+# name = "work"
+# scene = "desk"
+# off = ["auto_brightness"]
+#
+# [[profiles.rules]]
+# between = ["09:00", "17:00"]
+# days = ["mon", "tue", "wed", "thu", "fri"]
+#
+# [[profiles]]
+# name = "gaming"
+# scene = "dim"
+# off = ["schedules", "conferencing"]
+
+version = 2
+"#;
+
+/// Hand-editable part of `Config`, stored as TOML. `[obs]`, `[conferencing]`,
+/// `[auto_brightness]`, `[serve]`, `[discovery]`, `[device]`, `[cors]`, `[[presets]]`,
+/// `[[calibration]]` and `[[profiles]]` are left out: nothing changes them, so saving never
+/// rewrites them with defaults filled in.
+#[derive(Serialize)]
+struct ConfigFile<'a> {
+    version: u32,
+    groups: &'a [Group],
+    schedules: &'a [Schedule],
+    #[serde(skip_serializing_if = "is_empty_map")]
+    backends: &'a BTreeMap<String, BackendLimits>,
+    #[serde(skip_serializing_if = "is_empty_slice")]
+    custom_lights: &'a [custom::CustomLight],
+}
+
+fn is_empty_map<K, V>(map: &&BTreeMap<K, V>) -> bool {
+    map.is_empty()
+}
+
+fn is_empty_slice<T>(slice: &&[T]) -> bool {
+    slice.is_empty()
+}
+
+/// Daemon-managed part of `Config`, stored as JSON.
+#[derive(Serialize)]
+struct StateFile<'a> {
+    version: u32,
+    lights: &'a [LightRecord],
+}
+
+fn xdg_base_dir(var: &str, home_default: &str) -> Result<PathBuf, KeylightError> {
+    store::xdg_base_dir(var, home_default).map_err(|err| KeylightError::Other(err.to_string()))
+}
+
+fn config_dir() -> Result<PathBuf, KeylightError> {
+    Ok(xdg_base_dir("XDG_CONFIG_HOME", ".config")?.join(store::APP_DIR))
+}
+
+fn state_dir() -> Result<PathBuf, KeylightError> {
+    Ok(xdg_base_dir("XDG_STATE_HOME", ".local/state")?.join(store::APP_DIR))
+}
+
+fn cache_dir() -> Result<PathBuf, KeylightError> {
+    Ok(xdg_base_dir("XDG_CACHE_HOME", ".cache")?.join(store::APP_DIR))
+}
+
+fn config_path() -> Result<PathBuf, KeylightError> {
+    Ok(config_dir()?.join("config.toml"))
+}
+
+fn state_path() -> Result<PathBuf, KeylightError> {
+    Ok(state_dir()?.join("state.json"))
+}
+
+fn accessory_cache_path() -> Result<PathBuf, KeylightError> {
+    Ok(cache_dir()?.join("accessory-info.json"))
+}
+
+/// Single-file config used before the TOML/state split.
+fn config_json_path() -> Result<PathBuf, KeylightError> {
+    Ok(config_dir()?.join("config.json"))
+}
 
-    Ok(base.join("limelight-keylight").join("config.json"))
+/// Earlier builds kept `state.json` next to `config.toml`; move it under the state dir once.
+fn migrate_state_location() -> Result<(), KeylightError> {
+    let state = state_path()?;
+    let old = config_dir()?.join("state.json");
+    if state.exists() || !old.exists() {
+        return Ok(());
+    }
+    let moved = || -> std::io::Result<()> {
+        if let Some(parent) = state.parent() {
+            create_private_dir(parent)?;
+        }
+        if fs::rename(&old, &state).is_err() {
+            // Different filesystems: fall back to copy + remove.
+            fs::copy(&old, &state)?;
+            fs::remove_file(&old)?;
+        }
+        Ok(())
+    };
+    moved().map_err(|err| KeylightError::config(&old, err))?;
+    log_line!("moved {} to {}", old.display(), state.display());
+    Ok(())
 }
 
-fn load_config() -> Result<Config, Box<dyn Error>> {
+fn load_config() -> Result<Config, KeylightError> {
+    migrate_state_location()?;
     let path = config_path()?;
-    if path.exists() {
-        let bytes = fs::read(&path)?;
-        return Ok(serde_json::from_slice(&bytes)?);
+    let state = state_path()?;
+    if path.exists() || state.exists() {
+        let (config, from_version) = read_config(&path, &state)?;
+        if from_version >= CONFIG_VERSION {
+            return Ok(config);
+        }
+        let _editing = lock_config_edits();
+        // Another thread may have upgraded the files while this one waited.
+        let (config, from_version) = read_config(&path, &state)?;
+        if from_version < CONFIG_VERSION {
+            // Keep the originals next to the upgraded files in case a migration loses something.
+            for file in [&path, &state] {
+                if let Some(bytes) = read_optional(file)? {
+                    let backup = config_backup_path(file, from_version);
+                    write_private_file(&backup, &bytes)
+                        .map_err(|err| KeylightError::config(&backup, err))?;
+                }
+            }
+            save_config(&config)?;
+            save_state(&config)?;
+        }
+        return Ok(config);
+    }
+
+    let _editing = lock_config_edits();
+    if path.exists() || state.exists() {
+        // Written by another thread while this one waited.
+        return load_config();
+    }
+
+    // Split the pre-TOML single-file config into config.toml + state.json.
+    let json_path = config_json_path()?;
+    if json_path.exists() {
+        let (config, _) = read_config_json(&json_path)?;
+        save_config(&config)?;
+        save_state(&config)?;
+        fs::rename(&json_path, json_path.with_extension("json.bak"))
+            .map_err(|err| KeylightError::config(&json_path, err))?;
+        return Ok(config);
     }
 
     // Backward-compat: migrate old config path (limekit-keylight) to the new LimeLight location.
     let old_path = config_path_legacy()?;
     if old_path.exists() {
-        let bytes = fs::read(&old_path)?;
-        let config: Config = serde_json::from_slice(&bytes)?;
+        let (config, _) = read_config_json(&old_path)?;
         // Best-effort write; if it fails we can still operate off the old file.
-        let _ = save_config(&config);
+        let _ = save_config(&config).and_then(|_| save_state(&config));
         return Ok(config);
     }
 
     Ok(Config::default())
 }
 
-fn save_config(config: &Config) -> Result<(), Box<dyn Error>> {
+/// `config.toml` and `state.json` as one config, and the version they were saved at.
+fn read_config(
+    path: &std::path::Path,
+    state: &std::path::Path,
+) -> Result<(Config, u32), KeylightError> {
+    let mut value = store::read_merged(path, state)?;
+    if !path.exists() {
+        value["version"] = CONFIG_VERSION.into();
+    }
+    let (mut config, from_version) =
+        parse_config_value(value).map_err(|err| KeylightError::config(path, err))?;
+    limits::configure(&config.backends);
+    device::configure(&config.device);
+    cors::configure(&config.cors);
+    custom::merge_into(&mut config);
+    sort_lights(&mut config.lights);
+    let mut cache = load_accessory_cache();
+    for light in &mut config.lights {
+        if light.accessory_info.is_none() {
+            light.accessory_info = cache.remove(&light.id);
+        }
+    }
+    Ok((config, from_version))
+}
+
+fn read_optional(path: &std::path::Path) -> Result<Option<Vec<u8>>, KeylightError> {
+    Ok(store::read_optional(path)?)
+}
+
+/// A config file from before the TOML split, upgraded to `CONFIG_VERSION`.
+fn read_config_json(path: &std::path::Path) -> Result<(Config, u32), KeylightError> {
+    let bytes = fs::read(path).map_err(|err| KeylightError::config(path, err))?;
+    parse_config(&bytes).map_err(|err| KeylightError::config(path, err))
+}
+
+/// Write groups and schedules to `config.toml`. Only sections whose contents
+/// changed are replaced, so hand-written comments and formatting elsewhere survive.
+fn save_config(config: &Config) -> Result<(), KeylightError> {
     let path = config_path()?;
+    let in_file = |err: &dyn std::fmt::Display| KeylightError::config(&path, err);
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+        create_private_dir(parent).map_err(|err| in_file(&err))?;
+    }
+    let text = match read_optional(&path)? {
+        Some(bytes) => String::from_utf8(bytes).map_err(|err| in_file(&err))?,
+        None => CONFIG_TEMPLATE.to_string(),
+    };
+    let mut doc: toml_edit::DocumentMut = text.parse().map_err(|err| in_file(&err))?;
+    let current: Value = toml_edit::de::from_str(&text).map_err(|err| in_file(&err))?;
+
+    let file = ConfigFile {
+        version: CONFIG_VERSION,
+        groups: &config.groups,
+        schedules: &config.schedules,
+        backends: &config.backends,
+        custom_lights: &config.custom_lights,
+    };
+    let wanted = serde_json::to_value(&file).map_err(|err| in_file(&err))?;
+    let fresh = toml_edit::ser::to_document(&file).map_err(|err| in_file(&err))?;
+    for (key, item) in fresh.iter() {
+        if current.get(key) == wanted.get(key) {
+            continue;
+        }
+        doc[key] = item
+            .clone()
+            .into_array_of_tables()
+            .map(toml_edit::Item::ArrayOfTables)
+            .unwrap_or_else(|item| item);
     }
-    let bytes = serde_json::to_vec_pretty(config)?;
-    fs::write(path, bytes)?;
+    write_private_file(&path, doc.to_string().as_bytes()).map_err(|err| in_file(&err))?;
     Ok(())
 }
 
-fn config_path_legacy() -> Result<PathBuf, Box<dyn Error>> {
-    let base = if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
-        PathBuf::from(xdg)
-    } else if let Ok(home) = std::env::var("HOME") {
-        PathBuf::from(home).join(".config")
-    } else {
-        return Err("Unable to determine config directory".into());
-    };
+/// Write discovered lights to `state.json`, with accessory-info split out into the
+/// cache dir. Rewritten wholesale; not meant for hand edits.
+fn save_state(config: &Config) -> Result<(), KeylightError> {
+    let path = state_path()?;
+    let in_file = |err: &dyn std::fmt::Display| KeylightError::config(&path, err);
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent).map_err(|err| in_file(&err))?;
+    }
+    let lights: Vec<LightRecord> = config
+        .lights
+        .iter()
+        .map(|light| LightRecord {
+            accessory_info: None,
+            ..light.clone()
+        })
+        .collect();
+    let bytes = serde_json::to_vec_pretty(&StateFile {
+        version: CONFIG_VERSION,
+        lights: &lights,
+    })
+    .map_err(|err| in_file(&err))?;
+    write_private_file(&path, &bytes).map_err(|err| in_file(&err))?;
+    save_accessory_cache(&config.lights)
+}
+
+/// Held for reading while a file is written, so `shut_down` can wait for writes in
+/// progress and keep new ones from starting.
+static FILE_WRITES: RwLock<()> = RwLock::new(());
+
+/// `files::write_private_file`, unless the daemon is shutting down.
+fn write_private_file(path: &std::path::Path, bytes: &[u8]) -> std::io::Result<()> {
+    let _writing = FILE_WRITES.read().unwrap_or_else(PoisonError::into_inner);
+    files::write_private_file(path, bytes)
+}
+
+/// Warn about config/state files or directories that other users can read.
+fn warn_on_insecure_permissions() {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let paths = [config_dir(), config_path(), state_dir(), state_path()];
+        for path in paths.into_iter().flatten() {
+            let Ok(meta) = fs::metadata(&path) else {
+                continue;
+            };
+            if meta.permissions().mode() & 0o007 != 0 {
+                let fix = if meta.is_dir() { "700" } else { "600" };
+                log_line!(
+                    "warning: {} is accessible by other users; run `chmod {fix} {}`",
+                    path.display(),
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+/// Cached accessory-info keyed by light id. Missing or unreadable cache is not an error.
+fn load_accessory_cache() -> HashMap<String, Value> {
+    accessory_cache_path()
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save_accessory_cache(lights: &[LightRecord]) -> Result<(), KeylightError> {
+    let path = accessory_cache_path()?;
+    let in_file = |err: &dyn std::fmt::Display| KeylightError::config(&path, err);
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent).map_err(|err| in_file(&err))?;
+    }
+    let cache: HashMap<&str, &Value> = lights
+        .iter()
+        .filter_map(|light| Some((light.id.as_str(), light.accessory_info.as_ref()?)))
+        .collect();
+    let bytes = serde_json::to_vec_pretty(&cache).map_err(|err| in_file(&err))?;
+    write_private_file(&path, &bytes).map_err(|err| in_file(&err))?;
+    Ok(())
+}
+
+/// Parse raw config bytes, upgrading older schemas to `CONFIG_VERSION`.
+/// Returns the config along with the version it was stored as; errors are the
+/// reason alone, for the caller to put the file name to.
+fn parse_config(bytes: &[u8]) -> Result<(Config, u32), String> {
+    parse_config_value(serde_json::from_slice(bytes).map_err(|err| err.to_string())?)
+}
+
+fn parse_config_value(value: Value) -> Result<(Config, u32), String> {
+    let from_version = config_version(&value);
+    let value = migrate_config(value)?;
+    let config = serde_json::from_value(value).map_err(|err| err.to_string())?;
+    Ok((config, from_version))
+}
+
+fn config_version(value: &Value) -> u32 {
+    value
+        .get("version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or(0)
+}
+
+fn migrate_config(mut value: Value) -> Result<Value, String> {
+    let mut version = config_version(&value);
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "Config schema version {version} is newer than this keylightd supports ({CONFIG_VERSION}); refusing to overwrite it"
+        ));
+    }
+    while version < CONFIG_VERSION {
+        value = match version {
+            0 => migrate_config_v0_to_v1(value),
+            1 => migrate_config_v1_to_v2(value),
+            _ => unreachable!("missing config migration from version {version}"),
+        };
+        version += 1;
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("version".into(), version.into());
+        }
+    }
+    Ok(value)
+}
+
+/// v0 → v1: files written before the schema was versioned. Older builds could omit `groups`.
+fn migrate_config_v0_to_v1(mut value: Value) -> Value {
+    if let Some(obj) = value.as_object_mut() {
+        obj.entry("lights")
+            .or_insert_with(|| Value::Array(Vec::new()));
+        obj.entry("groups")
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+    value
+}
+
+/// v1 → v2: adds recurring `schedules`.
+fn migrate_config_v1_to_v2(mut value: Value) -> Value {
+    if let Some(obj) = value.as_object_mut() {
+        obj.entry("schedules")
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+    value
+}
+
+fn config_backup_path(path: &std::path::Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{version}.bak"));
+    path.with_file_name(name)
+}
 
-    Ok(base.join("limekit-keylight").join("config.json"))
+fn config_path_legacy() -> Result<PathBuf, KeylightError> {
+    Ok(xdg_base_dir("XDG_CONFIG_HOME", ".config")?
+        .join("limekit-keylight")
+        .join("config.json"))
 }
 
 #[cfg(test)]
@@ -1201,16 +4915,596 @@ mod tests {
     use super::*;
 
     #[test]
-    fn kelvin_to_mired_clamps_and_rounds() {
-        assert_eq!(kelvin_to_mired(7000), 143);
-        assert_eq!(kelvin_to_mired(2900), 344);
-        assert_eq!(kelvin_to_mired(1000), 344);
+    fn updates_clamp_to_the_light_range() {
+        let range = KelvinRange {
+            min: 3000,
+            max: 6500,
+        };
+        let request = UpdateRequest {
+            on: None,
+            brightness: None,
+            kelvin: Some(7000),
+            mired: None,
+            hue: None,
+            saturation: None,
+        };
+        assert_eq!(
+            request.to_light_update_in(range).temperature,
+            Some(range.kelvin_to_mired(6500))
+        );
+    }
+
+    #[test]
+    fn update_bounds_are_inclusive() {
+        for body in [
+            "{}",
+            r#"{"on": 0, "brightness": 0, "hue": 0, "saturation": 0}"#,
+            r#"{"on": 1, "brightness": 100, "hue": 360, "saturation": 100}"#,
+            r#"{"kelvin": 1000, "mired": 50}"#,
+            r#"{"kelvin": 20000, "mired": 1000}"#,
+        ] {
+            assert!(UpdateRequest::parse(body).is_ok(), "{body}");
+        }
+        for (body, problem) in [
+            (r#"{"on": 2}"#, "on must be 0 or 1"),
+            (r#"{"brightness": 101}"#, "brightness must be 0-100"),
+            (r#"{"kelvin": 999}"#, "kelvin must be 1000-20000"),
+            (r#"{"kelvin": 20001}"#, "kelvin must be 1000-20000"),
+            (r#"{"mired": 49}"#, "mired must be 50-1000"),
+            (r#"{"mired": 1001}"#, "mired must be 50-1000"),
+            (r#"{"hue": -0.5}"#, "hue must be 0-360"),
+            (r#"{"saturation": 100.5}"#, "saturation must be 0-100"),
+        ] {
+            assert_eq!(UpdateRequest::parse(body).unwrap_err(), problem);
+        }
+        let err = UpdateRequest::parse(r#"{"brightness": "high"}"#).unwrap_err();
+        assert!(
+            err.starts_with("Invalid JSON body for update request: "),
+            "{err}"
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn updates_in_range_are_sent_in_the_light_range(
+            on in 0u8..=1,
+            brightness in 0u8..=100,
+            kelvin in 1000u16..=20_000,
+            mired in proptest::option::of(50u16..=1000),
+            hue in 0.0..=360.0f64,
+            saturation in 0.0..=100.0f64,
+        ) {
+            let body = serde_json::json!({
+                "on": on, "brightness": brightness, "kelvin": kelvin, "mired": mired,
+                "hue": hue, "saturation": saturation,
+            });
+            let update = UpdateRequest::parse(&body.to_string()).unwrap();
+            let range = KelvinRange {
+                min: 2700,
+                max: 6500,
+            };
+            let sent = update.to_light_update_in(range);
+            let coolest = range.clamp_mired(0);
+            let warmest = range.clamp_mired(u16::MAX);
+            let temperature = sent.temperature.unwrap();
+            proptest::prop_assert!((coolest..=warmest).contains(&temperature));
+            proptest::prop_assert_eq!((sent.on, sent.brightness), (Some(on), Some(brightness)));
+            // serde_json's default float parsing can be off in the last bit.
+            let close = |sent: Option<f64>, asked: f64| sent.is_some_and(|v| (v - asked).abs() < 1e-9);
+            proptest::prop_assert!(close(sent.hue, hue) && close(sent.saturation, saturation));
+        }
+    }
+
+    #[test]
+    fn lights_persisted_before_backends_are_elgato_key_lights() {
+        let light: LightRecord = serde_json::from_value(serde_json::json!({
+            "id": "left",
+            "alias": null,
+            "name": "Key Light",
+            "hostname": "left.local",
+            "port": 9123,
+            "addresses": ["192.168.1.20"],
+            "last_seen_unix": 0
+        }))
+        .unwrap();
+        assert_eq!(light.backend, Backend::Elgato);
+        assert_eq!(light.capabilities, Capabilities::default());
+        assert!(!light.capabilities.color);
+    }
+
+    #[test]
+    fn resolved_group_explains_skipped_members() {
+        let light = |id: &str, enabled: bool, addresses: &[&str]| -> LightRecord {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "alias": null,
+                "name": id,
+                "hostname": format!("{id}.local"),
+                "port": 9123,
+                "addresses": addresses,
+                "last_seen_unix": 0,
+                "enabled": enabled
+            }))
+            .unwrap()
+        };
+        let config = Config {
+            lights: vec![
+                light("left", true, &["192.168.1.20"]),
+                light("right", false, &["192.168.1.21"]),
+                light("back", true, &[]),
+            ],
+            groups: vec![Group {
+                name: "desk".into(),
+                members: ["left", "right", "back", "gone", "left"]
+                    .map(String::from)
+                    .into(),
+                stagger_ms: None,
+            }],
+            ..Default::default()
+        };
+        let resolved = resolve_group(&config, "desk").unwrap();
+        assert_eq!(resolved.targets, vec!["192.168.1.20".to_string()]);
+        let skipped: Vec<_> = resolved.members.iter().map(|m| m.skipped).collect();
+        assert_eq!(
+            skipped,
+            vec![
+                None,
+                Some("light is disabled"),
+                Some("light has no known address"),
+                Some("no persisted light has this id, name or alias"),
+                None,
+            ]
+        );
+        assert!(resolved.members[1].light.is_some());
+        assert!(resolve_group(&config, "missing").is_err());
+        assert_eq!(
+            explain_members(&config, &["left".into(), "right".into()]),
+            vec![
+                "member=left, id=left, ip=192.168.1.20".to_string(),
+                "member=right, skipped=light is disabled".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn zones_are_stored_and_addressed_as_lights() {
+        let ring: LightRecord = serde_json::from_value(serde_json::json!({
+            "id": "Ring._elg._tcp.local.",
+            "alias": "ring",
+            "name": "Ring._elg._tcp.local.",
+            "hostname": "ring.local",
+            "port": 9123,
+            "addresses": ["192.168.1.30"],
+            "last_seen_unix": 0,
+            "soft_start": true
+        }))
+        .unwrap();
+        let mut config = Config {
+            lights: vec![ring.clone()],
+            groups: vec![Group {
+                name: "desk".into(),
+                members: vec!["ring".into(), "left".into()],
+                stagger_ms: None,
+            }],
+            ..Default::default()
+        };
+        assert!(store_zones(&mut config, ring.clone(), 2));
+        let ids: Vec<_> = config
+            .lights
+            .iter()
+            .map(|light| light.id.as_str())
+            .collect();
+        assert_eq!(ids, ["Ring._elg._tcp.local.#0", "Ring._elg._tcp.local.#1"]);
+        assert_eq!(
+            config.groups[0].members,
+            ["left", "Ring._elg._tcp.local.#0", "Ring._elg._tcp.local.#1"]
+        );
+        let outer = &config.lights[1];
+        assert_eq!((outer.alias.as_deref(), outer.soft_start), (None, true));
+        assert_eq!(outer.device_id(), "Ring._elg._tcp.local.");
+        assert_eq!(outer.address().as_deref(), Some("192.168.1.30#1"));
+        assert_eq!(
+            light_for_ip(&config, "192.168.1.30#1").map(|light| light.id.as_str()),
+            Some("Ring._elg._tcp.local.#1")
+        );
+
+        // Settings made on a zone survive rediscovery.
+        config.lights[1].alias = Some("outer".into());
+        assert!(!store_zones(&mut config, ring, 2));
+        assert_eq!(config.lights[1].alias.as_deref(), Some("outer"));
+        assert_eq!(config.lights.len(), 2);
+    }
+
+    #[test]
+    fn json_states_are_named_by_persisted_id() {
+        let light: LightRecord = serde_json::from_value(serde_json::json!({
+            "id": "left",
+            "alias": null,
+            "name": "left",
+            "hostname": "left.local",
+            "port": 9123,
+            "addresses": ["192.168.1.20"],
+            "last_seen_unix": 0
+        }))
+        .unwrap();
+        let config = Config {
+            lights: vec![light],
+            ..Default::default()
+        };
+        let state = LightState {
+            on: 1,
+            brightness: 40,
+            temperature: 200,
+            hue: None,
+            saturation: None,
+        };
+        assert_eq!(
+            serde_json::to_value(LightStateResponse::for_ip(&config, "192.168.1.20", &state))
+                .unwrap(),
+            serde_json::json!({"id": "left", "on": true, "brightness": 40, "kelvin": 5000})
+        );
+        let manual = LightStateResponse::for_ip(&config, "192.168.1.99", &state);
+        assert_eq!(manual.id, "192.168.1.99");
+    }
+
+    #[test]
+    fn group_members_are_removed_by_any_name_and_renames_follow_schedules() {
+        let mut config: Config = serde_json::from_value(serde_json::json!({
+            "version": CONFIG_VERSION,
+            "lights": [{
+                "id": "Key Light A._elg._tcp.local.",
+                "alias": "left",
+                "name": "Key Light A",
+                "hostname": "a.local",
+                "port": 9123,
+                "addresses": ["192.168.1.20"],
+                "last_seen_unix": 0
+            }],
+            "groups": [{"name": "desk", "members": ["Key Light A._elg._tcp.local.", "right"]}],
+            "schedules": [{
+                "name": "evening",
+                "at": "22:30",
+                "target": {"group": "desk"},
+                "update": {"on": 0}
+            }]
+        }))
+        .unwrap();
+
+        let group = remove_members_from(&mut config, "desk", &["left".into()]).unwrap();
+        assert_eq!(group.members, vec!["right".to_string()]);
+        assert!(remove_members_from(&mut config, "desk", &["left".into()]).is_err());
+        assert!(remove_members_from(&mut config, "missing", &["right".into()]).is_err());
+
+        assert_eq!(rename_group_in(&mut config, "desk", "office").unwrap(), 1);
+        assert_eq!(config.groups[0].name, "office");
+        assert_eq!(
+            config.schedules[0].target,
+            schedule::ScheduleTarget::Group("office".into())
+        );
+        config.groups.push(Group {
+            name: "shelf".into(),
+            members: Vec::new(),
+            stagger_ms: None,
+        });
+        assert!(rename_group_in(&mut config, "shelf", "office").is_err());
+        assert!(rename_group_in(&mut config, "shelf", "Office").is_err());
+        assert!(rename_group_in(&mut config, "desk", "studio").is_err());
+        assert_eq!(rename_group_in(&mut config, "office", "Office").unwrap(), 1);
+    }
+
+    #[test]
+    fn aliases_must_not_name_another_light() {
+        let light = |id: &str, alias: Option<&str>| -> LightRecord {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "alias": alias,
+                "name": "Elgato Key Light",
+                "hostname": format!("{id}.local"),
+                "port": 9123,
+                "addresses": [],
+                "last_seen_unix": 0
+            }))
+            .unwrap()
+        };
+        let config = Config {
+            lights: vec![light("a", Some("left")), light("b", None)],
+            ..Config::default()
+        };
+        assert!(check_alias(&config, "a", "left").is_ok());
+        for alias in ["left", "a"] {
+            assert!(matches!(
+                check_alias(&config, "b", alias),
+                Err(KeylightError::AliasTaken { id, .. }) if id == "a"
+            ));
+        }
+        assert!(check_alias(&config, "b", "right").is_ok());
+
+        let resolution = resolve_ident(&config, "Elgato Key Light");
+        assert!(resolution.ambiguous);
+        assert_eq!(resolution.matches.len(), 2);
+        let resolution = resolve_ident(&config, "left");
+        assert!(!resolution.ambiguous);
+        assert_eq!(resolution.matches[0].matched_by, "alias");
+        assert!(resolve_ident(&config, "right").matches.is_empty());
+    }
+
+    #[test]
+    fn groups_need_a_unique_name_and_known_members() {
+        let mut config: Config = serde_json::from_value(serde_json::json!({
+            "version": CONFIG_VERSION,
+            "lights": [{
+                "id": "Key Light A._elg._tcp.local.",
+                "alias": "left",
+                "name": "Key Light A",
+                "hostname": "a.local",
+                "port": 9123,
+                "addresses": ["192.168.1.20"],
+                "last_seen_unix": 0
+            }],
+            "groups": [{"name": "Office", "members": ["left"]}]
+        }))
+        .unwrap();
+        let mut store = |name: &str, idents: &[&str], stagger_ms| {
+            let members = idents.iter().map(|ident| ident.to_string()).collect();
+            store_group(&mut config, name.into(), members, stagger_ms)
+        };
+
+        assert!(store("  ", &["left"], None).is_err());
+        assert!(store("desk", &[" "], None).is_err());
+        let err = store("desk", &["left", "ring"], None).unwrap_err();
+        assert!(err.to_string().contains("'ring'"));
+        let err = store("office", &["left"], None).unwrap_err();
+        assert!(err.to_string().contains("'Office'"));
+
+        assert_eq!(
+            store(" desk ", &["Key Light A"], None).unwrap().name,
+            "desk"
+        );
+        assert!(store("Office", &["left"], Some(5001)).is_err());
+        store("Office", &["left", "left"], Some(200)).unwrap();
+        assert_eq!(config.groups.len(), 2);
+        assert_eq!(config.groups[0].members, ["left"]);
+        assert_eq!(config.groups[0].stagger_ms, Some(200));
+    }
+
+    #[test]
+    fn partial_set_failures_exit_differently() {
+        assert_eq!(set_exit_code(1, 1), 1);
+        assert_eq!(set_exit_code(3, 3), 1);
+        assert_eq!(set_exit_code(3, 1), 3);
+    }
+
+    #[test]
+    fn only_power_on_is_staggered() {
+        let config = Config {
+            groups: vec![Group {
+                name: "wall".into(),
+                members: vec!["left".into(), "right".into()],
+                stagger_ms: Some(250),
+            }],
+            ..Default::default()
+        };
+        let request = |on| UpdateRequest {
+            on,
+            brightness: Some(40),
+            kelvin: None,
+            mired: None,
+            hue: None,
+            saturation: None,
+        };
+        let stagger = |group, on| power_on_stagger(&config, group, &request(on));
+        assert_eq!(stagger(Some("wall"), Some(1)), Duration::from_millis(250));
+        assert_eq!(stagger(Some("wall"), Some(0)), Duration::ZERO);
+        assert_eq!(stagger(Some("wall"), None), Duration::ZERO);
+        assert_eq!(stagger(None, Some(1)), Duration::ZERO);
+    }
+
+    #[test]
+    fn unversioned_config_is_migrated_to_current() {
+        let (config, from_version) = parse_config(br#"{"lights": []}"#).unwrap();
+        assert_eq!(from_version, 0);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.groups.is_empty());
+    }
+
+    #[test]
+    fn only_config_changes_wait_for_each_other() {
+        assert!(!edits_config(&Method::Get, "/v1/lights"));
+        assert!(!edits_config(&Method::Put, "/v1/all"));
+        assert!(!edits_config(&Method::Put, "/v1/lights/Key%20Light%20A"));
+        assert!(!edits_config(&Method::Put, "/v1/groups/desk"));
+        assert!(!edits_config(&Method::Post, "/v1/lights/left/toggle"));
+        assert!(edits_config(&Method::Put, "/v1/lights/left/alias"));
+        assert!(edits_config(&Method::Put, "/v1/scenes/evening"));
+        assert!(edits_config(&Method::Post, "/v1/lights/refresh"));
+        assert!(edits_config(&Method::Delete, "/v1/groups/desk"));
+    }
+
+    #[test]
+    fn stalled_and_oversized_bodies_are_refused() {
+        use std::io::Write as _;
+
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let send = |content_length: usize, body: &str| {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let head = format!(
+                "PUT /v1/all HTTP/1.1\r\nHost: localhost\r\nContent-Length: {content_length}\r\n\r\n"
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(body.as_bytes()).unwrap();
+            stream
+        };
+
+        static READERS: ThreadSlots = ThreadSlots::new(1);
+        let read = |timeout| read_body_within(server.recv().unwrap(), timeout, &READERS);
+
+        // Over tiny_http's 1 KiB, so the body isn't read before recv() returns.
+        let stalled = send(2048, "{");
+        assert!(read(Duration::from_millis(100)).is_none());
+        // Its reader is still waiting, so there's none left for the next body.
+        let _waiting = send(8, r#"{"on":1}"#);
+        let (request, body) = read(Duration::from_secs(5)).unwrap();
+        assert!(matches!(body, Err(BodyReadError::Busy)));
+        drop(request);
+        // Hanging up ends the stalled read and frees its reader.
+        drop(stalled);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while READERS.busy.load(Ordering::Acquire) > 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let oversized = send(MAX_API_BODY_BYTES + 1, "");
+        let (request, body) = read(Duration::from_secs(5)).unwrap();
+        assert!(matches!(body, Err(BodyReadError::TooLarge)));
+        // Dropping a request reads what's left of its body, so the client goes first.
+        drop(oversized);
+        drop(request);
+
+        let _complete = send(8, r#"{"on":1}"#);
+        let (_, body) = read(Duration::from_secs(5)).unwrap();
+        assert_eq!(body.unwrap(), r#"{"on":1}"#);
+    }
+
+    #[test]
+    fn thread_slots_are_freed_when_their_holder_ends() {
+        static SLOTS: ThreadSlots = ThreadSlots::new(2);
+        let first = SLOTS.claim().unwrap();
+        let _second = SLOTS.claim().unwrap();
+        assert!(SLOTS.claim().is_none());
+        drop(first);
+        assert!(SLOTS.claim().is_some());
+    }
+
+    #[test]
+    fn response_format_from_query_and_accept_encoding() {
+        assert_eq!(ResponseFormat::parse("", None), ResponseFormat::default());
+        let format = ResponseFormat::parse("wait=5&pretty=true", Some("deflate, gzip;q=0.8"));
+        assert!(format.pretty && format.gzip);
+        assert!(ResponseFormat::parse("pretty", None).pretty);
+        assert!(!ResponseFormat::parse("pretty=false", Some("gzip;q=0")).pretty);
+        assert!(!ResponseFormat::parse("", Some("gzip;q=0")).gzip);
+        assert!(!ResponseFormat::parse("", Some("br")).gzip);
+    }
+
+    #[test]
+    fn verify_flag_is_parsed_from_query() {
+        assert_eq!(parse_verify(""), Ok(false));
+        assert_eq!(parse_verify("verify=true"), Ok(true));
+        assert_eq!(parse_verify("wait=5&verify"), Ok(true));
+        assert_eq!(parse_verify("verify=0"), Ok(false));
+        assert!(parse_verify("verify=yes").is_err());
+    }
+
+    #[test]
+    fn newer_config_version_is_rejected() {
+        let raw = format!(r#"{{"version": {}, "lights": []}}"#, CONFIG_VERSION + 1);
+        assert!(parse_config(raw.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn serve_section_fills_in_defaults() {
+        let raw = r#"{"version": 2, "lights": [], "serve": {"wait_for_discovery": true}}"#;
+        let (config, _) = parse_config(raw.as_bytes()).unwrap();
+        assert!(config.serve.discover_on_start);
+        assert!(config.serve.wait_for_discovery);
+        assert_eq!(config.serve.discovery_timeout_secs, 3);
+        let (config, _) = parse_config(br#"{"version": 2, "lights": []}"#).unwrap();
+        assert_eq!(config.serve, ServeConfig::default());
+        assert_eq!(config.device, device::DeviceConfig::default());
+        let raw = r#"{"version": 2, "lights": [], "device": {"retries": 2}}"#;
+        let (config, _) = parse_config(raw.as_bytes()).unwrap();
+        assert_eq!(config.device.retries, 2);
+        assert_eq!(config.device.timeout_ms, 3000);
+    }
+
+    #[test]
+    fn icons_are_presets_or_short_emoji() {
+        let icon = |value: &str| normalize_icon(Some(value.to_string()));
+        assert_eq!(icon(" ring "), Ok(Some("ring".to_string())));
+        assert_eq!(icon("💡"), Ok(Some("💡".to_string())));
+        assert_eq!(icon("👩‍💻"), Ok(Some("👩‍💻".to_string())));
+        assert_eq!(icon(""), Ok(None));
+        assert_eq!(normalize_icon(None), Ok(None));
+        assert!(icon("lamp").is_err());
+        assert!(icon("💡💡💡💡💡💡💡💡💡").is_err());
+    }
+
+    #[test]
+    fn room_and_notes_are_trimmed_and_cleared_when_empty() {
+        let room = |value: &str| meta_text(value.to_string(), MAX_ROOM_CHARS, "Room");
+        assert_eq!(room(" office "), Ok(Some("office".to_string())));
+        assert_eq!(room("  "), Ok(None));
+        assert!(room(&"x".repeat(MAX_ROOM_CHARS + 1)).is_err());
+        let record: LightRecord = serde_json::from_value(serde_json::json!({
+            "id": "left",
+            "alias": null,
+            "name": "left",
+            "hostname": "left.local",
+            "port": 9123,
+            "addresses": [],
+            "last_seen_unix": 0,
+            "location": "studio"
+        }))
+        .unwrap();
+        assert_eq!(record.room.as_deref(), Some("studio"));
+    }
+
+    #[test]
+    fn brightness_limits_fill_in_missing_bounds() {
+        let limits = BrightnessLimits::new(Some(10), None).unwrap();
+        assert_eq!(limits, Some(BrightnessLimits { min: 10, max: 100 }));
+        assert_eq!(BrightnessLimits::new(None, Some(100)), Ok(None));
+        assert!(BrightnessLimits::new(Some(60), Some(40)).is_err());
+        assert!(BrightnessLimits::new(None, Some(120)).is_err());
+    }
+
+    #[test]
+    fn positioned_lights_come_first_in_order() {
+        let light = |id: &str, sort_index: Option<u32>| -> LightRecord {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "alias": null,
+                "name": id,
+                "hostname": format!("{id}.local"),
+                "port": 9123,
+                "addresses": [],
+                "last_seen_unix": 0,
+                "sort_index": sort_index
+            }))
+            .unwrap()
+        };
+        let ids = |lights: &[LightRecord]| -> Vec<String> {
+            lights.iter().map(|light| light.id.clone()).collect()
+        };
+        let mut lights = vec![
+            light("new", None),
+            light("left", Some(1)),
+            light("later", None),
+            light("key", Some(0)),
+        ];
+        sort_lights(&mut lights);
+        assert_eq!(ids(&lights), ["key", "left", "new", "later"]);
+
+        move_item(&mut lights, 3, 0);
+        assert_eq!(ids(&lights), ["later", "key", "left", "new"]);
+        move_item(&mut lights, 0, 10);
+        assert_eq!(ids(&lights), ["key", "left", "new", "later"]);
+    }
+
+    #[test]
+    fn shutdown_is_refused_to_browsers_and_remote_peers() {
+        let local: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let remote: SocketAddr = "192.168.1.20:40000".parse().unwrap();
+        assert!(shutdown_allowed(Some(&local), false));
+        assert!(!shutdown_allowed(Some(&local), true));
+        assert!(!shutdown_allowed(Some(&remote), false));
+        assert!(!shutdown_allowed(None, false));
     }
 
     #[test]
-    fn mired_to_kelvin_clamps_and_rounds() {
-        assert_eq!(mired_to_kelvin(143), 6993);
-        assert_eq!(mired_to_kelvin(344), 2907);
-        assert_eq!(mired_to_kelvin(999), 2907);
+    fn config_template_is_current_version() {
+        let value: Value = toml_edit::de::from_str(CONFIG_TEMPLATE).unwrap();
+        assert_eq!(config_version(&value), CONFIG_VERSION);
     }
 }
@@ -0,0 +1,201 @@
+//! Automation and error notices for `GET /v1/notices`: a call started or ended, a
+//! schedule ran, a light stopped answering. The tray long-polls them and shows each
+//! one as a desktop notification.
+//!
+//! Unlike state events these are only worth seeing when fresh, so a client that
+//! fell behind just gets what's still retained instead of an error.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::events;
+
+const RETAINED_NOTICES: usize = 64;
+
+static LOG: Mutex<NoticeLog> = Mutex::new(NoticeLog::new());
+static POSTED: Condvar = Condvar::new();
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Notice {
+    /// Call detection applied the call's scene.
+    CallStarted {
+        scene: String,
+    },
+    /// The call ended and the lights went back to how they were.
+    CallEnded,
+    ScheduleApplied {
+        name: String,
+    },
+    LightUnreachable {
+        id: String,
+    },
+}
+
+impl Notice {
+    /// `automation` or `error`; clients turn each category on or off.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Notice::CallStarted { .. } | Notice::CallEnded | Notice::ScheduleApplied { .. } => {
+                "automation"
+            }
+            Notice::LightUnreachable { .. } => "error",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PostedNotice {
+    pub revision: u64,
+    pub category: &'static str,
+    pub posted_unix: u64,
+    #[serde(flatten)]
+    pub notice: Notice,
+}
+
+#[derive(Serialize, Debug)]
+pub struct NoticeBatch {
+    /// Pass this as `since` on the next poll.
+    pub revision: u64,
+    pub notices: Vec<PostedNotice>,
+}
+
+struct NoticeLog {
+    revision: u64,
+    recent: VecDeque<PostedNotice>,
+}
+
+impl NoticeLog {
+    const fn new() -> Self {
+        Self {
+            revision: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn post(&mut self, notice: Notice, posted_unix: u64) {
+        self.revision += 1;
+        self.recent.push_back(PostedNotice {
+            revision: self.revision,
+            category: notice.category(),
+            posted_unix,
+            notice,
+        });
+        if self.recent.len() > RETAINED_NOTICES {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Notices after `since`. A `since` from before a daemon restart (newer than
+    /// anything here) counts as having seen nothing.
+    fn since(&self, since: u64) -> Vec<PostedNotice> {
+        let since = if since > self.revision { 0 } else { since };
+        self.recent
+            .iter()
+            .filter(|notice| notice.revision > since)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Record a notice and wake pollers. Only `serve` keeps them.
+pub fn post(notice: Notice) {
+    if !events::enabled() {
+        return;
+    }
+    let posted_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut log = LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    log.post(notice, posted_unix);
+    POSTED.notify_all();
+}
+
+/// Notices after `since`, waiting up to `wait` for the first one. Without `since`
+/// the batch is empty and only carries the revision to start from.
+pub fn wait_since(since: Option<u64>, wait: Duration) -> NoticeBatch {
+    let mut log = LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let Some(since) = since else {
+        return NoticeBatch {
+            revision: log.revision,
+            notices: Vec::new(),
+        };
+    };
+    let deadline = Instant::now() + wait.min(events::MAX_WAIT);
+    loop {
+        let notices = log.since(since);
+        let now = Instant::now();
+        if !notices.is_empty() || now >= deadline {
+            return NoticeBatch {
+                revision: log.revision,
+                notices,
+            };
+        }
+        log = POSTED
+            .wait_timeout(log, deadline - now)
+            .map(|(log, _)| log)
+            .unwrap_or_else(|poisoned| poisoned.into_inner().0);
+    }
+}
+
+/// `since` (optional) and `wait` (seconds) from a `/v1/notices` query string.
+pub fn parse_query(query: &str) -> Result<(Option<u64>, Duration), String> {
+    let mut since = None;
+    let mut wait = events::DEFAULT_WAIT;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        match key {
+            "since" => {
+                since = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid since '{value}'"))?,
+                )
+            }
+            "wait" => {
+                wait = Duration::from_secs(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid wait '{value}'"))?,
+                )
+            }
+            _ => {}
+        }
+    }
+    Ok((since, wait))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notices_after_a_revision_are_returned_with_their_category() {
+        let mut log = NoticeLog::new();
+        log.post(
+            Notice::ScheduleApplied {
+                name: "evening".into(),
+            },
+            100,
+        );
+        log.post(Notice::LightUnreachable { id: "left".into() }, 101);
+
+        let notices = log.since(1);
+        assert_eq!(notices.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&notices[0]).unwrap(),
+            serde_json::json!({
+                "revision": 2,
+                "category": "error",
+                "posted_unix": 101,
+                "kind": "light_unreachable",
+                "id": "left"
+            })
+        );
+        // From before a restart: everything retained is new.
+        assert_eq!(log.since(9).len(), 2);
+        assert!(log.since(2).is_empty());
+    }
+}
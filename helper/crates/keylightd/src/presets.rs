@@ -0,0 +1,96 @@
+//! Named brightness and temperature settings from `[[presets]]` in `config.toml`,
+//! applied with `set --preset`, `POST /v1/presets/{name}/apply` and the chips on the
+//! tray's cards. Without a `presets` key the built-in ones below are used.
+
+use serde::{Deserialize, Serialize};
+
+use super::{KeylightError, UpdateRequest};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kelvin: Option<u16>,
+}
+
+impl Preset {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Preset name must not be empty".into());
+        }
+        if self.brightness.is_none() && self.kelvin.is_none() {
+            return Err(format!(
+                "Preset '{}' needs a brightness or a kelvin",
+                self.name
+            ));
+        }
+        if self.brightness.is_some_and(|value| value > 100) {
+            return Err(format!("Preset '{}': brightness must be 0-100", self.name));
+        }
+        Ok(())
+    }
+
+    /// Switches the light on at the preset's brightness and temperature; temperatures
+    /// are clamped to each light's range when sent.
+    pub fn update(&self) -> UpdateRequest {
+        UpdateRequest {
+            on: Some(1),
+            brightness: self.brightness,
+            kelvin: self.kelvin,
+            mired: None,
+            hue: None,
+            saturation: None,
+        }
+    }
+}
+
+pub fn defaults() -> Vec<Preset> {
+    [
+        ("Video", 70, 5600),
+        ("Warm", 30, 3200),
+        ("Bright", 100, 4500),
+    ]
+    .into_iter()
+    .map(|(name, brightness, kelvin)| Preset {
+        name: name.into(),
+        brightness: Some(brightness),
+        kelvin: Some(kelvin),
+    })
+    .collect()
+}
+
+/// The preset called `name`, ignoring case.
+pub fn find<'a>(presets: &'a [Preset], name: &str) -> Result<&'a Preset, KeylightError> {
+    let preset = presets
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| KeylightError::not_found("preset", name))?;
+    preset.validate().map_err(KeylightError::Invalid)?;
+    Ok(preset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_found_by_name_and_checked() {
+        let mut presets = defaults();
+        let video = find(&presets, "video").unwrap();
+        let update = video.update();
+        assert_eq!(
+            (update.on, update.brightness, update.kelvin),
+            (Some(1), Some(70), Some(5600))
+        );
+        assert!(find(&presets, "studio").is_err());
+
+        presets.push(Preset {
+            name: "Empty".into(),
+            brightness: None,
+            kelvin: None,
+        });
+        assert!(find(&presets, "empty").is_err());
+    }
+}
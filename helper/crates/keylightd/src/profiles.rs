@@ -0,0 +1,370 @@
+//! Profiles: named bundles of a scene and the automations that may run, like "work",
+//! "gaming" or "off-hours", from `[[profiles]]` in `config.toml`. At most one is
+//! active. `POST /v1/profiles/active` picks one by hand; otherwise the first profile
+//! whose rules match (a time window, whether a camera is in use) takes over when they
+//! start matching, and stays active until another does. Becoming active applies the
+//! profile's scene; the automations it turns off hold off as during do-not-disturb.
+
+use chrono::{Datelike, Local, NaiveTime};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use super::conferencing;
+use super::dnd;
+use super::logs::log_line;
+use super::schedule::WEEKDAYS;
+use super::snapshot;
+use super::{load_config, Config, KeylightError};
+
+const TICK: Duration = Duration::from_secs(5);
+
+static STATE: Mutex<State> = Mutex::new(State {
+    active: None,
+    manual: false,
+    ruled: None,
+    off: Vec::new(),
+});
+
+struct State {
+    active: Option<String>,
+    /// Picked through the API rather than by the rules.
+    manual: bool,
+    /// What the rules picked at the last check.
+    ruled: Option<String>,
+    /// Automations the active profile turns off.
+    off: Vec<Automation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Automation {
+    Schedules,
+    Obs,
+    Conferencing,
+    AutoBrightness,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Snapshot applied when the profile becomes active.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scene: Option<String>,
+    /// Automations that hold off while the profile is active.
+    #[serde(default)]
+    pub off: Vec<Automation>,
+    /// The profile takes over when any of these starts matching; without rules it's
+    /// only picked by hand.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+/// Conditions that all have to hold; unset ones always do.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Rule {
+    /// Local `HH:MM` start and end. A window that ends before it starts runs past
+    /// midnight.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub between: Option<[String; 2]>,
+    /// Weekdays (`mon`..`sun`); empty means every day.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<String>,
+    /// Whether some app must be capturing from a camera, or none may be. Needs
+    /// `pw-dump`; without it such rules never match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProfileStatus {
+    pub active: Option<String>,
+    /// Whether the active profile was picked by hand; the rules move on from it once
+    /// they pick another.
+    pub manual: bool,
+    pub profiles: Vec<Profile>,
+}
+
+/// What rules are checked against.
+struct Conditions<'a> {
+    time: NaiveTime,
+    weekday: &'a str,
+    /// Unknown when no rule asks, or `pw-dump` can't be run.
+    camera: Option<bool>,
+}
+
+fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").ok()
+}
+
+impl Profile {
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in &self.rules {
+            if let Some(time) = rule
+                .between
+                .iter()
+                .flatten()
+                .find(|t| parse_time(t).is_none())
+            {
+                return Err(format!("Invalid rule time '{time}' (expected HH:MM)"));
+            }
+            if let Some(day) = rule
+                .days
+                .iter()
+                .find(|day| !WEEKDAYS.contains(&day.to_ascii_lowercase().as_str()))
+            {
+                return Err(format!("Invalid weekday '{day}' (expected mon..sun)"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    fn matches(&self, now: &Conditions) -> bool {
+        let in_window = match &self.between {
+            None => true,
+            Some([from, until]) => match (parse_time(from), parse_time(until)) {
+                (Some(from), Some(until)) if from <= until => from <= now.time && now.time < until,
+                (Some(from), Some(until)) => now.time >= from || now.time < until,
+                _ => false,
+            },
+        };
+        let on_day = self.days.is_empty()
+            || self
+                .days
+                .iter()
+                .any(|day| day.eq_ignore_ascii_case(now.weekday));
+        let camera = self.camera.is_none() || self.camera == now.camera;
+        in_window && on_day && camera
+    }
+}
+
+/// The first profile whose rules match.
+fn pick<'a>(profiles: &'a [Profile], now: &Conditions) -> Option<&'a str> {
+    profiles
+        .iter()
+        .find(|profile| profile.rules.iter().any(|rule| rule.matches(now)))
+        .map(|profile| profile.name.as_str())
+}
+
+/// Note that the rules pick `ruled` now, and return the profile to switch to. That's
+/// only when their pick changed, so a profile picked by hand holds until they move on.
+fn follow(state: &mut State, ruled: Option<&str>) -> Option<String> {
+    if state.ruled.as_deref() == ruled {
+        return None;
+    }
+    state.ruled = ruled.map(str::to_string);
+    let ruled = ruled?;
+    if state.active.as_deref() == Some(ruled) {
+        state.manual = false;
+        return None;
+    }
+    Some(ruled.to_string())
+}
+
+fn lock() -> std::sync::MutexGuard<'static, State> {
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn activate(client: &Client, profile: &Profile, manual: bool) -> Result<(), KeylightError> {
+    {
+        let mut state = lock();
+        state.active = Some(profile.name.clone());
+        state.manual = manual;
+        state.off = profile.off.clone();
+    }
+    log_line!("profile '{}' is active", profile.name);
+    match &profile.scene {
+        Some(scene) => snapshot::restore(client, scene, true),
+        None => Ok(()),
+    }
+}
+
+/// Whether the active profile turns `automation` off.
+pub fn disabled(automation: Automation) -> bool {
+    lock().off.contains(&automation)
+}
+
+pub fn status(config: &Config) -> ProfileStatus {
+    let state = lock();
+    ProfileStatus {
+        active: state.active.clone(),
+        manual: state.manual,
+        profiles: config.profiles.clone(),
+    }
+}
+
+/// Make `name` the active profile, or go back to following the rules for `None`;
+/// what they pick then takes over at the next check.
+pub fn choose(
+    client: &Client,
+    config: &Config,
+    name: Option<&str>,
+) -> Result<(), KeylightError> {
+    let Some(name) = name else {
+        let mut state = lock();
+        state.manual = false;
+        state.ruled = None;
+        return Ok(());
+    };
+    let profile = config
+        .profiles
+        .iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| KeylightError::not_found("profile", name))?;
+    activate(client, profile, true)
+}
+
+/// Start following the profile rules if any profiles are configured. Called once by
+/// `serve`.
+pub fn spawn(client: Client) {
+    let profiles = match load_config() {
+        Ok(config) => config.profiles,
+        Err(err) => {
+            log_line!("profiles: {err}");
+            return;
+        }
+    };
+    if profiles.is_empty() {
+        return;
+    }
+    for profile in &profiles {
+        if let Err(err) = profile.validate() {
+            log_line!("profile '{}': {err}", profile.name);
+        }
+    }
+    let spawned = thread::Builder::new()
+        .name("profiles".into())
+        .spawn(move || run(client));
+    if let Err(err) = spawned {
+        log_line!("failed to start profiles: {err}");
+    }
+}
+
+fn run(client: Client) {
+    loop {
+        // Switches that fall in a do-not-disturb window happen once it ends.
+        if !dnd::active() {
+            if let Err(err) = check(&client) {
+                log_line!("profiles: {err}");
+            }
+        }
+        thread::sleep(TICK);
+    }
+}
+
+fn check(client: &Client) -> Result<(), KeylightError> {
+    let config = load_config()?;
+    let asks_camera = config
+        .profiles
+        .iter()
+        .flat_map(|profile| &profile.rules)
+        .any(|rule| rule.camera.is_some());
+    let now = Local::now();
+    let conditions = Conditions {
+        time: now.time(),
+        weekday: WEEKDAYS[now.weekday().num_days_from_monday() as usize],
+        camera: asks_camera
+            .then(|| conferencing::camera_in_use().ok())
+            .flatten(),
+    };
+    let switch = follow(&mut lock(), pick(&config.profiles, &conditions));
+    match switch.and_then(|name| config.profiles.iter().find(|profile| profile.name == name)) {
+        Some(profile) => Ok(activate(client, profile, false)?),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str, weekday: &'static str, camera: Option<bool>) -> Conditions<'static> {
+        Conditions {
+            time: parse_time(time).unwrap(),
+            weekday,
+            camera,
+        }
+    }
+
+    fn window(from: &str, until: &str) -> Rule {
+        Rule {
+            between: Some([from.into(), until.into()]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rules_check_windows_days_and_camera() {
+        let work = Rule {
+            days: vec!["Mon".into(), "fri".into()],
+            ..window("09:00", "17:00")
+        };
+        assert!(work.matches(&at("09:00", "mon", None)));
+        assert!(!work.matches(&at("17:00", "mon", None)));
+        assert!(!work.matches(&at("12:00", "tue", None)));
+
+        let night = window("22:00", "07:00");
+        assert!(night.matches(&at("23:30", "sat", None)));
+        assert!(night.matches(&at("06:59", "sun", None)));
+        assert!(!night.matches(&at("12:00", "sun", None)));
+
+        let call = Rule {
+            camera: Some(true),
+            ..Default::default()
+        };
+        assert!(call.matches(&at("12:00", "mon", Some(true))));
+        assert!(!call.matches(&at("12:00", "mon", Some(false))));
+        assert!(!call.matches(&at("12:00", "mon", None)));
+    }
+
+    #[test]
+    fn hand_picked_profiles_hold_until_the_rules_move_on() {
+        let profile = |name: &str, rules| Profile {
+            name: name.into(),
+            scene: None,
+            off: Vec::new(),
+            rules,
+        };
+        let profiles = [
+            profile(
+                "call",
+                vec![Rule {
+                    camera: Some(true),
+                    ..Default::default()
+                }],
+            ),
+            profile("work", vec![window("09:00", "17:00")]),
+            profile("gaming", Vec::new()),
+        ];
+        assert_eq!(
+            pick(&profiles, &at("10:00", "mon", Some(true))),
+            Some("call")
+        );
+        assert_eq!(pick(&profiles, &at("10:00", "mon", None)), Some("work"));
+        assert_eq!(pick(&profiles, &at("20:00", "mon", None)), None);
+
+        let mut state = State {
+            active: None,
+            manual: false,
+            ruled: None,
+            off: Vec::new(),
+        };
+        assert_eq!(follow(&mut state, Some("work")), Some("work".into()));
+        state.active = Some("gaming".into());
+        state.manual = true;
+        assert_eq!(follow(&mut state, Some("work")), None);
+        assert_eq!(follow(&mut state, None), None);
+        assert_eq!(follow(&mut state, Some("call")), Some("call".into()));
+
+        state.active = Some("call".into());
+        state.ruled = None;
+        assert_eq!(follow(&mut state, Some("call")), None);
+        assert!(!state.manual);
+    }
+}
@@ -0,0 +1,210 @@
+//! Ramps: fade lights up from off to a brightness and temperature over some minutes,
+//! like a sunrise. They start at the dimmest level and the light's warmest
+//! temperature. Started by `POST /v1/ramps` or by a schedule with `ramp_minutes`;
+//! each runs on its own thread until it ends or is cancelled.
+
+use keylight_core::store::SavedLight;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::logs::log_line;
+use super::schedule::ScheduleTarget;
+use super::soft_start::FLOOR;
+use super::{load_config, set_light, LightRecord, UpdateRequest};
+
+/// Longest ramp accepted.
+pub const MAX_MINUTES: u32 = 120;
+/// How often the lights are moved along; steps that change nothing aren't sent.
+const TICK: Duration = Duration::from_secs(1);
+
+static RAMPS: Mutex<Vec<Running>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+struct Running {
+    status: Ramp,
+    started: Instant,
+    cancelled: Arc<AtomicBool>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Ramp {
+    pub id: u64,
+    pub target: ScheduleTarget,
+    /// Brightness and temperature the ramp ends at.
+    pub brightness: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kelvin: Option<u16>,
+    pub minutes: u32,
+    /// Persisted ids of the lights being ramped.
+    pub lights: Vec<String>,
+    pub remaining_secs: u64,
+}
+
+/// Where a ramp to `brightness` and `kelvin` is after `progress` (0 to 1) of its time,
+/// for a light whose warmest temperature is `warmest`.
+fn level(progress: f64, brightness: u8, kelvin: Option<u16>, warmest: u16) -> (u8, Option<u16>) {
+    let progress = progress.clamp(0.0, 1.0);
+    let between = |from: f64, to: f64| from + (to - from) * progress;
+    let start = FLOOR.min(brightness);
+    let brightness = between(f64::from(start), f64::from(brightness)).round() as u8;
+    let kelvin = kelvin.map(|kelvin| between(f64::from(warmest), f64::from(kelvin)).round() as u16);
+    (brightness, kelvin)
+}
+
+/// Fade `lights` up to `brightness` (and `kelvin`, when given) over `minutes`.
+pub fn start(
+    client: &Client,
+    target: ScheduleTarget,
+    lights: Vec<LightRecord>,
+    brightness: u8,
+    kelvin: Option<u16>,
+    minutes: u32,
+) -> Result<Ramp, &'static str> {
+    if minutes == 0 || minutes > MAX_MINUTES {
+        return Err("Ramps must last between 1 and 120 minutes.");
+    }
+    if brightness == 0 || brightness > 100 {
+        return Err("A ramp's brightness must be within 1-100.");
+    }
+    if lights.is_empty() {
+        return Err("The target has no enabled lights.");
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let status = Ramp {
+        id,
+        target,
+        brightness,
+        kelvin,
+        minutes,
+        lights: lights.iter().map(|light| light.id.clone()).collect(),
+        remaining_secs: u64::from(minutes) * 60,
+    };
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let running = Running {
+        status: status.clone(),
+        started: Instant::now(),
+        cancelled: cancelled.clone(),
+    };
+    RAMPS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(running);
+    let client = client.clone();
+    let duration = Duration::from_secs(u64::from(minutes) * 60);
+    let spawned = thread::Builder::new()
+        .name(format!("ramp-{id}"))
+        .spawn(move || {
+            run(&client, &lights, brightness, kelvin, duration, &cancelled);
+            finish(id);
+        });
+    if let Err(err) = spawned {
+        log_line!("failed to start ramp: {err}");
+        finish(id);
+        return Err("The ramp could not be started.");
+    }
+    Ok(status)
+}
+
+fn run(
+    client: &Client,
+    lights: &[LightRecord],
+    brightness: u8,
+    kelvin: Option<u16>,
+    duration: Duration,
+    cancelled: &AtomicBool,
+) {
+    let started = Instant::now();
+    let config = load_config().unwrap_or_default();
+    let mut sent: HashMap<&str, (u8, Option<u16>)> = HashMap::new();
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        let progress = started.elapsed().as_secs_f64() / duration.as_secs_f64();
+        for light in lights {
+            let range = light.supported_kelvin();
+            let step = level(progress, brightness, kelvin, range.min);
+            let Some(ip) = light.address() else {
+                continue;
+            };
+            let update = UpdateRequest {
+                on: Some(1),
+                brightness: Some(step.0),
+                kelvin: step.1,
+                ..Default::default()
+            }
+            .calibrated(&config, Some(light))
+            .to_light_update_in(range);
+            let device = (update.brightness.unwrap_or(0), update.temperature);
+            if sent.get(light.id.as_str()) == Some(&device) {
+                continue;
+            }
+            match set_light(client, light.backend, &ip, &update) {
+                Ok(_) => {
+                    sent.insert(&light.id, device);
+                }
+                Err(err) => log_line!("ramp on {}: {err}", light.id),
+            }
+        }
+        if progress >= 1.0 {
+            return;
+        }
+        thread::sleep(TICK);
+    }
+}
+
+fn finish(id: u64) {
+    RAMPS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .retain(|running| running.status.id != id);
+}
+
+/// Running ramps, oldest first.
+pub fn list() -> Vec<Ramp> {
+    let ramps = RAMPS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    ramps
+        .iter()
+        .map(|running| {
+            let total = Duration::from_secs(u64::from(running.status.minutes) * 60);
+            let left = total.saturating_sub(running.started.elapsed());
+            Ramp {
+                remaining_secs: left.as_secs() + u64::from(left.subsec_nanos() > 0),
+                ..running.status.clone()
+            }
+        })
+        .collect()
+}
+
+/// Stop the ramp with `id` where it is; false when there is none (it may have ended).
+pub fn cancel(id: u64) -> bool {
+    let mut ramps = RAMPS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let Some(index) = ramps.iter().position(|running| running.status.id == id) else {
+        return false;
+    };
+    ramps.remove(index).cancelled.store(true, Ordering::Relaxed);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_rise_from_dim_and_warm() {
+        assert_eq!(level(0.0, 80, Some(5000), 2900), (FLOOR, Some(2900)));
+        assert_eq!(level(0.5, 83, Some(5000), 2900), (43, Some(3950)));
+        assert_eq!(level(1.0, 80, Some(5000), 2900), (80, Some(5000)));
+        assert_eq!(level(2.0, 80, None, 2900), (80, None));
+        assert_eq!(level(0.0, 1, None, 2900), (1, None));
+    }
+}
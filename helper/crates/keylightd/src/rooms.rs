@@ -0,0 +1,107 @@
+//! Rooms: lights that share a `room` (set with `PUT /v1/lights/{id}/meta`) form an
+//! implicit group. Nothing is stored for them; `GET /v1/rooms` derives them from the
+//! light records and `PUT /v1/rooms/{name}` takes the same updates as a group. Room
+//! names match regardless of case, spelled as on the first light that has them.
+
+use keylight_core::store::SavedLight;
+use serde::Serialize;
+
+use super::{Config, KeylightError, LightRecord};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Room {
+    pub name: String,
+    /// Ids of the lights in the room, in list order.
+    pub members: Vec<String>,
+}
+
+fn same_room(light: &LightRecord, name: &str) -> bool {
+    light
+        .room
+        .as_deref()
+        .is_some_and(|room| room.to_lowercase() == name.to_lowercase())
+}
+
+/// Every room a light is in, by name.
+pub fn list(config: &Config) -> Vec<Room> {
+    let mut rooms: Vec<Room> = Vec::new();
+    for light in &config.lights {
+        let Some(name) = &light.room else {
+            continue;
+        };
+        match rooms.iter_mut().find(|room| same_room(light, &room.name)) {
+            Some(room) => room.members.push(light.id.clone()),
+            None => rooms.push(Room {
+                name: name.clone(),
+                members: vec![light.id.clone()],
+            }),
+        }
+    }
+    rooms.sort_by_key(|room| room.name.to_lowercase());
+    rooms
+}
+
+/// Addresses of the enabled lights in the room `name`, as `resolve_targets` gives
+/// them for a group.
+pub fn targets(config: &Config, name: &str) -> Result<Vec<String>, KeylightError> {
+    let lights: Vec<&LightRecord> = config
+        .lights
+        .iter()
+        .filter(|light| same_room(light, name))
+        .collect();
+    if lights.is_empty() {
+        return Err(KeylightError::not_found("room", name));
+    }
+    let mut ips: Vec<String> = lights
+        .into_iter()
+        .filter(|light| light.enabled)
+        .filter_map(|light| light.address())
+        .collect();
+    ips.sort();
+    ips.dedup();
+    if ips.is_empty() {
+        return Err(KeylightError::invalid(format!(
+            "Room '{name}' has no enabled lights"
+        )));
+    }
+    Ok(ips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rooms_gather_lights_regardless_of_case() {
+        let light = |id: &str, room: Option<&str>, enabled: bool| -> LightRecord {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "alias": null,
+                "name": id,
+                "hostname": format!("{id}.local"),
+                "port": 9123,
+                "addresses": [format!("192.168.1.{}", id.len())],
+                "last_seen_unix": 0,
+                "enabled": enabled,
+                "room": room
+            }))
+            .unwrap()
+        };
+        let config = Config {
+            lights: vec![
+                light("left", Some("Office"), true),
+                light("ring", Some("studio"), true),
+                light("right", Some("office"), false),
+                light("strip", None, true),
+            ],
+            ..Config::default()
+        };
+        let rooms = list(&config);
+        let names: Vec<&str> = rooms.iter().map(|room| room.name.as_str()).collect();
+        assert_eq!(names, ["Office", "studio"]);
+        assert_eq!(rooms[0].members, ["left", "right"]);
+
+        assert_eq!(targets(&config, "OFFICE").unwrap(), ["192.168.1.4"]);
+        assert!(targets(&config, "kitchen").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! Request paths split into segments for the API to match on. Each segment is
+//! percent-decoded on its own, after splitting, so an id with a `/` in it (sent as
+//! `%2F`) stays one segment instead of reading as a nested route. Routes are then
+//! slice patterns with their parameters bound by name:
+//!
+//! ```ignore
+//! match (method, Segments::parse(path).as_strs().as_slice()) {
+//!     (Method::Put, ["v1", "lights", id, "alias"]) => ...,
+//!     (Method::Put, ["v1", "lights", id]) => ...,
+//! }
+//! ```
+
+/// The decoded segments of a request path.
+pub struct Segments(Vec<String>);
+
+impl Segments {
+    /// Split `path`, which has no query. `/` is `[""]`, and a trailing slash leaves
+    /// an empty last segment, so `/v1/lights/` names a light with an empty id rather
+    /// than the list. A segment that isn't UTF-8 once decoded is kept as sent; it
+    /// names nothing, so the request ends in a 404.
+    pub fn parse(path: &str) -> Segments {
+        let segments = path
+            .strip_prefix('/')
+            .unwrap_or(path)
+            .split('/')
+            .map(|raw| {
+                urlencoding::decode(raw)
+                    .map(|value| value.into_owned())
+                    .unwrap_or_else(|_| raw.to_string())
+            })
+            .collect();
+        Segments(segments)
+    }
+
+    /// The segments as string slices, for a slice pattern to match.
+    pub fn as_strs(&self) -> Vec<&str> {
+        self.0.iter().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_are_decoded_after_splitting() {
+        assert_eq!(Segments::parse("/").as_strs(), [""]);
+        assert_eq!(Segments::parse("/v1/lights").as_strs(), ["v1", "lights"]);
+        assert_eq!(
+            Segments::parse("/v1/lights/Key%20Light%2FLeft/alias").as_strs(),
+            ["v1", "lights", "Key Light/Left", "alias"]
+        );
+        assert_eq!(
+            Segments::parse("/v1/groups/").as_strs(),
+            ["v1", "groups", ""]
+        );
+        assert_eq!(
+            Segments::parse("/v1/lights/%FF").as_strs(),
+            ["v1", "lights", "%FF"]
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Recurring wall-clock schedules, run by `serve` in a background thread.
+
+use chrono::{Datelike, Local, NaiveTime, Timelike};
+use keylight_core::store::SavedLight;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+
+use super::dnd;
+use super::logs::log_line;
+use super::notices::{self, Notice};
+use super::profiles::{self, Automation};
+use super::ramps;
+use super::status;
+use super::{
+    load_config, lock_config_edits, mired_to_kelvin, save_state, set_light, Config, KeylightError,
+    LightRecord, UpdateRequest,
+};
+
+const TICK: Duration = Duration::from_secs(15);
+
+pub const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleTarget {
+    All,
+    Light(String),
+    Group(String),
+}
+
+impl ScheduleTarget {
+    /// The `id`, `group` and `all` arguments that select this target.
+    pub fn selection(self) -> (Option<String>, Option<String>, bool) {
+        match self {
+            ScheduleTarget::All => (None, None, true),
+            ScheduleTarget::Light(id) => (Some(id), None, false),
+            ScheduleTarget::Group(name) => (None, Some(name), false),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Schedule {
+    pub name: String,
+    /// Local wall-clock time, `HH:MM`.
+    pub at: String,
+    /// Weekdays to run on (`mon`..`sun`); empty means every day.
+    #[serde(default)]
+    pub days: Vec<String>,
+    pub target: ScheduleTarget,
+    pub update: UpdateRequest,
+    /// Fade the lights up from off to `update` over this many minutes instead of
+    /// switching them at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ramp_minutes: Option<u32>,
+    #[serde(default = "super::default_enabled")]
+    pub enabled: bool,
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Schedule name must not be empty".into());
+        }
+        self.time()
+            .ok_or_else(|| format!("Invalid schedule time '{}' (expected HH:MM)", self.at))?;
+        if let Some(day) = self
+            .days
+            .iter()
+            .find(|day| !WEEKDAYS.contains(&day.to_ascii_lowercase().as_str()))
+        {
+            return Err(format!("Invalid weekday '{day}' (expected mon..sun)"));
+        }
+        if let Some(minutes) = self.ramp_minutes {
+            if minutes == 0 || minutes > ramps::MAX_MINUTES {
+                return Err(format!(
+                    "Ramps must last between 1 and {} minutes",
+                    ramps::MAX_MINUTES
+                ));
+            }
+            if self.update.on == Some(0) {
+                return Err("A ramp can't switch lights off".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The wall-clock time in `at`. Parsed rather than compared as text, so "7:00"
+    /// runs at the same minute as "07:00".
+    fn time(&self) -> Option<NaiveTime> {
+        NaiveTime::parse_from_str(self.at.trim(), "%H:%M").ok()
+    }
+
+    fn is_due(&self, time: NaiveTime, weekday: &str) -> bool {
+        self.enabled
+            && self.time() == Some(time)
+            && (self.days.is_empty() || self.days.iter().any(|d| d.eq_ignore_ascii_case(weekday)))
+    }
+}
+
+/// Enabled lights a schedule target currently covers.
+pub fn target_records<'a>(config: &'a Config, target: &ScheduleTarget) -> Vec<&'a LightRecord> {
+    let matches = |light: &LightRecord, ident: &str| {
+        light.id == ident || light.name == ident || light.alias.as_deref() == Some(ident)
+    };
+    let mut records: Vec<&LightRecord> = match target {
+        ScheduleTarget::All => config.lights.iter().collect(),
+        ScheduleTarget::Light(ident) => {
+            config.lights.iter().filter(|l| matches(l, ident)).collect()
+        }
+        ScheduleTarget::Group(name) => config
+            .groups
+            .iter()
+            .find(|group| &group.name == name)
+            .map(|group| {
+                config
+                    .lights
+                    .iter()
+                    .filter(|light| group.members.iter().any(|m| matches(light, m)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    records.retain(|light| light.enabled);
+    records
+}
+
+pub fn spawn_runner(client: Client) {
+    let spawned = thread::Builder::new()
+        .name("scheduler".into())
+        .spawn(move || run(client));
+    match spawned {
+        Ok(_) => status::automation_started("schedules"),
+        Err(err) => log_line!("failed to start scheduler: {err}"),
+    }
+}
+
+fn run(client: Client) {
+    let mut last_minute = String::new();
+    loop {
+        let now = Local::now();
+        let minute = now.format("%Y-%m-%d %H:%M").to_string();
+        // Runs that fall in a do-not-disturb window, or while the active profile turns
+        // schedules off, are dropped, not caught up on.
+        if minute != last_minute && !dnd::active() && !profiles::disabled(Automation::Schedules) {
+            last_minute = minute;
+            let time = NaiveTime::from_hms_opt(now.hour(), now.minute(), 0).unwrap_or_default();
+            let weekday = WEEKDAYS[now.weekday().num_days_from_monday() as usize];
+            if let Err(err) = run_due(&client, time, weekday) {
+                log_line!("scheduler: {err}");
+            }
+        }
+        thread::sleep(TICK);
+    }
+}
+
+fn run_due(client: &Client, time: NaiveTime, weekday: &str) -> Result<(), KeylightError> {
+    let config = load_config()?;
+    let due: Vec<Schedule> = config
+        .schedules
+        .iter()
+        .filter(|schedule| schedule.is_due(time, weekday))
+        .cloned()
+        .collect();
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let mut skipped = Vec::new();
+    for schedule in &due {
+        let update = &schedule.update;
+        let mut applied = false;
+        let mut ramped = Vec::new();
+        for light in target_records(&config, &schedule.target) {
+            if light.schedule_paused {
+                continue;
+            }
+            if light.schedule_skip_next {
+                skipped.push(light.id.clone());
+                continue;
+            }
+            if schedule.ramp_minutes.is_some() {
+                ramped.push(light.clone());
+                continue;
+            }
+            let Some(ip) = light.address() else {
+                continue;
+            };
+            let update = update
+                .calibrated(&config, Some(light))
+                .to_light_update_in(light.supported_kelvin());
+            match set_light(client, light.backend, &ip, &update) {
+                Ok(_) => applied = true,
+                Err(err) => log_line!("schedule '{}' on {}: {err}", schedule.name, light.id),
+            }
+        }
+        if let (Some(minutes), false) = (schedule.ramp_minutes, ramped.is_empty()) {
+            let kelvin = update.kelvin.or(update.mired.map(mired_to_kelvin));
+            let brightness = update.brightness.unwrap_or(100);
+            let target = schedule.target.clone();
+            match ramps::start(client, target, ramped, brightness, kelvin, minutes) {
+                Ok(_) => applied = true,
+                Err(err) => log_line!("schedule '{}': {err}", schedule.name),
+            }
+        }
+        if applied {
+            notices::post(Notice::ScheduleApplied {
+                name: schedule.name.clone(),
+            });
+        }
+    }
+
+    if !skipped.is_empty() {
+        // Reloaded under the edit lock: the lights above took a while, and an API
+        // request may have saved in the meantime.
+        let _editing = lock_config_edits();
+        let mut config = load_config()?;
+        for light in config.lights.iter_mut().filter(|l| skipped.contains(&l.id)) {
+            light.schedule_skip_next = false;
+        }
+        save_state(&config)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(at: &str, days: &[&str]) -> Schedule {
+        Schedule {
+            name: "evening".into(),
+            at: at.into(),
+            days: days.iter().map(|d| d.to_string()).collect(),
+            target: ScheduleTarget::All,
+            update: UpdateRequest {
+                on: Some(0),
+                brightness: None,
+                kelvin: None,
+                mired: None,
+                hue: None,
+                saturation: None,
+            },
+            ramp_minutes: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn validate_rejects_bad_time_and_days() {
+        assert!(schedule("22:30", &["mon", "Fri"]).validate().is_ok());
+        assert!(schedule("25:00", &[]).validate().is_err());
+        assert!(schedule("22:30", &["someday"]).validate().is_err());
+    }
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn is_due_respects_weekdays() {
+        let every_day = schedule("07:00", &[]);
+        assert!(every_day.is_due(at(7, 0), "sun"));
+        assert!(!every_day.is_due(at(7, 1), "sun"));
+
+        let weekdays = schedule("07:00", &["mon", "tue"]);
+        assert!(weekdays.is_due(at(7, 0), "tue"));
+        assert!(!weekdays.is_due(at(7, 0), "sat"));
+    }
+
+    #[test]
+    fn single_digit_hour_is_due() {
+        let early = schedule("7:00", &[]);
+        assert!(early.validate().is_ok());
+        assert!(early.is_due(at(7, 0), "wed"));
+        assert!(!early.is_due(at(17, 0), "wed"));
+    }
+}
@@ -0,0 +1,1016 @@
+//! `keylightd smoke --mock`: an end-to-end check of a build without any hardware, for
+//! packagers. Two fake Key Lights answer the Elgato API on 127.0.0.2 and 127.0.0.3
+//! (all of 127/8 is loopback on Linux), the API server runs on a free port, and
+//! lights, groups, scenes, schedules and events are exercised through it. Config and
+//! state live in a temporary directory, so the user's setup isn't touched.
+//!
+//! `--latency-ms`, `--jitter-ms` and `--failure-rate` make the fake lights slow or
+//! flaky, for trying retries and timeouts by hand; checks may fail with them on.
+
+use reqwest::blocking::Client;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Server, StatusCode};
+
+use super::{
+    json_client_error, json_response, load_config, save_state, serve_requests, snapshot, status,
+    Backend, Capabilities, LightRecord, LightState, LightUpdate, LightsPayload,
+};
+
+/// Light id and the loopback address its fake answers on.
+const MOCK_LIGHTS: [(&str, &str); 2] = [("smoke-left", "127.0.0.2"), ("smoke-right", "127.0.0.3")];
+
+/// A fake light: the state it reports, and whether it answers every request with a
+/// 500 instead.
+struct Mock {
+    light: Mutex<LightState>,
+    broken: AtomicBool,
+    faults: Mutex<Faults>,
+    /// Requests answered so far, which picks each one's jitter.
+    requests: AtomicU64,
+}
+
+/// Network trouble a fake light simulates, so retries, timeouts and the offline
+/// breaker can be tested without a flaky light.
+#[derive(Debug, Clone, Copy, Default)]
+struct Faults {
+    /// Wait before every answer.
+    latency: Duration,
+    /// Up to this much more wait, varying per request but the same on every run.
+    jitter: Duration,
+    /// Answer this many of the next requests with a 500, then recover.
+    fail_next: u32,
+    /// Share of requests (0 to 1) answered with a 500, spread like the jitter.
+    failure_rate: f64,
+}
+
+impl Mock {
+    /// How long to wait before answering, and whether to fail the request.
+    fn next_fault(&self) -> (Duration, bool) {
+        let request = self.requests.fetch_add(1, Ordering::Relaxed);
+        let mut faults = self
+            .faults
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // A fixed spread over the requests rather than a random one, so a test sees
+        // the same delays and failures each run.
+        let spread = request.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let jitter = faults.jitter.mul_f64((spread >> 54) as f64 / 1023.0);
+        // Other bits of the spread, so the failed requests aren't the slowest ones.
+        let unlucky = ((spread >> 44) & 1023) as f64 / 1024.0 < faults.failure_rate;
+        let fail = faults.fail_next > 0 || unlucky;
+        faults.fail_next = faults.fail_next.saturating_sub(1);
+        (faults.latency + jitter, fail)
+    }
+}
+
+type MockState = Arc<Mock>;
+
+/// `smoke`'s options for the fake lights. Each value is `<light id>=<value>` for
+/// one light, or a bare value for both.
+pub struct FaultOptions<'a> {
+    pub latency_ms: &'a [String],
+    pub jitter_ms: &'a [String],
+    pub failure_rate: &'a [String],
+}
+
+impl FaultOptions<'_> {
+    /// The faults of each of `MOCK_LIGHTS`, in order.
+    fn faults(&self) -> Result<Vec<Faults>, String> {
+        let mut faults = vec![Faults::default(); MOCK_LIGHTS.len()];
+        set_per_light(
+            &mut faults,
+            "--latency-ms",
+            self.latency_ms,
+            |faults, ms| {
+                faults.latency = Duration::from_millis(ms);
+            },
+        )?;
+        set_per_light(&mut faults, "--jitter-ms", self.jitter_ms, |faults, ms| {
+            faults.jitter = Duration::from_millis(ms);
+        })?;
+        set_per_light(
+            &mut faults,
+            "--failure-rate",
+            self.failure_rate,
+            |faults, rate| {
+                faults.failure_rate = rate;
+            },
+        )?;
+        if faults
+            .iter()
+            .any(|faults| !(0.0..=1.0).contains(&faults.failure_rate))
+        {
+            return Err("--failure-rate must be between 0 and 1".into());
+        }
+        Ok(faults)
+    }
+}
+
+/// Apply each `[<light id>=]<value>` of `option` to the faults of that light, or of
+/// all of them.
+fn set_per_light<T: std::str::FromStr + Copy>(
+    faults: &mut [Faults],
+    option: &str,
+    values: &[String],
+    set: impl Fn(&mut Faults, T),
+) -> Result<(), String> {
+    for value in values {
+        let (light, number) = match value.split_once('=') {
+            Some((light, number)) => (Some(light), number),
+            None => (None, value.as_str()),
+        };
+        let number: T = number
+            .parse()
+            .map_err(|_| format!("Invalid {option} '{value}'"))?;
+        if let Some(light) = light {
+            if !MOCK_LIGHTS.iter().any(|(id, _)| *id == light) {
+                return Err(format!(
+                    "{option}: no fake light '{light}' (smoke-left or smoke-right)"
+                ));
+            }
+        }
+        for ((id, _), faults) in MOCK_LIGHTS.iter().zip(faults.iter_mut()) {
+            if light.is_none_or(|light| light == *id) {
+                set(faults, number);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run(client: &Client, options: &FaultOptions) -> Result<(), Box<dyn Error>> {
+    let faults = options.faults()?;
+    let dir = std::env::temp_dir().join(format!("keylightd-smoke-{}", std::process::id()));
+    use_dirs_in(&dir);
+    let result = run_in(client, &dir, &faults);
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+/// Keep config, state and cache under `dir` for the rest of the process.
+fn use_dirs_in(dir: &Path) {
+    for (var, sub) in [
+        ("XDG_CONFIG_HOME", "config"),
+        ("XDG_STATE_HOME", "state"),
+        ("XDG_CACHE_HOME", "cache"),
+    ] {
+        std::env::set_var(var, dir.join(sub));
+    }
+}
+
+fn run_in(client: &Client, dir: &Path, faults: &[Faults]) -> Result<(), Box<dyn Error>> {
+    let mut lights = Vec::new();
+    let mut config = load_config()?;
+    for ((id, ip), faults) in MOCK_LIGHTS.into_iter().zip(faults) {
+        let light = start_mock_light(ip)?;
+        *light.faults.lock().unwrap_or_else(PoisonError::into_inner) = *faults;
+        lights.push(light);
+        config.lights.push(mock_record(id, ip));
+    }
+    save_state(&config)?;
+
+    let server = Server::http("127.0.0.1:0")
+        .map_err(|err| format!("Failed to bind the API server: {err}"))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .ok_or("API server has no TCP address")?;
+    status::started();
+    super::events::enable();
+    let api_client = client.clone();
+    thread::spawn(move || serve_requests(&server, &api_client));
+
+    eprintln!(
+        "smoke: fake lights on 127.0.0.2-3, API on port {port}, state in {}",
+        dir.display()
+    );
+    let mut smoke = Smoke {
+        client: client.clone(),
+        base: format!("http://127.0.0.1:{port}"),
+        lights,
+        passed: 0,
+        failed: 0,
+    };
+    smoke.run_checks();
+    println!("passed={}, failed={}", smoke.passed, smoke.failed);
+    if smoke.failed > 0 {
+        return Err(format!("{} smoke check(s) failed", smoke.failed).into());
+    }
+    Ok(())
+}
+
+fn mock_record(id: &str, ip: &str) -> LightRecord {
+    LightRecord {
+        id: id.into(),
+        alias: None,
+        name: format!("Smoke {id}"),
+        hostname: ip.into(),
+        port: 9123,
+        addresses: vec![ip.into()],
+        last_seen_unix: 0,
+        enabled: true,
+        accessory_info: None,
+        schedule_paused: false,
+        schedule_skip_next: false,
+        backend: Backend::Elgato,
+        capabilities: Capabilities::default(),
+        kelvin_range: None,
+        soft_start: false,
+        icon: None,
+        room: None,
+        notes: None,
+        favorite: false,
+        brightness_limits: None,
+        sort_index: None,
+        zone: None,
+    }
+}
+
+/// Serve the parts of the Elgato API the daemon uses, keeping the light's state.
+fn start_mock_light(ip: &str) -> Result<MockState, Box<dyn Error>> {
+    let server = Server::http((ip, 9123))
+        .map_err(|err| format!("Failed to bind a fake light on {ip}:9123: {err}"))?;
+    let state = Arc::new(Mock {
+        light: Mutex::new(LightState {
+            on: 0,
+            brightness: 50,
+            temperature: 213,
+            hue: None,
+            saturation: None,
+        }),
+        broken: AtomicBool::new(false),
+        faults: Mutex::new(Faults::default()),
+        requests: AtomicU64::new(0),
+    });
+    let shared = Arc::clone(&state);
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+            let path = request
+                .url()
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            let (delay, fail) = shared.next_fault();
+            thread::sleep(delay);
+            let response = if fail {
+                json_client_error(StatusCode(500), "the fake light failed this request")
+            } else {
+                mock_response(&shared, request.method(), &path, &body)
+            };
+            request.respond(response).ok();
+        }
+    });
+    Ok(state)
+}
+
+fn mock_response(
+    state: &MockState,
+    method: &tiny_http::Method,
+    path: &str,
+    body: &str,
+) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    if state.broken.load(Ordering::Relaxed) {
+        return json_client_error(StatusCode(500), "the fake light is broken");
+    }
+    let mut light = state
+        .light
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match (method, path) {
+        (tiny_http::Method::Get, "/elgato/lights") => {}
+        (tiny_http::Method::Put, "/elgato/lights") => {
+            let Ok(payload) = serde_json::from_str::<LightsPayload<LightUpdate>>(body) else {
+                return json_client_error(StatusCode(400), "invalid lights payload");
+            };
+            for update in payload.lights {
+                light.on = update.on.unwrap_or(light.on);
+                light.brightness = update.brightness.unwrap_or(light.brightness);
+                light.temperature = update.temperature.unwrap_or(light.temperature);
+            }
+        }
+        (tiny_http::Method::Get, "/elgato/accessory-info") => {
+            return json_response(
+                StatusCode(200),
+                &json!({"productName": "Elgato Key Light", "firmwareVersion": "1.0.3"}),
+            );
+        }
+        (tiny_http::Method::Get, "/elgato/lights/settings") => {
+            return json_response(
+                StatusCode(200),
+                &json!({
+                    "powerOnBehavior": 1,
+                    "powerOnBrightness": 20,
+                    "powerOnTemperature": 213,
+                    "switchOnDurationMs": 100,
+                    "switchOffDurationMs": 300,
+                    "colorChangeDurationMs": 100
+                }),
+            );
+        }
+        (tiny_http::Method::Post, "/elgato/restart") => {
+            return json_response(StatusCode(200), &json!({}));
+        }
+        _ => return json_client_error(StatusCode(404), "not a mock endpoint"),
+    }
+    json_response(
+        StatusCode(200),
+        &LightsPayload {
+            number_of_lights: 1,
+            lights: vec![light.clone()],
+        },
+    )
+}
+
+struct Smoke {
+    client: Client,
+    base: String,
+    lights: Vec<MockState>,
+    passed: usize,
+    failed: usize,
+}
+
+impl Smoke {
+    fn run_checks(&mut self) {
+        self.check("health", |s| {
+            s.call(Method::GET, "/v1/health", None).map(drop)
+        });
+        self.check("status", |s| {
+            let status = s.call(Method::GET, "/v1/status", None)?;
+            expect(status["lights"] == 2, "status doesn't count both lights")
+        });
+        self.check("lights.list", |s| {
+            let lights = s.call(Method::GET, "/v1/lights", None)?;
+            expect(count(&lights) == 2, "expected 2 lights")
+        });
+        self.check("lights.states", |s| {
+            let states = s.call(Method::GET, "/v1/lights/states", None)?;
+            expect(count(&states) == 2, "expected a state for both lights")
+        });
+        self.check("lights.update", |s| {
+            s.call(
+                Method::PUT,
+                "/v1/lights/smoke-left",
+                Some(json!({"brightness": 42})),
+            )?;
+            expect(
+                s.light(0).brightness == 42,
+                "the light wasn't dimmed to 42%",
+            )
+        });
+        self.check("lights.toggle", |s| {
+            let before = s.light(0).on;
+            s.call(Method::POST, "/v1/lights/smoke-left/toggle", None)?;
+            expect(s.light(0).on != before, "the light wasn't toggled")
+        });
+        self.check("lights.alias", |s| {
+            let body = json!({"alias": "Desk Left"});
+            let record = s.call(Method::PUT, "/v1/lights/smoke-left/alias", Some(body))?;
+            expect(record["alias"] == "Desk Left", "the alias wasn't saved")
+        });
+        self.check("all", |s| {
+            s.call(Method::PUT, "/v1/all", Some(json!({"on": 1})))?;
+            expect(s.lights_on() == [1, 1], "not every light was switched on")
+        });
+        self.check("groups", |s| {
+            let group = json!({"name": "smoke", "members": ["Desk Left", "smoke-right"]});
+            s.call(Method::POST, "/v1/groups", Some(group))?;
+            let groups = s.call(Method::GET, "/v1/groups", None)?;
+            expect(count(&groups) == 1, "the group wasn't listed")?;
+            let resolved = s.call(Method::GET, "/v1/groups/smoke/resolved", None)?;
+            expect(
+                count(&resolved["targets"]) == 2,
+                "the group doesn't target both lights",
+            )?;
+            s.call(Method::PUT, "/v1/groups/smoke", Some(json!({"on": 0})))?;
+            expect(s.lights_on() == [0, 0], "the group wasn't switched off")?;
+            s.call(Method::DELETE, "/v1/groups/smoke", None).map(drop)
+        });
+        self.check("scenes", |s| {
+            s.call(
+                Method::PUT,
+                "/v1/all",
+                Some(json!({"on": 1, "brightness": 70})),
+            )?;
+            snapshot::save(&s.client, "smoke", true).map_err(|err| err.to_string())?;
+            s.call(
+                Method::PUT,
+                "/v1/all",
+                Some(json!({"on": 0, "brightness": 10})),
+            )?;
+            let snapshots = s.call(Method::GET, "/v1/snapshots", None)?;
+            expect(
+                snapshots.get("smoke").is_some(),
+                "the snapshot wasn't listed",
+            )?;
+            s.call(Method::POST, "/v1/snapshots/smoke/restore", None)?;
+            let restored = (0..s.lights.len()).all(|i| s.light(i).brightness == 70);
+            expect(
+                restored && s.lights_on() == [1, 1],
+                "the snapshot wasn't restored",
+            )
+        });
+        self.check("schedules", |s| {
+            let schedule = json!({
+                "name": "smoke",
+                "at": "03:00",
+                "target": "all",
+                "update": {"on": 0}
+            });
+            s.call(Method::POST, "/v1/schedules", Some(schedule))?;
+            let schedules = s.call(Method::GET, "/v1/schedules", None)?;
+            expect(count(&schedules) == 1, "the schedule wasn't listed")?;
+            s.call(Method::DELETE, "/v1/schedules/smoke", None)
+                .map(drop)
+        });
+        self.check("events", |s| {
+            let snapshot = s.call(Method::GET, "/v1/events/snapshot", None)?;
+            let since = snapshot["revision"]
+                .as_u64()
+                .ok_or("no revision in snapshot")?;
+            s.call(
+                Method::PUT,
+                "/v1/lights/smoke-right",
+                Some(json!({"brightness": 33})),
+            )?;
+            let path = format!("/v1/events?since={since}&wait=1");
+            let batch = s.call(Method::GET, &path, None)?;
+            let seen = batch["events"].as_array().is_some_and(|events| {
+                events
+                    .iter()
+                    .any(|e| e["id"] == "smoke-right" && e["brightness"] == 33)
+            });
+            expect(seen, "no event for the brightness change")
+        });
+        self.check("dnd", |s| {
+            let dnd = s.call(Method::POST, "/v1/dnd", Some(json!({"duration_secs": 60})))?;
+            expect(dnd["active"] == true, "do not disturb didn't start")?;
+            let dnd = s.call(Method::DELETE, "/v1/dnd", None)?;
+            expect(dnd["active"] == false, "do not disturb didn't end")
+        });
+    }
+
+    fn check(&mut self, name: &str, run: impl FnOnce(&Smoke) -> Result<(), String>) {
+        match run(self) {
+            Ok(()) => {
+                self.passed += 1;
+                println!("check={name}, result=ok");
+            }
+            Err(err) => {
+                self.failed += 1;
+                println!("check={name}, result=failed, error={err}");
+            }
+        }
+    }
+
+    /// Call the API and return the JSON body of a 2xx response.
+    fn call(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, String> {
+        let mut request = self
+            .client
+            .request(method.clone(), format!("{}{path}", self.base));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request
+            .send()
+            .map_err(|err| format!("{method} {path}: {err}"))?;
+        let status = response.status();
+        let body: Value = response.json().unwrap_or(Value::Null);
+        if !status.is_success() {
+            return Err(format!("{method} {path}: {status} {body}"));
+        }
+        Ok(body)
+    }
+
+    fn light(&self, index: usize) -> LightState {
+        self.lights[index]
+            .light
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn lights_on(&self) -> Vec<u8> {
+        (0..self.lights.len()).map(|i| self.light(i).on).collect()
+    }
+}
+
+fn count(value: &Value) -> usize {
+    value.as_array().map_or(0, Vec::len)
+}
+
+fn expect(ok: bool, failure: &str) -> Result<(), String> {
+    if ok {
+        Ok(())
+    } else {
+        Err(failure.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! The API end to end: the server runs in this process on a free port, each test
+    //! saves its own fake lights on loopback addresses no other test uses, and config
+    //! and state live in a temporary directory.
+
+    use super::*;
+    use crate::{save_config, Config, KelvinRange};
+    use keylight_core::discovery::Found;
+    use proptest::prelude::*;
+    use std::sync::{MutexGuard, OnceLock, PoisonError};
+    use std::time::Duration;
+
+    struct Daemon {
+        client: Client,
+        base: String,
+    }
+
+    fn daemon() -> &'static Daemon {
+        static DAEMON: OnceLock<Daemon> = OnceLock::new();
+        DAEMON.get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("keylightd-tests-{}", std::process::id()));
+            use_dirs_in(&dir);
+            let server = Server::http("127.0.0.1:0").unwrap();
+            let port = server.server_addr().to_ip().unwrap().port();
+            let client = Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap();
+            let api_client = client.clone();
+            thread::spawn(move || serve_requests(&server, &api_client));
+            Daemon {
+                client,
+                base: format!("http://127.0.0.1:{port}"),
+            }
+        })
+    }
+
+    /// The saved lights and groups are the process's, so tests take turns.
+    fn turn() -> MutexGuard<'static, ()> {
+        static TURN: Mutex<()> = Mutex::new(());
+        TURN.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Save a light `light-<last octet>` for each address, and no groups. Only the
+    /// addresses in `answering` get a fake light; the others refuse connections.
+    fn lights(ips: &[&str], answering: usize) -> Vec<MockState> {
+        daemon();
+        let mut config = Config::default();
+        let mut mocks = Vec::new();
+        for (index, ip) in ips.iter().enumerate() {
+            let octet = ip.rsplit('.').next().unwrap();
+            config
+                .lights
+                .push(mock_record(&format!("light-{octet}"), ip));
+            if index < answering {
+                mocks.push(start_mock_light(ip).unwrap());
+            }
+        }
+        save_config(&config).unwrap();
+        save_state(&config).unwrap();
+        mocks
+    }
+
+    /// Status and JSON body of an API call.
+    fn call(method: Method, path: &str, body: Option<Value>) -> (u16, Value) {
+        let daemon = daemon();
+        let mut request = daemon
+            .client
+            .request(method, format!("{}{path}", daemon.base));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().unwrap();
+        let status = response.status().as_u16();
+        (status, response.json().unwrap_or(Value::Null))
+    }
+
+    fn state(mock: &MockState) -> LightState {
+        mock.light.lock().unwrap().clone()
+    }
+
+    /// Status of a request handed straight to the API handler, for paths an HTTP
+    /// client would normalize before sending.
+    fn handle(method: tiny_http::Method, path: &str, body: &str) -> u16 {
+        crate::handle_api_request(&daemon().client, &method, path, "", body)
+            .status_code()
+            .0
+    }
+
+    /// An update with one value no light takes, or one its field's type can't hold.
+    fn out_of_range() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            (2..=i64::MAX).prop_map(|v| json!({"on": v})),
+            prop_oneof![i64::MIN..0, 101..=i64::MAX].prop_map(|v| json!({"brightness": v})),
+            prop_oneof![0..1000i64, 20_001..=i64::MAX].prop_map(|v| json!({"kelvin": v})),
+            prop_oneof![0..50i64, 1001..=i64::MAX].prop_map(|v| json!({"mired": v})),
+            prop_oneof![-1e9..-0.001f64, 360.001..1e9].prop_map(|v| json!({"hue": v})),
+            prop_oneof![-1e9..-0.001f64, 100.001..1e9].prop_map(|v| json!({"saturation": v})),
+        ]
+    }
+
+    #[test]
+    fn lights_are_listed_updated_and_read_back() {
+        let _turn = turn();
+        let mocks = lights(&["127.0.0.21", "127.0.0.22"], 2);
+        let (status, listed) = call(Method::GET, "/v1/lights", None);
+        assert_eq!((status, count(&listed)), (200, 2));
+
+        let update = json!({"on": 1, "brightness": 42, "kelvin": 5000});
+        let (status, _) = call(Method::PUT, "/v1/lights/light-21", Some(update));
+        assert_eq!(status, 200);
+        let light = state(&mocks[0]);
+        assert_eq!(
+            (light.on, light.brightness, light.temperature),
+            (1, 42, 200)
+        );
+        assert_eq!(state(&mocks[1]).on, 0);
+
+        let (status, states) = call(Method::GET, "/v1/lights/states", None);
+        assert_eq!(status, 200);
+        let read = states
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|state| state["id"] == "light-21")
+            .unwrap();
+        assert_eq!(
+            (&read["on"], &read["brightness"], &read["kelvin"]),
+            (&json!(true), &json!(42), &json!(5000))
+        );
+    }
+
+    #[test]
+    fn groups_and_all_reach_every_light_they_name() {
+        let _turn = turn();
+        let mocks = lights(&["127.0.0.31", "127.0.0.32", "127.0.0.33"], 3);
+        let group = json!({"name": "desk", "members": ["light-31", "light-32"]});
+        assert_eq!(call(Method::POST, "/v1/groups", Some(group)).0, 200);
+
+        let (status, results) = call(Method::PUT, "/v1/groups/desk", Some(json!({"on": 1})));
+        assert_eq!((status, count(&results)), (200, 2));
+        let on: Vec<u8> = mocks.iter().map(|mock| state(mock).on).collect();
+        assert_eq!(on, [1, 1, 0]);
+
+        let (status, results) = call(Method::PUT, "/v1/all", Some(json!({"brightness": 10})));
+        assert_eq!((status, count(&results)), (200, 3));
+        assert!(mocks.iter().all(|mock| state(mock).brightness == 10));
+
+        assert_eq!(
+            call(Method::POST, "/v1/lights/light-33/toggle", None).0,
+            200
+        );
+        assert_eq!(state(&mocks[2]).on, 1);
+    }
+
+    #[test]
+    fn fault_options_apply_to_one_fake_light_or_both() {
+        let strings = |values: &[&str]| -> Vec<String> {
+            values.iter().map(|value| value.to_string()).collect()
+        };
+        let latency = strings(&["100", "smoke-right=300"]);
+        let failure_rate = strings(&["smoke-left=0.25"]);
+        let options = FaultOptions {
+            latency_ms: &latency,
+            jitter_ms: &[],
+            failure_rate: &failure_rate,
+        };
+        let faults = options.faults().unwrap();
+        assert_eq!(faults[0].latency, Duration::from_millis(100));
+        assert_eq!(faults[1].latency, Duration::from_millis(300));
+        assert_eq!(
+            (faults[0].failure_rate, faults[1].failure_rate),
+            (0.25, 0.0)
+        );
+
+        let mock = Mock {
+            light: Mutex::new(LightState {
+                on: 0,
+                brightness: 50,
+                temperature: 213,
+                hue: None,
+                saturation: None,
+            }),
+            broken: AtomicBool::new(false),
+            faults: Mutex::new(faults[0]),
+            requests: AtomicU64::new(0),
+        };
+        let failed = (0..400).filter(|_| mock.next_fault().1).count();
+        assert!((60..140).contains(&failed), "{failed} of 400 failed");
+
+        for bad in [&["smoke-middle=1"][..], &["1.5"], &["often"]] {
+            let failure_rate = strings(bad);
+            let options = FaultOptions {
+                failure_rate: &failure_rate,
+                ..options
+            };
+            assert!(options.faults().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn a_dead_group_member_only_fails_its_own_entry() {
+        let _turn = turn();
+        // 127.0.0.75 is saved but nothing answers there.
+        let mocks = lights(&["127.0.0.74", "127.0.0.75"], 1);
+        let group = json!({"name": "desk", "members": ["light-75", "light-74"]});
+        assert_eq!(call(Method::POST, "/v1/groups", Some(group)).0, 200);
+
+        let (status, results) = call(Method::PUT, "/v1/groups/desk", Some(json!({"on": 1})));
+        assert_eq!((status, count(&results)), (200, 2), "{results}");
+        assert_eq!(results[0]["numberOfLights"], 1);
+        assert_eq!(
+            (&results[1]["id"], &results[1]["ip"]),
+            (&json!("light-75"), &json!("127.0.0.75"))
+        );
+        assert!(results[1]["error"].is_string());
+        assert_eq!(state(&mocks[0]).on, 1);
+
+        let group = json!({"name": "dead", "members": ["light-75"]});
+        assert_eq!(call(Method::POST, "/v1/groups", Some(group)).0, 200);
+        let (status, body) = call(Method::PUT, "/v1/groups/dead", Some(json!({"on": 1})));
+        assert_eq!(status, 502, "{body}");
+    }
+
+    #[test]
+    fn updates_are_clamped_to_what_the_light_takes() {
+        let _turn = turn();
+        let mocks = lights(&["127.0.0.41", "127.0.0.42"], 2);
+        let narrow = KelvinRange {
+            min: 2700,
+            max: 6500,
+        };
+        let mut config = crate::load_config().unwrap();
+        config.lights[1].kelvin_range = Some(narrow);
+        save_state(&config).unwrap();
+
+        call(
+            Method::PUT,
+            "/v1/lights/light-41",
+            Some(json!({"kelvin": 10000})),
+        );
+        assert_eq!(
+            state(&mocks[0]).temperature,
+            KelvinRange::DEFAULT.kelvin_to_mired(7000)
+        );
+        call(
+            Method::PUT,
+            "/v1/lights/light-41",
+            Some(json!({"kelvin": 1000})),
+        );
+        assert_eq!(
+            state(&mocks[0]).temperature,
+            KelvinRange::DEFAULT.kelvin_to_mired(2900)
+        );
+        call(
+            Method::PUT,
+            "/v1/lights/light-42",
+            Some(json!({"kelvin": 10000})),
+        );
+        assert_eq!(state(&mocks[1]).temperature, narrow.kelvin_to_mired(6500));
+
+        // Past what any light takes is refused rather than clamped.
+        let before = state(&mocks[1]).brightness;
+        let (status, body) = call(
+            Method::PUT,
+            "/v1/lights/light-42",
+            Some(json!({"brightness": 250})),
+        );
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "brightness must be 0-100");
+        assert_eq!(state(&mocks[1]).brightness, before);
+        let limits = json!({"min": 20, "max": 80});
+        let path = "/v1/lights/light-41/brightness_limits";
+        assert_eq!(call(Method::PUT, path, Some(limits)).0, 200);
+        call(
+            Method::PUT,
+            "/v1/lights/light-41",
+            Some(json!({"brightness": 95})),
+        );
+        assert_eq!(state(&mocks[0]).brightness, 80);
+        call(
+            Method::PUT,
+            "/v1/lights/light-41",
+            Some(json!({"brightness": 5})),
+        );
+        assert_eq!(state(&mocks[0]).brightness, 20);
+    }
+
+    #[test]
+    fn errors_answer_with_the_status_of_their_kind() {
+        let _turn = turn();
+        // 127.0.0.52 is saved but nothing answers there.
+        let mocks = lights(&["127.0.0.51", "127.0.0.52"], 1);
+        let on = || Some(json!({"on": 1}));
+
+        assert_eq!(call(Method::PUT, "/v1/lights/nope", on()).0, 404);
+        assert_eq!(call(Method::PUT, "/v1/groups/nope", on()).0, 404);
+        let group = json!({"name": "desk", "members": ["nope"]});
+        assert_eq!(call(Method::POST, "/v1/groups", Some(group)).0, 400);
+        let wrong_type = json!({"brightness": "high"});
+        assert_eq!(
+            call(Method::PUT, "/v1/lights/light-51", Some(wrong_type)).0,
+            400
+        );
+
+        let (status, body) = call(Method::PUT, "/v1/lights/light-52", on());
+        assert_eq!(status, 502, "{body}");
+        mocks[0].broken.store(true, Ordering::Relaxed);
+        let (status, body) = call(Method::PUT, "/v1/lights/light-51", on());
+        mocks[0].broken.store(false, Ordering::Relaxed);
+        assert_eq!(status, 502, "{body}");
+        assert_eq!(state(&mocks[0]).on, 0);
+
+        let alias = json!({"alias": "light-52"});
+        let (status, body) = call(Method::PUT, "/v1/lights/light-51/alias", Some(alias));
+        assert_eq!((status, &body["conflict"]), (409, &json!("light-52")));
+    }
+
+    #[test]
+    fn flaky_lights_are_retried_and_slow_ones_time_out() {
+        let _turn = turn();
+        let mocks = lights(&["127.0.0.56", "127.0.0.57"], 2);
+        let on = || Some(json!({"on": 1}));
+
+        *mocks[0].faults.lock().unwrap() = Faults {
+            fail_next: 2,
+            ..Faults::default()
+        };
+        let path = "/v1/lights/light-56?retries=2";
+        assert_eq!(call(Method::PUT, path, on()).0, 200);
+        assert_eq!(state(&mocks[0]).on, 1);
+        let greedy = "/v1/lights/light-56?retries=1000";
+        assert_eq!(call(Method::PUT, greedy, on()).0, 400);
+
+        *mocks[1].faults.lock().unwrap() = Faults {
+            latency: Duration::from_millis(300),
+            jitter: Duration::from_millis(100),
+            ..Faults::default()
+        };
+        let slow = "/v1/lights/light-57?timeout_ms=2000";
+        assert_eq!(call(Method::PUT, slow, on()).0, 200);
+        let hurried = "/v1/lights/light-57?timeout_ms=100&retries=0";
+        let (status, body) = call(Method::PUT, hurried, on());
+        assert_eq!(status, 502, "{body}");
+        let (status, body) = call(Method::PUT, hurried, on());
+        assert_eq!(status, 502, "{body}");
+
+        // Two failures in a row open the breaker: the next request fails without
+        // waiting on the light, however long it's allowed.
+        let started = std::time::Instant::now();
+        let (status, body) = call(Method::PUT, slow, on());
+        assert_eq!(status, 502, "{body}");
+        assert!(started.elapsed() < Duration::from_millis(300));
+        let (_, health) = call(Method::GET, "/v1/lights/health", None);
+        let offline = health
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|light| light["id"] == "light-57")
+            .map(|light| light["offline"].clone());
+        assert_eq!(offline, Some(json!(true)));
+    }
+
+    #[test]
+    fn any_alias_is_reached_through_its_percent_encoded_path() {
+        let _turn = turn();
+        let mocks = lights(&["127.0.0.61"], 1);
+        let alias = "[a-z0-9 %/#?&+._-]{1,12}|\\PC{1,6}";
+        proptest!(ProptestConfig::with_cases(32), |(alias in alias)| {
+            let mut config = load_config().unwrap();
+            config.lights[0].alias = Some(alias.clone());
+            save_state(&config).unwrap();
+            let encoded = urlencoding::encode(&alias);
+            let resolve = format!("/v1/resolve/{encoded}");
+            prop_assert_eq!(handle(tiny_http::Method::Get, &resolve, ""), 200);
+            let light = format!("/v1/lights/{encoded}");
+            prop_assert_eq!(handle(tiny_http::Method::Put, &light, r#"{"on":1}"#), 200);
+        });
+        assert_eq!(state(&mocks[0]).on, 1);
+    }
+
+    #[test]
+    fn undecodable_paths_are_not_found() {
+        let _turn = turn();
+        lights(&["127.0.0.62"], 0);
+        // Stray and truncated escapes and ones that aren't UTF-8 once decoded.
+        let raw = "(%[0-9a-fA-FzZ]{0,2}|[/a-f0-9])*";
+        proptest!(ProptestConfig::with_cases(64), |(raw in raw)| {
+            let resolve = format!("/v1/resolve/{raw}");
+            prop_assert_eq!(handle(tiny_http::Method::Get, &resolve, ""), 404);
+            let light = format!("/v1/lights/{raw}");
+            let status = handle(tiny_http::Method::Put, &light, r#"{"on":1}"#);
+            prop_assert!((400..500).contains(&status), "{}: {}", raw, status);
+        });
+    }
+
+    #[test]
+    fn malformed_and_out_of_range_updates_are_refused() {
+        let _turn = turn();
+        let mocks = lights(&["127.0.0.63"], 1);
+        let paths = [
+            "/v1/lights/light-63",
+            "/v1/groups/desk",
+            "/v1/rooms/desk",
+            "/v1/all",
+        ];
+        proptest!(ProptestConfig::with_cases(64), |(
+            path in proptest::sample::select(paths.to_vec()),
+            update in out_of_range(),
+            // An object that never closes.
+            malformed in "\\{[^}]*",
+        )| {
+            let update = update.to_string();
+            prop_assert_eq!(handle(tiny_http::Method::Put, path, &update), 400, "{}", update);
+            prop_assert_eq!(handle(tiny_http::Method::Put, path, &malformed), 400);
+        });
+        assert_eq!(state(&mocks[0]).on, 0);
+    }
+
+    #[test]
+    fn pages_on_other_origins_are_refused_by_default() {
+        let _turn = turn();
+        let mocks = lights(&["127.0.0.71"], 1);
+        let daemon = daemon();
+        let request = |method, path: &str| {
+            daemon
+                .client
+                .request(method, format!("{}{path}", daemon.base))
+                .header("Origin", "http://dashboard.example")
+        };
+
+        let preflight = request(Method::OPTIONS, "/v1/lights/light-71")
+            .header("Access-Control-Request-Method", "PUT")
+            .send()
+            .unwrap();
+        assert_eq!(preflight.status().as_u16(), 403);
+        let update = request(Method::PUT, "/v1/lights/light-71")
+            .body(r#"{"on":1}"#)
+            .send()
+            .unwrap();
+        assert_eq!(update.status().as_u16(), 403);
+        assert_eq!(state(&mocks[0]).on, 0);
+        let read = request(Method::GET, "/v1/lights").send().unwrap();
+        assert_eq!(read.status().as_u16(), 200);
+        assert!(read.headers().get("Access-Control-Allow-Origin").is_none());
+
+        let (status, _) = call(Method::OPTIONS, "/v1/lights", None);
+        assert_eq!(status, 204);
+    }
+
+    #[test]
+    fn discovery_saves_keep_api_edits_made_meanwhile() {
+        let _turn = turn();
+        lights(&["127.0.0.72"], 0);
+        let found = Found {
+            id: "light-73".into(),
+            hostname: "127.0.0.73".into(),
+            port: 9123,
+            addresses: vec!["127.0.0.73".into()],
+        };
+        // While an edit is under way, discovery waits for it instead of saving what
+        // it loaded before the edit.
+        let editing = crate::lock_config_edits();
+        let first = found.clone();
+        let discovery = thread::spawn(move || crate::discovery::store(&first, None).unwrap());
+        let mut config = load_config().unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(!discovery.is_finished());
+        config.lights[0].alias = Some("desk".into());
+        save_state(&config).unwrap();
+        drop(editing);
+        discovery.join().unwrap();
+        let config = load_config().unwrap();
+        assert_eq!(config.lights[0].alias.as_deref(), Some("desk"));
+
+        let discovery = thread::spawn(move || {
+            for _ in 0..40 {
+                crate::discovery::store(&found, None).unwrap();
+            }
+        });
+        for n in 0..40 {
+            let alias = json!({"alias": format!("desk {n}")});
+            let (status, body) = call(Method::PUT, "/v1/lights/light-72/alias", Some(alias));
+            assert_eq!(status, 200, "{body}");
+        }
+        discovery.join().unwrap();
+
+        let config = load_config().unwrap();
+        let edited = config.lights.iter().find(|light| light.id == "light-72");
+        assert_eq!(
+            edited.and_then(|light| light.alias.as_deref()),
+            Some("desk 39")
+        );
+        assert!(config.lights.iter().any(|light| light.id == "light-73"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Named captures of the live state of every light, for `snapshot save` / `snapshot restore`.
+//! Unlike groups or schedules these are machine state, so they live in the state dir.
+//! The API and the tray call them scenes.
+
+use keylight_core::store::SavedLight;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use super::hooks::{self, HookEvent};
+use super::{
+    create_private_dir, fetch_light_state, load_config, read_optional, set_light,
+    state_dir, write_private_file, KeylightError, LightUpdate, Progress,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub taken_unix: u64,
+    pub lights: Vec<SnapshotLight>,
+    /// Place in scene lists; snapshots saved before scenes could be reordered are
+    /// all 0 and sort by name.
+    #[serde(default)]
+    pub position: u32,
+    /// Starred to be shown among the favorites.
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+/// A snapshot as `GET /v1/scenes` lists it.
+#[derive(Serialize, Debug)]
+pub struct Scene {
+    pub name: String,
+    pub lights: usize,
+    pub taken_unix: u64,
+    pub favorite: bool,
+}
+
+impl Scene {
+    pub fn new(name: &str, snapshot: &Snapshot) -> Self {
+        Scene {
+            name: name.to_string(),
+            lights: snapshot.lights.len(),
+            taken_unix: snapshot.taken_unix,
+            favorite: snapshot.favorite,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotLight {
+    pub id: String,
+    pub on: u8,
+    pub brightness: u8,
+    /// Device units (mired), stored as read so restore is exact.
+    pub temperature: u16,
+}
+
+impl SnapshotLight {
+    fn to_update(&self) -> LightUpdate {
+        LightUpdate {
+            on: Some(self.on),
+            brightness: Some(self.brightness),
+            temperature: Some(self.temperature),
+            ..Default::default()
+        }
+    }
+}
+
+fn snapshots_path() -> Result<PathBuf, KeylightError> {
+    Ok(state_dir()?.join("snapshots.json"))
+}
+
+fn load_snapshots() -> Result<BTreeMap<String, Snapshot>, KeylightError> {
+    let path = snapshots_path()?;
+    match read_optional(&path)? {
+        Some(bytes) => {
+            serde_json::from_slice(&bytes).map_err(|err| KeylightError::config(&path, err))
+        }
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+fn save_snapshots(snapshots: &BTreeMap<String, Snapshot>) -> Result<(), KeylightError> {
+    let path = snapshots_path()?;
+    let in_file = |err: &dyn std::fmt::Display| KeylightError::config(&path, err);
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent).map_err(|err| in_file(&err))?;
+    }
+    let bytes = serde_json::to_vec_pretty(snapshots).map_err(|err| in_file(&err))?;
+    write_private_file(&path, &bytes).map_err(|err| in_file(&err))
+}
+
+/// Read every enabled light and store the result under `name`, replacing any
+/// snapshot with the same name. Unreachable lights are left out.
+pub fn save(client: &Client, name: &str, quiet: bool) -> Result<Snapshot, KeylightError> {
+    if name.trim().is_empty() {
+        return Err(KeylightError::invalid("Snapshot name must not be empty"));
+    }
+    let config = load_config()?;
+    let enabled: Vec<_> = config.lights.iter().filter(|l| l.enabled).collect();
+    if enabled.is_empty() {
+        return Err(KeylightError::invalid(
+            "No persisted lights found. Run `discover` first.",
+        ));
+    }
+    let mut progress = Progress::new(quiet, Some(enabled.len()));
+    let mut lights = Vec::new();
+    for light in enabled {
+        let state =
+            light.address().and_then(|ip| fetch_light_state(client, light.backend, &ip));
+        match state {
+            Some(state) => {
+                progress.step(&light.id, "captured");
+                lights.push(SnapshotLight {
+                    id: light.id.clone(),
+                    on: state.on,
+                    brightness: state.brightness,
+                    temperature: state.temperature,
+                });
+            }
+            None => progress.step(&light.id, "unreachable, skipped"),
+        }
+    }
+    if lights.is_empty() {
+        return Err(KeylightError::invalid(
+            "No lights could be read; snapshot not saved",
+        ));
+    }
+    let mut snapshots = load_snapshots()?;
+    // Retaking a snapshot keeps its place and star; a new one goes last.
+    let position = match snapshots.get(name) {
+        Some(existing) => existing.position,
+        None => snapshots
+            .values()
+            .map(|s| s.position + 1)
+            .max()
+            .unwrap_or(0),
+    };
+    let favorite = snapshots
+        .get(name)
+        .is_some_and(|existing| existing.favorite);
+    let snapshot = Snapshot {
+        taken_unix: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        lights,
+        position,
+        favorite,
+    };
+    snapshots.insert(name.to_string(), snapshot.clone());
+    save_snapshots(&snapshots)?;
+    Ok(snapshot)
+}
+
+/// Push a saved snapshot back to the lights. Lights that were removed since are
+/// skipped; the first device error aborts the rest.
+pub fn restore(client: &Client, name: &str, quiet: bool) -> Result<(), KeylightError> {
+    let snapshots = load_snapshots()?;
+    let snapshot = snapshots
+        .get(name)
+        .ok_or_else(|| KeylightError::not_found("snapshot", name))?;
+    let config = load_config()?;
+    let mut progress = Progress::new(quiet, Some(snapshot.lights.len()));
+    for saved in &snapshot.lights {
+        let Some((light, ip)) = config
+            .lights
+            .iter()
+            .find(|light| light.id == saved.id)
+            .and_then(|light| Some((light, light.address()?)))
+        else {
+            progress.step(&saved.id, "no longer persisted, skipped");
+            continue;
+        };
+        if let Err(err) = set_light(client, light.backend, &ip, &saved.to_update()) {
+            progress.step(&saved.id, &format!("failed: {err}"));
+            return Err(err);
+        }
+        progress.step(&saved.id, "restored");
+    }
+    hooks::fire(HookEvent::SceneApplied {
+        name: name.to_string(),
+    });
+    Ok(())
+}
+
+pub fn list() -> Result<BTreeMap<String, Snapshot>, KeylightError> {
+    load_snapshots()
+}
+
+/// Every snapshot in scene order.
+pub fn scenes() -> Result<Vec<Scene>, KeylightError> {
+    Ok(in_order(&load_snapshots()?))
+}
+
+fn in_order(snapshots: &BTreeMap<String, Snapshot>) -> Vec<Scene> {
+    let mut scenes: Vec<_> = snapshots.iter().collect();
+    // The map iterates by name, so a stable sort keeps ties alphabetical.
+    scenes.sort_by_key(|(_, snapshot)| snapshot.position);
+    scenes
+        .into_iter()
+        .map(|(name, snapshot)| Scene::new(name, snapshot))
+        .collect()
+}
+
+pub fn rename(from: &str, to: &str) -> Result<(), KeylightError> {
+    let to = to.trim();
+    if to.is_empty() {
+        return Err(KeylightError::invalid("Snapshot name must not be empty"));
+    }
+    let mut snapshots = load_snapshots()?;
+    if from != to && snapshots.contains_key(to) {
+        return Err(KeylightError::invalid(format!(
+            "A snapshot named '{}' already exists",
+            to
+        )));
+    }
+    let snapshot = snapshots
+        .remove(from)
+        .ok_or_else(|| KeylightError::not_found("snapshot", from))?;
+    snapshots.insert(to.to_string(), snapshot);
+    save_snapshots(&snapshots)
+}
+
+/// Star or unstar a snapshot.
+pub fn set_favorite(name: &str, favorite: bool) -> Result<Scene, KeylightError> {
+    let mut snapshots = load_snapshots()?;
+    let snapshot = snapshots
+        .get_mut(name)
+        .ok_or_else(|| KeylightError::not_found("snapshot", name))?;
+    snapshot.favorite = favorite;
+    let scene = Scene::new(name, snapshot);
+    save_snapshots(&snapshots)?;
+    Ok(scene)
+}
+
+pub fn delete(name: &str) -> Result<(), KeylightError> {
+    let mut snapshots = load_snapshots()?;
+    snapshots
+        .remove(name)
+        .ok_or_else(|| KeylightError::not_found("snapshot", name))?;
+    save_snapshots(&snapshots)
+}
+
+/// Put the named snapshots first, in the order given; the rest keep their order
+/// after them. Unknown names are an error so a stale list isn't half applied.
+pub fn reorder(names: &[String]) -> Result<(), KeylightError> {
+    let mut snapshots = load_snapshots()?;
+    if let Some(unknown) = names.iter().find(|name| !snapshots.contains_key(*name)) {
+        return Err(KeylightError::not_found("snapshot", unknown));
+    }
+    reposition(&mut snapshots, names);
+    save_snapshots(&snapshots)
+}
+
+fn reposition(snapshots: &mut BTreeMap<String, Snapshot>, names: &[String]) {
+    let rest: Vec<String> = in_order(snapshots)
+        .into_iter()
+        .map(|scene| scene.name)
+        .filter(|name| !names.contains(name))
+        .collect();
+    for (position, name) in names.iter().chain(&rest).enumerate() {
+        if let Some(snapshot) = snapshots.get_mut(name) {
+            snapshot.position = position as u32;
+        }
+    }
+}
+
+/// Applies snapshots for an automation (OBS, calls): saves the lights under
+/// `previous` before the first one and restores that once no scene is wanted.
+pub struct SceneSwitcher {
+    previous: &'static str,
+    applied: Option<String>,
+}
+
+impl SceneSwitcher {
+    pub const fn new(previous: &'static str) -> Self {
+        Self {
+            previous,
+            applied: None,
+        }
+    }
+
+    /// Apply `wanted`, or put the previous state back for `None`. Returns whether
+    /// anything was applied.
+    pub fn switch(&mut self, client: &Client, wanted: Option<&str>) -> Result<bool, KeylightError> {
+        if self.applied.as_deref() == wanted {
+            return Ok(false);
+        }
+        match wanted {
+            Some(name) => {
+                if self.applied.is_none() {
+                    save(client, self.previous, true)?;
+                }
+                restore(client, name, true)?;
+            }
+            None => restore(client, self.previous, true)?,
+        }
+        self.applied = wanted.map(str::to_string);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_sends_every_field() {
+        let light = SnapshotLight {
+            id: "left".into(),
+            on: 1,
+            brightness: 35,
+            temperature: 250,
+        };
+        let update = light.to_update();
+        assert_eq!(update.on, Some(1));
+        assert_eq!(update.brightness, Some(35));
+        assert_eq!(update.temperature, Some(250));
+    }
+
+    #[test]
+    fn reordering_moves_named_scenes_first() {
+        let snapshot = |position| Snapshot {
+            taken_unix: 0,
+            lights: Vec::new(),
+            position,
+            favorite: false,
+        };
+        // Legacy snapshots all sit at 0 and list by name.
+        let mut snapshots = BTreeMap::from([
+            ("call".to_string(), snapshot(0)),
+            ("late".to_string(), snapshot(0)),
+            ("stream".to_string(), snapshot(0)),
+        ]);
+        let names = |snapshots: &BTreeMap<String, Snapshot>| -> Vec<String> {
+            in_order(snapshots).into_iter().map(|s| s.name).collect()
+        };
+        assert_eq!(names(&snapshots), ["call", "late", "stream"]);
+
+        reposition(&mut snapshots, &["stream".to_string()]);
+        assert_eq!(names(&snapshots), ["stream", "call", "late"]);
+        reposition(&mut snapshots, &["late".to_string(), "stream".to_string()]);
+        assert_eq!(names(&snapshots), ["late", "stream", "call"]);
+    }
+}
@@ -0,0 +1,110 @@
+//! Soft start: for lights with `soft_start` set, switching on ramps brightness up from
+//! the bottom and switching off ramps it down, instead of snapping.
+
+use reqwest::blocking::Client;
+use std::thread;
+use std::time::Duration;
+
+use super::{
+    fetch_light_state, send_update, Backend, KeylightError, LightState, LightUpdate, LightsPayload,
+};
+
+const RAMP: Duration = Duration::from_millis(500);
+const STEPS: u8 = 10;
+/// Dimmest level the ramp starts from or ends at; Key Lights floor at 3%.
+pub const FLOOR: u8 = 3;
+
+/// Brightness for each ramp step after the first, ending exactly at `to`.
+fn ramp_levels(from: u8, to: u8) -> Vec<u8> {
+    (1..=STEPS)
+        .map(|step| {
+            let delta = (to as i16 - from as i16) * step as i16 / STEPS as i16;
+            (from as i16 + delta) as u8
+        })
+        .collect()
+}
+
+/// Apply a power change with a ramp. Anything other than an off→on or on→off switch
+/// (or a light whose state can't be read) is sent as-is.
+pub fn switch(
+    client: &Client,
+    backend: Backend,
+    ip: &str,
+    update: &LightUpdate,
+) -> Result<LightsPayload<LightState>, KeylightError> {
+    let Some(current) = fetch_light_state(client, backend, ip) else {
+        return send_update(client, backend, ip, update);
+    };
+    let pause = RAMP / STEPS as u32;
+    match (current.on, update.on) {
+        (0, Some(1)) => {
+            let target = update.brightness.unwrap_or(current.brightness).max(FLOOR);
+            let mut response = send_update(
+                client,
+                backend,
+                ip,
+                &LightUpdate {
+                    brightness: Some(FLOOR),
+                    ..update.clone()
+                },
+            )?;
+            for level in ramp_levels(FLOOR, target) {
+                thread::sleep(pause);
+                response = send_update(
+                    client,
+                    backend,
+                    ip,
+                    &LightUpdate {
+                        brightness: Some(level),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            Ok(response)
+        }
+        (1, Some(0)) => {
+            let start = current.brightness;
+            for level in ramp_levels(start, FLOOR.min(start)) {
+                send_update(
+                    client,
+                    backend,
+                    ip,
+                    &LightUpdate {
+                        brightness: Some(level),
+                        ..Default::default()
+                    },
+                )?;
+                thread::sleep(pause);
+            }
+            // Put the brightness back while switching off, so the next plain "on"
+            // doesn't come up at the floor.
+            send_update(
+                client,
+                backend,
+                ip,
+                &LightUpdate {
+                    brightness: Some(update.brightness.unwrap_or(start)),
+                    ..update.clone()
+                },
+            )
+        }
+        _ => send_update(client, backend, ip, update),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_end_on_the_target() {
+        let up = ramp_levels(FLOOR, 80);
+        assert_eq!(up.len(), STEPS as usize);
+        assert_eq!(up.last(), Some(&80));
+        assert!(up.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let down = ramp_levels(60, FLOOR);
+        assert_eq!(down.last(), Some(&FLOOR));
+        assert!(down.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+}
@@ -0,0 +1,93 @@
+//! What `GET /v1/status` reports about the running daemon: how long it's been up,
+//! which automations are running and when lights were last discovered.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use super::dnd::{self, DndStatus};
+use super::Config;
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+/// Automation threads that are running, by name.
+static AUTOMATIONS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+/// Unix time a discovery last completed or an announcement was picked up.
+static LAST_DISCOVERY: Mutex<Option<u64>> = Mutex::new(None);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DaemonStatus {
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    /// Enabled lights.
+    pub lights: usize,
+    /// Running automations: `schedules`, `obs`, `conferencing`, `auto_brightness`.
+    pub automations: Vec<&'static str>,
+    pub last_discovery_unix: Option<u64>,
+    pub dnd: DndStatus,
+}
+
+/// Start the uptime clock. Called once by `serve`.
+pub fn started() {
+    STARTED.get_or_init(Instant::now);
+}
+
+pub fn automation_started(name: &'static str) {
+    if let Ok(mut automations) = AUTOMATIONS.lock() {
+        automations.insert(name);
+    }
+}
+
+/// For automations that give up, like call detection without `pw-dump`.
+pub fn automation_stopped(name: &'static str) {
+    if let Ok(mut automations) = AUTOMATIONS.lock() {
+        automations.remove(name);
+    }
+}
+
+pub fn discovered() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if let Ok(mut last) = LAST_DISCOVERY.lock() {
+        *last = Some(now);
+    }
+}
+
+pub fn report(config: &Config) -> DaemonStatus {
+    let mut automations: Vec<_> = AUTOMATIONS
+        .lock()
+        .map(|automations| automations.iter().copied().collect())
+        .unwrap_or_default();
+    // The scheduler always runs; it only counts as an automation with something to do.
+    if config.schedules.is_empty() {
+        automations.retain(|name| *name != "schedules");
+    }
+    DaemonStatus {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: STARTED
+            .get()
+            .map_or(0, |started| started.elapsed().as_secs()),
+        lights: config.lights.iter().filter(|light| light.enabled).count(),
+        automations,
+        last_discovery_unix: LAST_DISCOVERY.lock().ok().and_then(|last| *last),
+        dnd: dnd::status(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_scheduler_is_not_reported() {
+        automation_started("schedules");
+        automation_started("obs");
+        let automations = report(&Config::default()).automations;
+        assert!(automations.contains(&"obs"));
+        assert!(!automations.contains(&"schedules"));
+        automation_stopped("obs");
+        assert!(!report(&Config::default()).automations.contains(&"obs"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Active scan of an IPv4 range for Elgato-compatible lights, for clones that don't
+//! announce themselves over mDNS or only answer unicast queries: every address is
+//! asked for `/elgato/accessory-info` on port 9123.
+
+use reqwest::blocking::Client;
+use serde_json::Value;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::logs::log_line;
+use super::{detect_kelvin_range, fetch_accessory_info, supports_color};
+use super::{Backend, Capabilities, DiscoveredLight};
+
+/// Largest range scanned, a /20; wider ones take too long to be useful.
+pub const MAX_HOSTS: u32 = 4096;
+/// Addresses probed at the same time.
+const PARALLEL: usize = 64;
+/// Most addresses have nothing listening; don't wait long for each.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(800);
+
+/// An IPv4 network in CIDR notation, like `192.168.1.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    network: u32,
+    prefix: u8,
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid subnet '{value}' (expected e.g. 192.168.1.0/24)");
+        let (address, prefix) = value.split_once('/').ok_or_else(invalid)?;
+        let address: Ipv4Addr = address.parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        if prefix > 32 {
+            return Err(invalid());
+        }
+        let subnet = Subnet {
+            network: u32::from(address) & mask(prefix),
+            prefix,
+        };
+        if subnet.size() > MAX_HOSTS {
+            return Err(format!(
+                "Subnet '{value}' is too large to scan (at most {MAX_HOSTS} addresses, a /20)"
+            ));
+        }
+        Ok(subnet)
+    }
+}
+
+impl std::fmt::Display for Subnet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", Ipv4Addr::from(self.network), self.prefix)
+    }
+}
+
+fn mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+}
+
+impl Subnet {
+    fn size(self) -> u32 {
+        1u32.checked_shl(32 - u32::from(self.prefix))
+            .unwrap_or(u32::MAX)
+    }
+
+    /// Addresses to probe: all but the network and broadcast addresses, which only
+    /// /31 and /32 networks don't have.
+    fn hosts(self) -> impl Iterator<Item = Ipv4Addr> {
+        let size = self.size();
+        let (first, last) = if size > 2 {
+            (1, size - 2)
+        } else {
+            (0, size - 1)
+        };
+        (first..=last).map(move |offset| Ipv4Addr::from(self.network + offset))
+    }
+}
+
+/// Lights answering in `subnet`, named and identified from their accessory info.
+pub fn scan(subnet: Subnet) -> Vec<DiscoveredLight> {
+    let client = match Client::builder().timeout(PROBE_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(err) => {
+            log_line!("subnet scan failed: {err}");
+            return Vec::new();
+        }
+    };
+    let hosts: Vec<Ipv4Addr> = subnet.hosts().collect();
+    let mut found = Vec::new();
+    for batch in hosts.chunks(PARALLEL) {
+        let client = &client;
+        found.extend(std::thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .iter()
+                .map(|ip| scope.spawn(move || probe(client, &ip.to_string())))
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok().flatten())
+                .collect::<Vec<_>>()
+        }));
+    }
+    found
+}
+
+fn probe(client: &Client, ip: &str) -> Option<DiscoveredLight> {
+    let info = fetch_accessory_info(client, ip)?;
+    Some(light_from_info(client, ip, info))
+}
+
+fn light_from_info(client: &Client, ip: &str, info: Value) -> DiscoveredLight {
+    let text = |key: &str| {
+        info.get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+    };
+    let id = match text("serialNumber") {
+        Some(serial) => format!("elgato-{serial}"),
+        None => format!("elgato-{ip}"),
+    };
+    let name = text("displayName")
+        .or_else(|| text("productName"))
+        .unwrap_or_else(|| format!("Light at {ip}"));
+    DiscoveredLight {
+        id,
+        name,
+        ip: ip.to_string(),
+        port: 9123,
+        backend: Backend::Elgato,
+        capabilities: Capabilities {
+            color: supports_color(Some(&info)),
+            ..Default::default()
+        },
+        kelvin_range: detect_kelvin_range(client, ip, Some(&info)),
+        accessory_info: Some(info),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subnets_list_their_hosts() {
+        let subnet: Subnet = "192.168.1.77/24".parse().unwrap();
+        let hosts: Vec<Ipv4Addr> = subnet.hosts().collect();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(hosts[253], Ipv4Addr::new(192, 168, 1, 254));
+        assert_eq!(subnet.to_string(), "192.168.1.0/24");
+
+        let single: Subnet = "10.0.0.5/32".parse().unwrap();
+        assert_eq!(
+            single.hosts().collect::<Vec<_>>(),
+            [Ipv4Addr::new(10, 0, 0, 5)]
+        );
+
+        assert!("10.0.0.0/16".parse::<Subnet>().is_err());
+        assert!("10.0.0.0/33".parse::<Subnet>().is_err());
+        assert!("10.0.0.0".parse::<Subnet>().is_err());
+        assert!("10.0.0.0/20".parse::<Subnet>().is_ok());
+    }
+}
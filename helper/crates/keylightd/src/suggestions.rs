@@ -0,0 +1,145 @@
+//! Group suggestions from light aliases: lights named "Desk Left" and "Desk Right"
+//! probably belong together. Only aliases count, since discovered names ("Elgato Key
+//! Light 1A2B") would put every light in one big group.
+
+use keylight_core::store;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{Config, LightRecord};
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GroupSuggestion {
+    /// The words the aliases start with.
+    pub name: String,
+    /// Light ids, ready for `POST /v1/groups`.
+    pub members: Vec<String>,
+    /// The members' aliases, for showing the suggestion.
+    pub aliases: Vec<String>,
+}
+
+/// Lights whose aliases start with the same word: each light, its alias, and the
+/// alias split into words.
+type Bucket<'a> = Vec<(&'a LightRecord, &'a str, Vec<&'a str>)>;
+
+fn words(alias: &str) -> Vec<&str> {
+    alias
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Enabled lights whose aliases share a first word, at least two per suggestion.
+/// Suggestions that match an existing group, by name or by members, are left out.
+pub fn suggest(config: &Config) -> Vec<GroupSuggestion> {
+    let mut buckets: BTreeMap<String, Bucket> = BTreeMap::new();
+    for light in config.lights.iter().filter(|light| light.enabled) {
+        let Some(alias) = light.alias.as_deref() else {
+            continue;
+        };
+        let split = words(alias);
+        // A lone word ("Desk") has nothing after the shared part to tell it apart.
+        if split.len() < 2 {
+            continue;
+        }
+        buckets
+            .entry(split[0].to_lowercase())
+            .or_default()
+            .push((light, alias, split));
+    }
+
+    let existing: Vec<BTreeSet<&str>> = config
+        .groups
+        .iter()
+        .map(|group| {
+            group
+                .members
+                .iter()
+                .filter_map(|member| store::find(&config.lights, member))
+                .map(|light| light.id.as_str())
+                .collect()
+        })
+        .collect();
+
+    let mut suggestions = Vec::new();
+    for lights in buckets.into_values().filter(|lights| lights.len() >= 2) {
+        let first = &lights[0].2;
+        let same_word = |n: usize| {
+            lights.iter().all(|(_, _, words)| {
+                words
+                    .get(n)
+                    .is_some_and(|w| w.eq_ignore_ascii_case(first[n]))
+            })
+        };
+        let shared = (1..first.len()).take_while(|&n| same_word(n)).count();
+        let name = first[..=shared].join(" ");
+        let members: BTreeSet<&str> = lights.iter().map(|(light, ..)| light.id.as_str()).collect();
+        let taken = config
+            .groups
+            .iter()
+            .any(|group| group.name.eq_ignore_ascii_case(&name))
+            || existing.contains(&members);
+        if taken {
+            continue;
+        }
+        suggestions.push(GroupSuggestion {
+            name,
+            members: lights.iter().map(|(light, ..)| light.id.clone()).collect(),
+            aliases: lights
+                .iter()
+                .map(|(_, alias, _)| alias.to_string())
+                .collect(),
+        });
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Group;
+    use super::*;
+
+    fn light(id: &str, alias: Option<&str>) -> LightRecord {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "alias": alias,
+            "name": id,
+            "hostname": format!("{id}.local"),
+            "port": 9123,
+            "addresses": ["192.168.1.20"],
+            "last_seen_unix": 0,
+            "enabled": true
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn aliases_sharing_a_prefix_are_suggested_once() {
+        let mut config = Config {
+            lights: vec![
+                light("a", Some("Desk Key Left")),
+                light("b", Some("desk-key right")),
+                light("c", Some("Shelf Strip")),
+                light("d", Some("Shelf")),
+                light("e", None),
+            ],
+            ..Default::default()
+        };
+        let suggestions = suggest(&config);
+        assert_eq!(
+            suggestions,
+            vec![GroupSuggestion {
+                name: "Desk Key".into(),
+                members: vec!["a".into(), "b".into()],
+                aliases: vec!["Desk Key Left".into(), "desk-key right".into()],
+            }]
+        );
+
+        config.groups.push(Group {
+            name: "office".into(),
+            members: vec!["desk-key right".into(), "a".into()],
+            stagger_ms: None,
+        });
+        assert!(suggest(&config).is_empty());
+    }
+}
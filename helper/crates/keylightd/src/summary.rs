@@ -0,0 +1,190 @@
+//! Compact per-light state for button controllers such as a Stream Deck plugin.
+//!
+//! `GET /v1/lights/{id}/summary` returns just what a key icon needs, with an ETag.
+//! Sending that ETag back in `If-None-Match` together with `?wait=<seconds>` turns
+//! the request into a long poll that answers as soon as the icon would change, or
+//! with 304 when nothing did.
+
+use keylight_core::store::SavedLight;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use super::events::{self, LightFields};
+use super::{
+    fetch_light_state, load_config, observed_fields, set_light, Config, KeylightError,
+    LightRecord, LightUpdate,
+};
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub id: String,
+    /// Alias if set, otherwise the device name.
+    pub label: String,
+    pub on: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kelvin: Option<u16>,
+    /// Whether the light answered the last time it was asked.
+    pub reachable: bool,
+}
+
+impl Summary {
+    fn new(record: &LightRecord, fields: Option<&LightFields>, reachable: bool) -> Self {
+        Self {
+            id: record.id.clone(),
+            label: record.alias.clone().unwrap_or_else(|| record.name.clone()),
+            on: fields.and_then(|f| f.on).unwrap_or(false),
+            brightness: fields.and_then(|f| f.brightness),
+            kelvin: fields.and_then(|f| f.kelvin),
+            reachable,
+        }
+    }
+
+    /// Quoted strong ETag; only meaningful to the daemon instance that issued it.
+    pub fn etag(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.label.hash(&mut hasher);
+        (self.on, self.brightness, self.kelvin, self.reachable).hash(&mut hasher);
+        format!("\"{:016x}\"", hasher.finish())
+    }
+}
+
+fn find_record(ident: &str) -> Result<(Config, LightRecord), KeylightError> {
+    let config = load_config()?;
+    let record = config
+        .lights
+        .iter()
+        .find(|light| {
+            light.id == ident || light.name == ident || light.alias.as_deref() == Some(ident)
+        })
+        .cloned()
+        .ok_or_else(|| KeylightError::LightNotFound(ident.to_string()))?;
+    Ok((config, record))
+}
+
+/// Ask the light for its state (feeding the event log) and summarize it.
+pub fn current(client: &Client, ident: &str) -> Result<Summary, KeylightError> {
+    let (config, record) = find_record(ident)?;
+    Ok(observe(client, &config, &record))
+}
+
+fn observe(client: &Client, config: &Config, record: &LightRecord) -> Summary {
+    let state = record.address()
+        .and_then(|ip| fetch_light_state(client, record.backend, &ip))
+        .map(|state| observed_fields(config, record, &state));
+    if let Some(fields) = &state {
+        events::record(&record.id, fields);
+    }
+    Summary::new(record, state.as_ref(), state.is_some())
+}
+
+/// Summary once its ETag differs from `etag`, or `None` if it didn't within `wait`.
+/// The light is queried once up front; after that changes come from the event log.
+pub fn wait_for_change(
+    client: &Client,
+    ident: &str,
+    etag: &str,
+    wait: Duration,
+) -> Result<Option<Summary>, KeylightError> {
+    let deadline = Instant::now() + wait.min(events::MAX_WAIT);
+    let (config, record) = find_record(ident)?;
+    let first = observe(client, &config, &record);
+    if first.etag() != etag {
+        return Ok(Some(first));
+    }
+    let (mut revision, _) = events::light(&record.id);
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        revision = match events::wait_since(revision, remaining) {
+            Ok(batch) if batch.events.is_empty() => break,
+            Ok(batch) => batch.revision,
+            // Fell behind a burst of events; just compare against the latest state.
+            Err(events::Expired) => events::light(&record.id).0,
+        };
+        let (_, fields) = events::light(&record.id);
+        let summary = Summary::new(&record, fields.as_ref(), first.reachable);
+        if summary.etag() != etag {
+            return Ok(Some(summary));
+        }
+    }
+    Ok(None)
+}
+
+/// Flip the light's power and return the resulting summary.
+pub fn toggle(client: &Client, ident: &str) -> Result<Summary, KeylightError> {
+    let (config, record) = find_record(ident)?;
+    if !record.enabled {
+        return Err(KeylightError::invalid(format!("Light '{ident}' is disabled")));
+    }
+    let ip = record
+        .address()
+        .ok_or_else(|| KeylightError::invalid("Light has no known address"))?;
+    let state = fetch_light_state(client, record.backend, &ip).ok_or_else(|| {
+        KeylightError::Unreachable {
+            ip: ip.clone(),
+            reason: "no state returned".to_string(),
+        }
+    })?;
+    let update = LightUpdate {
+        on: Some(u8::from(state.on == 0)),
+        ..Default::default()
+    };
+    let response = set_light(client, record.backend, &ip, &update)?;
+    let fields = response
+        .lights
+        .first()
+        .map(|state| observed_fields(&config, &record, state));
+    Ok(Summary::new(&record, fields.as_ref(), true))
+}
+
+/// `wait` (seconds) from a summary query string; absent means answer right away.
+pub fn parse_wait(query: &str) -> Result<Option<Duration>, String> {
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if key == "wait" {
+            let secs = value
+                .parse()
+                .map_err(|_| format!("Invalid wait '{value}'"))?;
+            return Ok(Some(Duration::from_secs(secs)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(on: bool) -> Summary {
+        Summary {
+            id: "left".into(),
+            label: "Left".into(),
+            on,
+            brightness: Some(40),
+            kelvin: Some(4500),
+            reachable: true,
+        }
+    }
+
+    #[test]
+    fn etag_follows_icon_state() {
+        assert_eq!(summary(true).etag(), summary(true).etag());
+        assert_ne!(summary(true).etag(), summary(false).etag());
+        let renamed = Summary {
+            label: "Desk".into(),
+            ..summary(true)
+        };
+        assert_ne!(renamed.etag(), summary(true).etag());
+        assert!(renamed.etag().starts_with('"') && renamed.etag().ends_with('"'));
+    }
+
+    #[test]
+    fn wait_is_optional() {
+        assert_eq!(parse_wait(""), Ok(None));
+        assert_eq!(parse_wait("wait=20"), Ok(Some(Duration::from_secs(20))));
+        assert!(parse_wait("wait=soon").is_err());
+    }
+}
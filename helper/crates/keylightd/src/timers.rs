@@ -0,0 +1,167 @@
+//! One-shot timers: "turn the office group off in 45 minutes". Unlike schedules they
+//! aren't tied to a time of day and run once. They live in memory only, so a daemon
+//! restart drops them, and as manual actions they run during do-not-disturb too.
+
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::logs::log_line;
+use super::schedule::ScheduleTarget;
+use super::{apply_update_to_targets, TargetResult, UpdateRequest};
+
+/// Longest delay accepted; anything later is what schedules are for.
+pub const MAX_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+const TICK: Duration = Duration::from_secs(1);
+
+static TIMERS: Mutex<Timers> = Mutex::new(Timers {
+    next_id: 1,
+    pending: Vec::new(),
+});
+
+struct Timers {
+    next_id: u64,
+    pending: Vec<Pending>,
+}
+
+struct Pending {
+    id: u64,
+    target: ScheduleTarget,
+    update: UpdateRequest,
+    due: Instant,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Timer {
+    pub id: u64,
+    pub target: ScheduleTarget,
+    pub update: UpdateRequest,
+    /// Whole seconds left, rounded up.
+    pub remaining_secs: u64,
+}
+
+impl Pending {
+    fn timer(&self) -> Timer {
+        let left = self.due.saturating_duration_since(Instant::now());
+        Timer {
+            id: self.id,
+            target: self.target.clone(),
+            update: self.update.clone(),
+            remaining_secs: left.as_secs() + u64::from(left.subsec_nanos() > 0),
+        }
+    }
+}
+
+/// Apply `update` to `target` once `delay` from now.
+pub fn start(
+    target: ScheduleTarget,
+    update: UpdateRequest,
+    delay: Duration,
+) -> Result<Timer, &'static str> {
+    if delay.is_zero() || delay > MAX_DELAY {
+        return Err("Timers must run between 1 second and 24 hours from now.");
+    }
+    let mut timers = TIMERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let pending = Pending {
+        id: timers.next_id,
+        target,
+        update,
+        due: Instant::now() + delay,
+    };
+    timers.next_id += 1;
+    let timer = pending.timer();
+    timers.pending.push(pending);
+    Ok(timer)
+}
+
+/// Pending timers, the next to run first.
+pub fn list() -> Vec<Timer> {
+    let timers = TIMERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut pending: Vec<&Pending> = timers.pending.iter().collect();
+    pending.sort_by_key(|pending| pending.due);
+    pending.into_iter().map(Pending::timer).collect()
+}
+
+/// Drop the timer with `id`; false when there is none (it may have run already).
+pub fn cancel(id: u64) -> bool {
+    let mut timers = TIMERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let before = timers.pending.len();
+    timers.pending.retain(|pending| pending.id != id);
+    timers.pending.len() < before
+}
+
+/// Start running timers as they come due. Called once by `serve`.
+pub fn spawn(client: Client) {
+    let spawned = thread::Builder::new()
+        .name("timers".into())
+        .spawn(move || run(client));
+    if let Err(err) = spawned {
+        log_line!("failed to start timers: {err}");
+    }
+}
+
+fn run(client: Client) {
+    loop {
+        for pending in take_due(Instant::now()) {
+            let (id, group, all) = pending.target.selection();
+            match apply_update_to_targets(&client, id, group, all, pending.update, false) {
+                Ok(results) => {
+                    for result in results {
+                        if let TargetResult::Failed { ip, error, .. } = result {
+                            log_line!("timer {}: {ip}: {error}", pending.id);
+                        }
+                    }
+                }
+                Err(err) => log_line!("timer {}: {err}", pending.id),
+            }
+        }
+        thread::sleep(TICK);
+    }
+}
+
+fn take_due(now: Instant) -> Vec<Pending> {
+    let mut timers = TIMERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let (due, waiting) = std::mem::take(&mut timers.pending)
+        .into_iter()
+        .partition(|pending| pending.due <= now);
+    timers.pending = waiting;
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers_run_once_and_can_be_cancelled() {
+        let off = UpdateRequest {
+            on: Some(0),
+            ..Default::default()
+        };
+        let target = ScheduleTarget::Group("timers-test".into());
+        assert!(start(target.clone(), off.clone(), Duration::ZERO).is_err());
+        assert!(start(target.clone(), off.clone(), MAX_DELAY * 2).is_err());
+
+        let soon = start(target.clone(), off.clone(), Duration::from_secs(60)).unwrap();
+        let later = start(target, off, Duration::from_secs(120)).unwrap();
+        assert_eq!(soon.remaining_secs, 60);
+        let listed: Vec<u64> = list().iter().map(|timer| timer.id).collect();
+        assert_eq!(listed, [soon.id, later.id]);
+
+        assert!(cancel(later.id));
+        assert!(!cancel(later.id));
+        let due = take_due(Instant::now() + Duration::from_secs(61));
+        assert!(due.iter().any(|pending| pending.id == soon.id));
+        assert!(!list().iter().any(|timer| timer.id == soon.id));
+    }
+}
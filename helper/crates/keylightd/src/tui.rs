@@ -0,0 +1,508 @@
+//! `keylightd tui`: a terminal interface for when the tray can't run, e.g. over SSH.
+//! Lights are listed with gauges for the selected one's brightness and temperature,
+//! and groups and scenes can be switched from the same screen. Like the other
+//! commands it talks to the lights directly.
+
+use keylight_core::store::SavedLight;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use reqwest::blocking::Client;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use super::limits::Backend;
+use super::{
+    fetch_light_state, load_config, locale, resolve_group, set_light, snapshot, Config,
+    KelvinRange, LightStateResponse, LightUpdate,
+};
+
+const BRIGHTNESS_STEP: i16 = 5;
+const KELVIN_STEP: i32 = 100;
+/// How often the lights are read again while no key is pressed.
+const REFRESH: Duration = Duration::from_secs(10);
+const HELP: &str = "Tab pane · ↑/↓ select · Space on/off · ←/→ brightness · \
+                    Shift+←/→ temperature · Enter apply scene · r reload · q quit";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Pane {
+    Lights,
+    Groups,
+    Scenes,
+}
+
+impl Pane {
+    fn next(self) -> Self {
+        match self {
+            Pane::Lights => Pane::Groups,
+            Pane::Groups => Pane::Scenes,
+            Pane::Scenes => Pane::Lights,
+        }
+    }
+}
+
+/// What a key asks for beyond changing the lights on screen.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Quit,
+    Reload,
+    ApplyScene(String),
+}
+
+struct Light {
+    id: String,
+    label: String,
+    ip: String,
+    backend: Backend,
+    range: KelvinRange,
+    /// `None` while the light isn't answering.
+    state: Option<LightStateResponse>,
+    /// Changed on screen and not sent yet.
+    pending: Option<LightUpdate>,
+}
+
+impl Light {
+    fn pending_update(&mut self) -> &mut LightUpdate {
+        self.pending.get_or_insert_with(LightUpdate::default)
+    }
+
+    fn set_on(&mut self, on: bool) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+        state.on = on;
+        self.pending_update().on = Some(u8::from(on));
+    }
+
+    fn step_brightness(&mut self, step: i16) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+        state.brightness = (i16::from(state.brightness) + step).clamp(0, 100) as u8;
+        let brightness = state.brightness;
+        self.pending_update().brightness = Some(brightness);
+    }
+
+    fn step_kelvin(&mut self, step: i32) {
+        let range = self.range;
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+        let kelvin = (i32::from(state.kelvin) + step).clamp(0, i32::from(u16::MAX)) as u16;
+        state.kelvin = range.clamp_kelvin(kelvin);
+        let mired = range.kelvin_to_mired(state.kelvin);
+        self.pending_update().temperature = Some(mired);
+    }
+
+    fn summary(&self) -> String {
+        match &self.state {
+            Some(state) if state.on => {
+                let format = locale::current();
+                let brightness = format.integer(state.brightness);
+                format!("{brightness}% · {}K", format.integer(state.kelvin))
+            }
+            Some(_) => "off".into(),
+            None => "not answering".into(),
+        }
+    }
+}
+
+struct App {
+    config: Config,
+    lights: Vec<Light>,
+    scenes: Vec<String>,
+    pane: Pane,
+    /// Selected row in each pane.
+    selected: [usize; 3],
+    /// Last error or action, shown above the key help.
+    status: String,
+}
+
+impl App {
+    fn new(config: Config, scenes: Vec<String>) -> Self {
+        let lights = config
+            .lights
+            .iter()
+            .filter(|light| light.enabled)
+            .filter_map(|light| {
+                Some(Light {
+                    id: light.id.clone(),
+                    label: light.alias.clone().unwrap_or_else(|| light.name.clone()),
+                    ip: light.address()?,
+                    backend: light.backend,
+                    range: light.supported_kelvin(),
+                    state: None,
+                    pending: None,
+                })
+            })
+            .collect();
+        Self {
+            config,
+            lights,
+            scenes,
+            pane: Pane::Lights,
+            selected: [0; 3],
+            status: String::new(),
+        }
+    }
+
+    fn load() -> Result<Self, Box<dyn Error>> {
+        let scenes = snapshot::scenes()?
+            .into_iter()
+            .map(|scene| scene.name)
+            .collect();
+        Ok(Self::new(load_config()?, scenes))
+    }
+
+    fn read_states(&mut self, client: &Client) {
+        for light in &mut self.lights {
+            light.state = fetch_light_state(client, light.backend, &light.ip).map(|state| {
+                LightStateResponse {
+                    id: light.id.clone(),
+                    on: state.on == 1,
+                    brightness: state.brightness,
+                    kelvin: light.range.mired_to_kelvin(state.temperature),
+                }
+            });
+            light.pending = None;
+        }
+    }
+
+    fn rows(&self, pane: Pane) -> usize {
+        match pane {
+            Pane::Lights => self.lights.len(),
+            Pane::Groups => self.config.groups.len(),
+            Pane::Scenes => self.scenes.len(),
+        }
+    }
+
+    fn selected(&self) -> usize {
+        self.selected[self.pane as usize]
+    }
+
+    /// Lights of the selected group that are on screen.
+    fn group_lights(&mut self) -> Vec<&mut Light> {
+        let Some(group) = self.config.groups.get(self.selected()) else {
+            return Vec::new();
+        };
+        let targets = resolve_group(&self.config, &group.name)
+            .map(|group| group.targets)
+            .unwrap_or_default();
+        self.lights
+            .iter_mut()
+            .filter(|light| targets.contains(&light.ip))
+            .collect()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Command> {
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        let index = self.selected();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Command::Quit),
+            KeyCode::Char('r') => return Some(Command::Reload),
+            KeyCode::Tab => self.pane = self.pane.next(),
+            KeyCode::Up => self.selected[self.pane as usize] = index.saturating_sub(1),
+            KeyCode::Down => {
+                let last = self.rows(self.pane).saturating_sub(1);
+                self.selected[self.pane as usize] = (index + 1).min(last);
+            }
+            KeyCode::Enter if self.pane == Pane::Scenes => {
+                return self.scenes.get(index).cloned().map(Command::ApplyScene);
+            }
+            KeyCode::Char(' ') => match self.pane {
+                Pane::Lights => {
+                    if let Some(light) = self.lights.get_mut(index) {
+                        let on = light.state.as_ref().is_some_and(|state| state.on);
+                        light.set_on(!on);
+                    }
+                }
+                Pane::Groups => {
+                    let mut members = self.group_lights();
+                    // On unless every member already is.
+                    let on = !members
+                        .iter()
+                        .all(|light| light.state.as_ref().is_some_and(|state| state.on));
+                    for light in &mut members {
+                        light.set_on(on);
+                    }
+                }
+                Pane::Scenes => {}
+            },
+            KeyCode::Left | KeyCode::Right => {
+                let sign = if key.code == KeyCode::Left { -1 } else { 1 };
+                let mut lights: Vec<&mut Light> = match self.pane {
+                    Pane::Lights => self.lights.get_mut(index).into_iter().collect(),
+                    Pane::Groups => self.group_lights(),
+                    Pane::Scenes => Vec::new(),
+                };
+                for light in &mut lights {
+                    if shift {
+                        light.step_kelvin(sign * KELVIN_STEP);
+                    } else {
+                        light.step_brightness(sign as i16 * BRIGHTNESS_STEP);
+                    }
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Send what changed on screen, one update per light.
+    fn send_pending(&mut self, client: &Client) {
+        for light in &mut self.lights {
+            let Some(update) = light.pending.take() else {
+                continue;
+            };
+            if let Err(err) = set_light(client, light.backend, &light.ip, &update) {
+                self.status = format!("{}: {err}", light.label);
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [top, bottom, status, help] = Layout::vertical([
+            Constraint::Min(8),
+            Constraint::Length(8),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [lights, details] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+        let [groups, scenes] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(bottom);
+
+        let light_rows = self
+            .lights
+            .iter()
+            .map(|light| {
+                let dot = match &light.state {
+                    Some(state) if state.on => "●",
+                    _ => "○",
+                };
+                format!("{dot} {}  {}", light.label, light.summary())
+            })
+            .collect();
+        self.draw_list(frame, lights, Pane::Lights, "Lights", light_rows);
+        let group_rows = self
+            .config
+            .groups
+            .iter()
+            .map(|group| group.name.clone())
+            .collect();
+        self.draw_list(frame, groups, Pane::Groups, "Groups", group_rows);
+        self.draw_list(frame, scenes, Pane::Scenes, "Scenes", self.scenes.clone());
+        self.draw_details(frame, details);
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+        frame.render_widget(
+            Paragraph::new(HELP).style(Style::new().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+
+    fn draw_list(&self, frame: &mut Frame, area: Rect, pane: Pane, title: &str, rows: Vec<String>) {
+        let focused = self.pane == pane;
+        let highlight = if focused {
+            Style::new().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::new().add_modifier(Modifier::BOLD)
+        };
+        let block = Block::bordered().title(title).border_style(if focused {
+            Style::new()
+        } else {
+            Style::new().add_modifier(Modifier::DIM)
+        });
+        let list = List::new(rows.into_iter().map(ListItem::new))
+            .block(block)
+            .highlight_style(highlight);
+        let mut state = ListState::default().with_selected(Some(self.selected[pane as usize]));
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let Some(light) = self.lights.get(self.selected[Pane::Lights as usize]) else {
+            frame.render_widget(
+                Paragraph::new("No enabled lights. Run `keylightd discover` first.")
+                    .block(Block::bordered()),
+                area,
+            );
+            return;
+        };
+        let block = Block::bordered().title(light.label.as_str());
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let Some(state) = &light.state else {
+            frame.render_widget(Paragraph::new("Not answering"), inner);
+            return;
+        };
+        let style = if state.on {
+            Style::new()
+        } else {
+            Style::new().add_modifier(Modifier::DIM)
+        };
+        let [brightness, temperature] =
+            Layout::vertical([Constraint::Length(3), Constraint::Length(3)]).areas(inner);
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title("Brightness"))
+                .gauge_style(style)
+                .ratio(f64::from(state.brightness) / 100.0)
+                .label(format!("{}%", locale::current().integer(state.brightness))),
+            brightness,
+        );
+        let span = f64::from(light.range.max.saturating_sub(light.range.min).max(1));
+        let ratio = f64::from(state.kelvin.saturating_sub(light.range.min)) / span;
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title("Temperature"))
+                .gauge_style(style)
+                .ratio(ratio.clamp(0.0, 1.0))
+                .label(format!("{}K", locale::current().integer(state.kelvin))),
+            temperature,
+        );
+    }
+
+    fn event_loop(
+        &mut self,
+        client: &Client,
+        terminal: &mut DefaultTerminal,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut last_read = Instant::now();
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(REFRESH.saturating_sub(last_read.elapsed()))? {
+                self.read_states(client);
+                last_read = Instant::now();
+                continue;
+            }
+            // Take every key already waiting, so a held arrow sends one update.
+            loop {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        match self.handle_key(key) {
+                            Some(Command::Quit) => return Ok(()),
+                            Some(Command::Reload) => {
+                                let pane = self.pane;
+                                *self = Self::load()?;
+                                self.pane = pane;
+                                self.read_states(client);
+                                last_read = Instant::now();
+                            }
+                            Some(Command::ApplyScene(name)) => {
+                                self.status = match snapshot::restore(client, &name, true) {
+                                    Ok(()) => format!("Applied '{name}'"),
+                                    Err(err) => format!("'{name}': {err}"),
+                                };
+                                self.read_states(client);
+                                last_read = Instant::now();
+                            }
+                            None => {}
+                        }
+                    }
+                }
+                if !event::poll(Duration::ZERO)? {
+                    break;
+                }
+            }
+            self.send_pending(client);
+        }
+    }
+}
+
+pub fn run(client: &Client) -> Result<(), Box<dyn Error>> {
+    let mut app = App::load()?;
+    app.read_states(client);
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(client, &mut terminal);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Group, LightRecord};
+
+    fn app() -> App {
+        let light = |id: &str, ip: &str| -> LightRecord {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "alias": id,
+                "name": id,
+                "hostname": format!("{id}.local"),
+                "port": 9123,
+                "addresses": [ip],
+                "last_seen_unix": 0,
+                "enabled": true
+            }))
+            .unwrap()
+        };
+        let config = Config {
+            lights: vec![
+                light("left", "192.168.1.20"),
+                light("right", "192.168.1.21"),
+            ],
+            groups: vec![Group {
+                name: "desk".into(),
+                members: vec!["left".into(), "right".into()],
+                stagger_ms: None,
+            }],
+            ..Default::default()
+        };
+        let mut app = App::new(config, vec!["stream".into()]);
+        for light in &mut app.lights {
+            light.state = Some(LightStateResponse {
+                id: light.id.clone(),
+                on: light.label == "left",
+                brightness: 98,
+                kelvin: 6900,
+            });
+        }
+        app
+    }
+
+    fn press(app: &mut App, code: KeyCode, modifiers: KeyModifiers) -> Option<Command> {
+        app.handle_key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn arrows_step_the_selected_light_within_its_range() {
+        let mut app = app();
+        press(&mut app, KeyCode::Right, KeyModifiers::NONE);
+        press(&mut app, KeyCode::Right, KeyModifiers::SHIFT);
+        let left = &app.lights[0];
+        let state = left.state.as_ref().unwrap();
+        assert_eq!((state.brightness, state.kelvin), (100, 7000));
+        let pending = left.pending.as_ref().unwrap();
+        assert_eq!(pending.brightness, Some(100));
+        assert_eq!(pending.temperature, Some(143));
+        assert_eq!(pending.on, None);
+        assert!(app.lights[1].pending.is_none());
+    }
+
+    #[test]
+    fn space_switches_a_group_on_unless_all_members_are() {
+        let mut app = app();
+        press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+        press(&mut app, KeyCode::Char(' '), KeyModifiers::NONE);
+        assert!(app
+            .lights
+            .iter()
+            .all(|light| light.state.as_ref().unwrap().on));
+        press(&mut app, KeyCode::Char(' '), KeyModifiers::NONE);
+        assert!(app
+            .lights
+            .iter()
+            .all(|light| !light.state.as_ref().unwrap().on));
+
+        press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+        assert_eq!(
+            press(&mut app, KeyCode::Enter, KeyModifiers::NONE),
+            Some(Command::ApplyScene("stream".into()))
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Read-back verification for `?verify=true` updates. Some firmware silently clamps
+//! or ignores values (WiZ won't dim below 10%, Key Lights stop at their kelvin range),
+//! so the state echoed by the write isn't proof; this reads the light again and
+//! reports every field that doesn't match what was sent.
+
+use reqwest::blocking::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{fetch_light_state, locale, Backend, KelvinRange, LightState, LightUpdate};
+
+/// Color is reported back as floats that don't always round-trip exactly.
+const COLOR_TOLERANCE: f64 = 1.0;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Verification {
+    /// Every field that was sent reads back as sent.
+    pub matched: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mismatches: Vec<Mismatch>,
+    /// The light couldn't be read back, so nothing was compared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub field: &'static str,
+    pub requested: Value,
+    pub actual: Value,
+}
+
+/// A compared value as shown to people: numbers in the user's locale.
+fn number(value: &Value) -> String {
+    match value.as_i64() {
+        Some(number) => locale::current().integer(number),
+        None => value.to_string(),
+    }
+}
+
+impl Verification {
+    /// One line per problem, for `set --verify`; none when everything matched.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .mismatches
+            .iter()
+            .map(|m| {
+                let (requested, actual) = (number(&m.requested), number(&m.actual));
+                format!("{}: requested {requested}, got {actual}", m.field)
+            })
+            .collect();
+        lines.extend(self.error.clone());
+        lines
+    }
+}
+
+/// Read the light at `ip` and compare it with `sent`. Temperatures are compared and
+/// reported in kelvin within `range`.
+pub fn read_back(
+    client: &Client,
+    backend: Backend,
+    ip: &str,
+    sent: &LightUpdate,
+    range: KelvinRange,
+) -> Verification {
+    match fetch_light_state(client, backend, ip) {
+        Some(actual) => {
+            let mismatches = compare(sent, &actual, range);
+            Verification {
+                matched: mismatches.is_empty(),
+                mismatches,
+                error: None,
+            }
+        }
+        None => Verification {
+            matched: false,
+            mismatches: Vec::new(),
+            error: Some("light could not be read back".into()),
+        },
+    }
+}
+
+fn compare(sent: &LightUpdate, actual: &LightState, range: KelvinRange) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    let mut check = |field, requested: Value, actual: Value, matches: bool| {
+        if !matches {
+            mismatches.push(Mismatch {
+                field,
+                requested,
+                actual,
+            });
+        }
+    };
+    if let Some(on) = sent.on {
+        check("on", json!(on == 1), json!(actual.on == 1), on == actual.on);
+    }
+    if let Some(brightness) = sent.brightness {
+        check(
+            "brightness",
+            json!(brightness),
+            json!(actual.brightness),
+            brightness == actual.brightness,
+        );
+    }
+    if let Some(mired) = sent.temperature {
+        let requested = range.mired_to_kelvin(mired);
+        // A strip in color mode reports no temperature at all.
+        let reported = (actual.temperature != 0).then(|| range.mired_to_kelvin(actual.temperature));
+        check(
+            "kelvin",
+            json!(requested),
+            json!(reported),
+            reported == Some(requested),
+        );
+    }
+    for (field, sent, actual) in [
+        ("hue", sent.hue, actual.hue),
+        ("saturation", sent.saturation, actual.saturation),
+    ] {
+        if let Some(sent) = sent {
+            let matches = actual.is_some_and(|actual| (actual - sent).abs() <= COLOR_TOLERANCE);
+            check(field, json!(sent), json!(actual), matches);
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_clamped_fields_only() {
+        let sent = LightUpdate {
+            on: Some(1),
+            brightness: Some(5),
+            temperature: Some(200),
+            ..Default::default()
+        };
+        let actual = LightState {
+            on: 1,
+            brightness: 10,
+            temperature: 200,
+            hue: None,
+            saturation: None,
+        };
+        let mismatches = compare(&sent, &actual, KelvinRange::DEFAULT);
+        assert_eq!(
+            mismatches,
+            vec![Mismatch {
+                field: "brightness",
+                requested: json!(5),
+                actual: json!(10),
+            }]
+        );
+
+        let color = LightUpdate {
+            hue: Some(120.0),
+            saturation: Some(80.0),
+            ..Default::default()
+        };
+        let strip = LightState {
+            hue: Some(120.4),
+            saturation: None,
+            ..actual
+        };
+        let mismatches = compare(&color, &strip, KelvinRange::DEFAULT);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].field, "saturation");
+    }
+
+    #[test]
+    fn problems_print_one_per_line() {
+        let clamped = Verification {
+            matched: false,
+            mismatches: vec![Mismatch {
+                field: "kelvin",
+                requested: json!(7000),
+                actual: json!(6500),
+            }],
+            error: None,
+        };
+        assert_eq!(clamped.lines(), ["kelvin: requested 7000, got 6500"]);
+        let unreadable = Verification {
+            matched: false,
+            mismatches: Vec::new(),
+            error: Some("light could not be read back".into()),
+        };
+        assert_eq!(unreadable.lines(), ["light could not be read back"]);
+    }
+}
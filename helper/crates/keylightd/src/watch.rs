@@ -0,0 +1,133 @@
+//! `keylightd watch`: polls the selected lights and prints a line each time one
+//! changes, stops answering or answers again, until interrupted. Lights are read
+//! directly, so it works without `serve` running.
+
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{
+    backend_for_ip, fetch_light_state, light_for_ip, load_config, locale, LightStateResponse,
+};
+
+/// One printed change; with `--json` a line of its own.
+#[derive(Serialize, Debug)]
+struct Change<'a> {
+    observed_unix: u64,
+    id: &'a str,
+    reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kelvin: Option<u16>,
+}
+
+impl<'a> Change<'a> {
+    fn new(observed_unix: u64, id: &'a str, state: Option<&LightStateResponse>) -> Self {
+        Self {
+            observed_unix,
+            id,
+            reachable: state.is_some(),
+            on: state.map(|state| state.on),
+            brightness: state.map(|state| state.brightness),
+            kelvin: state.map(|state| state.kelvin),
+        }
+    }
+
+    fn line(&self, json: bool) -> Result<String, Box<dyn Error>> {
+        if json {
+            return Ok(serde_json::to_string(self)?);
+        }
+        let mut line = format!("observed_unix={}, id={}", self.observed_unix, self.id);
+        match (self.on, self.brightness, self.kelvin) {
+            (Some(on), Some(brightness), Some(kelvin)) => {
+                let format = locale::current();
+                line.push_str(&format!(
+                    ", on={}, brightness={}, kelvin={}",
+                    u8::from(on),
+                    format.integer(brightness),
+                    format.integer(kelvin)
+                ))
+            }
+            _ => line.push_str(", reachable=false"),
+        }
+        Ok(line)
+    }
+}
+
+/// Poll `targets` (addresses) every `interval`. The first reading of each light is
+/// printed too, so the output starts with the current state.
+pub fn run(
+    client: &Client,
+    targets: &[String],
+    interval: Duration,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let config = load_config().unwrap_or_default();
+    let mut last: HashMap<&str, Option<LightStateResponse>> = HashMap::new();
+    let mut stdout = std::io::stdout();
+    loop {
+        for ip in targets {
+            let state = fetch_light_state(client, backend_for_ip(&config, ip), ip)
+                .map(|state| LightStateResponse::for_ip(&config, ip, &state));
+            if last.get(ip.as_str()) == Some(&state) {
+                continue;
+            }
+            let observed_unix = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let id = light_for_ip(&config, ip).map_or(ip.as_str(), |light| light.id.as_str());
+            let line = Change::new(observed_unix, id, state.as_ref()).line(json)?;
+            // Unlike println!, a closed pipe (`watch | head`) ends the watch quietly.
+            writeln!(stdout, "{line}")?;
+            last.insert(ip, state);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_print_as_text_or_json_lines() {
+        let state = LightStateResponse {
+            id: "left".into(),
+            on: true,
+            brightness: 40,
+            kelvin: 5000,
+        };
+        let seen = Change::new(100, "left", Some(&state));
+        assert_eq!(
+            seen.line(false).unwrap(),
+            "observed_unix=100, id=left, on=1, brightness=40, kelvin=5000"
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&seen.line(true).unwrap()).unwrap(),
+            serde_json::json!({
+                "observed_unix": 100,
+                "id": "left",
+                "reachable": true,
+                "on": true,
+                "brightness": 40,
+                "kelvin": 5000
+            })
+        );
+
+        let gone = Change::new(101, "192.168.1.20", None);
+        assert_eq!(
+            gone.line(false).unwrap(),
+            "observed_unix=101, id=192.168.1.20, reachable=false"
+        );
+        assert_eq!(
+            gone.line(true).unwrap(),
+            r#"{"observed_unix":101,"id":"192.168.1.20","reachable":false}"#
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! The control page served at `/`: one self-contained HTML file, built into the
+//! binary, that drives the same `/v1` API as the tray. Reach it from a phone by
+//! starting `serve --listen 0.0.0.0`.
+
+use tiny_http::{Header, Response, StatusCode};
+
+const INDEX: &str = include_str!("web/index.html");
+
+/// Inline script and style only, talking to this daemon; the page can't be framed.
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; script-src 'unsafe-inline'; \
+     style-src 'unsafe-inline'; connect-src 'self'; frame-ancestors 'none'";
+
+pub fn index() -> Response<std::io::Cursor<Vec<u8>>> {
+    let header =
+        |name: &str, value: &str| Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap();
+    Response::from_data(INDEX.as_bytes().to_vec())
+        .with_status_code(StatusCode(200))
+        .with_header(header("Content-Type", "text/html; charset=utf-8"))
+        .with_header(header("Content-Security-Policy", CONTENT_SECURITY_POLICY))
+        .with_header(header("X-Content-Type-Options", "nosniff"))
+        .with_header(header("Cache-Control", "no-cache"))
+}
@@ -0,0 +1,105 @@
+//! Estimate the color temperature of a reference photo, for `keylightd match`.
+
+use image::DynamicImage;
+use std::error::Error;
+use std::path::Path;
+
+/// Longest side the photo is scaled to before sampling; plenty for an average.
+const SAMPLE_SIZE: u32 = 256;
+
+pub fn estimate_kelvin_from_file(path: &Path) -> Result<u16, Box<dyn Error>> {
+    let img =
+        image::open(path).map_err(|err| format!("Unable to read {}: {err}", path.display()))?;
+    estimate_kelvin(&img)
+        .ok_or_else(|| "Photo has no usable (unclipped, non-dark) pixels to sample".into())
+}
+
+/// Gray-world estimate over mid-to-bright pixels: clipped highlights and near-black
+/// pixels carry no white-point information, so they are ignored. Returns the
+/// correlated color temperature of the average; callers clamp it to what a light supports.
+pub fn estimate_kelvin(img: &DynamicImage) -> Option<u16> {
+    let rgb = if img.width() > SAMPLE_SIZE || img.height() > SAMPLE_SIZE {
+        img.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8()
+    } else {
+        img.to_rgb8()
+    };
+    let mut sum = [0.0f64; 3];
+    let mut count = 0u32;
+    for pixel in rgb.pixels() {
+        if pixel.0.iter().any(|&c| c >= 250) {
+            continue;
+        }
+        let linear = pixel.0.map(srgb_to_linear);
+        let luminance = 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2];
+        if luminance < 0.05 {
+            continue;
+        }
+        for (total, value) in sum.iter_mut().zip(linear) {
+            *total += value;
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    let [r, g, b] = sum.map(|total| total / count as f64);
+    linear_rgb_to_kelvin(r, g, b)
+}
+
+fn srgb_to_linear(channel: u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Linear sRGB to CIE xy, then McCamy's approximation of CCT.
+fn linear_rgb_to_kelvin(r: f64, g: f64, b: f64) -> Option<u16> {
+    let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+    let total = x + y + z;
+    if total <= 0.0 {
+        return None;
+    }
+    let (cx, cy) = (x / total, y / total);
+    let n = (cx - 0.3320) / (0.1858 - cy);
+    let cct = 449.0 * n.powi(3) + 3525.0 * n.powi(2) + 6823.3 * n + 5520.33;
+    cct.is_finite()
+        .then(|| cct.clamp(1000.0, 20000.0).round() as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neutral_gray_is_about_d65() {
+        let kelvin = linear_rgb_to_kelvin(0.5, 0.5, 0.5).unwrap();
+        assert!((6400..=6600).contains(&kelvin), "{kelvin}");
+    }
+
+    #[test]
+    fn warm_photo_is_warmer_than_cool_photo() {
+        let warm = linear_rgb_to_kelvin(0.9, 0.55, 0.25).unwrap();
+        let cool = linear_rgb_to_kelvin(0.5, 0.55, 0.7).unwrap();
+        assert!(warm < 4000, "{warm}");
+        assert!(cool > 7000, "{cool}");
+    }
+
+    #[test]
+    fn clipped_and_dark_pixels_are_ignored() {
+        let img = image::RgbImage::from_fn(4, 4, |x, _| match x {
+            0 => image::Rgb([255, 255, 255]),
+            1 => image::Rgb([2, 2, 2]),
+            _ => image::Rgb([180, 180, 180]),
+        });
+        let kelvin = estimate_kelvin(&DynamicImage::ImageRgb8(img)).unwrap();
+        assert!((6400..=6600).contains(&kelvin), "{kelvin}");
+
+        let black = image::RgbImage::new(4, 4);
+        assert_eq!(estimate_kelvin(&DynamicImage::ImageRgb8(black)), None);
+    }
+}
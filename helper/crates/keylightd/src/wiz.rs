@@ -0,0 +1,255 @@
+//! WiZ lights: JSON requests over UDP port 38899, discovered by broadcasting
+//! `getSystemConfig` and collecting the replies.
+
+use serde_json::{json, Value};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::error::KeylightError;
+use super::logs::log_line;
+use super::{Backend, Capabilities, DiscoveredLight, KelvinRange, LightState, LightUpdate};
+
+pub const PORT: u16 = 38899;
+/// Range of WiZ tunable-white and RGB modules.
+pub const KELVIN_RANGE: KelvinRange = KelvinRange {
+    min: 2200,
+    max: 6500,
+};
+
+const REPLY_TIMEOUT: Duration = Duration::from_millis(700);
+/// UDP gets lost; resend a request this many times before giving up.
+const ATTEMPTS: usize = 3;
+
+fn request(ip: &str, method: &str, params: Value) -> Result<Value, KeylightError> {
+    let unreachable = |err: io::Error| KeylightError::unreachable(ip, err);
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(unreachable)?;
+    socket
+        .set_read_timeout(Some(REPLY_TIMEOUT))
+        .map_err(unreachable)?;
+    let body = json!({"method": method, "params": params}).to_string();
+    let mut buf = [0u8; 2048];
+    for _ in 0..ATTEMPTS {
+        socket
+            .send_to(body.as_bytes(), (ip, PORT))
+            .map_err(unreachable)?;
+        let Ok((len, _)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let reply: Value = serde_json::from_slice(&buf[..len])
+            .map_err(|err| KeylightError::protocol(ip, format!("WiZ {method} reply: {err}")))?;
+        if let Some(error) = reply.get("error") {
+            return Err(KeylightError::protocol(
+                ip,
+                format!("WiZ {method} failed: {error}"),
+            ));
+        }
+        return Ok(reply.get("result").cloned().unwrap_or(Value::Null));
+    }
+    Err(KeylightError::unreachable(
+        ip,
+        "no reply from the WiZ light",
+    ))
+}
+
+pub fn get_state(ip: &str) -> Option<LightState> {
+    let pilot = request(ip, "getPilot", json!({})).ok()?;
+    Some(state_from_pilot(&pilot))
+}
+
+/// Apply `update` and read back the resulting state.
+pub fn set(ip: &str, update: &LightUpdate) -> Result<LightState, KeylightError> {
+    request(ip, "setPilot", pilot_params(update))?;
+    let pilot = request(ip, "getPilot", json!({}))?;
+    Ok(state_from_pilot(&pilot))
+}
+
+fn pilot_params(update: &LightUpdate) -> Value {
+    let mut params = serde_json::Map::new();
+    if let Some(on) = update.on {
+        params.insert("state".into(), json!(on == 1));
+    }
+    if let Some(brightness) = update.brightness {
+        // WiZ dims between 10 and 100; 0 would be rejected.
+        params.insert("dimming".into(), json!(brightness.clamp(10, 100)));
+    }
+    if update.hue.is_some() || update.saturation.is_some() {
+        let [r, g, b] = hue_saturation_to_rgb(
+            update.hue.unwrap_or(0.0),
+            update.saturation.unwrap_or(100.0),
+        );
+        params.insert("r".into(), json!(r));
+        params.insert("g".into(), json!(g));
+        params.insert("b".into(), json!(b));
+    } else if let Some(mired) = update.temperature {
+        params.insert("temp".into(), json!(KELVIN_RANGE.mired_to_kelvin(mired)));
+    }
+    Value::Object(params)
+}
+
+fn state_from_pilot(pilot: &Value) -> LightState {
+    let field = |key: &str| pilot.get(key).and_then(Value::as_u64);
+    let temperature = field("temp")
+        .map(|kelvin| KELVIN_RANGE.kelvin_to_mired(kelvin as u16))
+        .unwrap_or(0);
+    let (hue, saturation) = match (field("r"), field("g"), field("b")) {
+        (Some(r), Some(g), Some(b)) if temperature == 0 => {
+            let (hue, saturation) = rgb_to_hue_saturation(r as u8, g as u8, b as u8);
+            (Some(hue), Some(saturation))
+        }
+        _ => (None, None),
+    };
+    LightState {
+        on: u8::from(pilot.get("state").and_then(Value::as_bool).unwrap_or(false)),
+        brightness: field("dimming").unwrap_or(100).min(100) as u8,
+        temperature,
+        hue,
+        saturation,
+    }
+}
+
+/// Broadcast for WiZ modules and collect everything that answers before `timeout`.
+pub fn discover(timeout: Duration) -> Vec<DiscoveredLight> {
+    match discover_inner(timeout) {
+        Ok(found) => found,
+        Err(err) => {
+            log_line!("WiZ discovery failed: {err}");
+            Vec::new()
+        }
+    }
+}
+
+fn discover_inner(timeout: Duration) -> io::Result<Vec<DiscoveredLight>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    let body = json!({"method": "getSystemConfig", "params": {}}).to_string();
+    socket.send_to(body.as_bytes(), (Ipv4Addr::BROADCAST, PORT))?;
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<DiscoveredLight> = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let Ok((len, SocketAddr::V4(from))) = socket.recv_from(&mut buf) else {
+            break;
+        };
+        let Ok(reply) = serde_json::from_slice::<Value>(&buf[..len]) else {
+            continue;
+        };
+        let Some(light) = discovered_from_config(&reply, &from.ip().to_string()) else {
+            continue;
+        };
+        if !found.iter().any(|known| known.id == light.id) {
+            found.push(light);
+        }
+    }
+    Ok(found)
+}
+
+fn discovered_from_config(reply: &Value, ip: &str) -> Option<DiscoveredLight> {
+    let result = reply.get("result")?;
+    let mac = result.get("mac")?.as_str()?;
+    let module = result
+        .get("moduleName")
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    let capabilities = capabilities_for_module(module);
+    Some(DiscoveredLight {
+        id: format!("wiz-{mac}"),
+        name: if module.is_empty() {
+            format!("WiZ {mac}")
+        } else {
+            format!("WiZ {module}")
+        },
+        ip: ip.to_string(),
+        port: PORT,
+        backend: Backend::Wiz,
+        capabilities,
+        kelvin_range: capabilities.temperature.then_some(KELVIN_RANGE),
+        accessory_info: None,
+    })
+}
+
+/// Module names look like `ESP01_SHRGB1C_31` (RGB + tunable white), `ESP56_SHTW3_01`
+/// (tunable white) or `ESP06_SHDW9_01` (dimmable only).
+fn capabilities_for_module(module: &str) -> Capabilities {
+    let color = module.contains("RGB");
+    Capabilities {
+        brightness: true,
+        temperature: color || module.contains("TW"),
+        color,
+    }
+}
+
+fn hue_saturation_to_rgb(hue: f64, saturation: f64) -> [u8; 3] {
+    let s = (saturation / 100.0).clamp(0.0, 1.0);
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // Desaturate towards white; value stays at full, brightness is `dimming`.
+    [r, g, b].map(|c| ((1.0 - s + s * c) * 255.0).round() as u8)
+}
+
+fn rgb_to_hue_saturation(r: u8, g: u8, b: u8) -> (f64, f64) {
+    let [r, g, b] = [r, g, b].map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    if delta <= f64::EPSILON {
+        return (0.0, 0.0);
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue.round(), (delta / max * 100.0).round())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_names_map_to_capabilities() {
+        let rgb = capabilities_for_module("ESP01_SHRGB1C_31");
+        assert!(rgb.color && rgb.temperature);
+        let tunable = capabilities_for_module("ESP56_SHTW3_01");
+        assert!(!tunable.color && tunable.temperature);
+        let dimmable = capabilities_for_module("ESP06_SHDW9_01");
+        assert!(!dimmable.color && !dimmable.temperature && dimmable.brightness);
+    }
+
+    #[test]
+    fn pilot_params_prefer_color_over_temperature() {
+        let update = LightUpdate {
+            on: Some(1),
+            brightness: Some(0),
+            temperature: Some(250),
+            hue: Some(120.0),
+            saturation: Some(100.0),
+        };
+        assert_eq!(
+            pilot_params(&update),
+            json!({"state": true, "dimming": 10, "r": 0, "g": 255, "b": 0})
+        );
+    }
+
+    #[test]
+    fn color_pilot_round_trips_hue() {
+        let [r, g, b] = hue_saturation_to_rgb(200.0, 60.0);
+        let (hue, saturation) = rgb_to_hue_saturation(r, g, b);
+        assert!((hue - 200.0).abs() <= 1.0, "{hue}");
+        assert!((saturation - 60.0).abs() <= 1.0, "{saturation}");
+    }
+}